@@ -0,0 +1,194 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+
+use crate::client::Client;
+use crate::commands::CommandError;
+use crate::resp::data::RESPDataType;
+
+/// Outcome of a command that is allowed to block the calling client.
+pub enum CommandOutcome {
+    Reply(RESPDataType),
+    Block(BlockRequest),
+}
+
+/// What a blocking command needs to park its client until one of `keys` is ready.
+pub struct BlockRequest {
+    /// The command to re-execute once one of the keys is signalled as ready.
+    pub args: Vec<Bytes>,
+    pub keys: Vec<Bytes>,
+    pub deadline: Option<Instant>,
+}
+
+/// A client parked on one or more keys of the database it has selected. The
+/// client's connection is owned by the registry, so no worker thread is tied
+/// up while it waits.
+pub struct BlockedClient {
+    pub client: Client,
+    pub args: Vec<Bytes>,
+    pub keys: Vec<Bytes>,
+    pub deadline: Option<Instant>,
+}
+
+/// A key along with the index of the database holding it.
+pub type DbKey = (usize, Bytes);
+
+/// Registry of blocked clients, indexed by the keys they are waiting on.
+#[derive(Default)]
+pub struct BlockingState {
+    /// Clients waiting on each key, longest waiting first.
+    waiting: HashMap<DbKey, VecDeque<u64>>,
+    clients: HashMap<u64, BlockedClient>,
+    /// Keys that received data since blocked clients were last served.
+    ready_keys: Vec<DbKey>,
+    ready_set: HashSet<DbKey>,
+}
+
+impl BlockingState {
+    pub fn block(&mut self, client: Client, request: BlockRequest) {
+        let (id, db) = (client.id, client.db);
+        for key in &request.keys {
+            let queue = self.waiting.entry((db, key.clone())).or_default();
+            if !queue.contains(&id) {
+                queue.push_back(id);
+            }
+        }
+        self.clients.insert(
+            id,
+            BlockedClient {
+                client,
+                args: request.args,
+                keys: request.keys,
+                deadline: request.deadline,
+            },
+        );
+    }
+
+    pub fn blocked_count(&self) -> usize {
+        self.clients.len()
+    }
+
+    /// Mark `key` of database `db` as ready if any client is waiting on it.
+    pub fn signal_key_as_ready(&mut self, db: usize, key: &Bytes) {
+        let db_key = (db, key.clone());
+        if self.waiting.contains_key(&db_key) && self.ready_set.insert(db_key.clone()) {
+            self.ready_keys.push(db_key);
+        }
+    }
+
+    pub fn take_ready_keys(&mut self) -> Vec<DbKey> {
+        self.ready_set.clear();
+        std::mem::take(&mut self.ready_keys)
+    }
+
+    /// The clients waiting on `key`, longest waiting first.
+    pub fn waiters(&self, key: &DbKey) -> Vec<u64> {
+        self.waiting
+            .get(key)
+            .map(|queue| queue.iter().copied().collect())
+            .unwrap_or_default()
+    }
+
+    /// The keys of database `db` that clients are waiting on.
+    pub fn waited_keys(&self, db: usize) -> Vec<Bytes> {
+        self.waiting
+            .keys()
+            .filter(|(key_db, _)| *key_db == db)
+            .map(|(_, key)| key.clone())
+            .collect()
+    }
+
+    /// Temporarily take a blocked client out of the registry while keeping its
+    /// place in the key queues, so that it can be put back with `restore`.
+    pub fn take(&mut self, id: u64) -> Option<BlockedClient> {
+        self.clients.remove(&id)
+    }
+
+    pub fn restore(&mut self, blocked: BlockedClient) {
+        self.clients.insert(blocked.client.id, blocked);
+    }
+
+    /// Remove a client, previously taken out with `take`, from every key queue.
+    pub fn unblock(&mut self, blocked: &BlockedClient) {
+        for key in &blocked.keys {
+            let db_key = (blocked.client.db, key.clone());
+            if let Some(queue) = self.waiting.get_mut(&db_key) {
+                queue.retain(|id| *id != blocked.client.id);
+                if queue.is_empty() {
+                    self.waiting.remove(&db_key);
+                }
+            }
+        }
+    }
+
+    fn remove_where(&mut self, predicate: impl Fn(&BlockedClient) -> bool) -> Vec<BlockedClient> {
+        let ids: Vec<u64> = self
+            .clients
+            .iter()
+            .filter(|(_, blocked)| predicate(blocked))
+            .map(|(id, _)| *id)
+            .collect();
+        let mut removed = Vec::with_capacity(ids.len());
+        for id in ids {
+            let blocked = self.clients.remove(&id).unwrap();
+            self.unblock(&blocked);
+            removed.push(blocked);
+        }
+        removed
+    }
+
+    /// Remove and return the clients whose timeout has elapsed.
+    pub fn take_timed_out(&mut self, now: Instant) -> Vec<BlockedClient> {
+        self.remove_where(|blocked| blocked.deadline.is_some_and(|deadline| deadline <= now))
+    }
+
+    /// Remove and return the clients whose peer went away while blocked.
+    pub fn take_disconnected(&mut self) -> Vec<BlockedClient> {
+        self.remove_where(|blocked| blocked.client.is_disconnected())
+    }
+}
+
+/// Parse a blocking timeout given in (possibly fractional) seconds, 0 meaning forever.
+pub fn parse_timeout(arg: &[u8]) -> Result<Option<Instant>, CommandError> {
+    let timeout = std::str::from_utf8(arg)
+        .ok()
+        .and_then(|s| s.parse::<f64>().ok())
+        .filter(|timeout| timeout.is_finite())
+        .ok_or(CommandError::Custom(String::from(
+            "ERR timeout is not a float or out of range",
+        )))?;
+    if timeout < 0.0 {
+        return Err(CommandError::Custom(String::from(
+            "ERR timeout is negative",
+        )));
+    }
+    if timeout == 0.0 {
+        return Ok(None);
+    }
+    Ok(Some(Instant::now() + Duration::from_secs_f64(timeout)))
+}
+
+/// Reply with `reply` if there is something to reply with, otherwise block on
+/// `keys` unless blocking is not allowed (as inside MULTI), in which case
+/// reply with `empty`, the command's reply when there is nothing to serve.
+pub fn reply_or_block(
+    reply: Option<RESPDataType>,
+    empty: RESPDataType,
+    args: &[Bytes],
+    keys: &[Bytes],
+    deadline: Option<Instant>,
+    may_block: bool,
+) -> Result<CommandOutcome, CommandError> {
+    Ok(match reply {
+        Some(reply) => CommandOutcome::Reply(reply),
+        None if !may_block => CommandOutcome::Reply(empty),
+        None => CommandOutcome::Block(BlockRequest {
+            args: args.to_vec(),
+            keys: keys.to_vec(),
+            deadline,
+        }),
+    })
+}
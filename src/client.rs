@@ -0,0 +1,130 @@
+use std::{
+    io::{self, prelude::*},
+    net::TcpStream,
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+};
+
+use bytes::{Buf, BytesMut};
+
+use crate::resp::data::{RESPDataType, RESPError};
+use crate::resp::deserializer::RespDeserializer;
+use crate::resp::serializer::RespSerializer;
+
+static NEXT_CLIENT_ID: AtomicU64 = AtomicU64::new(1);
+static CONNECTED_CLIENTS: AtomicUsize = AtomicUsize::new(0);
+/// Bytes allocated for the query and reply buffers of every client.
+static CLIENT_BUFFERS: AtomicUsize = AtomicUsize::new(0);
+
+/// Number of clients connected, blocked ones included.
+pub fn connected_clients() -> usize {
+    CONNECTED_CLIENTS.load(Ordering::Relaxed)
+}
+
+/// Bytes held by the buffers of all clients.
+pub fn client_buffers_memory() -> usize {
+    CLIENT_BUFFERS.load(Ordering::Relaxed)
+}
+
+/// A connected client along with its buffers and per connection state.
+pub struct Client {
+    pub id: u64,
+    stream: TcpStream,
+    query_buffer: BytesMut,
+    reply_buffer: BytesMut,
+    /// Commands queued between MULTI and EXEC.
+    pub multi: Option<Vec<RESPDataType>>,
+    /// Set when a command was refused while queuing, making EXEC fail.
+    pub multi_failed: bool,
+    /// Index of the database selected with SELECT.
+    pub db: usize,
+    /// Size of the buffers last added to `CLIENT_BUFFERS`.
+    buffers_size: usize,
+}
+
+impl Client {
+    pub fn new(stream: TcpStream) -> Self {
+        CONNECTED_CLIENTS.fetch_add(1, Ordering::Relaxed);
+        Client {
+            id: NEXT_CLIENT_ID.fetch_add(1, Ordering::Relaxed),
+            stream,
+            query_buffer: BytesMut::new(),
+            reply_buffer: BytesMut::new(),
+            multi: None,
+            multi_failed: false,
+            db: 0,
+            buffers_size: 0,
+        }
+    }
+
+    /// Take the next complete command out of the query buffer, if one has fully arrived.
+    pub fn next_command(&mut self) -> Result<Option<RESPDataType>, RESPError> {
+        let resp_deserializer = RespDeserializer;
+        match resp_deserializer.deserialize(&self.query_buffer, 0)? {
+            Some((pos, command)) => {
+                self.query_buffer.advance(pos);
+                Ok(Some(command))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Read whatever is available on the socket into the query buffer.
+    pub fn read_from_socket(&mut self) -> io::Result<usize> {
+        let mut buffer = [0; 16 * 1024];
+        let size = self.stream.read(&mut buffer)?;
+        self.query_buffer.extend_from_slice(&buffer[..size]);
+        self.account_buffers();
+        Ok(size)
+    }
+
+    pub fn add_reply(&mut self, reply: &RESPDataType) {
+        let resp_serializer = RespSerializer;
+        self.reply_buffer
+            .extend_from_slice(&resp_serializer.serialize(reply));
+        self.account_buffers();
+    }
+
+    /// Write all pending replies to the socket.
+    pub fn flush(&mut self) -> io::Result<()> {
+        if !self.reply_buffer.is_empty() {
+            self.stream.write_all(&self.reply_buffer)?;
+            self.reply_buffer.clear();
+        }
+        Ok(())
+    }
+
+    /// Bring `CLIENT_BUFFERS` up to date with the size of the buffers.
+    fn account_buffers(&mut self) {
+        let size = self.query_buffer.capacity() + self.reply_buffer.capacity();
+        if size > self.buffers_size {
+            CLIENT_BUFFERS.fetch_add(size - self.buffers_size, Ordering::Relaxed);
+        } else {
+            CLIENT_BUFFERS.fetch_sub(self.buffers_size - size, Ordering::Relaxed);
+        }
+        self.buffers_size = size;
+    }
+
+    /// Check, without consuming any input, whether the peer has closed the connection.
+    pub fn is_disconnected(&self) -> bool {
+        if self.stream.set_nonblocking(true).is_err() {
+            return true;
+        }
+        let mut buffer = [0; 1];
+        let result = self.stream.peek(&mut buffer);
+        if self.stream.set_nonblocking(false).is_err() {
+            return true;
+        }
+        match result {
+            Ok(0) => true,
+            Ok(_) => false,
+            Err(e) => e.kind() != io::ErrorKind::WouldBlock,
+        }
+    }
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        CONNECTED_CLIENTS.fetch_sub(1, Ordering::Relaxed);
+        CLIENT_BUFFERS.fetch_sub(self.buffers_size, Ordering::Relaxed);
+    }
+}
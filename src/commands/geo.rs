@@ -0,0 +1,652 @@
+use bytes::Bytes;
+
+use super::zset::{get_zset, handle_zadd, store_zset};
+use super::{check_arity, is_keyword, parse_float, parse_int, CommandError, CommandResult};
+use crate::resp::data::RESPDataType;
+use crate::store::Store;
+use crate::types::geohash::{self, Search, Shape};
+use crate::types::zset::{ScoreRange, ZSet};
+
+/// Meters per unit of distance.
+fn parse_unit(arg: &[u8]) -> Result<f64, CommandError> {
+    match arg.to_ascii_lowercase().as_slice() {
+        b"m" => Ok(1.0),
+        b"km" => Ok(1000.0),
+        b"ft" => Ok(0.3048),
+        b"mi" => Ok(1609.34),
+        _ => Err(CommandError::Custom(String::from(
+            "ERR unsupported unit provided. please use M, KM, FT, MI",
+        ))),
+    }
+}
+
+fn parse_coordinates(longitude: &[u8], latitude: &[u8]) -> Result<(f64, f64), CommandError> {
+    let longitude = parse_float(longitude)?;
+    let latitude = parse_float(latitude)?;
+    if !geohash::is_valid(longitude, latitude) {
+        return Err(CommandError::Custom(format!(
+            "ERR invalid longitude,latitude pair {:.6},{:.6}",
+            longitude, latitude
+        )));
+    }
+    Ok((longitude, latitude))
+}
+
+/// A coordinate with 17 decimals, trailing zeros removed.
+fn format_coordinate(value: f64) -> Bytes {
+    let formatted = format!("{:.17}", value);
+    let trimmed = formatted.trim_end_matches('0').trim_end_matches('.');
+    Bytes::copy_from_slice(trimmed.as_bytes())
+}
+
+fn format_distance(distance: f64) -> Bytes {
+    Bytes::from(format!("{:.4}", distance))
+}
+
+fn coordinates_reply((longitude, latitude): (f64, f64)) -> RESPDataType {
+    RESPDataType::Array(vec![
+        RESPDataType::BulkString(format_coordinate(longitude)),
+        RESPDataType::BulkString(format_coordinate(latitude)),
+    ])
+}
+
+/// GEOADD key [NX|XX] [CH] longitude latitude member [longitude latitude member ...]
+pub fn handle_geoadd(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, -5)?;
+    let mut index = 2;
+    while args
+        .get(index)
+        .is_some_and(|arg| ["NX", "XX", "CH"].iter().any(|flag| is_keyword(arg, flag)))
+    {
+        index += 1;
+    }
+    let triples = &args[index..];
+    if triples.is_empty() || !triples.len().is_multiple_of(3) {
+        return Err(CommandError::Custom(String::from(
+            "ERR syntax error. Try GEOADD key [x1] [y1] [name1] [x2] [y2] [name2] ... ",
+        )));
+    }
+
+    // Rewrite as a ZADD whose scores are the geohashes of the coordinates.
+    let mut zadd_args = vec![Bytes::from("ZADD")];
+    zadd_args.extend_from_slice(&args[1..index]);
+    for triple in triples.chunks(3) {
+        let (longitude, latitude) = parse_coordinates(&triple[0], &triple[1])?;
+        let score = geohash::encode_wgs84(longitude, latitude).unwrap();
+        zadd_args.push(Bytes::from(score.to_string()));
+        zadd_args.push(triple[2].clone());
+    }
+    handle_zadd(&zadd_args, store)
+}
+
+/// GEOPOS key [member ...]
+pub fn handle_geopos(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, -2)?;
+    let zset = get_zset(store, &args[1])?;
+    Ok(RESPDataType::Array(
+        args[2..]
+            .iter()
+            .map(|member| match zset.and_then(|zset| zset.score(member)) {
+                Some(score) => coordinates_reply(geohash::decode_score(score)),
+                None => RESPDataType::NullArray,
+            })
+            .collect(),
+    ))
+}
+
+/// GEODIST key member1 member2 [M|KM|FT|MI]
+pub fn handle_geodist(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, -4)?;
+    let conversion = match &args[4..] {
+        [] => 1.0,
+        [unit] => parse_unit(unit)?,
+        _ => return Err(CommandError::Syntax),
+    };
+    let Some(zset) = get_zset(store, &args[1])? else {
+        return Ok(RESPDataType::NullBulkString);
+    };
+    let (Some(first), Some(second)) = (zset.score(&args[2]), zset.score(&args[3])) else {
+        return Ok(RESPDataType::NullBulkString);
+    };
+    let (lon1, lat1) = geohash::decode_score(first);
+    let (lon2, lat2) = geohash::decode_score(second);
+    let distance = geohash::distance(lon1, lat1, lon2, lat2) / conversion;
+    Ok(RESPDataType::BulkString(format_distance(distance)))
+}
+
+/// GEOHASH key [member ...]
+pub fn handle_geohash(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, -2)?;
+    let zset = get_zset(store, &args[1])?;
+    Ok(RESPDataType::Array(
+        args[2..]
+            .iter()
+            .map(|member| match zset.and_then(|zset| zset.score(member)) {
+                Some(score) => {
+                    RESPDataType::BulkString(Bytes::from(geohash::geohash_string(score)))
+                }
+                None => RESPDataType::NullBulkString,
+            })
+            .collect(),
+    ))
+}
+
+enum Origin {
+    Member(Bytes),
+    Coordinates(f64, f64),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Sort {
+    Asc,
+    Desc,
+}
+
+/// The options of GEOSEARCH and GEOSEARCHSTORE.
+struct SearchOptions {
+    origin: Origin,
+    /// The shape in the requested unit.
+    shape: Shape,
+    /// Meters per unit of distance.
+    conversion: f64,
+    sort: Option<Sort>,
+    count: Option<usize>,
+    any: bool,
+    with_coord: bool,
+    with_dist: bool,
+    with_hash: bool,
+    store_dist: bool,
+}
+
+fn parse_search_options(
+    args: &[Bytes],
+    start: usize,
+    is_store: bool,
+) -> Result<SearchOptions, CommandError> {
+    let mut origin = None;
+    let mut origins = 0;
+    let mut shape = None;
+    let mut shapes = 0;
+    let mut conversion = 1.0;
+    let mut sort = None;
+    let mut count = None;
+    let mut any = false;
+    let (mut with_coord, mut with_dist, mut with_hash, mut store_dist) =
+        (false, false, false, false);
+
+    let mut index = start;
+    while let Some(arg) = args.get(index) {
+        let remaining = args.len() - index - 1;
+        match arg.to_ascii_uppercase().as_slice() {
+            b"FROMMEMBER" if remaining >= 1 => {
+                origin = Some(Origin::Member(args[index + 1].clone()));
+                origins += 1;
+                index += 1;
+            }
+            b"FROMLONLAT" if remaining >= 2 => {
+                let (longitude, latitude) = parse_coordinates(&args[index + 1], &args[index + 2])?;
+                origin = Some(Origin::Coordinates(longitude, latitude));
+                origins += 1;
+                index += 2;
+            }
+            b"BYRADIUS" if remaining >= 2 => {
+                let radius = parse_float(&args[index + 1])?;
+                if radius < 0.0 {
+                    return Err(CommandError::Custom(String::from(
+                        "ERR radius cannot be negative",
+                    )));
+                }
+                conversion = parse_unit(&args[index + 2])?;
+                shape = Some(Shape::Radius(radius));
+                shapes += 1;
+                index += 2;
+            }
+            b"BYBOX" if remaining >= 3 => {
+                let width = parse_float(&args[index + 1])?;
+                let height = parse_float(&args[index + 2])?;
+                if width < 0.0 || height < 0.0 {
+                    return Err(CommandError::Custom(String::from(
+                        "ERR height or width cannot be negative",
+                    )));
+                }
+                conversion = parse_unit(&args[index + 3])?;
+                shape = Some(Shape::Box { width, height });
+                shapes += 1;
+                index += 3;
+            }
+            b"ASC" => sort = Some(Sort::Asc),
+            b"DESC" => sort = Some(Sort::Desc),
+            b"COUNT" if remaining >= 1 => {
+                let value = parse_int(&args[index + 1])?;
+                if value <= 0 {
+                    return Err(CommandError::Custom(String::from("ERR COUNT must be > 0")));
+                }
+                count = Some(value as usize);
+                index += 1;
+                if args
+                    .get(index + 1)
+                    .is_some_and(|arg| is_keyword(arg, "ANY"))
+                {
+                    any = true;
+                    index += 1;
+                }
+            }
+            b"WITHCOORD" if !is_store => with_coord = true,
+            b"WITHDIST" if !is_store => with_dist = true,
+            b"WITHHASH" if !is_store => with_hash = true,
+            b"STOREDIST" if is_store => store_dist = true,
+            _ => return Err(CommandError::Syntax),
+        }
+        index += 1;
+    }
+
+    let command = String::from_utf8_lossy(&args[0]).into_owned();
+    let (Some(origin), 1) = (origin, origins) else {
+        return Err(CommandError::Custom(format!(
+            "ERR exactly one of FROMMEMBER or FROMLONLAT can be specified for {}",
+            command
+        )));
+    };
+    let (Some(shape), 1) = (shape, shapes) else {
+        return Err(CommandError::Custom(format!(
+            "ERR exactly one of BYRADIUS and BYBOX can be specified for {}",
+            command
+        )));
+    };
+    if any && count.is_none() {
+        return Err(CommandError::Custom(String::from(
+            "ERR the ANY argument requires COUNT argument",
+        )));
+    }
+    Ok(SearchOptions {
+        origin,
+        shape,
+        conversion,
+        sort,
+        count,
+        any,
+        with_coord,
+        with_dist,
+        with_hash,
+        store_dist,
+    })
+}
+
+struct GeoPoint {
+    member: Bytes,
+    score: f64,
+    /// Distance from the search origin in meters.
+    distance: f64,
+}
+
+/// The members within `search`, scanning the boxes around its origin in the
+/// same order as redis and stopping after `limit` members unless it is zero.
+fn search_points(zset: &ZSet, search: &Search, limit: usize) -> Vec<GeoPoint> {
+    let boxes = search.boxes();
+    let mut points = Vec::new();
+    let mut last_processed = 0;
+    for (i, hash) in boxes.iter().enumerate() {
+        let Some(hash) = hash else {
+            continue;
+        };
+        // With huge radiuses neighbors can be the same box as the previous one.
+        if last_processed != 0 && boxes[last_processed] == Some(*hash) {
+            continue;
+        }
+        if limit != 0 && points.len() >= limit {
+            break;
+        }
+        let (min, max) = hash.score_range();
+        let (start, end) = zset.score_range_ranks(&ScoreRange {
+            min,
+            max,
+            min_exclusive: false,
+            max_exclusive: true,
+        });
+        for (member, score) in zset.range(start, end, false) {
+            let (longitude, latitude) = geohash::decode_score(score);
+            let Some(distance) = search.distance_if_within(longitude, latitude) else {
+                continue;
+            };
+            points.push(GeoPoint {
+                member: Bytes::copy_from_slice(member),
+                score,
+                distance,
+            });
+            if limit != 0 && points.len() >= limit {
+                break;
+            }
+        }
+        last_processed = i;
+    }
+    points
+}
+
+/// Run the search described by `options` against the sorted set at `key`,
+/// returning None if there is no such key.
+fn geosearch(
+    store: &Store,
+    key: &[u8],
+    options: &SearchOptions,
+) -> Result<Option<Vec<GeoPoint>>, CommandError> {
+    let Some(zset) = get_zset(store, key)? else {
+        return Ok(None);
+    };
+    let (longitude, latitude) = match &options.origin {
+        Origin::Coordinates(longitude, latitude) => (*longitude, *latitude),
+        Origin::Member(member) => {
+            let score = zset.score(member).ok_or(CommandError::Custom(String::from(
+                "ERR could not decode requested zset member",
+            )))?;
+            geohash::decode_score(score)
+        }
+    };
+    let shape = match options.shape {
+        Shape::Radius(radius) => Shape::Radius(radius * options.conversion),
+        Shape::Box { width, height } => Shape::Box {
+            width: width * options.conversion,
+            height: height * options.conversion,
+        },
+    };
+    let search = Search {
+        longitude,
+        latitude,
+        shape,
+    };
+    let limit = if options.any {
+        options.count.unwrap_or(0)
+    } else {
+        0
+    };
+    let mut points = search_points(zset, &search, limit);
+
+    // COUNT without ANY returns the closest members.
+    let sort = match options.sort {
+        None if options.count.is_some() && !options.any => Some(Sort::Asc),
+        sort => sort,
+    };
+    match sort {
+        Some(Sort::Asc) => points.sort_by(|a, b| a.distance.total_cmp(&b.distance)),
+        Some(Sort::Desc) => points.sort_by(|a, b| b.distance.total_cmp(&a.distance)),
+        None => {}
+    }
+    if let Some(count) = options.count {
+        points.truncate(count);
+    }
+    Ok(Some(points))
+}
+
+/// GEOSEARCH key FROMMEMBER member|FROMLONLAT longitude latitude BYRADIUS radius unit|BYBOX width height unit [ASC|DESC] [COUNT count [ANY]] [WITHCOORD] [WITHDIST] [WITHHASH]
+pub fn handle_geosearch(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, -7)?;
+    let options = parse_search_options(args, 2, false)?;
+    let points = geosearch(store, &args[1], &options)?.unwrap_or_default();
+    let with_any = options.with_coord || options.with_dist || options.with_hash;
+    Ok(RESPDataType::Array(
+        points
+            .into_iter()
+            .map(|point| {
+                let member = RESPDataType::BulkString(point.member);
+                if !with_any {
+                    return member;
+                }
+                let mut reply = vec![member];
+                if options.with_dist {
+                    reply.push(RESPDataType::BulkString(format_distance(
+                        point.distance / options.conversion,
+                    )));
+                }
+                if options.with_hash {
+                    reply.push(RESPDataType::Integer(point.score as i64));
+                }
+                if options.with_coord {
+                    reply.push(coordinates_reply(geohash::decode_score(point.score)));
+                }
+                RESPDataType::Array(reply)
+            })
+            .collect(),
+    ))
+}
+
+/// GEOSEARCHSTORE destination source FROMMEMBER member|FROMLONLAT longitude latitude BYRADIUS radius unit|BYBOX width height unit [ASC|DESC] [COUNT count [ANY]] [STOREDIST]
+pub fn handle_geosearchstore(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, -8)?;
+    let options = parse_search_options(args, 3, true)?;
+    let points = geosearch(store, &args[2], &options)?.unwrap_or_default();
+    let mut zset = ZSet::new();
+    for point in points {
+        let score = if options.store_dist {
+            point.distance / options.conversion
+        } else {
+            point.score
+        };
+        zset.insert(point.member, score);
+    }
+    store_zset(store, &args[1], zset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::test_helpers::{args, bulk, bulks};
+
+    fn sicily() -> Store {
+        let mut store = Store::init();
+        let reply = handle_geoadd(
+            &args(&[
+                "GEOADD",
+                "Sicily",
+                "13.361389",
+                "38.115556",
+                "Palermo",
+                "15.087269",
+                "37.502669",
+                "Catania",
+            ]),
+            &mut store,
+        );
+        assert_eq!(reply, Ok(RESPDataType::Integer(2)));
+        store
+    }
+
+    #[test]
+    fn test_geoadd_and_lookups() {
+        let mut store = sicily();
+        assert!(handle_geoadd(&args(&["GEOADD", "Sicily", "1", "86", "x"]), &mut store).is_err());
+        assert!(handle_geoadd(&args(&["GEOADD", "Sicily", "1", "2"]), &mut store).is_err());
+        assert_eq!(
+            get_zset(&store, b"Sicily")
+                .unwrap()
+                .unwrap()
+                .score(b"Palermo"),
+            Some(3479099956230698.0)
+        );
+        assert_eq!(
+            handle_geodist(
+                &args(&["GEODIST", "Sicily", "Palermo", "Catania"]),
+                &mut store
+            ),
+            Ok(bulk("166274.1516"))
+        );
+        assert_eq!(
+            handle_geodist(
+                &args(&["GEODIST", "Sicily", "Palermo", "Catania", "km"]),
+                &mut store
+            ),
+            Ok(bulk("166.2742"))
+        );
+        assert_eq!(
+            handle_geodist(
+                &args(&["GEODIST", "Sicily", "Palermo", "Nowhere"]),
+                &mut store
+            ),
+            Ok(RESPDataType::NullBulkString)
+        );
+        assert_eq!(
+            handle_geohash(
+                &args(&["GEOHASH", "Sicily", "Palermo", "Nowhere"]),
+                &mut store
+            ),
+            Ok(RESPDataType::Array(vec![
+                bulk("sqc8b49rny0"),
+                RESPDataType::NullBulkString
+            ]))
+        );
+        assert_eq!(
+            handle_geopos(
+                &args(&["GEOPOS", "Sicily", "Palermo", "Nowhere"]),
+                &mut store
+            ),
+            Ok(RESPDataType::Array(vec![
+                bulks(&["13.36138933897018433", "38.11555639549629859"]),
+                RESPDataType::NullArray
+            ]))
+        );
+    }
+
+    #[test]
+    fn test_geosearch() {
+        let mut store = sicily();
+        assert_eq!(
+            handle_geosearch(
+                &args(&[
+                    "GEOSEARCH",
+                    "Sicily",
+                    "FROMLONLAT",
+                    "15",
+                    "37",
+                    "BYRADIUS",
+                    "200",
+                    "km",
+                    "ASC"
+                ]),
+                &mut store
+            ),
+            Ok(bulks(&["Catania", "Palermo"]))
+        );
+        assert_eq!(
+            handle_geosearch(
+                &args(&[
+                    "GEOSEARCH",
+                    "Sicily",
+                    "FROMLONLAT",
+                    "15",
+                    "37",
+                    "BYBOX",
+                    "400",
+                    "400",
+                    "km",
+                    "DESC",
+                    "WITHDIST",
+                ]),
+                &mut store
+            ),
+            Ok(RESPDataType::Array(vec![
+                bulks(&["Palermo", "190.4424"]),
+                bulks(&["Catania", "56.4413"]),
+            ]))
+        );
+        assert_eq!(
+            handle_geosearch(
+                &args(&[
+                    "GEOSEARCH",
+                    "Sicily",
+                    "FROMMEMBER",
+                    "Palermo",
+                    "BYRADIUS",
+                    "10",
+                    "km",
+                    "COUNT",
+                    "1",
+                    "WITHHASH",
+                ]),
+                &mut store
+            ),
+            Ok(RESPDataType::Array(vec![RESPDataType::Array(vec![
+                bulk("Palermo"),
+                RESPDataType::Integer(3479099956230698)
+            ])]))
+        );
+        assert!(handle_geosearch(
+            &args(&[
+                "GEOSEARCH",
+                "Sicily",
+                "FROMMEMBER",
+                "Nowhere",
+                "BYRADIUS",
+                "10",
+                "km"
+            ]),
+            &mut store
+        )
+        .is_err());
+        assert!(handle_geosearch(
+            &args(&["GEOSEARCH", "Sicily", "BYRADIUS", "10", "km", "ANY"]),
+            &mut store
+        )
+        .is_err());
+        assert_eq!(
+            handle_geosearch(
+                &args(&[
+                    "GEOSEARCH",
+                    "none",
+                    "FROMLONLAT",
+                    "15",
+                    "37",
+                    "BYRADIUS",
+                    "1",
+                    "m"
+                ]),
+                &mut store
+            ),
+            Ok(RESPDataType::Array(Vec::new()))
+        );
+    }
+
+    #[test]
+    fn test_geosearchstore() {
+        let mut store = sicily();
+        assert_eq!(
+            handle_geosearchstore(
+                &args(&[
+                    "GEOSEARCHSTORE",
+                    "near",
+                    "Sicily",
+                    "FROMLONLAT",
+                    "15",
+                    "37",
+                    "BYRADIUS",
+                    "100",
+                    "km",
+                    "STOREDIST",
+                ]),
+                &mut store
+            ),
+            Ok(RESPDataType::Integer(1))
+        );
+        let score = get_zset(&store, b"near")
+            .unwrap()
+            .unwrap()
+            .score(b"Catania");
+        assert_eq!(
+            score.map(|score| format!("{:.4}", score)).as_deref(),
+            Some("56.4413")
+        );
+        assert!(handle_geosearchstore(
+            &args(&[
+                "GEOSEARCHSTORE",
+                "near",
+                "Sicily",
+                "FROMLONLAT",
+                "15",
+                "37",
+                "BYRADIUS",
+                "1",
+                "km",
+                "WITHDIST",
+            ]),
+            &mut store
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,820 @@
+use bytes::Bytes;
+
+use super::{
+    bulk_array, check_arity, is_keyword, ok, parse_float, parse_int, parse_random_count,
+    parse_scan_options, random_repeated_indexes, scan_reply, wrong_number_of_arguments,
+    CommandError, CommandResult, ExpireCondition, ScanTarget,
+};
+use crate::resp::data::RESPDataType;
+use crate::store::{Store, Value};
+use crate::types::hash::Hash;
+use crate::util::{mstime, random_distinct_indexes, random_index};
+
+/// Get the hash at `key`, first lazily deleting any of its fields that expired.
+pub fn get_hash<'a>(store: &'a mut Store, key: &[u8]) -> Result<Option<&'a Hash>, CommandError> {
+    store.expire_hash_fields(key, mstime());
+    match store.get_from_key_val_store(key) {
+        None => Ok(None),
+        Some(Value::Hash(hash)) => Ok(Some(hash)),
+        Some(_) => Err(CommandError::WrongType),
+    }
+}
+
+pub fn get_hash_mut<'a>(
+    store: &'a mut Store,
+    key: &[u8],
+) -> Result<Option<&'a mut Hash>, CommandError> {
+    store.expire_hash_fields(key, mstime());
+    match store.get_mut_from_key_val_store(key) {
+        None => Ok(None),
+        Some(Value::Hash(hash)) => Ok(Some(hash)),
+        Some(_) => Err(CommandError::WrongType),
+    }
+}
+
+/// Get the hash at `key`, creating an empty one if the key does not exist.
+/// Callers must add at least one field so that no empty hash is left behind.
+fn get_or_create_hash<'a>(store: &'a mut Store, key: &Bytes) -> Result<&'a mut Hash, CommandError> {
+    if get_hash(store, key)?.is_none() {
+        store.insert_key_val(key.clone(), Value::Hash(Hash::new()));
+    }
+    Ok(get_hash_mut(store, key)?.unwrap())
+}
+
+fn bulk_or_nil(value: Option<&[u8]>) -> RESPDataType {
+    value
+        .map(|value| RESPDataType::BulkString(Bytes::copy_from_slice(value)))
+        .unwrap_or(RESPDataType::NullBulkString)
+}
+
+/// HSET key field value [field value ...]
+pub fn handle_hset(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, -4)?;
+    if !args.len().is_multiple_of(2) {
+        return Err(wrong_number_of_arguments(args));
+    }
+    let hash = get_or_create_hash(store, &args[1])?;
+    let created = args[2..]
+        .chunks(2)
+        .filter(|pair| hash.insert(pair[0].clone(), pair[1].clone()))
+        .count();
+    store.add_dirty((args.len() - 2) / 2);
+    Ok(RESPDataType::Integer(created as i64))
+}
+
+/// HMSET key field value [field value ...]
+pub fn handle_hmset(args: &[Bytes], store: &mut Store) -> CommandResult {
+    handle_hset(args, store)?;
+    Ok(ok())
+}
+
+/// HSETNX key field value
+pub fn handle_hsetnx(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, 4)?;
+    if get_hash(store, &args[1])?.is_some_and(|hash| hash.contains(&args[2])) {
+        return Ok(RESPDataType::Integer(0));
+    }
+    get_or_create_hash(store, &args[1])?.insert(args[2].clone(), args[3].clone());
+    store.add_dirty(1);
+    Ok(RESPDataType::Integer(1))
+}
+
+/// HGET key field
+pub fn handle_hget(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, 3)?;
+    let hash = get_hash(store, &args[1])?;
+    Ok(bulk_or_nil(hash.and_then(|hash| hash.get(&args[2]))))
+}
+
+/// HMGET key field [field ...]
+pub fn handle_hmget(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, -3)?;
+    let hash = get_hash(store, &args[1])?;
+    Ok(RESPDataType::Array(
+        args[2..]
+            .iter()
+            .map(|field| bulk_or_nil(hash.and_then(|hash| hash.get(field))))
+            .collect(),
+    ))
+}
+
+/// HDEL key field [field ...]
+pub fn handle_hdel(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, -3)?;
+    let key = &args[1];
+    if !get_hash(store, key)?.is_some_and(|hash| args[2..].iter().any(|field| hash.contains(field)))
+    {
+        return Ok(RESPDataType::Integer(0));
+    }
+    let hash = get_hash_mut(store, key)?.unwrap();
+    let deleted = args[2..]
+        .iter()
+        .filter(|field| hash.remove(field).is_some())
+        .count();
+    store.add_dirty(deleted);
+    store.remove_if_empty(key);
+    Ok(RESPDataType::Integer(deleted as i64))
+}
+
+/// HEXISTS key field
+pub fn handle_hexists(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, 3)?;
+    let exists = get_hash(store, &args[1])?.is_some_and(|hash| hash.contains(&args[2]));
+    Ok(RESPDataType::Integer(exists as i64))
+}
+
+/// HLEN key
+pub fn handle_hlen(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, 2)?;
+    let len = get_hash(store, &args[1])?.map_or(0, |hash| hash.len());
+    Ok(RESPDataType::Integer(len as i64))
+}
+
+/// HSTRLEN key field
+pub fn handle_hstrlen(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, 3)?;
+    let len = get_hash(store, &args[1])?
+        .and_then(|hash| hash.get(&args[2]))
+        .map_or(0, |value| value.len());
+    Ok(RESPDataType::Integer(len as i64))
+}
+
+/// HKEYS key
+pub fn handle_hkeys(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, 2)?;
+    let hash = get_hash(store, &args[1])?;
+    Ok(bulk_array(
+        hash.into_iter()
+            .flat_map(|hash| hash.iter().map(|(field, _)| field)),
+    ))
+}
+
+/// HVALS key
+pub fn handle_hvals(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, 2)?;
+    let hash = get_hash(store, &args[1])?;
+    Ok(bulk_array(
+        hash.into_iter()
+            .flat_map(|hash| hash.iter().map(|(_, value)| value)),
+    ))
+}
+
+/// HGETALL key
+pub fn handle_hgetall(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, 2)?;
+    let hash = get_hash(store, &args[1])?;
+    Ok(bulk_array(hash.into_iter().flat_map(|hash| {
+        hash.iter().flat_map(|(field, value)| [field, value])
+    })))
+}
+
+/// HINCRBY key field increment
+pub fn handle_hincrby(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, 4)?;
+    let increment = parse_int(&args[3])?;
+    let current = match get_hash(store, &args[1])?.and_then(|hash| hash.get(&args[2])) {
+        Some(value) => parse_int(value)
+            .map_err(|_| CommandError::Custom(String::from("ERR hash value is not an integer")))?,
+        None => 0,
+    };
+    let value = current
+        .checked_add(increment)
+        .ok_or(CommandError::Custom(String::from(
+            "ERR increment or decrement would overflow",
+        )))?;
+    get_or_create_hash(store, &args[1])?
+        .insert_keep_ttl(args[2].clone(), Bytes::from(value.to_string()));
+    store.add_dirty(1);
+    Ok(RESPDataType::Integer(value))
+}
+
+/// HINCRBYFLOAT key field increment
+pub fn handle_hincrbyfloat(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, 4)?;
+    let increment = parse_float(&args[3])?;
+    let current = match get_hash(store, &args[1])?.and_then(|hash| hash.get(&args[2])) {
+        Some(value) => parse_float(value)
+            .map_err(|_| CommandError::Custom(String::from("ERR hash value is not a float")))?,
+        None => 0.0,
+    };
+    let value = current + increment;
+    if !value.is_finite() {
+        return Err(CommandError::Custom(String::from(
+            "ERR increment would produce NaN or Infinity",
+        )));
+    }
+    let value = Bytes::from(value.to_string());
+    get_or_create_hash(store, &args[1])?.insert_keep_ttl(args[2].clone(), value.clone());
+    store.add_dirty(1);
+    Ok(RESPDataType::BulkString(value))
+}
+
+/// HSCAN key cursor [MATCH pattern] [COUNT count] [NOVALUES]
+pub fn handle_hscan(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, -3)?;
+    let options = parse_scan_options(args, 2, ScanTarget::Hash)?;
+    let Some(hash) = get_hash(store, &args[1])? else {
+        return Ok(scan_reply(0, vec![]));
+    };
+    let mut elements = Vec::new();
+    let cursor = hash.scan(options.cursor, options.count, |field, value| {
+        if options.matches(field) {
+            elements.push(Bytes::copy_from_slice(field));
+            if !options.no_values {
+                elements.push(Bytes::copy_from_slice(value));
+            }
+        }
+    });
+    Ok(scan_reply(cursor, elements))
+}
+
+/// HRANDFIELD key [count [WITHVALUES]]
+pub fn handle_hrandfield(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, -2)?;
+    if args.len() > 4 || (args.len() == 4 && !is_keyword(&args[3], "WITHVALUES")) {
+        return Err(CommandError::Syntax);
+    }
+    let count = args
+        .get(2)
+        .map(|count| parse_random_count(count))
+        .transpose()?;
+    let with_values = args.len() == 4;
+    let hash = get_hash(store, &args[1])?;
+
+    let Some(count) = count else {
+        let field = hash.map(|hash| {
+            let index = random_index(hash.len());
+            hash.iter().nth(index).unwrap().0
+        });
+        return Ok(bulk_or_nil(field));
+    };
+    let Some(hash) = hash else {
+        return Ok(RESPDataType::Array(vec![]));
+    };
+
+    let pairs: Vec<(&[u8], &[u8])> = hash.iter().collect();
+    let indexes: Box<dyn Iterator<Item = usize>> = if count >= 0 {
+        Box::new(random_distinct_indexes(pairs.len(), count as usize).into_iter())
+    } else {
+        Box::new(random_repeated_indexes(pairs.len(), count))
+    };
+    Ok(bulk_array(indexes.flat_map(|index| {
+        let (field, value) = pairs[index];
+        if with_values {
+            vec![field, value]
+        } else {
+            vec![field]
+        }
+    })))
+}
+
+/// Largest expiration time a hash field can have, in unix milliseconds.
+const HASH_FIELD_EXPIRE_TIME_MAX: i64 = (1 << 48) - 1;
+
+/// Parse the trailing `FIELDS numfields field [field ...]` block starting at `index`.
+fn parse_fields(args: &[Bytes], index: usize) -> Result<&[Bytes], CommandError> {
+    if !args.get(index).is_some_and(|arg| is_keyword(arg, "FIELDS")) {
+        return Err(CommandError::Custom(String::from(
+            "ERR Mandatory argument FIELDS is missing or not at the right position",
+        )));
+    }
+    let numfields = args
+        .get(index + 1)
+        .ok_or_else(|| wrong_number_of_arguments(args))
+        .and_then(|numfields| parse_int(numfields))?;
+    if numfields <= 0 {
+        return Err(CommandError::Custom(String::from(
+            "ERR Parameter `numFields` should be greater than 0",
+        )));
+    }
+    let fields = &args[index + 2..];
+    if fields.len() as i64 != numfields {
+        return Err(CommandError::Custom(String::from(
+            "ERR The `numfields` parameter must match the number of arguments",
+        )));
+    }
+    Ok(fields)
+}
+
+fn integer_array(values: impl IntoIterator<Item = i64>) -> RESPDataType {
+    RESPDataType::Array(values.into_iter().map(RESPDataType::Integer).collect())
+}
+
+/// Shared implementation of HEXPIRE, HPEXPIRE, HEXPIREAT and HPEXPIREAT. The time
+/// argument is multiplied by `unit_ms` and is relative to now unless `absolute`.
+fn expire_fields(args: &[Bytes], store: &mut Store, unit_ms: i64, absolute: bool) -> CommandResult {
+    check_arity(args, -6)?;
+    let time = parse_int(&args[2])?;
+    let (condition, fields_index) = match args[3].to_ascii_uppercase().as_slice() {
+        b"NX" => (ExpireCondition::Nx, 4),
+        b"XX" => (ExpireCondition::Xx, 4),
+        b"GT" => (ExpireCondition::Gt, 4),
+        b"LT" => (ExpireCondition::Lt, 4),
+        _ => (ExpireCondition::Always, 3),
+    };
+    let fields = parse_fields(args, fields_index)?;
+
+    let now = mstime();
+    let when = time
+        .checked_mul(unit_ms)
+        .and_then(|when| {
+            if absolute {
+                Some(when)
+            } else {
+                when.checked_add(now)
+            }
+        })
+        .filter(|when| time >= 0 && *when <= HASH_FIELD_EXPIRE_TIME_MAX)
+        .ok_or(CommandError::Custom(format!(
+            "ERR invalid expire time, must be >= 0 and <= {}",
+            HASH_FIELD_EXPIRE_TIME_MAX
+        )))?;
+
+    let key = &args[1];
+    let Some(hash) = get_hash(store, key)? else {
+        return Ok(integer_array(fields.iter().map(|_| -2)));
+    };
+    let changes = |field: &Bytes| {
+        hash.contains(field) && condition.allows(hash.field_expire_time(field), when)
+    };
+    if !fields.iter().any(changes) {
+        return Ok(integer_array(fields.iter().map(|field| {
+            if hash.contains(field) {
+                0
+            } else {
+                -2
+            }
+        })));
+    }
+    let hash = get_hash_mut(store, key)?.unwrap();
+    let results: Vec<i64> = fields
+        .iter()
+        .map(|field| {
+            if !hash.contains(field) {
+                -2
+            } else if !condition.allows(hash.field_expire_time(field), when) {
+                0
+            } else if when <= now {
+                hash.remove(field);
+                2
+            } else {
+                hash.set_field_expire_time(field, when);
+                1
+            }
+        })
+        .collect();
+    let next = hash.next_field_expire_time();
+    store.add_dirty(results.iter().filter(|result| **result > 0).count());
+    store.remove_if_empty(key);
+    if let Some(next) = next {
+        store.schedule_hash_field_expiry(key, next);
+    }
+    Ok(integer_array(results))
+}
+
+/// HEXPIRE key seconds [NX|XX|GT|LT] FIELDS numfields field [field ...]
+pub fn handle_hexpire(args: &[Bytes], store: &mut Store) -> CommandResult {
+    expire_fields(args, store, 1000, false)
+}
+
+/// HPEXPIRE key milliseconds [NX|XX|GT|LT] FIELDS numfields field [field ...]
+pub fn handle_hpexpire(args: &[Bytes], store: &mut Store) -> CommandResult {
+    expire_fields(args, store, 1, false)
+}
+
+/// HEXPIREAT key unix-time-seconds [NX|XX|GT|LT] FIELDS numfields field [field ...]
+pub fn handle_hexpireat(args: &[Bytes], store: &mut Store) -> CommandResult {
+    expire_fields(args, store, 1000, true)
+}
+
+/// HPEXPIREAT key unix-time-milliseconds [NX|XX|GT|LT] FIELDS numfields field [field ...]
+pub fn handle_hpexpireat(args: &[Bytes], store: &mut Store) -> CommandResult {
+    expire_fields(args, store, 1, true)
+}
+
+/// How HTTL and friends report a field's expiration time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TtlReport {
+    Seconds,
+    Milliseconds,
+    UnixSeconds,
+    UnixMilliseconds,
+}
+
+/// Shared implementation of HTTL, HPTTL, HEXPIRETIME and HPEXPIRETIME: -2 for a
+/// missing field, -1 for a field without TTL, otherwise the requested time.
+fn field_ttls(args: &[Bytes], store: &mut Store, report: TtlReport) -> CommandResult {
+    check_arity(args, -5)?;
+    let fields = parse_fields(args, 2)?;
+    let now = mstime();
+    let hash = get_hash(store, &args[1])?;
+    Ok(integer_array(fields.iter().map(
+        |field| match hash.filter(|hash| hash.contains(field)) {
+            None => -2,
+            Some(hash) => match hash.field_expire_time(field) {
+                None => -1,
+                Some(when) => match report {
+                    TtlReport::Seconds => (when - now + 999) / 1000,
+                    TtlReport::Milliseconds => when - now,
+                    TtlReport::UnixSeconds => when / 1000,
+                    TtlReport::UnixMilliseconds => when,
+                },
+            },
+        },
+    )))
+}
+
+/// HTTL key FIELDS numfields field [field ...]
+pub fn handle_httl(args: &[Bytes], store: &mut Store) -> CommandResult {
+    field_ttls(args, store, TtlReport::Seconds)
+}
+
+/// HPTTL key FIELDS numfields field [field ...]
+pub fn handle_hpttl(args: &[Bytes], store: &mut Store) -> CommandResult {
+    field_ttls(args, store, TtlReport::Milliseconds)
+}
+
+/// HEXPIRETIME key FIELDS numfields field [field ...]
+pub fn handle_hexpiretime(args: &[Bytes], store: &mut Store) -> CommandResult {
+    field_ttls(args, store, TtlReport::UnixSeconds)
+}
+
+/// HPEXPIRETIME key FIELDS numfields field [field ...]
+pub fn handle_hpexpiretime(args: &[Bytes], store: &mut Store) -> CommandResult {
+    field_ttls(args, store, TtlReport::UnixMilliseconds)
+}
+
+/// HPERSIST key FIELDS numfields field [field ...]
+pub fn handle_hpersist(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, -5)?;
+    let fields = parse_fields(args, 2)?;
+    let Some(hash) = get_hash(store, &args[1])? else {
+        return Ok(integer_array(fields.iter().map(|_| -2)));
+    };
+    if !fields
+        .iter()
+        .any(|field| hash.contains(field) && hash.field_expire_time(field).is_some())
+    {
+        return Ok(integer_array(fields.iter().map(|field| {
+            if hash.contains(field) {
+                -1
+            } else {
+                -2
+            }
+        })));
+    }
+    let hash = get_hash_mut(store, &args[1])?.unwrap();
+    let results: Vec<i64> = fields
+        .iter()
+        .map(|field| {
+            if !hash.contains(field) {
+                -2
+            } else if hash.clear_field_expire_time(field) {
+                1
+            } else {
+                -1
+            }
+        })
+        .collect();
+    store.add_dirty(results.iter().filter(|result| **result == 1).count());
+    Ok(integer_array(results))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::test_helpers::{args, bulk};
+    use crate::commands::RANDOM_REPLY_MAX;
+
+    #[test]
+    fn test_hset_hget_hdel() {
+        let mut store = Store::init();
+        assert_eq!(
+            handle_hset(&args(&["HSET", "h", "a", "1", "b", "2"]), &mut store),
+            Ok(RESPDataType::Integer(2))
+        );
+        assert_eq!(
+            handle_hset(&args(&["HSET", "h", "a", "3"]), &mut store),
+            Ok(RESPDataType::Integer(0))
+        );
+        assert!(handle_hset(&args(&["HSET", "h", "a"]), &mut store).is_err());
+        assert_eq!(
+            handle_hget(&args(&["HGET", "h", "a"]), &mut store),
+            Ok(bulk("3"))
+        );
+        assert_eq!(
+            handle_hmget(&args(&["HMGET", "h", "b", "zz"]), &mut store),
+            Ok(RESPDataType::Array(vec![
+                bulk("2"),
+                RESPDataType::NullBulkString
+            ]))
+        );
+        assert_eq!(
+            handle_hdel(&args(&["HDEL", "h", "a", "b", "c"]), &mut store),
+            Ok(RESPDataType::Integer(2))
+        );
+        assert!(store.get_from_key_val_store(b"h").is_none());
+    }
+
+    #[test]
+    fn test_hsetnx_and_strlen() {
+        let mut store = Store::init();
+        assert_eq!(
+            handle_hsetnx(&args(&["HSETNX", "h", "f", "hello"]), &mut store),
+            Ok(RESPDataType::Integer(1))
+        );
+        assert_eq!(
+            handle_hsetnx(&args(&["HSETNX", "h", "f", "bye"]), &mut store),
+            Ok(RESPDataType::Integer(0))
+        );
+        assert_eq!(
+            handle_hstrlen(&args(&["HSTRLEN", "h", "f"]), &mut store),
+            Ok(RESPDataType::Integer(5))
+        );
+        assert_eq!(
+            handle_hgetall(&args(&["HGETALL", "h"]), &mut store),
+            Ok(RESPDataType::Array(vec![bulk("f"), bulk("hello")]))
+        );
+    }
+
+    #[test]
+    fn test_hincrby() {
+        let mut store = Store::init();
+        assert_eq!(
+            handle_hincrby(&args(&["HINCRBY", "h", "n", "5"]), &mut store),
+            Ok(RESPDataType::Integer(5))
+        );
+        assert_eq!(
+            handle_hincrby(&args(&["HINCRBY", "h", "n", "-7"]), &mut store),
+            Ok(RESPDataType::Integer(-2))
+        );
+        handle_hset(&args(&["HSET", "h", "s", "abc"]), &mut store).unwrap();
+        assert!(handle_hincrby(&args(&["HINCRBY", "h", "s", "1"]), &mut store).is_err());
+        handle_hset(
+            &args(&["HSET", "h", "big", "9223372036854775807"]),
+            &mut store,
+        )
+        .unwrap();
+        assert!(handle_hincrby(&args(&["HINCRBY", "h", "big", "1"]), &mut store).is_err());
+    }
+
+    #[test]
+    fn test_hincrbyfloat() {
+        let mut store = Store::init();
+        assert_eq!(
+            handle_hincrbyfloat(&args(&["HINCRBYFLOAT", "h", "f", "10.5"]), &mut store),
+            Ok(bulk("10.5"))
+        );
+        assert_eq!(
+            handle_hincrbyfloat(&args(&["HINCRBYFLOAT", "h", "f", "-0.5"]), &mut store),
+            Ok(bulk("10"))
+        );
+        assert!(
+            handle_hincrbyfloat(&args(&["HINCRBYFLOAT", "x", "f", "inf"]), &mut store).is_err()
+        );
+        assert!(store.get_from_key_val_store(b"x").is_none());
+    }
+
+    #[test]
+    fn test_hrandfield() {
+        let mut store = Store::init();
+        handle_hset(
+            &args(&["HSET", "h", "a", "1", "b", "2", "c", "3"]),
+            &mut store,
+        )
+        .unwrap();
+        match handle_hrandfield(&args(&["HRANDFIELD", "h", "5"]), &mut store) {
+            Ok(RESPDataType::Array(fields)) => assert_eq!(fields.len(), 3),
+            other => panic!("unexpected reply {:?}", other),
+        }
+        match handle_hrandfield(&args(&["HRANDFIELD", "h", "-5", "WITHVALUES"]), &mut store) {
+            Ok(RESPDataType::Array(fields)) => assert_eq!(fields.len(), 10),
+            other => panic!("unexpected reply {:?}", other),
+        }
+        assert_eq!(
+            handle_hrandfield(&args(&["HRANDFIELD", "missing"]), &mut store),
+            Ok(RESPDataType::NullBulkString)
+        );
+        assert!(handle_hrandfield(&args(&["HRANDFIELD", "h", "1", "BAD"]), &mut store).is_err());
+
+        let out_of_range = Err(CommandError::Custom(String::from(
+            "ERR value is out of range",
+        )));
+        for count in [
+            "-9223372036854775808",
+            "-4611686018427387904",
+            "4611686018427387904",
+        ] {
+            assert_eq!(
+                handle_hrandfield(&args(&["HRANDFIELD", "h", count]), &mut store),
+                out_of_range
+            );
+        }
+        match handle_hrandfield(
+            &args(&["HRANDFIELD", "h", "-4611686018427387903"]),
+            &mut store,
+        ) {
+            Ok(RESPDataType::Array(fields)) => {
+                assert_eq!(fields.len() as u64, RANDOM_REPLY_MAX)
+            }
+            other => panic!("unexpected reply {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_hscan() {
+        let mut store = Store::init();
+        handle_hset(&args(&["HSET", "h", "a", "1", "b", "2"]), &mut store).unwrap();
+        // The compact encoding is returned in one go, whatever the count.
+        assert_eq!(
+            handle_hscan(&args(&["HSCAN", "h", "0", "COUNT", "1"]), &mut store),
+            Ok(RESPDataType::Array(vec![
+                bulk("0"),
+                RESPDataType::Array(vec![bulk("a"), bulk("1"), bulk("b"), bulk("2")]),
+            ]))
+        );
+        assert_eq!(
+            handle_hscan(
+                &args(&["HSCAN", "h", "0", "MATCH", "b", "NOVALUES"]),
+                &mut store
+            ),
+            Ok(RESPDataType::Array(vec![
+                bulk("0"),
+                RESPDataType::Array(vec![bulk("b")]),
+            ]))
+        );
+
+        for i in 0..200 {
+            let field = format!("f{}", i);
+            handle_hset(&args(&["HSET", "h", &field, "v"]), &mut store).unwrap();
+        }
+        let mut fields = 0;
+        let mut cursor = String::from("0");
+        loop {
+            let reply = handle_hscan(&args(&["HSCAN", "h", &cursor, "MATCH", "f*"]), &mut store);
+            let Ok(RESPDataType::Array(reply)) = reply else {
+                panic!("unexpected reply {:?}", reply);
+            };
+            let [RESPDataType::BulkString(next), RESPDataType::Array(elements)] = &reply[..] else {
+                panic!("unexpected reply {:?}", reply);
+            };
+            fields += elements.len() / 2;
+            cursor = String::from_utf8(next.to_vec()).unwrap();
+            if cursor == "0" {
+                break;
+            }
+        }
+        assert!(fields >= 200);
+        assert_eq!(
+            handle_hscan(&args(&["HSCAN", "missing", "0"]), &mut store),
+            Ok(RESPDataType::Array(vec![
+                bulk("0"),
+                RESPDataType::Array(vec![])
+            ]))
+        );
+    }
+
+    fn integers(values: &[i64]) -> RESPDataType {
+        integer_array(values.iter().copied())
+    }
+
+    #[test]
+    fn test_hexpire_conditions_and_ttl() {
+        let mut store = Store::init();
+        handle_hset(&args(&["HSET", "h", "a", "1", "b", "2"]), &mut store).unwrap();
+        assert_eq!(
+            handle_hexpire(
+                &args(&["HEXPIRE", "h", "100", "FIELDS", "3", "a", "b", "c"]),
+                &mut store
+            ),
+            Ok(integers(&[1, 1, -2]))
+        );
+        assert_eq!(
+            handle_hexpire(
+                &args(&["HEXPIRE", "h", "50", "GT", "FIELDS", "1", "a"]),
+                &mut store
+            ),
+            Ok(integers(&[0]))
+        );
+        assert_eq!(
+            handle_hexpire(
+                &args(&["HEXPIRE", "h", "50", "LT", "FIELDS", "1", "a"]),
+                &mut store
+            ),
+            Ok(integers(&[1]))
+        );
+        assert_eq!(
+            handle_hexpire(
+                &args(&["HEXPIRE", "h", "500", "NX", "FIELDS", "1", "a"]),
+                &mut store
+            ),
+            Ok(integers(&[0]))
+        );
+        match handle_httl(&args(&["HTTL", "h", "FIELDS", "2", "a", "zz"]), &mut store) {
+            Ok(RESPDataType::Array(ttls)) => {
+                assert!(matches!(ttls[0], RESPDataType::Integer(ttl) if ttl > 45 && ttl <= 50));
+                assert_eq!(ttls[1], RESPDataType::Integer(-2));
+            }
+            other => panic!("unexpected reply {:?}", other),
+        }
+        assert_eq!(
+            handle_hpersist(
+                &args(&["HPERSIST", "h", "FIELDS", "2", "a", "a"]),
+                &mut store
+            ),
+            Ok(integers(&[1, -1]))
+        );
+        assert_eq!(
+            handle_hexpire(
+                &args(&["HEXPIRE", "h", "10", "XX", "FIELDS", "1", "a"]),
+                &mut store
+            ),
+            Ok(integers(&[0]))
+        );
+    }
+
+    #[test]
+    fn test_hexpire_in_the_past_deletes_fields() {
+        let mut store = Store::init();
+        handle_hset(&args(&["HSET", "h", "a", "1"]), &mut store).unwrap();
+        assert_eq!(
+            handle_hpexpireat(
+                &args(&["HPEXPIREAT", "h", "1", "FIELDS", "1", "a"]),
+                &mut store
+            ),
+            Ok(integers(&[2]))
+        );
+        assert!(store.get_from_key_val_store(b"h").is_none());
+        assert_eq!(
+            handle_hexpire(
+                &args(&["HEXPIRE", "h", "1", "FIELDS", "1", "a"]),
+                &mut store
+            ),
+            Ok(integers(&[-2]))
+        );
+    }
+
+    #[test]
+    fn test_expired_fields_are_hidden_and_collected() {
+        let mut store = Store::init();
+        handle_hset(&args(&["HSET", "h", "a", "1", "b", "2"]), &mut store).unwrap();
+        if let Some(Value::Hash(hash)) = store.get_mut_from_key_val_store(b"h") {
+            hash.set_field_expire_time(&Bytes::from("a"), mstime() - 1);
+        }
+        assert_eq!(
+            handle_hget(&args(&["HGET", "h", "a"]), &mut store),
+            Ok(RESPDataType::NullBulkString)
+        );
+        assert_eq!(
+            handle_hlen(&args(&["HLEN", "h"]), &mut store),
+            Ok(RESPDataType::Integer(1))
+        );
+
+        handle_hpexpire(
+            &args(&["HPEXPIRE", "h", "1", "FIELDS", "1", "b"]),
+            &mut store,
+        )
+        .unwrap();
+        assert_eq!(store.active_expire_hash_fields(mstime() + 10, 10), 1);
+        assert!(store.get_from_key_val_store(b"h").is_none());
+    }
+
+    #[test]
+    fn test_only_writes_and_expirations_count_as_changes() {
+        let mut store = Store::init();
+        handle_hset(&args(&["HSET", "h", "a", "1", "b", "2"]), &mut store).unwrap();
+        let dirty = store.persistence.dirty;
+        handle_hget(&args(&["HGET", "h", "a"]), &mut store).unwrap();
+        handle_hlen(&args(&["HLEN", "h"]), &mut store).unwrap();
+        handle_hgetall(&args(&["HGETALL", "h"]), &mut store).unwrap();
+        assert_eq!(store.persistence.dirty, dirty);
+
+        handle_hset(&args(&["HSET", "h", "c", "3"]), &mut store).unwrap();
+        assert_eq!(store.persistence.dirty, dirty + 1);
+        if let Some(Value::Hash(hash)) = store.get_mut_from_key_val_store(b"h") {
+            hash.set_field_expire_time(&Bytes::from("a"), mstime() - 1);
+        }
+        let dirty = store.persistence.dirty;
+        handle_hlen(&args(&["HLEN", "h"]), &mut store).unwrap();
+        assert_eq!(store.persistence.dirty, dirty + 1);
+    }
+
+    #[test]
+    fn test_hexpire_argument_errors() {
+        let mut store = Store::init();
+        assert!(
+            handle_hexpire(&args(&["HEXPIRE", "h", "1", "FIELD", "1", "a"]), &mut store).is_err()
+        );
+        assert!(handle_hexpire(
+            &args(&["HEXPIRE", "h", "1", "FIELDS", "2", "a"]),
+            &mut store
+        )
+        .is_err());
+        assert!(handle_hexpire(
+            &args(&["HEXPIRE", "h", "-1", "FIELDS", "1", "a"]),
+            &mut store
+        )
+        .is_err());
+        assert!(handle_httl(&args(&["HTTL", "h", "FIELDS", "0", "a"]), &mut store).is_err());
+    }
+}
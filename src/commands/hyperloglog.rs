@@ -0,0 +1,275 @@
+use bytes::Bytes;
+
+use super::{check_arity, ok, CommandError, CommandResult};
+use crate::resp::data::RESPDataType;
+use crate::store::{Store, Value};
+use crate::types::hyperloglog::{self, Encoding, HyperLogLog, InvalidHll, HLL_REGISTERS};
+
+impl From<InvalidHll> for CommandError {
+    fn from(_: InvalidHll) -> Self {
+        CommandError::Custom(String::from("INVALIDOBJ Corrupted HLL object detected"))
+    }
+}
+
+fn not_a_hyperloglog() -> CommandError {
+    CommandError::Custom(String::from(
+        "WRONGTYPE Key is not a valid HyperLogLog string value.",
+    ))
+}
+
+/// The HyperLogLog held by `key`, None if there is no such key.
+fn get_hll(store: &Store, key: &[u8]) -> Result<Option<HyperLogLog>, CommandError> {
+    match store.get_from_key_val_store(key) {
+        Some(Value::String(bytes)) => HyperLogLog::from_bytes(bytes)
+            .map(Some)
+            .ok_or_else(not_a_hyperloglog),
+        Some(_) => Err(CommandError::WrongType),
+        None => Ok(None),
+    }
+}
+
+/// Write `hll` back to `key`, keeping the TTL of an existing key.
+fn store_hll(store: &mut Store, key: &Bytes, hll: HyperLogLog) {
+    let bytes = Bytes::from(hll.into_bytes());
+    match store.get_mut_from_key_val_store(key) {
+        Some(Value::String(value)) => {
+            *value = bytes;
+            store.add_dirty(1);
+        }
+        _ => store.set_key_val(key.clone(), bytes),
+    }
+}
+
+/// PFADD key [element ...]
+pub fn handle_pfadd(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, -2)?;
+    let (mut hll, mut updated) = match get_hll(store, &args[1])? {
+        Some(hll) => (hll, false),
+        None => (HyperLogLog::new(), true),
+    };
+    for element in &args[2..] {
+        updated |= hll.add(element)?;
+    }
+    if updated {
+        hll.invalidate_cache();
+        store_hll(store, &args[1], hll);
+    }
+    Ok(RESPDataType::Integer(updated as i64))
+}
+
+/// PFCOUNT key [key ...]
+pub fn handle_pfcount(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, -2)?;
+    if args.len() > 2 {
+        // Count the union of all keys, without touching any of them.
+        let mut max = [0; HLL_REGISTERS];
+        for key in &args[1..] {
+            if let Some(hll) = get_hll(store, key)? {
+                hll.merge_into(&mut max)?;
+            }
+        }
+        return Ok(RESPDataType::Integer(hyperloglog::count_raw(&max) as i64));
+    }
+
+    let Some(mut hll) = get_hll(store, &args[1])? else {
+        return Ok(RESPDataType::Integer(0));
+    };
+    let count = match hll.cached_cardinality() {
+        Some(count) => count,
+        None => {
+            let count = hll.count()?;
+            hll.set_cached_cardinality(count);
+            store_hll(store, &args[1], hll);
+            count
+        }
+    };
+    Ok(RESPDataType::Integer(count as i64))
+}
+
+/// PFMERGE destkey [sourcekey ...]
+pub fn handle_pfmerge(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, -2)?;
+    let mut max = [0; HLL_REGISTERS];
+    let mut use_dense = false;
+    for key in &args[1..] {
+        if let Some(hll) = get_hll(store, key)? {
+            use_dense |= hll.encoding() == Encoding::Dense;
+            hll.merge_into(&mut max)?;
+        }
+    }
+
+    let mut hll = get_hll(store, &args[1])?.unwrap_or_default();
+    if use_dense {
+        hll.to_dense()?;
+    }
+    for (index, value) in max.iter().enumerate() {
+        if *value != 0 {
+            hll.set(index, *value)?;
+        }
+    }
+    hll.invalidate_cache();
+    store_hll(store, &args[1], hll);
+    Ok(ok())
+}
+
+/// PFDEBUG GETREG|DECODE|ENCODING|TODENSE key
+pub fn handle_pfdebug(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, -3)?;
+    let subcommand = String::from_utf8_lossy(&args[1]).into_owned();
+    let Some(mut hll) = get_hll(store, &args[2])? else {
+        return Err(CommandError::Custom(String::from(
+            "ERR The specified key does not exist",
+        )));
+    };
+    if args.len() != 3 {
+        return Err(CommandError::Custom(format!(
+            "ERR Wrong number of arguments for the '{}' subcommand",
+            subcommand
+        )));
+    }
+
+    match subcommand.to_ascii_uppercase().as_str() {
+        "GETREG" => {
+            let registers = hll.dense_registers()?;
+            store_hll(store, &args[2], hll);
+            Ok(RESPDataType::Array(
+                registers
+                    .into_iter()
+                    .map(|register| RESPDataType::Integer(register as i64))
+                    .collect(),
+            ))
+        }
+        "DECODE" => {
+            if hll.encoding() != Encoding::Sparse {
+                return Err(CommandError::Custom(String::from(
+                    "ERR HLL encoding is not sparse",
+                )));
+            }
+            Ok(RESPDataType::BulkString(Bytes::from(hll.decode())))
+        }
+        "ENCODING" => Ok(RESPDataType::SimpleString(Bytes::from(
+            match hll.encoding() {
+                Encoding::Dense => "dense",
+                Encoding::Sparse => "sparse",
+            },
+        ))),
+        "TODENSE" => {
+            let converted = hll.to_dense()?;
+            if converted {
+                store_hll(store, &args[2], hll);
+            }
+            Ok(RESPDataType::Integer(converted as i64))
+        }
+        _ => Err(CommandError::Custom(format!(
+            "ERR Unknown PFDEBUG subcommand '{}'",
+            subcommand
+        ))),
+    }
+}
+
+/// PFSELFTEST
+pub fn handle_pfselftest(args: &[Bytes], _store: &mut Store) -> CommandResult {
+    check_arity(args, 1)?;
+    hyperloglog::self_test(10_000_000).map_err(CommandError::Custom)?;
+    Ok(ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::test_helpers::args;
+
+    #[test]
+    fn test_pfadd_and_pfcount() {
+        let mut store = Store::init();
+        assert_eq!(
+            handle_pfadd(&args(&["PFADD", "hll"]), &mut store),
+            Ok(RESPDataType::Integer(1))
+        );
+        assert_eq!(
+            handle_pfadd(&args(&["PFADD", "hll", "a", "b", "c"]), &mut store),
+            Ok(RESPDataType::Integer(1))
+        );
+        assert_eq!(
+            handle_pfadd(&args(&["PFADD", "hll", "a", "b"]), &mut store),
+            Ok(RESPDataType::Integer(0))
+        );
+        assert_eq!(
+            handle_pfcount(&args(&["PFCOUNT", "hll"]), &mut store),
+            Ok(RESPDataType::Integer(3))
+        );
+        // The count is now cached in the header.
+        let cached = get_hll(&store, b"hll").unwrap().unwrap();
+        assert_eq!(cached.cached_cardinality(), Some(3));
+
+        handle_pfadd(&args(&["PFADD", "other", "c", "d"]), &mut store).unwrap();
+        assert_eq!(
+            handle_pfcount(&args(&["PFCOUNT", "hll", "other", "none"]), &mut store),
+            Ok(RESPDataType::Integer(4))
+        );
+        assert_eq!(
+            handle_pfcount(&args(&["PFCOUNT", "none"]), &mut store),
+            Ok(RESPDataType::Integer(0))
+        );
+
+        store.set_key_val(Bytes::from("string"), Bytes::from("value"));
+        assert_eq!(
+            handle_pfadd(&args(&["PFADD", "string", "a"]), &mut store),
+            Err(not_a_hyperloglog())
+        );
+    }
+
+    #[test]
+    fn test_pfmerge() {
+        let mut store = Store::init();
+        handle_pfadd(&args(&["PFADD", "a", "1", "2", "3"]), &mut store).unwrap();
+        handle_pfadd(&args(&["PFADD", "b", "3", "4"]), &mut store).unwrap();
+        handle_pfdebug(&args(&["PFDEBUG", "TODENSE", "b"]), &mut store).unwrap();
+        assert_eq!(
+            handle_pfmerge(&args(&["PFMERGE", "dest", "a", "b"]), &mut store),
+            Ok(ok())
+        );
+        assert_eq!(
+            handle_pfdebug(&args(&["PFDEBUG", "ENCODING", "dest"]), &mut store),
+            Ok(RESPDataType::SimpleString(Bytes::from("dense")))
+        );
+        assert_eq!(
+            handle_pfcount(&args(&["PFCOUNT", "dest"]), &mut store),
+            Ok(RESPDataType::Integer(4))
+        );
+    }
+
+    #[test]
+    fn test_pfdebug() {
+        let mut store = Store::init();
+        handle_pfadd(&args(&["PFADD", "hll", "a"]), &mut store).unwrap();
+        let Ok(RESPDataType::BulkString(decoded)) =
+            handle_pfdebug(&args(&["PFDEBUG", "DECODE", "hll"]), &mut store)
+        else {
+            panic!("expected the sparse opcodes");
+        };
+        assert_eq!(decoded.split(|byte| *byte == b' ').count(), 3);
+        assert_eq!(
+            handle_pfdebug(&args(&["PFDEBUG", "TODENSE", "hll"]), &mut store),
+            Ok(RESPDataType::Integer(1))
+        );
+        assert_eq!(
+            handle_pfdebug(&args(&["PFDEBUG", "TODENSE", "hll"]), &mut store),
+            Ok(RESPDataType::Integer(0))
+        );
+        let Ok(RESPDataType::Array(registers)) =
+            handle_pfdebug(&args(&["PFDEBUG", "GETREG", "hll"]), &mut store)
+        else {
+            panic!("expected the registers");
+        };
+        assert_eq!(registers.len(), HLL_REGISTERS);
+        assert!(handle_pfdebug(&args(&["PFDEBUG", "DECODE", "hll"]), &mut store).is_err());
+        assert!(handle_pfdebug(&args(&["PFDEBUG", "GETREG", "none"]), &mut store).is_err());
+        assert_eq!(
+            handle_pfdebug(&args(&["PFDEBUG", "NOPE", "hll"]), &mut store),
+            Err(CommandError::Custom(String::from(
+                "ERR Unknown PFDEBUG subcommand 'NOPE'"
+            )))
+        );
+    }
+}
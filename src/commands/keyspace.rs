@@ -0,0 +1,1479 @@
+use std::io;
+use std::time::Duration;
+
+use bytes::Bytes;
+
+use super::{
+    bulk_array, check_arity, check_subcommand_arity, help_reply, is_keyword, ok, parse_int,
+    parse_scan_options, scan_reply, unknown_subcommand, CommandError, CommandResult,
+    ExpireCondition, ScanTarget,
+};
+use crate::evict;
+use crate::lazyfree;
+use crate::rdb;
+use crate::resp::data::RESPDataType;
+use crate::store::{is_shared_integer, Db, Store, Value};
+use crate::util::{mstime, string_match};
+
+/// DEL key [key ...]
+pub fn handle_del(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, -2)?;
+    let deleted = args[1..]
+        .iter()
+        .filter(|key| store.delete_key(key, store.lazyfree.user_del))
+        .count();
+    Ok(RESPDataType::Integer(deleted as i64))
+}
+
+/// UNLINK key [key ...]
+pub fn handle_unlink(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, -2)?;
+    let deleted = args[1..]
+        .iter()
+        .filter(|key| store.delete_key(key, true))
+        .count();
+    Ok(RESPDataType::Integer(deleted as i64))
+}
+
+/// EXISTS key [key ...]
+pub fn handle_exists(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, -2)?;
+    let existing = args[1..]
+        .iter()
+        .filter(|key| store.contains_key(key))
+        .count();
+    Ok(RESPDataType::Integer(existing as i64))
+}
+
+/// TOUCH key [key ...]
+pub fn handle_touch(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, -2)?;
+    let touched = args[1..]
+        .iter()
+        .filter(|key| store.get_from_key_val_store(key).is_some())
+        .count();
+    Ok(RESPDataType::Integer(touched as i64))
+}
+
+/// TYPE key
+pub fn handle_type(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, 2)?;
+    let type_name = store
+        .peek(&args[1])
+        .map_or("none", |value| value.type_name());
+    Ok(RESPDataType::SimpleString(Bytes::from(type_name)))
+}
+
+/// The reference count redis reports for shared objects.
+const OBJ_SHARED_REFCOUNT: i64 = i32::MAX as i64;
+
+const OBJECT_HELP: &[&str] = &[
+    "OBJECT <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+    "ENCODING <key>",
+    "    Return the kind of internal representation used in order to store the value",
+    "    associated with a <key>.",
+    "FREQ <key>",
+    "    Return the access frequency index of the <key>. The returned integer is",
+    "    proportional to the logarithm of the recent access frequency of the key.",
+    "IDLETIME <key>",
+    "    Return the idle time of the <key>, that is the approximated number of",
+    "    seconds elapsed since the last access to the key.",
+    "REFCOUNT <key>",
+    "    Return the number of references of the value associated with the specified",
+    "    <key>.",
+    "HELP",
+    "    Print this help.",
+];
+
+/// OBJECT ENCODING|FREQ|IDLETIME|REFCOUNT|HELP ...
+pub fn handle_object(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, -2)?;
+    let subcommand = args[1].to_ascii_uppercase();
+    if subcommand == b"HELP" {
+        return Ok(help_reply(OBJECT_HELP));
+    }
+    if !matches!(
+        subcommand.as_slice(),
+        b"ENCODING" | b"FREQ" | b"IDLETIME" | b"REFCOUNT"
+    ) {
+        return Err(unknown_subcommand(args));
+    }
+    check_subcommand_arity(args, 3)?;
+    let key = &args[2];
+    let (Some(value), Some(access)) = (store.peek(key), store.get_access(key)) else {
+        return Ok(RESPDataType::NullBulkString);
+    };
+    let options = store.maxmemory;
+    let reply = match subcommand.as_slice() {
+        b"ENCODING" => RESPDataType::BulkString(Bytes::from(value.encoding())),
+        b"REFCOUNT" => {
+            let shared = matches!(value, Value::String(string) if is_shared_integer(string));
+            RESPDataType::Integer(if shared { OBJ_SHARED_REFCOUNT } else { 1 })
+        }
+        b"IDLETIME" => {
+            if options.policy.is_lfu() {
+                return Err(CommandError::Custom(String::from(
+                    "ERR An LFU maxmemory policy is selected, idle time not tracked. Please note that when switching between policies at runtime LRU and LFU data will take some time to adjust.",
+                )));
+            }
+            RESPDataType::Integer((evict::estimate_idle_time(access) / 1000) as i64)
+        }
+        _ => {
+            if !options.policy.is_lfu() {
+                return Err(CommandError::Custom(String::from(
+                    "ERR An LFU maxmemory policy is not selected, access frequency not tracked. Please note that when switching between policies at runtime LRU and LFU data will take some time to adjust.",
+                )));
+            }
+            let frequency = evict::lfu_decr_and_return(access, options.lfu_decay_time);
+            RESPDataType::Integer(frequency as i64)
+        }
+    };
+    Ok(reply)
+}
+
+/// RENAME key newkey
+pub fn handle_rename(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, 3)?;
+    if !store.contains_key(&args[1]) {
+        return Err(CommandError::NoSuchKey);
+    }
+    if args[1] != args[2] {
+        store.rename(&args[1], args[2].clone());
+    }
+    Ok(ok())
+}
+
+/// RENAMENX key newkey
+pub fn handle_renamenx(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, 3)?;
+    if !store.contains_key(&args[1]) {
+        return Err(CommandError::NoSuchKey);
+    }
+    if store.contains_key(&args[2]) {
+        return Ok(RESPDataType::Integer(0));
+    }
+    store.rename(&args[1], args[2].clone());
+    Ok(RESPDataType::Integer(1))
+}
+
+fn db_index_out_of_range() -> CommandError {
+    CommandError::Custom(String::from("ERR DB index is out of range"))
+}
+
+/// Parse the index of an existing database.
+fn parse_db_index(arg: &[u8], store: &Store) -> Result<usize, CommandError> {
+    let index = parse_int(arg)?;
+    if index < 0 || index as usize >= store.db_count() {
+        return Err(db_index_out_of_range());
+    }
+    Ok(index as usize)
+}
+
+/// Run `f` with the database at `index` selected, selecting back the current
+/// database afterwards.
+fn with_db<T>(store: &mut Store, index: usize, f: impl FnOnce(&mut Store) -> T) -> T {
+    let selected = store.selected_db();
+    store.select(index);
+    let result = f(store);
+    store.select(selected);
+    result
+}
+
+/// COPY source destination [DB destination-db] [REPLACE]
+pub fn handle_copy(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, -3)?;
+    let mut replace = false;
+    let mut db = store.selected_db();
+    let mut index = 3;
+    while let Some(arg) = args.get(index) {
+        if is_keyword(arg, "REPLACE") {
+            replace = true;
+        } else if is_keyword(arg, "DB") && index + 1 < args.len() {
+            db = parse_db_index(&args[index + 1], store)?;
+            index += 1;
+        } else {
+            return Err(CommandError::Syntax);
+        }
+        index += 1;
+    }
+
+    let (source, destination) = (&args[1], &args[2]);
+    if source == destination && db == store.selected_db() {
+        return Err(same_object());
+    }
+    let Some(value) = store.get_from_key_val_store(source).cloned() else {
+        return Ok(RESPDataType::Integer(0));
+    };
+    let expire = store.get_expire(source);
+    let copied = with_db(store, db, |store| {
+        if store.contains_key(destination) {
+            if !replace {
+                return false;
+            }
+            store.delete_key(destination, store.lazyfree.server_del);
+        }
+        store.insert_key_val(destination.clone(), value);
+        if let Some(when) = expire {
+            store.set_expire(destination.clone(), when);
+        }
+        true
+    });
+    Ok(RESPDataType::Integer(copied as i64))
+}
+
+fn same_object() -> CommandError {
+    CommandError::Custom(String::from(
+        "ERR source and destination objects are the same",
+    ))
+}
+
+/// MOVE key db
+pub fn handle_move(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, 3)?;
+    let db = parse_db_index(&args[2], store)?;
+    if db == store.selected_db() {
+        return Err(same_object());
+    }
+    let key = &args[1];
+    if !store.contains_key(key) || with_db(store, db, |store| store.contains_key(key)) {
+        return Ok(RESPDataType::Integer(0));
+    }
+    store.move_key(key, db);
+    Ok(RESPDataType::Integer(1))
+}
+
+/// DUMP key
+pub fn handle_dump(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, 2)?;
+    Ok(match store.get_from_key_val_store(&args[1]) {
+        Some(value) => RESPDataType::BulkString(Bytes::from(rdb::dump(value))),
+        None => RESPDataType::NullBulkString,
+    })
+}
+
+/// RESTORE key ttl serialized-value [REPLACE] [ABSTTL] [IDLETIME seconds] [FREQ frequency]
+pub fn handle_restore(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, -4)?;
+    let mut replace = false;
+    let mut absttl = false;
+    let mut idle_time = None;
+    let mut frequency = None;
+    let mut index = 4;
+    while let Some(arg) = args.get(index) {
+        let has_value = index + 1 < args.len();
+        if is_keyword(arg, "REPLACE") {
+            replace = true;
+        } else if is_keyword(arg, "ABSTTL") {
+            absttl = true;
+        } else if is_keyword(arg, "IDLETIME") && has_value && frequency.is_none() {
+            let seconds = parse_int(&args[index + 1])?;
+            if seconds < 0 {
+                return Err(CommandError::Custom(String::from(
+                    "ERR Invalid IDLETIME value, must be >= 0",
+                )));
+            }
+            idle_time = Some(seconds);
+            index += 1;
+        } else if is_keyword(arg, "FREQ") && has_value && idle_time.is_none() {
+            let count = parse_int(&args[index + 1])?;
+            if !(0..=255).contains(&count) {
+                return Err(CommandError::Custom(String::from(
+                    "ERR Invalid FREQ value, must be >= 0 and <= 255",
+                )));
+            }
+            frequency = Some(count);
+            index += 1;
+        } else {
+            return Err(CommandError::Syntax);
+        }
+        index += 1;
+    }
+    let key = &args[1];
+    let mut ttl = parse_int(&args[2])?;
+    if ttl < 0 {
+        return Err(CommandError::Custom(String::from(
+            "ERR Invalid TTL value, must be >= 0",
+        )));
+    }
+    if !replace && store.contains_key(key) {
+        return Err(CommandError::Custom(String::from(
+            "BUSYKEY Target key name already exists.",
+        )));
+    }
+    if !rdb::verify_dump_payload(&args[3]) {
+        return Err(CommandError::Custom(String::from(
+            "ERR DUMP payload version or checksum are wrong",
+        )));
+    }
+    let mut value = rdb::restore(&args[3])
+        .map_err(|_| CommandError::Custom(String::from("ERR Bad data format")))?;
+
+    if replace {
+        store.delete_key(key, store.lazyfree.server_del);
+    }
+    let now = mstime();
+    if ttl > 0 && !absttl {
+        ttl = ttl.saturating_add(now);
+    }
+    // A value that would expire right away is not created at all.
+    if let Value::Hash(hash) = &mut value {
+        hash.expire_fields(now);
+    }
+    if (ttl > 0 && ttl <= now) || value.is_empty_collection() {
+        return Ok(ok());
+    }
+    store.insert_key_val(key.clone(), value);
+    if ttl > 0 {
+        store.set_expire(key.clone(), ttl);
+    }
+    store.set_access(
+        key,
+        frequency.map(|count| count as u8),
+        idle_time.map(|seconds| seconds as u64),
+    );
+    Ok(ok())
+}
+
+/// How a MIGRATE attempt failed to talk to the target instance.
+enum MigrateFailure {
+    Write(io::Error),
+    Read(io::Error),
+}
+
+impl MigrateFailure {
+    /// Whether trying again on a fresh connection may help: not after a
+    /// timeout, and only while the target has not processed anything.
+    fn may_retry(&self) -> bool {
+        let (MigrateFailure::Write(e) | MigrateFailure::Read(e)) = self;
+        !matches!(
+            e.kind(),
+            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+        )
+    }
+}
+
+/// MIGRATE host port key|"" destination-db timeout [COPY] [REPLACE]
+/// [AUTH password] [AUTH2 username password] [KEYS key [key ...]]
+pub fn handle_migrate(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, -6)?;
+    let mut copy = false;
+    let mut replace = false;
+    let mut auth: &[Bytes] = &[];
+    let mut keys = &args[3..4];
+    let mut index = 6;
+    while let Some(arg) = args.get(index) {
+        let more_args = args.len() - 1 - index;
+        if is_keyword(arg, "COPY") {
+            copy = true;
+        } else if is_keyword(arg, "REPLACE") {
+            replace = true;
+        } else if is_keyword(arg, "AUTH") && more_args >= 1 {
+            auth = &args[index + 1..index + 2];
+            index += 1;
+        } else if is_keyword(arg, "AUTH2") && more_args >= 2 {
+            auth = &args[index + 1..index + 3];
+            index += 2;
+        } else if is_keyword(arg, "KEYS") {
+            if !args[3].is_empty() {
+                return Err(CommandError::Custom(String::from(
+                    "ERR When using MIGRATE KEYS option, the key argument must be set to the empty string",
+                )));
+            }
+            keys = &args[index + 1..];
+            break;
+        } else {
+            return Err(CommandError::Syntax);
+        }
+        index += 1;
+    }
+    let timeout = parse_int(&args[5])?;
+    let db = parse_int(&args[4])?;
+    let timeout = Duration::from_millis(if timeout <= 0 { 1000 } else { timeout as u64 });
+
+    // Missing keys are skipped, they may well have just expired.
+    let keys: Vec<&Bytes> = keys.iter().filter(|key| store.contains_key(key)).collect();
+    if keys.is_empty() {
+        return Ok(RESPDataType::SimpleString(Bytes::from("NOKEY")));
+    }
+
+    let address = format!(
+        "{}:{}",
+        String::from_utf8_lossy(&args[1]),
+        String::from_utf8_lossy(&args[2])
+    );
+    let mut may_retry = true;
+    loop {
+        match migrate_keys(store, &address, db, timeout, &keys, auth, replace, copy) {
+            Err(failure) if may_retry && failure.may_retry() => may_retry = false,
+            Err(failure) => {
+                let action = match failure {
+                    MigrateFailure::Write(_) => "writing",
+                    MigrateFailure::Read(_) => "reading",
+                };
+                return Err(CommandError::Custom(format!(
+                    "IOERR error or timeout {} to target instance",
+                    action
+                )));
+            }
+            Ok(reply) => return reply,
+        }
+    }
+}
+
+/// One attempt at sending `keys` with RESTORE over a cached connection,
+/// deleting the ones the target accepted unless `copy`. Fails without having
+/// replied if the connection broke before any key was restored.
+#[allow(clippy::too_many_arguments)]
+fn migrate_keys(
+    store: &mut Store,
+    address: &str,
+    db: i64,
+    timeout: Duration,
+    keys: &[&Bytes],
+    auth: &[Bytes],
+    replace: bool,
+    copy: bool,
+) -> Result<CommandResult, MigrateFailure> {
+    let Ok(mut socket) = store.migrate_sockets.take(address, timeout) else {
+        return Ok(Err(CommandError::Custom(String::from(
+            "IOERR error or timeout connecting to the client",
+        ))));
+    };
+    let command = |parts: Vec<Bytes>| {
+        RESPDataType::Array(parts.into_iter().map(RESPDataType::BulkString).collect())
+    };
+    let mut commands = Vec::new();
+    if !auth.is_empty() {
+        let mut parts = vec![Bytes::from("AUTH")];
+        parts.extend_from_slice(auth);
+        commands.push(command(parts));
+    }
+    let select = socket.last_db != Some(db);
+    if select {
+        commands.push(command(vec![
+            Bytes::from("SELECT"),
+            Bytes::from(db.to_string()),
+        ]));
+    }
+    let preamble = commands.len();
+
+    let now = mstime();
+    let mut migrating = Vec::new();
+    for key in keys {
+        let Some(value) = store.get_from_key_val_store(key) else {
+            continue;
+        };
+        let ttl = match store.get_expire(key) {
+            Some(when) if when < now => continue,
+            Some(when) => (when - now).max(1),
+            None => 0,
+        };
+        let mut parts = vec![
+            Bytes::from("RESTORE"),
+            (*key).clone(),
+            Bytes::from(ttl.to_string()),
+            Bytes::from(rdb::dump(value)),
+        ];
+        if replace {
+            parts.push(Bytes::from("REPLACE"));
+        }
+        commands.push(command(parts));
+        migrating.push(*key);
+    }
+    socket
+        .send(&commands, timeout)
+        .map_err(MigrateFailure::Write)?;
+
+    let mut target_error = None;
+    for _ in 0..preamble {
+        let reply = socket.read_reply(timeout).map_err(MigrateFailure::Read)?;
+        if let (RESPDataType::Error(message), None) = (reply, &target_error) {
+            target_error = Some(message);
+        }
+    }
+    let rejected_all = target_error.is_some();
+    let mut restored = Vec::new();
+    let mut read_error = None;
+    for key in &migrating {
+        match socket.read_reply(timeout) {
+            Ok(RESPDataType::Error(message)) => {
+                target_error.get_or_insert(message);
+            }
+            Ok(_) if !rejected_all => restored.push(*key),
+            Ok(_) => {}
+            Err(e) => {
+                read_error = Some(e);
+                break;
+            }
+        }
+    }
+    // Nothing happened on the target yet, the caller may try again.
+    if restored.is_empty() && target_error.is_none() {
+        if let Some(e) = read_error {
+            return Err(MigrateFailure::Read(e));
+        }
+    }
+    if !copy {
+        for key in &restored {
+            store.delete_key(key, store.lazyfree.server_del);
+        }
+    }
+
+    match target_error {
+        Some(message) => {
+            // The target may have failed to select the database.
+            socket.last_db = None;
+            if read_error.is_none() {
+                store.migrate_sockets.put(address.to_string(), socket);
+            }
+            Ok(Err(CommandError::Custom(format!(
+                "ERR Target instance replied with error: {}",
+                String::from_utf8_lossy(&message)
+            ))))
+        }
+        None if read_error.is_some() => Ok(Err(CommandError::Custom(String::from(
+            "IOERR error or timeout reading to target instance",
+        )))),
+        None => {
+            socket.last_db = Some(db);
+            store.migrate_sockets.put(address.to_string(), socket);
+            Ok(Ok(ok()))
+        }
+    }
+}
+
+/// SELECT index, switching the database of the calling client, kept in `db`.
+pub fn handle_select(args: &[Bytes], store: &mut Store, db: &mut usize) -> CommandResult {
+    check_arity(args, 2)?;
+    *db = parse_db_index(&args[1], store)?;
+    store.select(*db);
+    Ok(ok())
+}
+
+/// SWAPDB index1 index2
+pub fn handle_swapdb(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, 3)?;
+    let parse = |arg: &[u8], which: &str| {
+        let index = parse_int(arg)
+            .map_err(|_| CommandError::Custom(format!("ERR invalid {} DB index", which)))?;
+        if index < 0 || index as usize >= store.db_count() {
+            return Err(db_index_out_of_range());
+        }
+        Ok(index as usize)
+    };
+    let first = parse(&args[1], "first")?;
+    let second = parse(&args[2], "second")?;
+    store.swap_dbs(first, second);
+    Ok(ok())
+}
+
+/// Parse the `[ASYNC|SYNC]` option of FLUSHDB and FLUSHALL, returning whether
+/// the flushed data should be freed in the background. Without an option this
+/// is up to lazyfree-lazy-user-flush.
+fn parse_flush_mode(args: &[Bytes], store: &Store) -> Result<bool, CommandError> {
+    match args {
+        [_] => Ok(store.lazyfree.user_flush),
+        [_, mode] if is_keyword(mode, "SYNC") => Ok(false),
+        [_, mode] if is_keyword(mode, "ASYNC") => Ok(true),
+        _ => Err(CommandError::Syntax),
+    }
+}
+
+/// Release flushed databases, on the lazyfree thread when `lazy` so that
+/// dropping millions of values does not stall the server.
+fn free_dbs(dbs: Vec<Db>, lazy: bool) {
+    let keys = dbs.iter().map(Db::len).sum();
+    if lazy && keys > 0 {
+        lazyfree::free_in_background(dbs, keys);
+    }
+}
+
+/// FLUSHDB [ASYNC|SYNC]
+pub fn handle_flushdb(args: &[Bytes], store: &mut Store) -> CommandResult {
+    let lazy = parse_flush_mode(args, store)?;
+    let db = store.flush_db(store.selected_db());
+    free_dbs(vec![db], lazy);
+    Ok(ok())
+}
+
+/// FLUSHALL [ASYNC|SYNC]
+pub fn handle_flushall(args: &[Bytes], store: &mut Store) -> CommandResult {
+    let lazy = parse_flush_mode(args, store)?;
+    let dbs = store.flush_all();
+    free_dbs(dbs, lazy);
+    Ok(ok())
+}
+
+/// RANDOMKEY
+pub fn handle_randomkey(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, 1)?;
+    Ok(match store.random_key() {
+        Some(key) => RESPDataType::BulkString(key),
+        None => RESPDataType::NullBulkString,
+    })
+}
+
+/// DBSIZE
+pub fn handle_dbsize(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, 1)?;
+    Ok(RESPDataType::Integer(store.dbsize() as i64))
+}
+
+/// KEYS pattern
+pub fn handle_keys(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, 2)?;
+    let all_keys = args[1] == "*";
+    let keys: Vec<&Bytes> = store
+        .keys()
+        .filter(|key| all_keys || string_match(&args[1], key, false))
+        .filter(|key| store.contains_key(key))
+        .collect();
+    Ok(bulk_array(keys))
+}
+
+/// SCAN cursor [MATCH pattern] [COUNT count] [TYPE type]
+pub fn handle_scan(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, -2)?;
+    let options = parse_scan_options(args, 1, ScanTarget::Keyspace)?;
+    if let Some(type_name) = &options.type_name {
+        let known = ["string", "list", "set", "zset", "hash", "stream"];
+        if !known.iter().any(|known| is_keyword(type_name, known)) {
+            return Err(CommandError::Custom(format!(
+                "ERR unknown type name '{}'",
+                String::from_utf8_lossy(type_name)
+            )));
+        }
+    }
+    let mut keys = Vec::new();
+    let cursor = store.scan(options.cursor, options.count, |key, value| {
+        let type_matches = options
+            .type_name
+            .as_ref()
+            .is_none_or(|type_name| is_keyword(type_name, value.type_name()));
+        if type_matches && options.matches(key) {
+            keys.push(key.clone());
+        }
+    });
+    keys.retain(|key| !store.expire_if_needed(key));
+    Ok(scan_reply(cursor, keys))
+}
+
+/// Parse the `[NX|XX|GT|LT]` options of EXPIRE and friends, every one of
+/// which must allow an update for it to happen.
+fn parse_expire_conditions(args: &[Bytes]) -> Result<Vec<ExpireCondition>, CommandError> {
+    let mut conditions = Vec::new();
+    for arg in args {
+        conditions.push(match arg.to_ascii_uppercase().as_slice() {
+            b"NX" => ExpireCondition::Nx,
+            b"XX" => ExpireCondition::Xx,
+            b"GT" => ExpireCondition::Gt,
+            b"LT" => ExpireCondition::Lt,
+            _ => {
+                return Err(CommandError::Custom(format!(
+                    "ERR Unsupported option {}",
+                    String::from_utf8_lossy(arg)
+                )))
+            }
+        });
+    }
+    let has = |condition| conditions.contains(&condition);
+    if has(ExpireCondition::Nx)
+        && (has(ExpireCondition::Xx) || has(ExpireCondition::Gt) || has(ExpireCondition::Lt))
+    {
+        return Err(CommandError::Custom(String::from(
+            "ERR NX and XX, GT or LT options at the same time are not compatible",
+        )));
+    }
+    if has(ExpireCondition::Gt) && has(ExpireCondition::Lt) {
+        return Err(CommandError::Custom(String::from(
+            "ERR GT and LT options at the same time are not compatible",
+        )));
+    }
+    Ok(conditions)
+}
+
+/// Shared implementation of EXPIRE, PEXPIRE, EXPIREAT and PEXPIREAT. The time
+/// argument is multiplied by `unit_ms` and is relative to now unless `absolute`.
+/// A time in the past deletes the key.
+fn expire_key(args: &[Bytes], store: &mut Store, unit_ms: i64, absolute: bool) -> CommandResult {
+    check_arity(args, -3)?;
+    let conditions = parse_expire_conditions(&args[3..])?;
+    let time = parse_int(&args[2])?;
+    let now = mstime();
+    let when = time
+        .checked_mul(unit_ms)
+        .and_then(|when| {
+            if absolute {
+                Some(when)
+            } else {
+                when.checked_add(now)
+            }
+        })
+        .ok_or(CommandError::Custom(format!(
+            "ERR invalid expire time in '{}' command",
+            String::from_utf8_lossy(&args[0]).to_lowercase()
+        )))?;
+
+    let key = &args[1];
+    if !store.contains_key(key) {
+        return Ok(RESPDataType::Integer(0));
+    }
+    let current = store.get_expire(key);
+    if !conditions
+        .iter()
+        .all(|condition| condition.allows(current, when))
+    {
+        return Ok(RESPDataType::Integer(0));
+    }
+    if when <= now {
+        store.delete_key(key, store.lazyfree.expire);
+    } else {
+        store.set_expire(key.clone(), when);
+    }
+    Ok(RESPDataType::Integer(1))
+}
+
+/// EXPIRE key seconds [NX|XX|GT|LT]
+pub fn handle_expire(args: &[Bytes], store: &mut Store) -> CommandResult {
+    expire_key(args, store, 1000, false)
+}
+
+/// PEXPIRE key milliseconds [NX|XX|GT|LT]
+pub fn handle_pexpire(args: &[Bytes], store: &mut Store) -> CommandResult {
+    expire_key(args, store, 1, false)
+}
+
+/// EXPIREAT key unix-time-seconds [NX|XX|GT|LT]
+pub fn handle_expireat(args: &[Bytes], store: &mut Store) -> CommandResult {
+    expire_key(args, store, 1000, true)
+}
+
+/// PEXPIREAT key unix-time-milliseconds [NX|XX|GT|LT]
+pub fn handle_pexpireat(args: &[Bytes], store: &mut Store) -> CommandResult {
+    expire_key(args, store, 1, true)
+}
+
+/// How TTL and friends report a key's expiration time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TtlReport {
+    Seconds,
+    Milliseconds,
+    UnixSeconds,
+    UnixMilliseconds,
+}
+
+/// Shared implementation of TTL, PTTL, EXPIRETIME and PEXPIRETIME: -2 for a
+/// missing key, -1 for a key without TTL, otherwise the requested time.
+/// Seconds are rounded to the nearest one, as redis does.
+fn key_ttl(args: &[Bytes], store: &mut Store, report: TtlReport) -> CommandResult {
+    check_arity(args, 2)?;
+    let key = &args[1];
+    if !store.contains_key(key) {
+        return Ok(RESPDataType::Integer(-2));
+    }
+    let Some(when) = store.get_expire(key) else {
+        return Ok(RESPDataType::Integer(-1));
+    };
+    let ttl = (when - mstime()).max(0);
+    Ok(RESPDataType::Integer(match report {
+        TtlReport::Seconds => (ttl + 500) / 1000,
+        TtlReport::Milliseconds => ttl,
+        TtlReport::UnixSeconds => (when + 500) / 1000,
+        TtlReport::UnixMilliseconds => when,
+    }))
+}
+
+/// TTL key
+pub fn handle_ttl(args: &[Bytes], store: &mut Store) -> CommandResult {
+    key_ttl(args, store, TtlReport::Seconds)
+}
+
+/// PTTL key
+pub fn handle_pttl(args: &[Bytes], store: &mut Store) -> CommandResult {
+    key_ttl(args, store, TtlReport::Milliseconds)
+}
+
+/// EXPIRETIME key
+pub fn handle_expiretime(args: &[Bytes], store: &mut Store) -> CommandResult {
+    key_ttl(args, store, TtlReport::UnixSeconds)
+}
+
+/// PEXPIRETIME key
+pub fn handle_pexpiretime(args: &[Bytes], store: &mut Store) -> CommandResult {
+    key_ttl(args, store, TtlReport::UnixMilliseconds)
+}
+
+/// PERSIST key
+pub fn handle_persist(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, 2)?;
+    let persisted = store.contains_key(&args[1]) && store.remove_expire(&args[1]);
+    Ok(RESPDataType::Integer(persisted as i64))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::commands::list::handle_rpush;
+    use crate::commands::set::handle_sadd;
+    use crate::commands::test_helpers::args;
+    use crate::evict::MaxMemoryPolicy;
+
+    fn populated() -> Store {
+        let mut store = Store::init();
+        store.set_key_val(Bytes::from("s"), Bytes::from("v"));
+        handle_rpush(&args(&["RPUSH", "l", "a", "b"]), &mut store).unwrap();
+        store
+    }
+
+    #[test]
+    fn test_del_exists_type_dbsize() {
+        let mut store = populated();
+        assert_eq!(
+            handle_exists(&args(&["EXISTS", "s", "l", "s", "none"]), &mut store),
+            Ok(RESPDataType::Integer(3))
+        );
+        assert_eq!(
+            handle_type(&args(&["TYPE", "l"]), &mut store),
+            Ok(RESPDataType::SimpleString(Bytes::from("list")))
+        );
+        assert_eq!(
+            handle_type(&args(&["TYPE", "none"]), &mut store),
+            Ok(RESPDataType::SimpleString(Bytes::from("none")))
+        );
+        assert_eq!(
+            handle_dbsize(&args(&["DBSIZE"]), &mut store),
+            Ok(RESPDataType::Integer(2))
+        );
+        assert_eq!(
+            handle_del(&args(&["DEL", "s", "l", "none"]), &mut store),
+            Ok(RESPDataType::Integer(2))
+        );
+        assert_eq!(
+            handle_dbsize(&args(&["DBSIZE"]), &mut store),
+            Ok(RESPDataType::Integer(0))
+        );
+        assert_eq!(
+            handle_randomkey(&args(&["RANDOMKEY"]), &mut store),
+            Ok(RESPDataType::NullBulkString)
+        );
+    }
+
+    #[test]
+    fn test_rename_keeps_ttl() {
+        let mut store = populated();
+        handle_expire(&args(&["EXPIRE", "l", "100"]), &mut store).unwrap();
+        assert_eq!(
+            handle_rename(&args(&["RENAME", "l", "s"]), &mut store),
+            Ok(ok())
+        );
+        assert_eq!(
+            handle_type(&args(&["TYPE", "s"]), &mut store),
+            Ok(RESPDataType::SimpleString(Bytes::from("list")))
+        );
+        assert_eq!(
+            handle_ttl(&args(&["TTL", "s"]), &mut store),
+            Ok(RESPDataType::Integer(100))
+        );
+        assert_eq!(
+            handle_rename(&args(&["RENAME", "l", "x"]), &mut store),
+            Err(CommandError::NoSuchKey)
+        );
+        store.set_key_val(Bytes::from("other"), Bytes::from("v"));
+        assert_eq!(
+            handle_renamenx(&args(&["RENAMENX", "s", "other"]), &mut store),
+            Ok(RESPDataType::Integer(0))
+        );
+        assert_eq!(
+            handle_renamenx(&args(&["RENAMENX", "s", "new"]), &mut store),
+            Ok(RESPDataType::Integer(1))
+        );
+        assert!(store.get_expire(b"new").is_some());
+    }
+
+    #[test]
+    fn test_copy() {
+        let mut store = populated();
+        assert_eq!(
+            handle_copy(&args(&["COPY", "l", "s"]), &mut store),
+            Ok(RESPDataType::Integer(0))
+        );
+        assert_eq!(
+            handle_copy(&args(&["COPY", "l", "s", "DB", "0", "REPLACE"]), &mut store),
+            Ok(RESPDataType::Integer(1))
+        );
+        handle_rpush(&args(&["RPUSH", "s", "c"]), &mut store).unwrap();
+        assert_eq!(
+            handle_copy(&args(&["COPY", "l", "l2"]), &mut store),
+            Ok(RESPDataType::Integer(1))
+        );
+        assert!(handle_copy(&args(&["COPY", "l", "l"]), &mut store).is_err());
+        assert!(handle_copy(&args(&["COPY", "l", "x", "DB", "16"]), &mut store).is_err());
+        assert_eq!(
+            handle_copy(&args(&["COPY", "l", "l", "DB", "1"]), &mut store),
+            Ok(RESPDataType::Integer(1))
+        );
+        store.select(1);
+        assert!(store.contains_key(b"l"));
+        assert_eq!(
+            handle_copy(&args(&["COPY", "none", "x"]), &mut store),
+            Ok(RESPDataType::Integer(0))
+        );
+    }
+
+    #[test]
+    fn test_expire_conditions() {
+        let mut store = populated();
+        assert_eq!(
+            handle_ttl(&args(&["TTL", "s"]), &mut store),
+            Ok(RESPDataType::Integer(-1))
+        );
+        assert_eq!(
+            handle_ttl(&args(&["TTL", "none"]), &mut store),
+            Ok(RESPDataType::Integer(-2))
+        );
+        assert_eq!(
+            handle_expire(&args(&["EXPIRE", "s", "100", "GT"]), &mut store),
+            Ok(RESPDataType::Integer(0))
+        );
+        assert_eq!(
+            handle_expire(&args(&["EXPIRE", "s", "100", "LT"]), &mut store),
+            Ok(RESPDataType::Integer(1))
+        );
+        assert_eq!(
+            handle_pexpire(&args(&["PEXPIRE", "s", "200000", "XX", "GT"]), &mut store),
+            Ok(RESPDataType::Integer(1))
+        );
+        assert_eq!(
+            handle_ttl(&args(&["TTL", "s"]), &mut store),
+            Ok(RESPDataType::Integer(200))
+        );
+        assert_eq!(
+            handle_expire(&args(&["EXPIRE", "s", "1", "NX"]), &mut store),
+            Ok(RESPDataType::Integer(0))
+        );
+        assert!(handle_expire(&args(&["EXPIRE", "s", "1", "NX", "XX"]), &mut store).is_err());
+        assert!(handle_expire(&args(&["EXPIRE", "s", "1", "GT", "LT"]), &mut store).is_err());
+        assert!(handle_expire(&args(&["EXPIRE", "s", "1", "FOO"]), &mut store).is_err());
+        assert!(handle_expire(&args(&["EXPIRE", "s", "9223372036854775807"]), &mut store).is_err());
+        assert_eq!(
+            handle_persist(&args(&["PERSIST", "s"]), &mut store),
+            Ok(RESPDataType::Integer(1))
+        );
+        assert_eq!(
+            handle_persist(&args(&["PERSIST", "s"]), &mut store),
+            Ok(RESPDataType::Integer(0))
+        );
+        assert_eq!(
+            handle_pexpireat(&args(&["PEXPIREAT", "s", "1700000000000"]), &mut store),
+            Ok(RESPDataType::Integer(1))
+        );
+        assert!(!store.contains_key(b"s"));
+    }
+
+    #[test]
+    fn test_expired_keys_are_hidden_and_collected() {
+        let mut store = populated();
+        let when = mstime() + 1000;
+        store.set_expire(Bytes::from("s"), when);
+        assert_eq!(
+            handle_expiretime(&args(&["EXPIRETIME", "s"]), &mut store),
+            Ok(RESPDataType::Integer((when + 500) / 1000))
+        );
+        assert_eq!(
+            handle_pexpiretime(&args(&["PEXPIRETIME", "s"]), &mut store),
+            Ok(RESPDataType::Integer(when))
+        );
+        store.set_expire(Bytes::from("s"), mstime() - 1);
+        assert_eq!(
+            handle_exists(&args(&["EXISTS", "s"]), &mut store),
+            Ok(RESPDataType::Integer(0))
+        );
+        assert_eq!(
+            handle_randomkey(&args(&["RANDOMKEY"]), &mut store),
+            Ok(RESPDataType::BulkString(Bytes::from("l")))
+        );
+        store.set_expire(Bytes::from("l"), mstime() - 1);
+        // RANDOMKEY may already have reclaimed "s".
+        assert!(store.active_expire_keys(mstime(), 10) >= 1);
+        assert_eq!(store.dbsize(), 0);
+    }
+
+    #[test]
+    fn test_keys_and_scan() {
+        let mut store = Store::init();
+        for i in 0..500 {
+            store.set_key_val(Bytes::from(format!("key:{}", i)), Bytes::from("v"));
+        }
+        handle_rpush(&args(&["RPUSH", "key:list", "a"]), &mut store).unwrap();
+        let Ok(RESPDataType::Array(keys)) = handle_keys(&args(&["KEYS", "key:4?"]), &mut store)
+        else {
+            panic!("expected an array");
+        };
+        assert_eq!(keys.len(), 10);
+
+        let mut seen = HashSet::new();
+        let mut cursor = String::from("0");
+        loop {
+            let reply = handle_scan(
+                &args(&[
+                    "SCAN", &cursor, "MATCH", "key:*", "COUNT", "20", "TYPE", "string",
+                ]),
+                &mut store,
+            );
+            let Ok(RESPDataType::Array(reply)) = reply else {
+                panic!("expected an array");
+            };
+            let [RESPDataType::BulkString(next), RESPDataType::Array(keys)] = &reply[..] else {
+                panic!("expected a cursor and keys");
+            };
+            for key in keys {
+                let RESPDataType::BulkString(key) = key else {
+                    panic!("expected a key");
+                };
+                seen.insert(key.clone());
+            }
+            cursor = String::from_utf8(next.to_vec()).unwrap();
+            if cursor == "0" {
+                break;
+            }
+        }
+        assert_eq!(seen.len(), 500);
+
+        assert_eq!(
+            handle_scan(&args(&["SCAN", "x"]), &mut store),
+            Err(CommandError::Custom(String::from("ERR invalid cursor")))
+        );
+        assert_eq!(
+            handle_scan(&args(&["SCAN", "0", "TYPE", "nope"]), &mut store),
+            Err(CommandError::Custom(String::from(
+                "ERR unknown type name 'nope'"
+            )))
+        );
+        assert_eq!(
+            handle_scan(&args(&["SCAN", "0", "NOVALUES"]), &mut store),
+            Err(CommandError::Syntax)
+        );
+    }
+
+    #[test]
+    fn test_select_move_swapdb_flush() {
+        let mut store = populated();
+        let mut db = 0;
+        assert_eq!(
+            handle_select(&args(&["SELECT", "16"]), &mut store, &mut db),
+            Err(CommandError::Custom(String::from(
+                "ERR DB index is out of range"
+            )))
+        );
+        assert_eq!(
+            handle_move(&args(&["MOVE", "s", "0"]), &mut store),
+            Err(same_object())
+        );
+        assert_eq!(
+            handle_move(&args(&["MOVE", "s", "2"]), &mut store),
+            Ok(RESPDataType::Integer(1))
+        );
+        assert!(!store.contains_key(b"s"));
+
+        assert_eq!(
+            handle_select(&args(&["SELECT", "2"]), &mut store, &mut db),
+            Ok(ok())
+        );
+        assert_eq!(db, 2);
+        assert!(store.contains_key(b"s"));
+        store.set_key_val(Bytes::from("l"), Bytes::from("v"));
+        // The key exists in the destination database.
+        assert_eq!(
+            handle_move(&args(&["MOVE", "l", "0"]), &mut store),
+            Ok(RESPDataType::Integer(0))
+        );
+
+        assert_eq!(
+            handle_swapdb(&args(&["SWAPDB", "0", "x"]), &mut store),
+            Err(CommandError::Custom(String::from(
+                "ERR invalid second DB index"
+            )))
+        );
+        assert_eq!(
+            handle_swapdb(&args(&["SWAPDB", "0", "2"]), &mut store),
+            Ok(ok())
+        );
+        assert_eq!(
+            store.get_from_key_val_store(b"l").unwrap().type_name(),
+            "list"
+        );
+
+        assert_eq!(
+            handle_flushdb(&args(&["FLUSHDB", "ASYNC"]), &mut store),
+            Ok(ok())
+        );
+        assert_eq!(store.dbsize(), 0);
+        store.select(0);
+        assert!(store.contains_key(b"s"));
+        assert_eq!(
+            handle_flushall(&args(&["FLUSHALL", "LAZY"]), &mut store),
+            Err(CommandError::Syntax)
+        );
+        assert_eq!(handle_flushall(&args(&["FLUSHALL"]), &mut store), Ok(ok()));
+        assert_eq!(store.dbsize(), 0);
+    }
+
+    #[test]
+    fn test_unlink_and_lazy_del() {
+        let mut store = populated();
+        let mut sadd = vec![Bytes::from("SADD"), Bytes::from("big")];
+        sadd.extend((0..1000).map(|i| Bytes::from(format!("m{}", i))));
+        handle_sadd(&sadd, &mut store).unwrap();
+
+        let freed = lazyfree::freed_objects();
+        assert_eq!(
+            handle_unlink(&args(&["UNLINK", "big", "s", "none"]), &mut store),
+            Ok(RESPDataType::Integer(2))
+        );
+        assert_eq!(store.dbsize(), 1);
+        while lazyfree::freed_objects() == freed {
+            std::thread::yield_now();
+        }
+
+        // With lazyfree-lazy-user-del, DEL frees in the background too.
+        store.lazyfree.user_del = true;
+        handle_sadd(&sadd, &mut store).unwrap();
+        let freed = lazyfree::freed_objects();
+        assert_eq!(
+            handle_del(&args(&["DEL", "big"]), &mut store),
+            Ok(RESPDataType::Integer(1))
+        );
+        while lazyfree::freed_objects() == freed {
+            std::thread::yield_now();
+        }
+    }
+
+    #[test]
+    fn test_object() {
+        let mut store = populated();
+        let object = |store: &mut Store, subcommand: &str, key: &str| {
+            handle_object(&args(&["OBJECT", subcommand, key]), store)
+        };
+        let encoding = |store: &mut Store, key: &str| match object(store, "ENCODING", key) {
+            Ok(RESPDataType::BulkString(name)) => name,
+            reply => panic!("unexpected reply {:?}", reply),
+        };
+        store.set_key_val(Bytes::from("int"), Bytes::from("12345"));
+        store.set_key_val(Bytes::from("embstr"), Bytes::from("012"));
+        store.set_key_val(Bytes::from("raw"), Bytes::from("x".repeat(45)));
+        handle_sadd(&args(&["SADD", "set", "1", "2"]), &mut store).unwrap();
+        assert_eq!(encoding(&mut store, "int"), "int");
+        assert_eq!(encoding(&mut store, "embstr"), "embstr");
+        assert_eq!(encoding(&mut store, "raw"), "raw");
+        assert_eq!(encoding(&mut store, "l"), "listpack");
+        assert_eq!(encoding(&mut store, "set"), "intset");
+        assert_eq!(
+            object(&mut store, "ENCODING", "none"),
+            Ok(RESPDataType::NullBulkString)
+        );
+
+        assert_eq!(
+            object(&mut store, "REFCOUNT", "s"),
+            Ok(RESPDataType::Integer(1))
+        );
+        store.set_key_val(Bytes::from("shared"), Bytes::from("100"));
+        assert_eq!(
+            object(&mut store, "REFCOUNT", "shared"),
+            Ok(RESPDataType::Integer(i32::MAX as i64))
+        );
+        store.maxmemory.maxmemory = usize::MAX;
+        store.maxmemory.policy = MaxMemoryPolicy::AllKeysLru;
+        store.set_key_val(Bytes::from("unshared"), Bytes::from("100"));
+        assert_eq!(
+            object(&mut store, "REFCOUNT", "unshared"),
+            Ok(RESPDataType::Integer(1))
+        );
+
+        // Idle time under LRU, frequency under LFU, restored by RESTORE.
+        store.set_access(b"s", None, Some(100));
+        assert!(matches!(
+            object(&mut store, "IDLETIME", "s"),
+            Ok(RESPDataType::Integer(100 | 101))
+        ));
+        handle_touch(&args(&["TOUCH", "s"]), &mut store).unwrap();
+        assert!(matches!(
+            object(&mut store, "IDLETIME", "s"),
+            Ok(RESPDataType::Integer(0 | 1))
+        ));
+        assert!(object(&mut store, "FREQ", "s").is_err());
+        store.maxmemory.policy = MaxMemoryPolicy::AllKeysLfu;
+        store.set_access(b"s", Some(42), None);
+        assert_eq!(
+            object(&mut store, "FREQ", "s"),
+            Ok(RESPDataType::Integer(42))
+        );
+        assert!(object(&mut store, "IDLETIME", "s").is_err());
+
+        assert!(matches!(
+            handle_object(&args(&["OBJECT", "NOPE", "s"]), &mut store),
+            Err(CommandError::Custom(_))
+        ));
+        assert!(handle_object(&args(&["OBJECT", "ENCODING"]), &mut store).is_err());
+    }
+
+    #[test]
+    fn test_dump_and_restore() {
+        let mut store = populated();
+        let dump = |store: &mut Store, key: &str| match handle_dump(&args(&["DUMP", key]), store)
+            .unwrap()
+        {
+            RESPDataType::BulkString(payload) => payload,
+            reply => panic!("unexpected reply {:?}", reply),
+        };
+        let restore = |store: &mut Store, parts: &[&str], payload: &Bytes| {
+            let mut restore_args = args(parts);
+            restore_args.insert(3, payload.clone());
+            handle_restore(&restore_args, store)
+        };
+        assert_eq!(
+            handle_dump(&args(&["DUMP", "none"]), &mut store),
+            Ok(RESPDataType::NullBulkString)
+        );
+        let payload = dump(&mut store, "l");
+
+        assert_eq!(
+            restore(&mut store, &["RESTORE", "l", "0"], &payload),
+            Err(CommandError::Custom(String::from(
+                "BUSYKEY Target key name already exists."
+            )))
+        );
+        assert_eq!(
+            restore(&mut store, &["RESTORE", "l2", "0"], &payload),
+            Ok(ok())
+        );
+        assert_eq!(dump(&mut store, "l2"), payload);
+        assert_eq!(store.get_expire(b"l2"), None);
+
+        // REPLACE with a relative TTL, then an absolute one.
+        assert_eq!(
+            restore(&mut store, &["RESTORE", "s", "5000", "REPLACE"], &payload),
+            Ok(ok())
+        );
+        let ttl = store.get_expire(b"s").unwrap() - mstime();
+        assert!(ttl > 4000 && ttl <= 5000);
+        let when = (mstime() + 100_000).to_string();
+        let parts = ["RESTORE", "s", &when, "REPLACE", "ABSTTL", "IDLETIME", "10"];
+        assert_eq!(restore(&mut store, &parts, &payload), Ok(ok()));
+        assert_eq!(store.get_expire(b"s"), Some(when.parse().unwrap()));
+        // A TTL already in the past deletes rather than creates the key.
+        let parts = ["RESTORE", "s", "1", "REPLACE", "ABSTTL", "FREQ", "3"];
+        assert_eq!(restore(&mut store, &parts, &payload), Ok(ok()));
+        assert!(!store.contains_key(b"s"));
+
+        let error = |message: &str| Err(CommandError::Custom(String::from(message)));
+        let mut corrupt = payload.to_vec();
+        corrupt[1] ^= 0xff;
+        assert_eq!(
+            restore(&mut store, &["RESTORE", "x", "0"], &Bytes::from(corrupt)),
+            error("ERR DUMP payload version or checksum are wrong")
+        );
+        assert_eq!(
+            restore(&mut store, &["RESTORE", "x", "-1"], &payload),
+            error("ERR Invalid TTL value, must be >= 0")
+        );
+        assert_eq!(
+            restore(
+                &mut store,
+                &["RESTORE", "x", "0", "IDLETIME", "-1"],
+                &payload
+            ),
+            error("ERR Invalid IDLETIME value, must be >= 0")
+        );
+        assert_eq!(
+            restore(&mut store, &["RESTORE", "x", "0", "FREQ", "256"], &payload),
+            error("ERR Invalid FREQ value, must be >= 0 and <= 255")
+        );
+        assert_eq!(
+            restore(
+                &mut store,
+                &["RESTORE", "x", "0", "FREQ", "1", "IDLETIME", "1"],
+                &payload
+            ),
+            Err(CommandError::Syntax)
+        );
+        assert!(!store.contains_key(b"x"));
+    }
+
+    /// A target instance accepting a single connection, replying OK to every
+    /// command except RESTOREs of "busy", and reporting the commands it got.
+    fn fake_target() -> (u16, std::sync::mpsc::Receiver<Vec<Bytes>>) {
+        use crate::resp::deserializer::RespDeserializer;
+        use bytes::{Buf, BytesMut};
+        use std::io::{Read, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (sender, receiver) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buffer = BytesMut::new();
+            let mut chunk = [0; 4096];
+            while let Ok(size @ 1..) = stream.read(&mut chunk) {
+                buffer.extend_from_slice(&chunk[..size]);
+                while let Ok(Some((len, RESPDataType::Array(parts)))) =
+                    RespDeserializer.deserialize(&buffer, 0)
+                {
+                    buffer.advance(len);
+                    let parts: Vec<Bytes> = parts
+                        .into_iter()
+                        .map(|part| match part {
+                            RESPDataType::BulkString(part) => part,
+                            part => panic!("unexpected {:?}", part),
+                        })
+                        .collect();
+                    let reply: &[u8] = if parts[0] == "RESTORE" && parts[1] == "busy" {
+                        b"-BUSYKEY Target key name already exists.\r\n"
+                    } else {
+                        b"+OK\r\n"
+                    };
+                    stream.write_all(reply).unwrap();
+                    sender.send(parts).unwrap();
+                }
+            }
+        });
+        (port, receiver)
+    }
+
+    #[test]
+    fn test_migrate() {
+        let mut store = populated();
+        let (port, received) = fake_target();
+        let port = port.to_string();
+        let migrate = |store: &mut Store, extra: &[&str]| {
+            let mut parts = vec!["MIGRATE", "127.0.0.1", &port];
+            parts.extend_from_slice(extra);
+            handle_migrate(&args(&parts), store)
+        };
+
+        assert_eq!(
+            migrate(&mut store, &["none", "0", "100"]),
+            Ok(RESPDataType::SimpleString(Bytes::from("NOKEY")))
+        );
+        assert!(matches!(
+            migrate(&mut store, &["s", "0", "100", "KEYS", "l"]),
+            Err(CommandError::Custom(_))
+        ));
+
+        store.set_expire(Bytes::from("s"), mstime() + 60_000);
+        assert_eq!(
+            migrate(&mut store, &["", "3", "1000", "COPY", "KEYS", "s", "none"]),
+            Ok(ok())
+        );
+        assert_eq!(received.recv().unwrap(), args(&["SELECT", "3"]));
+        let restore = received.recv().unwrap();
+        assert_eq!(restore[..2], args(&["RESTORE", "s"]));
+        let ttl: i64 = std::str::from_utf8(&restore[2]).unwrap().parse().unwrap();
+        assert!(ttl > 50_000 && ttl <= 60_000);
+        assert!(store.contains_key(b"s"));
+
+        // The connection is reused and the database still selected.
+        assert_eq!(
+            migrate(&mut store, &["l", "3", "1000", "REPLACE"]),
+            Ok(ok())
+        );
+        let restore = received.recv().unwrap();
+        assert_eq!(restore[..3], args(&["RESTORE", "l", "0"]));
+        assert_eq!(restore[4], "REPLACE");
+        assert!(!store.contains_key(b"l"));
+        assert_eq!(store.migrate_sockets.len(), 1);
+
+        // Keys the target refuses stay, the others move.
+        store.set_key_val(Bytes::from("busy"), Bytes::from("v"));
+        assert_eq!(
+            migrate(&mut store, &["", "3", "1000", "KEYS", "busy", "s"]),
+            Err(CommandError::Custom(String::from(
+                "ERR Target instance replied with error: BUSYKEY Target key name already exists."
+            )))
+        );
+        assert!(store.contains_key(b"busy"));
+        assert!(!store.contains_key(b"s"));
+
+        store
+            .migrate_sockets
+            .close_idle(std::time::Instant::now() + Duration::from_secs(60));
+        assert!(store.migrate_sockets.is_empty());
+        let closed = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let closed_port = closed.local_addr().unwrap().port().to_string();
+        drop(closed);
+        assert_eq!(
+            handle_migrate(
+                &args(&["MIGRATE", "127.0.0.1", &closed_port, "busy", "0", "100"]),
+                &mut store
+            ),
+            Err(CommandError::Custom(String::from(
+                "IOERR error or timeout connecting to the client"
+            )))
+        );
+    }
+
+    #[test]
+    fn test_migrate_to_server() {
+        use crate::commands::list::handle_lrange;
+        use crate::server::Server;
+        use crate::thread_pool::ThreadPool;
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port().to_string();
+        let target = Server::new(Store::init(), ThreadPool::new(2));
+        {
+            let mut target = target.store.lock().unwrap();
+            target.select(3);
+            target.set_key_val(Bytes::from("s"), Bytes::from("old"));
+        }
+        let server = target.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                server.accept(stream.unwrap());
+            }
+        });
+
+        let mut store = populated();
+        store.set_expire(Bytes::from("s"), mstime() + 60_000);
+        assert_eq!(
+            handle_migrate(
+                &args(&[
+                    "MIGRATE",
+                    "127.0.0.1",
+                    &port,
+                    "",
+                    "3",
+                    "1000",
+                    "COPY",
+                    "REPLACE",
+                    "KEYS",
+                    "s",
+                    "l"
+                ]),
+                &mut store
+            ),
+            Ok(ok())
+        );
+        assert!(store.contains_key(b"s") && store.contains_key(b"l"));
+
+        let mut target = target.store.lock().unwrap();
+        target.select(3);
+        assert!(matches!(
+            target.get_from_key_val_store(b"s"),
+            Some(Value::String(value)) if value == "v"
+        ));
+        let ttl = target.get_expire(b"s").unwrap() - mstime();
+        assert!(ttl > 50_000 && ttl <= 60_000);
+        assert_eq!(target.get_expire(b"l"), None);
+        assert_eq!(
+            handle_lrange(&args(&["LRANGE", "l", "0", "-1"]), &mut target),
+            Ok(RESPDataType::Array(vec![
+                RESPDataType::BulkString(Bytes::from("a")),
+                RESPDataType::BulkString(Bytes::from("b")),
+            ]))
+        );
+        target.select(0);
+        assert!(!target.contains_key(b"s"));
+    }
+}
@@ -0,0 +1,816 @@
+use bytes::Bytes;
+
+use super::{
+    bulk_array, check_arity, is_keyword, normalize_index, normalize_range, ok, parse_int,
+    wrong_number_of_arguments, CommandError, CommandResult,
+};
+use crate::blocking::{parse_timeout, reply_or_block, CommandOutcome};
+use crate::resp::data::RESPDataType;
+use crate::store::{Store, Value};
+use crate::types::quicklist::QuickList;
+
+/// The end of a list an operation applies to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListEnd {
+    Left,
+    Right,
+}
+
+impl ListEnd {
+    pub fn parse(arg: &[u8]) -> Result<ListEnd, CommandError> {
+        if is_keyword(arg, "LEFT") {
+            Ok(ListEnd::Left)
+        } else if is_keyword(arg, "RIGHT") {
+            Ok(ListEnd::Right)
+        } else {
+            Err(CommandError::Syntax)
+        }
+    }
+}
+
+pub fn push_to(list: &mut QuickList, end: ListEnd, value: Bytes) {
+    match end {
+        ListEnd::Left => list.push_front(value),
+        ListEnd::Right => list.push_back(value),
+    }
+}
+
+pub fn pop_from(list: &mut QuickList, end: ListEnd) -> Option<Bytes> {
+    match end {
+        ListEnd::Left => list.pop_front(),
+        ListEnd::Right => list.pop_back(),
+    }
+}
+
+pub fn get_list<'a>(store: &'a Store, key: &[u8]) -> Result<Option<&'a QuickList>, CommandError> {
+    match store.get_from_key_val_store(key) {
+        None => Ok(None),
+        Some(Value::List(list)) => Ok(Some(list)),
+        Some(_) => Err(CommandError::WrongType),
+    }
+}
+
+pub fn get_list_mut<'a>(
+    store: &'a mut Store,
+    key: &[u8],
+) -> Result<Option<&'a mut QuickList>, CommandError> {
+    match store.get_mut_from_key_val_store(key) {
+        None => Ok(None),
+        Some(Value::List(list)) => Ok(Some(list)),
+        Some(_) => Err(CommandError::WrongType),
+    }
+}
+
+fn push(args: &[Bytes], store: &mut Store, end: ListEnd, only_if_exists: bool) -> CommandResult {
+    check_arity(args, -3)?;
+    let key = &args[1];
+    let len = match get_list_mut(store, key)? {
+        Some(list) => {
+            for value in &args[2..] {
+                push_to(list, end, value.clone());
+            }
+            let len = list.len();
+            store.add_dirty(args.len() - 2);
+            len
+        }
+        None if only_if_exists => 0,
+        None => {
+            let mut list = QuickList::new();
+            for value in &args[2..] {
+                push_to(&mut list, end, value.clone());
+            }
+            let len = list.len();
+            store.insert_key_val(key.clone(), Value::List(list));
+            len
+        }
+    };
+    Ok(RESPDataType::Integer(len as i64))
+}
+
+/// LPUSH key element [element ...]
+pub fn handle_lpush(args: &[Bytes], store: &mut Store) -> CommandResult {
+    push(args, store, ListEnd::Left, false)
+}
+
+/// RPUSH key element [element ...]
+pub fn handle_rpush(args: &[Bytes], store: &mut Store) -> CommandResult {
+    push(args, store, ListEnd::Right, false)
+}
+
+/// LPUSHX key element [element ...]
+pub fn handle_lpushx(args: &[Bytes], store: &mut Store) -> CommandResult {
+    push(args, store, ListEnd::Left, true)
+}
+
+/// RPUSHX key element [element ...]
+pub fn handle_rpushx(args: &[Bytes], store: &mut Store) -> CommandResult {
+    push(args, store, ListEnd::Right, true)
+}
+
+fn pop(args: &[Bytes], store: &mut Store, end: ListEnd) -> CommandResult {
+    check_arity(args, -2)?;
+    if args.len() > 3 {
+        return Err(wrong_number_of_arguments(args));
+    }
+    let key = &args[1];
+    let count = match args.get(2) {
+        Some(arg) => {
+            let count = parse_int(arg)?;
+            if count < 0 {
+                return Err(CommandError::OutOfRange);
+            }
+            Some(count as usize)
+        }
+        None => None,
+    };
+    if get_list(store, key)?.is_none() {
+        return Ok(match count {
+            Some(_) => RESPDataType::NullArray,
+            None => RESPDataType::NullBulkString,
+        });
+    }
+    if count == Some(0) {
+        return Ok(RESPDataType::Array(vec![]));
+    }
+    let list = get_list_mut(store, key)?.unwrap();
+    let (reply, popped) = match count {
+        None => (
+            pop_from(list, end)
+                .map(RESPDataType::BulkString)
+                .unwrap_or(RESPDataType::NullBulkString),
+            1,
+        ),
+        Some(count) => {
+            let values: Vec<RESPDataType> = (0..count)
+                .map_while(|_| pop_from(list, end))
+                .map(RESPDataType::BulkString)
+                .collect();
+            let popped = values.len();
+            (RESPDataType::Array(values), popped)
+        }
+    };
+    store.add_dirty(popped);
+    store.remove_if_empty(key);
+    Ok(reply)
+}
+
+/// LPOP key [count]
+pub fn handle_lpop(args: &[Bytes], store: &mut Store) -> CommandResult {
+    pop(args, store, ListEnd::Left)
+}
+
+/// RPOP key [count]
+pub fn handle_rpop(args: &[Bytes], store: &mut Store) -> CommandResult {
+    pop(args, store, ListEnd::Right)
+}
+
+/// LLEN key
+pub fn handle_llen(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, 2)?;
+    let len = get_list(store, &args[1])?.map_or(0, |list| list.len());
+    Ok(RESPDataType::Integer(len as i64))
+}
+
+/// LRANGE key start stop
+pub fn handle_lrange(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, 4)?;
+    let start = parse_int(&args[2])?;
+    let stop = parse_int(&args[3])?;
+    let Some(list) = get_list(store, &args[1])? else {
+        return Ok(RESPDataType::Array(vec![]));
+    };
+    match normalize_range(start, stop, list.len()) {
+        Some((start, stop)) => Ok(bulk_array(list.iter_from(start).take(stop - start + 1))),
+        None => Ok(RESPDataType::Array(vec![])),
+    }
+}
+
+/// LINDEX key index
+pub fn handle_lindex(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, 3)?;
+    let index = parse_int(&args[2])?;
+    let value = get_list(store, &args[1])?.and_then(|list| {
+        normalize_index(index, list.len())
+            .and_then(|index| list.get(index).map(Bytes::copy_from_slice))
+    });
+    Ok(value
+        .map(RESPDataType::BulkString)
+        .unwrap_or(RESPDataType::NullBulkString))
+}
+
+/// LSET key index element
+pub fn handle_lset(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, 4)?;
+    let index = parse_int(&args[2])?;
+    let list = get_list(store, &args[1])?.ok_or(CommandError::NoSuchKey)?;
+    let index = normalize_index(index, list.len()).ok_or(CommandError::IndexOutOfRange)?;
+    get_list_mut(store, &args[1])?
+        .unwrap()
+        .set(index, args[3].clone());
+    store.add_dirty(1);
+    Ok(ok())
+}
+
+/// LINSERT key BEFORE|AFTER pivot element
+pub fn handle_linsert(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, 5)?;
+    let after = if is_keyword(&args[2], "AFTER") {
+        true
+    } else if is_keyword(&args[2], "BEFORE") {
+        false
+    } else {
+        return Err(CommandError::Syntax);
+    };
+    let Some(list) = get_list(store, &args[1])? else {
+        return Ok(RESPDataType::Integer(0));
+    };
+    let Some(position) = list.iter().position(|value| value == args[3]) else {
+        return Ok(RESPDataType::Integer(-1));
+    };
+    let list = get_list_mut(store, &args[1])?.unwrap();
+    list.insert(position + after as usize, args[4].clone());
+    let len = list.len();
+    store.add_dirty(1);
+    Ok(RESPDataType::Integer(len as i64))
+}
+
+/// LREM key count element
+pub fn handle_lrem(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, 4)?;
+    let count = parse_int(&args[2])?;
+    let key = &args[1];
+    let Some(list) = get_list(store, key)? else {
+        return Ok(RESPDataType::Integer(0));
+    };
+    if !list.iter().any(|value| value == args[3]) {
+        return Ok(RESPDataType::Integer(0));
+    }
+    let removed = get_list_mut(store, key)?.unwrap().remove_matching(
+        &args[3],
+        count.unsigned_abs() as usize,
+        count < 0,
+    );
+    store.add_dirty(removed);
+    store.remove_if_empty(key);
+    Ok(RESPDataType::Integer(removed as i64))
+}
+
+/// LTRIM key start stop
+pub fn handle_ltrim(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, 4)?;
+    let start = parse_int(&args[2])?;
+    let stop = parse_int(&args[3])?;
+    let key = &args[1];
+    let Some(list) = get_list(store, key)? else {
+        return Ok(ok());
+    };
+    let len = list.len();
+    let range = normalize_range(start, stop, len);
+    if range == Some((0, len - 1)) {
+        return Ok(ok());
+    }
+    let list = get_list_mut(store, key)?.unwrap();
+    match range {
+        Some((start, stop)) => list.trim(start, stop),
+        None => list.clear(),
+    }
+    let removed = len - list.len();
+    store.add_dirty(removed);
+    store.remove_if_empty(key);
+    Ok(ok())
+}
+
+/// LPOS key element [RANK rank] [COUNT num-matches] [MAXLEN len]
+pub fn handle_lpos(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, -3)?;
+    let mut rank: i64 = 1;
+    let mut count: Option<usize> = None;
+    let mut maxlen: usize = 0;
+    let mut i = 3;
+    while i < args.len() {
+        let value = args.get(i + 1).ok_or(CommandError::Syntax)?;
+        if is_keyword(&args[i], "RANK") {
+            rank = parse_int(value)?;
+            if rank == 0 {
+                return Err(CommandError::Custom(String::from(
+                    "ERR RANK can't be zero: use 1 to start from the first match, 2 from the second ... or use negative to start from the end of the list",
+                )));
+            }
+            if rank == i64::MIN {
+                return Err(CommandError::Custom(String::from(
+                    "ERR value is out of range, value must between -9223372036854775807 and 9223372036854775807",
+                )));
+            }
+        } else if is_keyword(&args[i], "COUNT") {
+            let value = parse_int(value)?;
+            if value < 0 {
+                return Err(CommandError::Custom(String::from(
+                    "ERR COUNT can't be negative",
+                )));
+            }
+            count = Some(value as usize);
+        } else if is_keyword(&args[i], "MAXLEN") {
+            let value = parse_int(value)?;
+            if value < 0 {
+                return Err(CommandError::Custom(String::from(
+                    "ERR MAXLEN can't be negative",
+                )));
+            }
+            maxlen = value as usize;
+        } else {
+            return Err(CommandError::Syntax);
+        }
+        i += 2;
+    }
+
+    let Some(list) = get_list(store, &args[1])? else {
+        return Ok(match count {
+            Some(_) => RESPDataType::Array(vec![]),
+            None => RESPDataType::NullBulkString,
+        });
+    };
+    let len = list.len();
+    let scanned = if maxlen == 0 { len } else { maxlen.min(len) };
+    let element = &args[2];
+    let wanted = match count {
+        Some(0) => usize::MAX,
+        Some(count) => count,
+        None => 1,
+    };
+    let positions: Vec<usize> = if rank > 0 {
+        list.iter()
+            .take(scanned)
+            .enumerate()
+            .filter(|(_, value)| *value == element)
+            .map(|(index, _)| index)
+            .skip(rank as usize - 1)
+            .take(wanted)
+            .collect()
+    } else {
+        list.iter()
+            .rev()
+            .take(scanned)
+            .enumerate()
+            .filter(|(_, value)| *value == element)
+            .map(|(index, _)| len - 1 - index)
+            .skip(rank.unsigned_abs() as usize - 1)
+            .take(wanted)
+            .collect()
+    };
+    Ok(match count {
+        Some(_) => RESPDataType::Array(
+            positions
+                .into_iter()
+                .map(|position| RESPDataType::Integer(position as i64))
+                .collect(),
+        ),
+        None => positions
+            .first()
+            .map(|position| RESPDataType::Integer(*position as i64))
+            .unwrap_or(RESPDataType::NullBulkString),
+    })
+}
+
+/// Pop up to `count` values from the first non empty list among `keys`.
+fn pop_from_first(
+    store: &mut Store,
+    keys: &[Bytes],
+    end: ListEnd,
+    count: usize,
+) -> Result<Option<(Bytes, Vec<Bytes>)>, CommandError> {
+    for key in keys {
+        if let Some(list) = get_list_mut(store, key)? {
+            let values: Vec<Bytes> = (0..count).map_while(|_| pop_from(list, end)).collect();
+            store.add_dirty(values.len());
+            store.remove_if_empty(key);
+            return Ok(Some((key.clone(), values)));
+        }
+    }
+    Ok(None)
+}
+
+/// Atomically pop a value from `source` and push it to `destination`.
+fn move_value(
+    store: &mut Store,
+    source: &Bytes,
+    destination: &Bytes,
+    from: ListEnd,
+    to: ListEnd,
+) -> Result<Option<Bytes>, CommandError> {
+    if get_list(store, source)?.is_none() {
+        return Ok(None);
+    }
+    get_list(store, destination)?;
+    let value = get_list_mut(store, source)?
+        .and_then(|list| pop_from(list, from))
+        .unwrap();
+    store.add_dirty(1);
+    store.remove_if_empty(source);
+    match get_list_mut(store, destination)? {
+        Some(list) => push_to(list, to, value.clone()),
+        None => {
+            let mut list = QuickList::new();
+            list.push_back(value.clone());
+            store.insert_key_val(destination.clone(), Value::List(list));
+        }
+    }
+    Ok(Some(value))
+}
+
+/// Parse `numkeys key [key ...] LEFT|RIGHT [COUNT count]` starting at `numkeys_index`.
+fn parse_mpop_args(
+    args: &[Bytes],
+    numkeys_index: usize,
+) -> Result<(&[Bytes], ListEnd, usize), CommandError> {
+    let numkeys = parse_int(&args[numkeys_index])?;
+    if numkeys <= 0 {
+        return Err(CommandError::Custom(String::from(
+            "ERR numkeys should be greater than 0",
+        )));
+    }
+    let keys_start = numkeys_index + 1;
+    let keys_end = keys_start.saturating_add(numkeys as usize);
+    if keys_end >= args.len() {
+        return Err(CommandError::Syntax);
+    }
+    let end = ListEnd::parse(&args[keys_end])?;
+    let count = match &args[keys_end + 1..] {
+        [] => 1,
+        [option, value] if is_keyword(option, "COUNT") => {
+            let count = parse_int(value)?;
+            if count <= 0 {
+                return Err(CommandError::Custom(String::from(
+                    "ERR count should be greater than 0",
+                )));
+            }
+            count as usize
+        }
+        _ => return Err(CommandError::Syntax),
+    };
+    Ok((&args[keys_start..keys_end], end, count))
+}
+
+fn mpop_reply(popped: Option<(Bytes, Vec<Bytes>)>) -> RESPDataType {
+    match popped {
+        Some((key, values)) => {
+            RESPDataType::Array(vec![RESPDataType::BulkString(key), bulk_array(&values)])
+        }
+        None => RESPDataType::NullArray,
+    }
+}
+
+/// LMOVE source destination LEFT|RIGHT LEFT|RIGHT
+pub fn handle_lmove(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, 5)?;
+    let from = ListEnd::parse(&args[3])?;
+    let to = ListEnd::parse(&args[4])?;
+    let value = move_value(store, &args[1], &args[2], from, to)?;
+    Ok(value
+        .map(RESPDataType::BulkString)
+        .unwrap_or(RESPDataType::NullBulkString))
+}
+
+/// RPOPLPUSH source destination
+pub fn handle_rpoplpush(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, 3)?;
+    let value = move_value(store, &args[1], &args[2], ListEnd::Right, ListEnd::Left)?;
+    Ok(value
+        .map(RESPDataType::BulkString)
+        .unwrap_or(RESPDataType::NullBulkString))
+}
+
+/// LMPOP numkeys key [key ...] LEFT|RIGHT [COUNT count]
+pub fn handle_lmpop(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, -4)?;
+    let (keys, end, count) = parse_mpop_args(args, 1)?;
+    Ok(mpop_reply(pop_from_first(store, keys, end, count)?))
+}
+
+fn blocking_pop(
+    args: &[Bytes],
+    store: &mut Store,
+    end: ListEnd,
+    may_block: bool,
+) -> Result<CommandOutcome, CommandError> {
+    check_arity(args, -3)?;
+    let keys = &args[1..args.len() - 1];
+    let deadline = parse_timeout(&args[args.len() - 1])?;
+    let reply = pop_from_first(store, keys, end, 1)?.map(|(key, mut values)| {
+        RESPDataType::Array(vec![
+            RESPDataType::BulkString(key),
+            RESPDataType::BulkString(values.pop().unwrap()),
+        ])
+    });
+    reply_or_block(
+        reply,
+        RESPDataType::NullArray,
+        args,
+        keys,
+        deadline,
+        may_block,
+    )
+}
+
+/// BLPOP key [key ...] timeout
+pub fn handle_blpop(
+    args: &[Bytes],
+    store: &mut Store,
+    may_block: bool,
+) -> Result<CommandOutcome, CommandError> {
+    blocking_pop(args, store, ListEnd::Left, may_block)
+}
+
+/// BRPOP key [key ...] timeout
+pub fn handle_brpop(
+    args: &[Bytes],
+    store: &mut Store,
+    may_block: bool,
+) -> Result<CommandOutcome, CommandError> {
+    blocking_pop(args, store, ListEnd::Right, may_block)
+}
+
+fn blocking_move(
+    args: &[Bytes],
+    store: &mut Store,
+    from: ListEnd,
+    to: ListEnd,
+    timeout: &[u8],
+    may_block: bool,
+) -> Result<CommandOutcome, CommandError> {
+    let deadline = parse_timeout(timeout)?;
+    let reply = move_value(store, &args[1], &args[2], from, to)?.map(RESPDataType::BulkString);
+    reply_or_block(
+        reply,
+        RESPDataType::NullBulkString,
+        args,
+        &args[1..2],
+        deadline,
+        may_block,
+    )
+}
+
+/// BLMOVE source destination LEFT|RIGHT LEFT|RIGHT timeout
+pub fn handle_blmove(
+    args: &[Bytes],
+    store: &mut Store,
+    may_block: bool,
+) -> Result<CommandOutcome, CommandError> {
+    check_arity(args, 6)?;
+    let from = ListEnd::parse(&args[3])?;
+    let to = ListEnd::parse(&args[4])?;
+    blocking_move(args, store, from, to, &args[5], may_block)
+}
+
+/// BRPOPLPUSH source destination timeout
+pub fn handle_brpoplpush(
+    args: &[Bytes],
+    store: &mut Store,
+    may_block: bool,
+) -> Result<CommandOutcome, CommandError> {
+    check_arity(args, 4)?;
+    blocking_move(
+        args,
+        store,
+        ListEnd::Right,
+        ListEnd::Left,
+        &args[3],
+        may_block,
+    )
+}
+
+/// BLMPOP timeout numkeys key [key ...] LEFT|RIGHT [COUNT count]
+pub fn handle_blmpop(
+    args: &[Bytes],
+    store: &mut Store,
+    may_block: bool,
+) -> Result<CommandOutcome, CommandError> {
+    check_arity(args, -5)?;
+    let deadline = parse_timeout(&args[1])?;
+    let (keys, end, count) = parse_mpop_args(args, 2)?;
+    let reply = pop_from_first(store, keys, end, count)?.map(|popped| mpop_reply(Some(popped)));
+    reply_or_block(
+        reply,
+        RESPDataType::NullArray,
+        args,
+        keys,
+        deadline,
+        may_block,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::test_helpers::{args, bulks};
+
+    #[test]
+    fn test_push_and_range() {
+        let mut store = Store::init();
+        assert_eq!(
+            handle_rpush(&args(&["RPUSH", "l", "b", "c"]), &mut store),
+            Ok(RESPDataType::Integer(2))
+        );
+        assert_eq!(
+            handle_lpush(&args(&["LPUSH", "l", "a"]), &mut store),
+            Ok(RESPDataType::Integer(3))
+        );
+        assert_eq!(
+            handle_lrange(&args(&["LRANGE", "l", "0", "-1"]), &mut store),
+            Ok(bulks(&["a", "b", "c"]))
+        );
+        assert_eq!(
+            handle_lrange(&args(&["LRANGE", "l", "-2", "10"]), &mut store),
+            Ok(bulks(&["b", "c"]))
+        );
+        assert_eq!(
+            handle_lpushx(&args(&["LPUSHX", "missing", "a"]), &mut store),
+            Ok(RESPDataType::Integer(0))
+        );
+        assert!(store.get_from_key_val_store(b"missing").is_none());
+    }
+
+    #[test]
+    fn test_pop_with_count_removes_empty_list() {
+        let mut store = Store::init();
+        handle_rpush(&args(&["RPUSH", "l", "a", "b", "c"]), &mut store).unwrap();
+        assert_eq!(
+            handle_rpop(&args(&["RPOP", "l", "2"]), &mut store),
+            Ok(bulks(&["c", "b"]))
+        );
+        assert_eq!(
+            handle_lpop(&args(&["LPOP", "l"]), &mut store),
+            Ok(RESPDataType::BulkString(Bytes::from("a")))
+        );
+        assert!(store.get_from_key_val_store(b"l").is_none());
+        assert_eq!(
+            handle_lpop(&args(&["LPOP", "l", "2"]), &mut store),
+            Ok(RESPDataType::NullArray)
+        );
+        assert_eq!(
+            handle_lpop(&args(&["LPOP", "l", "-1"]), &mut store),
+            Err(CommandError::OutOfRange)
+        );
+    }
+
+    #[test]
+    fn test_wrong_type() {
+        let mut store = Store::init();
+        store.set_key_val(Bytes::from("s"), Bytes::from("v"));
+        assert_eq!(
+            handle_lpush(&args(&["LPUSH", "s", "a"]), &mut store),
+            Err(CommandError::WrongType)
+        );
+    }
+
+    #[test]
+    fn test_lset_linsert_lrem_ltrim() {
+        let mut store = Store::init();
+        handle_rpush(&args(&["RPUSH", "l", "a", "x", "b", "x"]), &mut store).unwrap();
+        assert_eq!(
+            handle_lset(&args(&["LSET", "l", "-1", "y"]), &mut store),
+            Ok(ok())
+        );
+        assert_eq!(
+            handle_lset(&args(&["LSET", "l", "9", "y"]), &mut store),
+            Err(CommandError::IndexOutOfRange)
+        );
+        assert_eq!(
+            handle_linsert(&args(&["LINSERT", "l", "AFTER", "b", "c"]), &mut store),
+            Ok(RESPDataType::Integer(5))
+        );
+        assert_eq!(
+            handle_linsert(&args(&["LINSERT", "l", "BEFORE", "zz", "c"]), &mut store),
+            Ok(RESPDataType::Integer(-1))
+        );
+        assert_eq!(
+            handle_lrem(&args(&["LREM", "l", "0", "x"]), &mut store),
+            Ok(RESPDataType::Integer(1))
+        );
+        assert_eq!(
+            handle_lrange(&args(&["LRANGE", "l", "0", "-1"]), &mut store),
+            Ok(bulks(&["a", "b", "c", "y"]))
+        );
+        assert_eq!(
+            handle_ltrim(&args(&["LTRIM", "l", "1", "2"]), &mut store),
+            Ok(ok())
+        );
+        assert_eq!(
+            handle_lrange(&args(&["LRANGE", "l", "0", "-1"]), &mut store),
+            Ok(bulks(&["b", "c"]))
+        );
+        handle_ltrim(&args(&["LTRIM", "l", "5", "1"]), &mut store).unwrap();
+        assert!(store.get_from_key_val_store(b"l").is_none());
+    }
+
+    #[test]
+    fn test_lpos() {
+        let mut store = Store::init();
+        handle_rpush(
+            &args(&["RPUSH", "l", "a", "b", "c", "1", "2", "3", "c", "c"]),
+            &mut store,
+        )
+        .unwrap();
+        assert_eq!(
+            handle_lpos(&args(&["LPOS", "l", "c"]), &mut store),
+            Ok(RESPDataType::Integer(2))
+        );
+        assert_eq!(
+            handle_lpos(&args(&["LPOS", "l", "c", "RANK", "2"]), &mut store),
+            Ok(RESPDataType::Integer(6))
+        );
+        assert_eq!(
+            handle_lpos(&args(&["LPOS", "l", "c", "RANK", "-1"]), &mut store),
+            Ok(RESPDataType::Integer(7))
+        );
+        assert_eq!(
+            handle_lpos(&args(&["LPOS", "l", "c", "COUNT", "0"]), &mut store),
+            Ok(RESPDataType::Array(vec![
+                RESPDataType::Integer(2),
+                RESPDataType::Integer(6),
+                RESPDataType::Integer(7)
+            ]))
+        );
+        assert_eq!(
+            handle_lpos(
+                &args(&["LPOS", "l", "c", "COUNT", "0", "MAXLEN", "3"]),
+                &mut store
+            ),
+            Ok(RESPDataType::Array(vec![RESPDataType::Integer(2)]))
+        );
+        assert_eq!(
+            handle_lpos(&args(&["LPOS", "l", "z"]), &mut store),
+            Ok(RESPDataType::NullBulkString)
+        );
+        assert!(handle_lpos(&args(&["LPOS", "l", "c", "RANK", "0"]), &mut store).is_err());
+    }
+
+    #[test]
+    fn test_lmove_rotates_and_creates_destination() {
+        let mut store = Store::init();
+        handle_rpush(&args(&["RPUSH", "src", "a", "b"]), &mut store).unwrap();
+        assert_eq!(
+            handle_lmove(&args(&["LMOVE", "src", "src", "LEFT", "RIGHT"]), &mut store),
+            Ok(RESPDataType::BulkString(Bytes::from("a")))
+        );
+        assert_eq!(
+            handle_lmove(&args(&["LMOVE", "src", "dst", "RIGHT", "LEFT"]), &mut store),
+            Ok(RESPDataType::BulkString(Bytes::from("a")))
+        );
+        assert_eq!(
+            handle_lrange(&args(&["LRANGE", "dst", "0", "-1"]), &mut store),
+            Ok(bulks(&["a"]))
+        );
+        assert_eq!(
+            handle_lmove(&args(&["LMOVE", "none", "dst", "LEFT", "LEFT"]), &mut store),
+            Ok(RESPDataType::NullBulkString)
+        );
+    }
+
+    #[test]
+    fn test_lmpop() {
+        let mut store = Store::init();
+        handle_rpush(&args(&["RPUSH", "b", "1", "2", "3"]), &mut store).unwrap();
+        assert_eq!(
+            handle_lmpop(
+                &args(&["LMPOP", "2", "a", "b", "RIGHT", "COUNT", "2"]),
+                &mut store
+            ),
+            Ok(RESPDataType::Array(vec![
+                RESPDataType::BulkString(Bytes::from("b")),
+                bulks(&["3", "2"])
+            ]))
+        );
+        assert_eq!(
+            handle_lmpop(&args(&["LMPOP", "1", "a", "LEFT"]), &mut store),
+            Ok(RESPDataType::NullArray)
+        );
+        assert!(handle_lmpop(&args(&["LMPOP", "0", "a", "LEFT"]), &mut store).is_err());
+        assert_eq!(
+            handle_lmpop(&args(&["LMPOP", "2", "a", "LEFT"]), &mut store),
+            Err(CommandError::Syntax)
+        );
+    }
+
+    #[test]
+    fn test_blocking_pop_blocks_only_when_allowed() {
+        let mut store = Store::init();
+        let blpop = args(&["BLPOP", "a", "b", "0"]);
+        match handle_blpop(&blpop, &mut store, true) {
+            Ok(CommandOutcome::Block(request)) => {
+                assert_eq!(request.keys, args(&["a", "b"]));
+                assert!(request.deadline.is_none());
+            }
+            _ => panic!("expected BLPOP to block"),
+        }
+        match handle_blpop(&blpop, &mut store, false) {
+            Ok(CommandOutcome::Reply(reply)) => assert_eq!(reply, RESPDataType::NullArray),
+            _ => panic!("expected BLPOP not to block"),
+        }
+        handle_rpush(&args(&["RPUSH", "b", "x"]), &mut store).unwrap();
+        match handle_blpop(&blpop, &mut store, true) {
+            Ok(CommandOutcome::Reply(reply)) => assert_eq!(reply, bulks(&["b", "x"])),
+            _ => panic!("expected BLPOP to be served"),
+        }
+        assert!(handle_blpop(&args(&["BLPOP", "a", "-1"]), &mut store, true).is_err());
+    }
+}
@@ -0,0 +1,313 @@
+use bytes::Bytes;
+
+use super::{
+    check_arity, check_subcommand_arity, format_double, help_reply, is_keyword, ok, parse_int,
+    unknown_subcommand, CommandError, CommandResult,
+};
+use crate::client;
+use crate::memory;
+use crate::resp::data::RESPDataType;
+use crate::store::Store;
+
+/// Below this much memory there is not enough data for MEMORY DOCTOR to judge.
+const DOCTOR_MIN_MEMORY: usize = 5 * 1024 * 1024;
+/// Peak memory past this ratio of the current use is worth reporting.
+const DOCTOR_PEAK_RATIO: f64 = 1.5;
+/// Resident memory past this ratio of the allocated memory is worth reporting.
+const DOCTOR_FRAGMENTATION_RATIO: f64 = 1.4;
+/// Average client buffers larger than this are worth reporting.
+const DOCTOR_BIG_CLIENT_BUFFERS: usize = 200 * 1024;
+
+const MEMORY_HELP: &[&str] = &[
+    "MEMORY <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+    "DOCTOR",
+    "    Return memory problems reports.",
+    "MALLOC-STATS",
+    "    Return internal statistics report from the memory allocator.",
+    "PURGE",
+    "    Attempt to purge dirty pages for reclamation by the allocator.",
+    "STATS",
+    "    Return information about the memory usage of the server.",
+    "USAGE <key> [SAMPLES <count>]",
+    "    Return memory in bytes used by <key> and its value. Nested values are",
+    "    sampled up to <count> times (default: 5, 0 means sample all).",
+    "HELP",
+    "    Print this help.",
+];
+
+/// A breakdown of where the memory of the server goes.
+struct MemoryStats {
+    peak: usize,
+    total: usize,
+    startup: usize,
+    clients: usize,
+    /// Keyspace and expires table sizes of each database holding keys.
+    keyspaces: Vec<(usize, usize, usize)>,
+    overhead: usize,
+    keys: usize,
+    dataset: usize,
+    resident: Option<usize>,
+}
+
+impl MemoryStats {
+    fn collect(store: &Store) -> MemoryStats {
+        let total = memory::used_memory();
+        let peak = memory::update_peak_memory();
+        let startup = memory::startup_memory();
+        let clients = client::client_buffers_memory();
+        let keyspaces = store.keyspace_overheads();
+        let overhead = startup
+            + clients
+            + keyspaces
+                .iter()
+                .map(|(_, main, expires)| main + expires)
+                .sum::<usize>();
+        MemoryStats {
+            peak,
+            total,
+            startup,
+            clients,
+            keyspaces,
+            overhead,
+            keys: store.key_count(),
+            dataset: total.saturating_sub(overhead),
+            resident: memory::resident_memory(),
+        }
+    }
+
+    fn fragmentation(&self) -> Option<f64> {
+        self.resident
+            .map(|resident| resident as f64 / self.total.max(1) as f64)
+    }
+}
+
+/// MEMORY USAGE|STATS|DOCTOR|PURGE|MALLOC-STATS|HELP ...
+pub fn handle_memory(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, -2)?;
+    let subcommand = args[1].to_ascii_uppercase();
+    match subcommand.as_slice() {
+        b"HELP" => Ok(help_reply(MEMORY_HELP)),
+        b"USAGE" => memory_usage(args, store),
+        b"STATS" | b"DOCTOR" | b"PURGE" | b"MALLOC-STATS" => {
+            check_subcommand_arity(args, 2)?;
+            Ok(match subcommand.as_slice() {
+                b"STATS" => memory_stats(&MemoryStats::collect(store)),
+                b"DOCTOR" => memory_doctor(&MemoryStats::collect(store)),
+                b"PURGE" => {
+                    memory::purge();
+                    ok()
+                }
+                _ => RESPDataType::BulkString(Bytes::from(
+                    "Stats not supported for the current allocator",
+                )),
+            })
+        }
+        _ => Err(unknown_subcommand(args)),
+    }
+}
+
+/// MEMORY USAGE key [SAMPLES count]
+fn memory_usage(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_subcommand_arity(args, -3)?;
+    let mut samples = memory::DEFAULT_USAGE_SAMPLES;
+    let mut options = args[3..].iter();
+    while let Some(option) = options.next() {
+        let value = options.next().ok_or(CommandError::Syntax)?;
+        if !is_keyword(option, "SAMPLES") {
+            return Err(CommandError::Syntax);
+        }
+        samples = usize::try_from(parse_int(value)?).map_err(|_| CommandError::Syntax)?;
+    }
+    Ok(match store.memory_usage(&args[2], samples) {
+        Some(bytes) => RESPDataType::Integer(bytes as i64),
+        None => RESPDataType::NullBulkString,
+    })
+}
+
+fn memory_stats(stats: &MemoryStats) -> RESPDataType {
+    let integer = |value: usize| RESPDataType::Integer(value as i64);
+    let double = |value: f64| RESPDataType::BulkString(format_double(value));
+    let percentage = |part: usize, whole: usize| double(part as f64 * 100.0 / whole.max(1) as f64);
+    let dataset_share = stats.total.saturating_sub(stats.startup);
+
+    let mut fields = vec![
+        ("peak.allocated", integer(stats.peak)),
+        ("total.allocated", integer(stats.total)),
+        ("startup.allocated", integer(stats.startup)),
+        ("clients.normal", integer(stats.clients)),
+    ];
+    let db_names: Vec<String> = stats
+        .keyspaces
+        .iter()
+        .map(|(index, _, _)| format!("db.{}", index))
+        .collect();
+    for ((_, main, expires), name) in stats.keyspaces.iter().zip(&db_names) {
+        let overheads = RESPDataType::Array(vec![
+            RESPDataType::BulkString(Bytes::from("overhead.hashtable.main")),
+            integer(*main),
+            RESPDataType::BulkString(Bytes::from("overhead.hashtable.expires")),
+            integer(*expires),
+        ]);
+        fields.push((name, overheads));
+    }
+    fields.extend([
+        ("overhead.total", integer(stats.overhead)),
+        ("keys.count", integer(stats.keys)),
+        (
+            "keys.bytes-per-key",
+            integer(dataset_share.checked_div(stats.keys).unwrap_or(0)),
+        ),
+        ("dataset.bytes", integer(stats.dataset)),
+        (
+            "dataset.percentage",
+            percentage(stats.dataset, dataset_share),
+        ),
+        ("peak.percentage", percentage(stats.total, stats.peak)),
+    ]);
+    if let (Some(resident), Some(fragmentation)) = (stats.resident, stats.fragmentation()) {
+        fields.extend([
+            ("fragmentation", double(fragmentation)),
+            (
+                "fragmentation.bytes",
+                RESPDataType::Integer(resident as i64 - stats.total as i64),
+            ),
+        ]);
+    }
+    RESPDataType::Array(
+        fields
+            .into_iter()
+            .flat_map(|(name, value)| {
+                [
+                    RESPDataType::BulkString(Bytes::copy_from_slice(name.as_bytes())),
+                    value,
+                ]
+            })
+            .collect(),
+    )
+}
+
+fn memory_doctor(stats: &MemoryStats) -> RESPDataType {
+    let report = if stats.total < DOCTOR_MIN_MEMORY {
+        String::from(
+            "This instance is empty or is using very little memory, there is not enough \
+             data to detect memory issues. Fill it with some data and ask again.\n",
+        )
+    } else {
+        let mut issues = Vec::new();
+        if stats.peak as f64 > stats.total as f64 * DOCTOR_PEAK_RATIO {
+            issues.push(
+                " * Peak memory: In the past this instance used more than 150% the memory \
+                 that is currently using. The allocator is normally not able to release \
+                 memory after a peak, so you can expect a large fragmentation ratio, which \
+                 is harmless: the memory is used again as the dataset grows. Try MEMORY \
+                 PURGE to reclaim it sooner.\n",
+            );
+        }
+        if stats
+            .fragmentation()
+            .is_some_and(|ratio| ratio > DOCTOR_FRAGMENTATION_RATIO)
+        {
+            issues.push(
+                " * High fragmentation: The resident set size of the process is more than \
+                 1.4 times the memory it allocated. This is usually due to a memory peak \
+                 (see above), or to a workload that fragments memory a lot. MEMORY PURGE \
+                 may give some of it back to the system.\n",
+            );
+        }
+        let clients = client::connected_clients();
+        if clients > 0 && stats.clients / clients > DOCTOR_BIG_CLIENT_BUFFERS {
+            issues.push(
+                " * Big client buffers: The clients buffers in this instance are greater \
+                 than 200K per client on average. This happens when clients send very \
+                 large pipelines or read large replies slower than they are produced.\n",
+            );
+        }
+        if issues.is_empty() {
+            String::from("I can't find any memory issue in this instance.\n")
+        } else {
+            format!(
+                "I detected a few issues in the memory of this instance:\n\n{}",
+                issues.join("\n")
+            )
+        }
+    };
+    RESPDataType::BulkString(Bytes::from(report))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::list::handle_rpush;
+    use crate::commands::test_helpers::args;
+
+    fn usage(store: &mut Store, parts: &[&str]) -> i64 {
+        match handle_memory(&args(parts), store) {
+            Ok(RESPDataType::Integer(bytes)) => bytes,
+            reply => panic!("unexpected reply {:?}", reply),
+        }
+    }
+
+    #[test]
+    fn test_memory_usage() {
+        let mut store = Store::init();
+        store.set_key_val(Bytes::from("small"), Bytes::from("v"));
+        store.set_key_val(Bytes::from("large"), Bytes::from("v".repeat(1000)));
+        let small = usage(&mut store, &["MEMORY", "USAGE", "small"]);
+        let large = usage(&mut store, &["MEMORY", "USAGE", "large"]);
+        assert_eq!(large - small, 999);
+
+        // Sampling extrapolates from the first elements.
+        let mut push = args(&["RPUSH", "list"]);
+        push.extend((0..100).map(|_| Bytes::from("short")));
+        push.push(Bytes::from("x".repeat(10_000)));
+        handle_rpush(&push, &mut store).unwrap();
+        let sampled = usage(&mut store, &["MEMORY", "USAGE", "list"]);
+        let exact = usage(&mut store, &["MEMORY", "USAGE", "list", "SAMPLES", "0"]);
+        assert!(exact > sampled + 9000);
+
+        assert_eq!(
+            handle_memory(&args(&["MEMORY", "USAGE", "none"]), &mut store),
+            Ok(RESPDataType::NullBulkString)
+        );
+        for parts in [
+            &["MEMORY", "USAGE", "list", "SAMPLES", "-1"][..],
+            &["MEMORY", "USAGE", "list", "SAMPLES"],
+            &["MEMORY", "USAGE", "list", "COUNT", "1"],
+        ] {
+            assert_eq!(
+                handle_memory(&args(parts), &mut store),
+                Err(CommandError::Syntax)
+            );
+        }
+    }
+
+    #[test]
+    fn test_memory_stats_and_doctor() {
+        let mut store = Store::init();
+        store.set_key_val(Bytes::from("key"), Bytes::from("value"));
+        let Ok(RESPDataType::Array(stats)) = handle_memory(&args(&["MEMORY", "STATS"]), &mut store)
+        else {
+            panic!("MEMORY STATS should reply with an array");
+        };
+        let names: Vec<&RESPDataType> = stats.iter().step_by(2).collect();
+        for name in ["total.allocated", "db.0", "overhead.total", "dataset.bytes"] {
+            assert!(names.contains(&&RESPDataType::BulkString(Bytes::from(name))));
+        }
+        let keys = stats
+            .iter()
+            .position(|field| *field == RESPDataType::BulkString(Bytes::from("keys.count")))
+            .unwrap();
+        assert_eq!(stats[keys + 1], RESPDataType::Integer(1));
+
+        assert!(matches!(
+            handle_memory(&args(&["MEMORY", "DOCTOR"]), &mut store),
+            Ok(RESPDataType::BulkString(_))
+        ));
+        assert_eq!(
+            handle_memory(&args(&["MEMORY", "PURGE"]), &mut store),
+            Ok(ok())
+        );
+        assert!(handle_memory(&args(&["MEMORY", "STATS", "extra"]), &mut store).is_err());
+        assert!(handle_memory(&args(&["MEMORY", "NOPE"]), &mut store).is_err());
+    }
+}
@@ -0,0 +1,435 @@
+pub mod geo;
+pub mod hash;
+pub mod hyperloglog;
+pub mod keyspace;
+pub mod list;
+pub mod memory;
+pub mod persistence;
+pub mod set;
+pub mod sort;
+pub mod stream;
+pub mod zset;
+
+use std::fmt;
+
+use bytes::Bytes;
+
+use crate::resp::data::RESPDataType;
+use crate::util::{random_index, string_match};
+
+/// Errors raised while executing a command, rendered as RESP errors.
+#[derive(Debug, PartialEq, Eq)]
+pub enum CommandError {
+    WrongType,
+    WrongNumberOfArguments(String),
+    NotInteger,
+    NotFloat,
+    OutOfRange,
+    IndexOutOfRange,
+    NoSuchKey,
+    Syntax,
+    Custom(String),
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommandError::WrongType => write!(
+                f,
+                "WRONGTYPE Operation against a key holding the wrong kind of value"
+            ),
+            CommandError::WrongNumberOfArguments(command) => {
+                write!(f, "ERR wrong number of arguments for '{}' command", command)
+            }
+            CommandError::NotInteger => write!(f, "ERR value is not an integer or out of range"),
+            CommandError::NotFloat => write!(f, "ERR value is not a valid float"),
+            CommandError::OutOfRange => write!(f, "ERR value is out of range, must be positive"),
+            CommandError::IndexOutOfRange => write!(f, "ERR index out of range"),
+            CommandError::NoSuchKey => write!(f, "ERR no such key"),
+            CommandError::Syntax => write!(f, "ERR syntax error"),
+            CommandError::Custom(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl From<CommandError> for RESPDataType {
+    fn from(error: CommandError) -> Self {
+        RESPDataType::Error(Bytes::from(error.to_string()))
+    }
+}
+
+pub type CommandResult = Result<RESPDataType, CommandError>;
+
+/// Check the argument count against a redis style arity: a positive arity is an
+/// exact count, a negative one is a minimum. Counts include the command name.
+pub fn check_arity(args: &[Bytes], arity: i64) -> Result<(), CommandError> {
+    let count = args.len() as i64;
+    if (arity >= 0 && count != arity) || (arity < 0 && count < -arity) {
+        return Err(wrong_number_of_arguments(args));
+    }
+    Ok(())
+}
+
+pub fn wrong_number_of_arguments(args: &[Bytes]) -> CommandError {
+    CommandError::WrongNumberOfArguments(String::from_utf8_lossy(&args[0]).to_lowercase())
+}
+
+/// Parse a strictly formatted signed 64 bit integer, as redis' `string2ll` does.
+pub fn parse_int(arg: &[u8]) -> Result<i64, CommandError> {
+    let valid = match arg {
+        [] => false,
+        [b'0'] => true,
+        [b'-', b'1'..=b'9', rest @ ..] | [b'1'..=b'9', rest @ ..] => {
+            rest.iter().all(u8::is_ascii_digit)
+        }
+        _ => false,
+    };
+    if !valid {
+        return Err(CommandError::NotInteger);
+    }
+    std::str::from_utf8(arg)
+        .ok()
+        .and_then(|s| s.parse::<i64>().ok())
+        .ok_or(CommandError::NotInteger)
+}
+
+/// Parse a float argument, accepting `inf` and `-inf` but rejecting NaN.
+pub fn parse_float(arg: &[u8]) -> Result<f64, CommandError> {
+    std::str::from_utf8(arg)
+        .ok()
+        .and_then(|s| s.parse::<f64>().ok())
+        .filter(|value| !value.is_nan())
+        .ok_or(CommandError::NotFloat)
+}
+
+/// Most elements a negative count makes HRANDFIELD, SRANDMEMBER and
+/// ZRANDMEMBER reply with. Redis streams such replies while ours are built in
+/// memory first, so larger counts are capped rather than exhausting it.
+pub const RANDOM_REPLY_MAX: u64 = 1 << 20;
+
+/// Parse the count of HRANDFIELD, SRANDMEMBER or ZRANDMEMBER, refusing the
+/// ones whose reply length would overflow, as redis does.
+pub fn parse_random_count(arg: &[u8]) -> Result<i64, CommandError> {
+    let count = parse_int(arg)?;
+    if !(-i64::MAX / 2..=i64::MAX / 2).contains(&count) {
+        return Err(CommandError::Custom(String::from(
+            "ERR value is out of range",
+        )));
+    }
+    Ok(count)
+}
+
+/// The indexes in `0..len` to reply with for a negative `count`: picked one
+/// at a time, repeats allowed, and at most `RANDOM_REPLY_MAX` of them.
+pub fn random_repeated_indexes(len: usize, count: i64) -> impl Iterator<Item = usize> {
+    (0..count.unsigned_abs().min(RANDOM_REPLY_MAX)).map(move |_| random_index(len))
+}
+
+/// Case insensitive comparison of an argument against a keyword.
+pub fn is_keyword(arg: &[u8], keyword: &str) -> bool {
+    arg.eq_ignore_ascii_case(keyword.as_bytes())
+}
+
+/// Resolve a possibly negative redis index against a collection length.
+pub fn normalize_index(index: i64, len: usize) -> Option<usize> {
+    let index = if index < 0 { len as i64 + index } else { index };
+    if index < 0 || index >= len as i64 {
+        None
+    } else {
+        Some(index as usize)
+    }
+}
+
+/// Resolve a redis style inclusive `start..=stop` range, returning None when it is empty.
+pub fn normalize_range(start: i64, stop: i64, len: usize) -> Option<(usize, usize)> {
+    let len = len as i64;
+    let start = if start < 0 {
+        (len + start).max(0)
+    } else {
+        start
+    };
+    let stop = if stop < 0 {
+        len + stop
+    } else {
+        stop.min(len - 1)
+    };
+    if start > stop || start >= len {
+        None
+    } else {
+        Some((start as usize, stop as usize))
+    }
+}
+
+/// Format a double the way redis replies with one: the shortest representation
+/// that parses back to the same value, in `%g` style exponent notation when the
+/// decimal exponent is below -4 or at least 17.
+pub fn format_double(value: f64) -> Bytes {
+    if value.is_infinite() {
+        return Bytes::from(if value > 0.0 { "inf" } else { "-inf" });
+    }
+    let scientific = format!("{:e}", value);
+    let (mantissa, exponent) = scientific.split_once('e').unwrap();
+    let exponent: i32 = exponent.parse().unwrap();
+    if value == 0.0 || (-4..17).contains(&exponent) {
+        return Bytes::from(value.to_string());
+    }
+    let sign = if exponent < 0 { '-' } else { '+' };
+    Bytes::from(format!("{}e{}{:02}", mantissa, sign, exponent.abs()))
+}
+
+/// Condition under which EXPIRE, HEXPIRE and friends update an expiration time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExpireCondition {
+    Always,
+    /// Only set when the key or field has no expiration time.
+    Nx,
+    /// Only set when the key or field already has an expiration time.
+    Xx,
+    /// Only set when the new expiration time is later than the current one.
+    Gt,
+    /// Only set when the new expiration time is earlier than the current one.
+    Lt,
+}
+
+impl ExpireCondition {
+    pub fn allows(&self, current: Option<i64>, when: i64) -> bool {
+        match self {
+            ExpireCondition::Always => true,
+            ExpireCondition::Nx => current.is_none(),
+            ExpireCondition::Xx => current.is_some(),
+            ExpireCondition::Gt => current.is_some_and(|current| when > current),
+            ExpireCondition::Lt => current.is_none_or(|current| when < current),
+        }
+    }
+}
+
+/// What a command of the SCAN family iterates over, which decides the options
+/// it accepts on top of MATCH and COUNT.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScanTarget {
+    /// SCAN, which accepts TYPE.
+    Keyspace,
+    /// HSCAN, which accepts NOVALUES.
+    Hash,
+    Set,
+    /// ZSCAN, which accepts NOSCORES.
+    ZSet,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScanOptions {
+    pub cursor: u64,
+    pub count: usize,
+    pub pattern: Option<Bytes>,
+    pub type_name: Option<Bytes>,
+    /// Reply with the fields or members only (NOVALUES and NOSCORES).
+    pub no_values: bool,
+}
+
+impl ScanOptions {
+    /// Whether `element` satisfies the MATCH pattern, if any.
+    pub fn matches(&self, element: &[u8]) -> bool {
+        self.pattern
+            .as_ref()
+            .is_none_or(|pattern| string_match(pattern, element, false))
+    }
+}
+
+/// Parse the cursor at `args[cursor_index]` and the options following it.
+pub fn parse_scan_options(
+    args: &[Bytes],
+    cursor_index: usize,
+    target: ScanTarget,
+) -> Result<ScanOptions, CommandError> {
+    let cursor = std::str::from_utf8(&args[cursor_index])
+        .ok()
+        .and_then(|cursor| cursor.parse::<u64>().ok())
+        .ok_or_else(|| CommandError::Custom(String::from("ERR invalid cursor")))?;
+    let mut options = ScanOptions {
+        cursor,
+        count: 10,
+        pattern: None,
+        type_name: None,
+        no_values: false,
+    };
+    let mut i = cursor_index + 1;
+    while i < args.len() {
+        let has_value = i + 1 < args.len();
+        if is_keyword(&args[i], "COUNT") && has_value {
+            let count = parse_int(&args[i + 1])?;
+            if count < 1 {
+                return Err(CommandError::Syntax);
+            }
+            options.count = count as usize;
+            i += 2;
+        } else if is_keyword(&args[i], "MATCH") && has_value {
+            // A lone star matches everything, skip matching altogether.
+            options.pattern = Some(args[i + 1].clone()).filter(|pattern| pattern != "*");
+            i += 2;
+        } else if is_keyword(&args[i], "TYPE") && has_value && target == ScanTarget::Keyspace {
+            options.type_name = Some(args[i + 1].clone());
+            i += 2;
+        } else if (is_keyword(&args[i], "NOVALUES") && target == ScanTarget::Hash)
+            || (is_keyword(&args[i], "NOSCORES") && target == ScanTarget::ZSet)
+        {
+            options.no_values = true;
+            i += 1;
+        } else {
+            return Err(CommandError::Syntax);
+        }
+    }
+    Ok(options)
+}
+
+/// The reply of the SCAN family: the next cursor and a batch of elements.
+pub fn scan_reply(cursor: u64, elements: Vec<Bytes>) -> RESPDataType {
+    RESPDataType::Array(vec![
+        RESPDataType::BulkString(Bytes::from(cursor.to_string())),
+        RESPDataType::Array(elements.into_iter().map(RESPDataType::BulkString).collect()),
+    ])
+}
+
+pub fn ok() -> RESPDataType {
+    RESPDataType::SimpleString(Bytes::from("OK"))
+}
+
+pub fn bulk_array<T: AsRef<[u8]>>(values: impl IntoIterator<Item = T>) -> RESPDataType {
+    RESPDataType::Array(
+        values
+            .into_iter()
+            .map(|value| RESPDataType::BulkString(Bytes::copy_from_slice(value.as_ref())))
+            .collect(),
+    )
+}
+
+/// The reply of a HELP subcommand.
+pub fn help_reply(lines: &[&str]) -> RESPDataType {
+    RESPDataType::Array(
+        lines
+            .iter()
+            .map(|line| RESPDataType::SimpleString(Bytes::copy_from_slice(line.as_bytes())))
+            .collect(),
+    )
+}
+
+pub fn unknown_subcommand(args: &[Bytes]) -> CommandError {
+    CommandError::Custom(format!(
+        "ERR unknown subcommand '{}'. Try {} HELP.",
+        String::from_utf8_lossy(&args[1]),
+        String::from_utf8_lossy(&args[0]).to_ascii_uppercase()
+    ))
+}
+
+/// Check the arity of a subcommand, reported as `command|subcommand`.
+pub fn check_subcommand_arity(args: &[Bytes], arity: i64) -> Result<(), CommandError> {
+    check_arity(args, arity).map_err(|_| {
+        CommandError::WrongNumberOfArguments(format!(
+            "{}|{}",
+            String::from_utf8_lossy(&args[0]).to_ascii_lowercase(),
+            String::from_utf8_lossy(&args[1]).to_ascii_lowercase()
+        ))
+    })
+}
+
+/// Fixtures shared by the tests of the command modules.
+#[cfg(test)]
+pub mod test_helpers {
+    use bytes::Bytes;
+
+    use crate::resp::data::RESPDataType;
+
+    /// The arguments of a command, given as strings.
+    pub fn args(parts: &[&str]) -> Vec<Bytes> {
+        parts
+            .iter()
+            .map(|part| Bytes::copy_from_slice(part.as_bytes()))
+            .collect()
+    }
+
+    pub fn bulk(value: &str) -> RESPDataType {
+        RESPDataType::BulkString(Bytes::copy_from_slice(value.as_bytes()))
+    }
+
+    pub fn bulks(values: &[&str]) -> RESPDataType {
+        RESPDataType::Array(values.iter().map(|value| bulk(value)).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_int() {
+        assert_eq!(parse_int(b"0"), Ok(0));
+        assert_eq!(parse_int(b"-15"), Ok(-15));
+        assert_eq!(parse_int(b"9223372036854775807"), Ok(i64::MAX));
+        assert_eq!(parse_int(b"+1"), Err(CommandError::NotInteger));
+        assert_eq!(parse_int(b"01"), Err(CommandError::NotInteger));
+        assert_eq!(parse_int(b" 1"), Err(CommandError::NotInteger));
+        assert_eq!(parse_int(b"-0"), Err(CommandError::NotInteger));
+        assert_eq!(
+            parse_int(b"9223372036854775808"),
+            Err(CommandError::NotInteger)
+        );
+    }
+
+    #[test]
+    fn test_normalize_range() {
+        assert_eq!(normalize_range(0, -1, 5), Some((0, 4)));
+        assert_eq!(normalize_range(-100, 100, 5), Some((0, 4)));
+        assert_eq!(normalize_range(3, 1, 5), None);
+        assert_eq!(normalize_range(5, 10, 5), None);
+        assert_eq!(normalize_range(0, -1, 0), None);
+    }
+
+    #[test]
+    fn test_check_arity() {
+        let args = vec![Bytes::from("LPUSH"), Bytes::from("key")];
+        assert!(check_arity(&args, 2).is_ok());
+        assert!(check_arity(&args, -2).is_ok());
+        assert_eq!(
+            check_arity(&args, -3),
+            Err(CommandError::WrongNumberOfArguments(String::from("lpush")))
+        );
+    }
+
+    #[test]
+    fn test_parse_scan_options() {
+        let args: Vec<Bytes> = [
+            "HSCAN", "key", "12", "match", "a*", "COUNT", "5", "NOVALUES",
+        ]
+        .into_iter()
+        .map(Bytes::from)
+        .collect();
+        let options = parse_scan_options(&args, 2, ScanTarget::Hash).unwrap();
+        assert_eq!(options.cursor, 12);
+        assert_eq!(options.count, 5);
+        assert!(options.matches(b"abc") && !options.matches(b"b"));
+        assert!(options.no_values);
+        assert_eq!(
+            parse_scan_options(&args, 2, ScanTarget::Set),
+            Err(CommandError::Syntax)
+        );
+        assert_eq!(
+            parse_scan_options(&args, 3, ScanTarget::Hash),
+            Err(CommandError::Custom(String::from("ERR invalid cursor")))
+        );
+        assert_eq!(
+            parse_scan_options(&args[..6], 2, ScanTarget::Hash),
+            Err(CommandError::Syntax)
+        );
+    }
+
+    #[test]
+    fn test_format_double() {
+        assert_eq!(format_double(1.5), Bytes::from("1.5"));
+        assert_eq!(format_double(-3.0), Bytes::from("-3"));
+        assert_eq!(format_double(0.1 + 0.2), Bytes::from("0.30000000000000004"));
+        assert_eq!(format_double(0.0001), Bytes::from("0.0001"));
+        assert_eq!(format_double(0.00001), Bytes::from("1e-05"));
+        assert_eq!(format_double(1e20), Bytes::from("1e+20"));
+        assert_eq!(format_double(1.25e100), Bytes::from("1.25e+100"));
+        assert_eq!(format_double(f64::NEG_INFINITY), Bytes::from("-inf"));
+    }
+}
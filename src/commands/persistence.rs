@@ -0,0 +1,184 @@
+use bytes::Bytes;
+
+use super::{check_arity, is_keyword, ok, CommandError, CommandResult};
+use crate::resp::data::RESPDataType;
+use crate::store::Store;
+
+fn save_in_progress() -> CommandError {
+    CommandError::Custom(String::from("ERR Background save already in progress"))
+}
+
+/// SAVE
+pub fn handle_save(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, 1)?;
+    if store.persistence.is_saving() {
+        return Err(save_in_progress());
+    }
+    let snapshot = store.snapshot();
+    store
+        .persistence
+        .save(&snapshot)
+        .map_err(|_| CommandError::Custom(String::from("ERR")))?;
+    Ok(ok())
+}
+
+/// BGSAVE [SCHEDULE]
+pub fn handle_bgsave(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, -1)?;
+    let schedule = match args.len() {
+        1 => false,
+        2 if is_keyword(&args[1], "SCHEDULE") => true,
+        _ => return Err(CommandError::Syntax),
+    };
+    if store.persistence.is_saving() {
+        if !schedule {
+            return Err(save_in_progress());
+        }
+        store.persistence.schedule_background_save();
+        return Ok(RESPDataType::SimpleString(Bytes::from(
+            "Background saving scheduled",
+        )));
+    }
+    let snapshot = store.snapshot();
+    store
+        .persistence
+        .background_save(snapshot)
+        .map_err(|_| CommandError::Custom(String::from("ERR")))?;
+    Ok(RESPDataType::SimpleString(Bytes::from(
+        "Background saving started",
+    )))
+}
+
+/// LASTSAVE
+pub fn handle_lastsave(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, 1)?;
+    Ok(RESPDataType::Integer(store.persistence.last_save()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, process, thread};
+
+    use super::*;
+    use crate::commands::test_helpers::args;
+    use crate::config::Config;
+    use crate::rdb::file;
+
+    #[test]
+    fn test_save_and_bgsave() {
+        let dir = std::env::temp_dir().join(format!("save-test-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut config = Config::default();
+        config.persistence.dir = dir.clone();
+        let path = config.persistence.path();
+        let mut store = Store::new(&config);
+        store.set_key_val(Bytes::from("key"), Bytes::from("before"));
+        assert!(store.persistence.dirty > 0);
+
+        assert_eq!(handle_save(&args(&["SAVE"]), &mut store), Ok(ok()));
+        assert_eq!(store.persistence.dirty, 0);
+        assert!(matches!(
+            handle_lastsave(&args(&["LASTSAVE"]), &mut store),
+            Ok(RESPDataType::Integer(time)) if time > 0
+        ));
+
+        // The background save writes the keyspace as it was when started.
+        assert_eq!(
+            handle_bgsave(&args(&["BGSAVE"]), &mut store),
+            Ok(RESPDataType::SimpleString(Bytes::from(
+                "Background saving started"
+            )))
+        );
+        store.set_key_val(Bytes::from("key"), Bytes::from("after"));
+        assert_eq!(
+            handle_save(&args(&["SAVE"]), &mut store),
+            Err(save_in_progress())
+        );
+        assert_eq!(
+            handle_bgsave(&args(&["BGSAVE"]), &mut store),
+            Err(save_in_progress())
+        );
+        assert_eq!(
+            handle_bgsave(&args(&["BGSAVE", "SCHEDULE"]), &mut store),
+            Ok(RESPDataType::SimpleString(Bytes::from(
+                "Background saving scheduled"
+            )))
+        );
+        while store.persistence.is_saving() {
+            if store.persistence.cron() {
+                let snapshot = store.snapshot();
+                store.persistence.background_save(snapshot).unwrap();
+                break;
+            }
+            thread::yield_now();
+        }
+        while store.persistence.is_saving() {
+            store.persistence.cron();
+            thread::yield_now();
+        }
+        let snapshot = file::load(&path, config.databases).unwrap().unwrap();
+        assert!(matches!(
+            &*snapshot[0][0].value,
+            crate::store::Value::String(value) if value == "after"
+        ));
+
+        let mut restarted = Store::new(&config);
+        restarted.load_snapshot(snapshot);
+        assert_eq!(
+            restarted.peek(b"key").map(|value| value.type_name()),
+            Some("string")
+        );
+        assert_eq!(
+            handle_bgsave(&args(&["BGSAVE", "NOW"]), &mut store),
+            Err(CommandError::Syntax)
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_no_op_writes_neither_count_nor_copy() {
+        use crate::commands::{hash, hyperloglog, list, set, stream, zset};
+
+        let mut store = Store::init();
+        list::handle_rpush(&args(&["RPUSH", "list", "a", "b"]), &mut store).unwrap();
+        set::handle_sadd(&args(&["SADD", "set", "a"]), &mut store).unwrap();
+        hash::handle_hset(&args(&["HSET", "hash", "f", "v"]), &mut store).unwrap();
+        zset::handle_zadd(&args(&["ZADD", "zset", "1", "a"]), &mut store).unwrap();
+        stream::handle_xadd(&args(&["XADD", "stream", "1-1", "f", "v"]), &mut store).unwrap();
+        stream::handle_xgroup(&args(&["XGROUP", "CREATE", "stream", "g", "0"]), &mut store)
+            .unwrap();
+        hyperloglog::handle_pfadd(&args(&["PFADD", "hll", "a"]), &mut store).unwrap();
+        let snapshot = store.snapshot();
+        let dirty = store.persistence.dirty;
+
+        list::handle_lrem(&args(&["LREM", "list", "0", "x"]), &mut store).unwrap();
+        list::handle_linsert(&args(&["LINSERT", "list", "BEFORE", "x", "y"]), &mut store).unwrap();
+        list::handle_ltrim(&args(&["LTRIM", "list", "0", "-1"]), &mut store).unwrap();
+        set::handle_sadd(&args(&["SADD", "set", "a"]), &mut store).unwrap();
+        set::handle_srem(&args(&["SREM", "set", "x"]), &mut store).unwrap();
+        hash::handle_hsetnx(&args(&["HSETNX", "hash", "f", "w"]), &mut store).unwrap();
+        hash::handle_hdel(&args(&["HDEL", "hash", "x"]), &mut store).unwrap();
+        hash::handle_hpersist(&args(&["HPERSIST", "hash", "FIELDS", "1", "f"]), &mut store)
+            .unwrap();
+        zset::handle_zadd(&args(&["ZADD", "zset", "1", "a"]), &mut store).unwrap();
+        zset::handle_zadd(&args(&["ZADD", "zset", "NX", "2", "a"]), &mut store).unwrap();
+        zset::handle_zrem(&args(&["ZREM", "zset", "x"]), &mut store).unwrap();
+        stream::handle_xdel(&args(&["XDEL", "stream", "9-9"]), &mut store).unwrap();
+        stream::handle_xack(&args(&["XACK", "stream", "g", "1-1"]), &mut store).unwrap();
+        stream::handle_xtrim(&args(&["XTRIM", "stream", "MAXLEN", "5"]), &mut store).unwrap();
+        hyperloglog::handle_pfadd(&args(&["PFADD", "hll", "a"]), &mut store).unwrap();
+
+        assert_eq!(store.persistence.dirty, dirty);
+        for entry in &snapshot[0] {
+            assert!(
+                std::ptr::eq(store.peek(&entry.key).unwrap(), &*entry.value),
+                "{:?} was copied",
+                entry.key
+            );
+        }
+
+        set::handle_sadd(&args(&["SADD", "set", "a", "b", "c"]), &mut store).unwrap();
+        list::handle_lrem(&args(&["LREM", "list", "0", "a"]), &mut store).unwrap();
+        assert_eq!(store.persistence.dirty, dirty + 3);
+    }
+}
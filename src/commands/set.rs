@@ -0,0 +1,558 @@
+use bytes::Bytes;
+
+use super::{
+    bulk_array, check_arity, is_keyword, parse_int, parse_random_count, parse_scan_options,
+    random_repeated_indexes, scan_reply, CommandError, CommandResult, ScanTarget,
+};
+use crate::resp::data::RESPDataType;
+use crate::store::{Store, Value};
+use crate::types::set::Set;
+
+pub fn get_set<'a>(store: &'a Store, key: &[u8]) -> Result<Option<&'a Set>, CommandError> {
+    match store.get_from_key_val_store(key) {
+        None => Ok(None),
+        Some(Value::Set(set)) => Ok(Some(set)),
+        Some(_) => Err(CommandError::WrongType),
+    }
+}
+
+pub fn get_set_mut<'a>(
+    store: &'a mut Store,
+    key: &[u8],
+) -> Result<Option<&'a mut Set>, CommandError> {
+    match store.get_mut_from_key_val_store(key) {
+        None => Ok(None),
+        Some(Value::Set(set)) => Ok(Some(set)),
+        Some(_) => Err(CommandError::WrongType),
+    }
+}
+
+/// Get the set at `key`, creating an empty one if the key does not exist.
+/// Callers must add at least one member so that no empty set is left behind.
+fn get_or_create_set<'a>(store: &'a mut Store, key: &Bytes) -> Result<&'a mut Set, CommandError> {
+    if get_set(store, key)?.is_none() {
+        store.insert_key_val(key.clone(), Value::Set(Set::new()));
+    }
+    Ok(get_set_mut(store, key)?.unwrap())
+}
+
+/// Look up the sets at `keys`, failing if any of them holds another type.
+fn get_sets<'a>(store: &'a Store, keys: &[Bytes]) -> Result<Vec<Option<&'a Set>>, CommandError> {
+    keys.iter().map(|key| get_set(store, key)).collect()
+}
+
+/// Replace whatever `destination` holds with `set`, deleting it if the set is
+/// empty, and reply with the set's cardinality as the STORE commands do.
+fn store_set(store: &mut Store, destination: &Bytes, set: Set) -> CommandResult {
+    let len = set.len();
+    store.delete_key(destination, store.lazyfree.server_del);
+    if len > 0 {
+        store.insert_key_val(destination.clone(), Value::Set(set));
+    }
+    Ok(RESPDataType::Integer(len as i64))
+}
+
+fn set_reply(set: &Set) -> RESPDataType {
+    RESPDataType::Array(set.iter().map(RESPDataType::BulkString).collect())
+}
+
+/// SADD key member [member ...]
+pub fn handle_sadd(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, -3)?;
+    let key = &args[1];
+    if get_set(store, key)?.is_some_and(|set| args[2..].iter().all(|member| set.contains(member))) {
+        return Ok(RESPDataType::Integer(0));
+    }
+    let set = get_or_create_set(store, key)?;
+    let added = args[2..]
+        .iter()
+        .filter(|member| set.insert((*member).clone()))
+        .count();
+    store.add_dirty(added);
+    Ok(RESPDataType::Integer(added as i64))
+}
+
+/// SREM key member [member ...]
+pub fn handle_srem(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, -3)?;
+    let key = &args[1];
+    if !get_set(store, key)?.is_some_and(|set| args[2..].iter().any(|member| set.contains(member)))
+    {
+        return Ok(RESPDataType::Integer(0));
+    }
+    let set = get_set_mut(store, key)?.unwrap();
+    let removed = args[2..].iter().filter(|member| set.remove(member)).count();
+    store.add_dirty(removed);
+    store.remove_if_empty(key);
+    Ok(RESPDataType::Integer(removed as i64))
+}
+
+/// SISMEMBER key member
+pub fn handle_sismember(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, 3)?;
+    let is_member = get_set(store, &args[1])?.is_some_and(|set| set.contains(&args[2]));
+    Ok(RESPDataType::Integer(is_member as i64))
+}
+
+/// SMISMEMBER key member [member ...]
+pub fn handle_smismember(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, -3)?;
+    let set = get_set(store, &args[1])?;
+    Ok(RESPDataType::Array(
+        args[2..]
+            .iter()
+            .map(|member| RESPDataType::Integer(set.is_some_and(|set| set.contains(member)) as i64))
+            .collect(),
+    ))
+}
+
+/// SCARD key
+pub fn handle_scard(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, 2)?;
+    let len = get_set(store, &args[1])?.map_or(0, |set| set.len());
+    Ok(RESPDataType::Integer(len as i64))
+}
+
+/// SMEMBERS key
+pub fn handle_smembers(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, 2)?;
+    Ok(get_set(store, &args[1])?.map_or(RESPDataType::Array(vec![]), set_reply))
+}
+
+/// SPOP key [count]
+pub fn handle_spop(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, -2)?;
+    if args.len() > 3 {
+        return Err(CommandError::Syntax);
+    }
+    let count = match args.get(2) {
+        Some(count) => match parse_int(count)? {
+            count if count < 0 => return Err(CommandError::OutOfRange),
+            count => Some(count as usize),
+        },
+        None => None,
+    };
+    let key = &args[1];
+    if get_set(store, key)?.is_none() || count == Some(0) {
+        return Ok(match count {
+            Some(_) => RESPDataType::Array(vec![]),
+            None => RESPDataType::NullBulkString,
+        });
+    }
+    let popped = get_set_mut(store, key)?
+        .unwrap()
+        .pop_random(count.unwrap_or(1));
+    store.add_dirty(popped.len());
+    store.remove_if_empty(key);
+    Ok(match count {
+        Some(_) => bulk_array(&popped),
+        None => RESPDataType::BulkString(popped.into_iter().next().unwrap()),
+    })
+}
+
+/// SRANDMEMBER key [count]
+pub fn handle_srandmember(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, -2)?;
+    if args.len() > 3 {
+        return Err(CommandError::Syntax);
+    }
+    let count = args
+        .get(2)
+        .map(|count| parse_random_count(count))
+        .transpose()?;
+    let set = get_set(store, &args[1])?;
+
+    let Some(count) = count else {
+        return Ok(set
+            .and_then(Set::random_member)
+            .map_or(RESPDataType::NullBulkString, RESPDataType::BulkString));
+    };
+    let Some(set) = set else {
+        return Ok(RESPDataType::Array(vec![]));
+    };
+    if count >= 0 {
+        return Ok(bulk_array(set.random_members(count as usize)));
+    }
+    let members: Vec<Bytes> = set.iter().collect();
+    Ok(bulk_array(
+        random_repeated_indexes(members.len(), count).map(|index| &members[index]),
+    ))
+}
+
+/// SSCAN key cursor [MATCH pattern] [COUNT count]
+pub fn handle_sscan(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, -3)?;
+    let options = parse_scan_options(args, 2, ScanTarget::Set)?;
+    let Some(set) = get_set(store, &args[1])? else {
+        return Ok(scan_reply(0, vec![]));
+    };
+    let mut members = Vec::new();
+    let cursor = set.scan(options.cursor, options.count, |member| {
+        if options.matches(&member) {
+            members.push(member);
+        }
+    });
+    Ok(scan_reply(cursor, members))
+}
+
+/// SMOVE source destination member
+pub fn handle_smove(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, 4)?;
+    let (source, destination, member) = (&args[1], &args[2], &args[3]);
+    let Some(source_set) = get_set(store, source)? else {
+        get_set(store, destination)?;
+        return Ok(RESPDataType::Integer(0));
+    };
+    let is_member = source_set.contains(member);
+    get_set(store, destination)?;
+    if !is_member {
+        return Ok(RESPDataType::Integer(0));
+    }
+    if source == destination {
+        return Ok(RESPDataType::Integer(1));
+    }
+    get_set_mut(store, source)?.unwrap().remove(member);
+    store.remove_if_empty(source);
+    get_or_create_set(store, destination)?.insert(member.clone());
+    store.add_dirty(1);
+    Ok(RESPDataType::Integer(1))
+}
+
+/// Intersect the sets, stopping once `limit` members are found. A missing key
+/// is an empty set, so it makes the whole intersection empty.
+fn intersect(sets: &[Option<&Set>], limit: usize) -> Set {
+    let Some(mut sets) = sets.iter().copied().collect::<Option<Vec<&Set>>>() else {
+        return Set::new();
+    };
+    sets.sort_by_key(|set| set.len());
+    let (smallest, others) = sets.split_first().unwrap();
+    smallest
+        .iter()
+        .filter(|member| others.iter().all(|set| set.contains(member)))
+        .take(limit)
+        .collect()
+}
+
+fn union(sets: &[Option<&Set>]) -> Set {
+    sets.iter().flatten().flat_map(|set| set.iter()).collect()
+}
+
+/// The members of the first set that are in none of the others.
+fn difference(sets: &[Option<&Set>]) -> Set {
+    let Some(first) = sets[0] else {
+        return Set::new();
+    };
+    first
+        .iter()
+        .filter(|member| !sets[1..].iter().flatten().any(|set| set.contains(member)))
+        .collect()
+}
+
+/// SINTER key [key ...]
+pub fn handle_sinter(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, -2)?;
+    Ok(set_reply(&intersect(
+        &get_sets(store, &args[1..])?,
+        usize::MAX,
+    )))
+}
+
+/// SINTERSTORE destination key [key ...]
+pub fn handle_sinterstore(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, -3)?;
+    let set = intersect(&get_sets(store, &args[2..])?, usize::MAX);
+    store_set(store, &args[1], set)
+}
+
+/// SUNION key [key ...]
+pub fn handle_sunion(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, -2)?;
+    Ok(set_reply(&union(&get_sets(store, &args[1..])?)))
+}
+
+/// SUNIONSTORE destination key [key ...]
+pub fn handle_sunionstore(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, -3)?;
+    let set = union(&get_sets(store, &args[2..])?);
+    store_set(store, &args[1], set)
+}
+
+/// SDIFF key [key ...]
+pub fn handle_sdiff(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, -2)?;
+    Ok(set_reply(&difference(&get_sets(store, &args[1..])?)))
+}
+
+/// SDIFFSTORE destination key [key ...]
+pub fn handle_sdiffstore(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, -3)?;
+    let set = difference(&get_sets(store, &args[2..])?);
+    store_set(store, &args[1], set)
+}
+
+/// SINTERCARD numkeys key [key ...] [LIMIT limit]
+pub fn handle_sintercard(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, -3)?;
+    let numkeys = parse_int(&args[1])?;
+    if numkeys <= 0 {
+        return Err(CommandError::Custom(String::from(
+            "ERR numkeys should be greater than 0",
+        )));
+    }
+    let keys_end = 2usize.saturating_add(numkeys as usize);
+    if keys_end > args.len() {
+        return Err(CommandError::Custom(String::from(
+            "ERR Number of keys can't be greater than number of args",
+        )));
+    }
+    let limit = match &args[keys_end..] {
+        [] => 0,
+        [option, limit] if is_keyword(option, "LIMIT") => match parse_int(limit) {
+            Ok(limit) if limit >= 0 => limit as usize,
+            _ => {
+                return Err(CommandError::Custom(String::from(
+                    "ERR LIMIT can't be negative",
+                )))
+            }
+        },
+        _ => return Err(CommandError::Syntax),
+    };
+    let limit = if limit == 0 { usize::MAX } else { limit };
+    let sets = get_sets(store, &args[2..keys_end])?;
+    Ok(RESPDataType::Integer(intersect(&sets, limit).len() as i64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::test_helpers::args;
+    use crate::commands::RANDOM_REPLY_MAX;
+
+    fn sorted_members(reply: CommandResult) -> Vec<Bytes> {
+        let Ok(RESPDataType::Array(members)) = reply else {
+            panic!("unexpected reply {:?}", reply);
+        };
+        let mut members: Vec<Bytes> = members
+            .into_iter()
+            .map(|member| match member {
+                RESPDataType::BulkString(member) => member,
+                other => panic!("unexpected member {:?}", other),
+            })
+            .collect();
+        members.sort();
+        members
+    }
+
+    fn members(values: &[&str]) -> Vec<Bytes> {
+        args(values)
+    }
+
+    #[test]
+    fn test_sscan() {
+        let mut store = Store::init();
+        handle_sadd(&args(&["SADD", "s", "1", "2", "10"]), &mut store).unwrap();
+        let reply = handle_sscan(&args(&["SSCAN", "s", "0", "MATCH", "1*"]), &mut store);
+        let Ok(RESPDataType::Array(reply)) = reply else {
+            panic!("unexpected reply {:?}", reply);
+        };
+        assert_eq!(reply[0], RESPDataType::BulkString(Bytes::from("0")));
+        assert_eq!(sorted_members(Ok(reply[1].clone())), members(&["1", "10"]));
+        assert_eq!(
+            handle_sscan(&args(&["SSCAN", "s", "0", "NOSCORES"]), &mut store),
+            Err(CommandError::Syntax)
+        );
+    }
+
+    #[test]
+    fn test_sadd_srem_sismember() {
+        let mut store = Store::init();
+        assert_eq!(
+            handle_sadd(&args(&["SADD", "s", "1", "2", "2", "x"]), &mut store),
+            Ok(RESPDataType::Integer(3))
+        );
+        assert_eq!(
+            handle_sismember(&args(&["SISMEMBER", "s", "x"]), &mut store),
+            Ok(RESPDataType::Integer(1))
+        );
+        assert_eq!(
+            handle_smismember(&args(&["SMISMEMBER", "s", "1", "3"]), &mut store),
+            Ok(RESPDataType::Array(vec![
+                RESPDataType::Integer(1),
+                RESPDataType::Integer(0)
+            ]))
+        );
+        assert_eq!(
+            handle_srem(&args(&["SREM", "s", "1", "2", "x", "y"]), &mut store),
+            Ok(RESPDataType::Integer(3))
+        );
+        assert!(store.get_from_key_val_store(b"s").is_none());
+        store.set_key_val(Bytes::from("str"), Bytes::from("v"));
+        assert_eq!(
+            handle_sadd(&args(&["SADD", "str", "a"]), &mut store),
+            Err(CommandError::WrongType)
+        );
+    }
+
+    #[test]
+    fn test_spop_and_srandmember() {
+        let mut store = Store::init();
+        handle_sadd(&args(&["SADD", "s", "a", "b", "c"]), &mut store).unwrap();
+        assert_eq!(
+            sorted_members(handle_srandmember(
+                &args(&["SRANDMEMBER", "s", "10"]),
+                &mut store
+            )),
+            members(&["a", "b", "c"])
+        );
+        assert_eq!(
+            sorted_members(handle_srandmember(
+                &args(&["SRANDMEMBER", "s", "-5"]),
+                &mut store
+            ))
+            .len(),
+            5
+        );
+        for count in [
+            "-9223372036854775808",
+            "-4611686018427387904",
+            "4611686018427387904",
+        ] {
+            assert_eq!(
+                handle_srandmember(&args(&["SRANDMEMBER", "s", count]), &mut store),
+                Err(CommandError::Custom(String::from(
+                    "ERR value is out of range"
+                )))
+            );
+        }
+        assert_eq!(
+            sorted_members(handle_srandmember(
+                &args(&["SRANDMEMBER", "s", "-4611686018427387903"]),
+                &mut store
+            ))
+            .len() as u64,
+            RANDOM_REPLY_MAX
+        );
+        assert_eq!(
+            handle_spop(&args(&["SPOP", "s", "-1"]), &mut store),
+            Err(CommandError::OutOfRange)
+        );
+        assert_eq!(
+            sorted_members(handle_spop(&args(&["SPOP", "s", "2"]), &mut store)).len(),
+            2
+        );
+        assert!(matches!(
+            handle_spop(&args(&["SPOP", "s"]), &mut store),
+            Ok(RESPDataType::BulkString(_))
+        ));
+        assert!(store.get_from_key_val_store(b"s").is_none());
+        assert_eq!(
+            handle_spop(&args(&["SPOP", "s"]), &mut store),
+            Ok(RESPDataType::NullBulkString)
+        );
+    }
+
+    #[test]
+    fn test_smove() {
+        let mut store = Store::init();
+        handle_sadd(&args(&["SADD", "src", "a"]), &mut store).unwrap();
+        assert_eq!(
+            handle_smove(&args(&["SMOVE", "src", "dst", "b"]), &mut store),
+            Ok(RESPDataType::Integer(0))
+        );
+        assert_eq!(
+            handle_smove(&args(&["SMOVE", "src", "dst", "a"]), &mut store),
+            Ok(RESPDataType::Integer(1))
+        );
+        assert!(store.get_from_key_val_store(b"src").is_none());
+        assert_eq!(
+            sorted_members(handle_smembers(&args(&["SMEMBERS", "dst"]), &mut store)),
+            members(&["a"])
+        );
+        store.set_key_val(Bytes::from("str"), Bytes::from("v"));
+        assert_eq!(
+            handle_smove(&args(&["SMOVE", "dst", "str", "a"]), &mut store),
+            Err(CommandError::WrongType)
+        );
+    }
+
+    #[test]
+    fn test_set_algebra() {
+        let mut store = Store::init();
+        handle_sadd(&args(&["SADD", "a", "1", "2", "3", "x"]), &mut store).unwrap();
+        handle_sadd(&args(&["SADD", "b", "2", "3", "4"]), &mut store).unwrap();
+        handle_sadd(&args(&["SADD", "c", "3", "x"]), &mut store).unwrap();
+
+        assert_eq!(
+            sorted_members(handle_sinter(&args(&["SINTER", "a", "b"]), &mut store)),
+            members(&["2", "3"])
+        );
+        assert_eq!(
+            sorted_members(handle_sinter(
+                &args(&["SINTER", "a", "missing"]),
+                &mut store
+            )),
+            members(&[])
+        );
+        assert_eq!(
+            sorted_members(handle_sunion(
+                &args(&["SUNION", "b", "c", "missing"]),
+                &mut store
+            )),
+            members(&["2", "3", "4", "x"])
+        );
+        assert_eq!(
+            sorted_members(handle_sdiff(&args(&["SDIFF", "a", "b", "c"]), &mut store)),
+            members(&["1"])
+        );
+
+        assert_eq!(
+            handle_sinterstore(&args(&["SINTERSTORE", "dst", "a", "b", "c"]), &mut store),
+            Ok(RESPDataType::Integer(1))
+        );
+        assert_eq!(
+            sorted_members(handle_smembers(&args(&["SMEMBERS", "dst"]), &mut store)),
+            members(&["3"])
+        );
+        assert_eq!(
+            handle_sdiffstore(&args(&["SDIFFSTORE", "dst", "c", "a"]), &mut store),
+            Ok(RESPDataType::Integer(0))
+        );
+        assert!(store.get_from_key_val_store(b"dst").is_none());
+        assert_eq!(
+            handle_sunionstore(&args(&["SUNIONSTORE", "a", "a", "b"]), &mut store),
+            Ok(RESPDataType::Integer(5))
+        );
+
+        store.set_key_val(Bytes::from("str"), Bytes::from("v"));
+        assert_eq!(
+            handle_sinter(&args(&["SINTER", "missing", "str"]), &mut store),
+            Err(CommandError::WrongType)
+        );
+    }
+
+    #[test]
+    fn test_sintercard() {
+        let mut store = Store::init();
+        handle_sadd(&args(&["SADD", "a", "1", "2", "3"]), &mut store).unwrap();
+        handle_sadd(&args(&["SADD", "b", "1", "2", "3", "4"]), &mut store).unwrap();
+        assert_eq!(
+            handle_sintercard(&args(&["SINTERCARD", "2", "a", "b"]), &mut store),
+            Ok(RESPDataType::Integer(3))
+        );
+        assert_eq!(
+            handle_sintercard(
+                &args(&["SINTERCARD", "2", "a", "b", "LIMIT", "2"]),
+                &mut store
+            ),
+            Ok(RESPDataType::Integer(2))
+        );
+        assert!(handle_sintercard(&args(&["SINTERCARD", "3", "a", "b"]), &mut store).is_err());
+        assert!(
+            handle_sintercard(&args(&["SINTERCARD", "1", "a", "LIMIT", "-1"]), &mut store).is_err()
+        );
+        assert_eq!(
+            handle_sintercard(&args(&["SINTERCARD", "1", "a", "b"]), &mut store),
+            Err(CommandError::Syntax)
+        );
+    }
+}
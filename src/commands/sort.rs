@@ -0,0 +1,388 @@
+use std::cmp::Ordering;
+
+use bytes::Bytes;
+
+use super::hash::get_hash;
+use super::{check_arity, is_keyword, parse_int, CommandError, CommandResult};
+use crate::resp::data::RESPDataType;
+use crate::store::{Store, Value};
+use crate::types::quicklist::QuickList;
+
+/// Options of SORT and SORT_RO.
+struct SortOptions {
+    desc: bool,
+    alpha: bool,
+    /// Offset and count, a negative count meaning up to the end.
+    limit: Option<(i64, i64)>,
+    by: Option<Bytes>,
+    /// Skip sorting, when BY is given a pattern without any `*`.
+    dont_sort: bool,
+    get: Vec<Bytes>,
+    store: Option<Bytes>,
+}
+
+fn parse_sort_options(args: &[Bytes], allow_store: bool) -> Result<SortOptions, CommandError> {
+    let mut options = SortOptions {
+        desc: false,
+        alpha: false,
+        limit: None,
+        by: None,
+        dont_sort: false,
+        get: Vec::new(),
+        store: None,
+    };
+    let mut i = 2;
+    while i < args.len() {
+        let left = args.len() - i - 1;
+        if is_keyword(&args[i], "ASC") {
+            options.desc = false;
+        } else if is_keyword(&args[i], "DESC") {
+            options.desc = true;
+        } else if is_keyword(&args[i], "ALPHA") {
+            options.alpha = true;
+        } else if is_keyword(&args[i], "LIMIT") && left >= 2 {
+            options.limit = Some((parse_int(&args[i + 1])?, parse_int(&args[i + 2])?));
+            i += 2;
+        } else if is_keyword(&args[i], "STORE") && left >= 1 && allow_store {
+            options.store = Some(args[i + 1].clone());
+            i += 1;
+        } else if is_keyword(&args[i], "BY") && left >= 1 {
+            // Sorting by a constant key is the same as not sorting at all.
+            options.dont_sort = !args[i + 1].contains(&b'*');
+            options.by = Some(args[i + 1].clone());
+            i += 1;
+        } else if is_keyword(&args[i], "GET") && left >= 1 {
+            options.get.push(args[i + 1].clone());
+            i += 1;
+        } else {
+            return Err(CommandError::Syntax);
+        }
+        i += 1;
+    }
+    Ok(options)
+}
+
+/// Resolve a BY or GET `pattern` for `element`: the first `*` is replaced by
+/// the element to name a string key, or a hash field with a `key->field`
+/// pattern. `#` stands for the element itself. Returns None if the key or
+/// field does not exist or holds another type.
+fn lookup_by_pattern(store: &mut Store, pattern: &[u8], element: &Bytes) -> Option<Bytes> {
+    if pattern == b"#" {
+        return Some(element.clone());
+    }
+    // Getting a fixed key makes no sense, so a pattern needs a `*`.
+    let star = pattern.iter().position(|byte| *byte == b'*')?;
+    let rest = &pattern[star + 1..];
+    let (postfix, field) = match rest.windows(2).position(|window| window == b"->") {
+        Some(arrow) if arrow + 2 < rest.len() => (&rest[..arrow], Some(&rest[arrow + 2..])),
+        _ => (rest, None),
+    };
+    let key = [&pattern[..star], element.as_ref(), postfix].concat();
+    match field {
+        Some(field) => get_hash(store, &key)
+            .ok()??
+            .get(field)
+            .map(Bytes::copy_from_slice),
+        None => match store.get_from_key_val_store(&key)? {
+            Value::String(value) => Some(value.clone()),
+            _ => None,
+        },
+    }
+}
+
+/// What an element is compared by.
+enum SortKey {
+    Score(f64),
+    /// The value of the BY pattern, None when it does not resolve.
+    Alpha(Option<Bytes>),
+    /// The element itself, sorted alphabetically.
+    Element,
+}
+
+/// Parse a weight the way `strtod` does for SORT: leading whitespace is
+/// skipped and an empty string is zero.
+fn parse_score(value: &[u8]) -> Option<f64> {
+    let value = std::str::from_utf8(value).ok()?.trim_start();
+    if value.is_empty() {
+        return Some(0.0);
+    }
+    value.parse::<f64>().ok().filter(|score| !score.is_nan())
+}
+
+fn compare(a: &(Bytes, SortKey), b: &(Bytes, SortKey)) -> Ordering {
+    match (&a.1, &b.1) {
+        // Equal scores fall back to comparing the elements, so that the order
+        // is well defined.
+        (SortKey::Score(x), SortKey::Score(y)) => x.total_cmp(y).then_with(|| a.0.cmp(&b.0)),
+        // A missing value sorts first.
+        (SortKey::Alpha(x), SortKey::Alpha(y)) => x.cmp(y),
+        _ => a.0.cmp(&b.0),
+    }
+}
+
+/// Shared implementation of SORT and SORT_RO.
+fn sort(args: &[Bytes], store: &mut Store, allow_store: bool) -> CommandResult {
+    check_arity(args, -2)?;
+    let mut options = parse_sort_options(args, allow_store)?;
+
+    let mut elements: Vec<Bytes> = match store.get_from_key_val_store(&args[1]) {
+        None => Vec::new(),
+        Some(Value::List(list)) => list.iter().map(Bytes::copy_from_slice).collect(),
+        Some(Value::Set(set)) => {
+            // The order of a set is not deterministic, so sort it anyway when
+            // storing the result.
+            if options.dont_sort && options.store.is_some() {
+                options.dont_sort = false;
+                options.alpha = true;
+                options.by = None;
+            }
+            set.iter().collect()
+        }
+        Some(Value::ZSet(zset)) => {
+            let mut members: Vec<Bytes> = zset
+                .iter()
+                .map(|(member, _)| Bytes::copy_from_slice(member))
+                .collect();
+            if options.dont_sort && options.desc {
+                members.reverse();
+            }
+            members
+        }
+        Some(_) => return Err(CommandError::WrongType),
+    };
+
+    let len = elements.len() as i64;
+    let (mut start, mut end) = match options.limit {
+        Some((offset, count)) => {
+            let start = offset.max(0);
+            let end = if count < 0 {
+                len - 1
+            } else {
+                start + count - 1
+            };
+            (start, end)
+        }
+        None => (0, len - 1),
+    };
+    if start >= len {
+        start = len - 1;
+        end = len - 2;
+    }
+    end = end.min(len - 1);
+
+    if !options.dont_sort {
+        let mut keyed = Vec::with_capacity(elements.len());
+        for element in elements {
+            let by = match &options.by {
+                Some(pattern) => lookup_by_pattern(store, pattern, &element),
+                None => None,
+            };
+            let key = if options.alpha {
+                match options.by {
+                    Some(_) => SortKey::Alpha(by),
+                    None => SortKey::Element,
+                }
+            } else {
+                let weight = match &options.by {
+                    Some(_) => by.as_deref().map(parse_score).unwrap_or(Some(0.0)),
+                    None => parse_score(&element),
+                };
+                SortKey::Score(weight.ok_or_else(|| {
+                    CommandError::Custom(String::from(
+                        "ERR One or more scores can't be converted into double",
+                    ))
+                })?)
+            };
+            keyed.push((element, key));
+        }
+        keyed.sort_by(|a, b| {
+            let ordering = compare(a, b);
+            if options.desc {
+                ordering.reverse()
+            } else {
+                ordering
+            }
+        });
+        elements = keyed.into_iter().map(|(element, _)| element).collect();
+    }
+
+    let selected = if start <= end {
+        &elements[start as usize..=end as usize]
+    } else {
+        &[]
+    };
+    let mut output: Vec<Option<Bytes>> = Vec::new();
+    for element in selected {
+        if options.get.is_empty() {
+            output.push(Some(element.clone()));
+        }
+        for pattern in &options.get {
+            output.push(lookup_by_pattern(store, pattern, element));
+        }
+    }
+
+    let Some(destination) = options.store else {
+        return Ok(RESPDataType::Array(
+            output
+                .into_iter()
+                .map(|value| value.map_or(RESPDataType::NullBulkString, RESPDataType::BulkString))
+                .collect(),
+        ));
+    };
+    let stored = output.len();
+    store.delete_key(&destination, store.lazyfree.server_del);
+    if stored > 0 {
+        let mut list = QuickList::new();
+        for value in output {
+            list.push_back(value.unwrap_or_default());
+        }
+        store.insert_key_val(destination, Value::List(list));
+    }
+    Ok(RESPDataType::Integer(stored as i64))
+}
+
+/// SORT key [BY pattern] [LIMIT offset count] [GET pattern ...] [ASC|DESC]
+/// [ALPHA] [STORE destination]
+pub fn handle_sort(args: &[Bytes], store: &mut Store) -> CommandResult {
+    sort(args, store, true)
+}
+
+/// SORT_RO key [BY pattern] [LIMIT offset count] [GET pattern ...] [ASC|DESC] [ALPHA]
+pub fn handle_sort_ro(args: &[Bytes], store: &mut Store) -> CommandResult {
+    sort(args, store, false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::hash::handle_hset;
+    use crate::commands::list::{handle_lrange, handle_rpush};
+    use crate::commands::set::handle_sadd;
+    use crate::commands::test_helpers::{args, bulks};
+    use crate::commands::zset::handle_zadd;
+
+    #[test]
+    fn test_sort_numeric_and_alpha() {
+        let mut store = Store::init();
+        handle_rpush(&args(&["RPUSH", "l", "3", "10", "1", "2"]), &mut store).unwrap();
+        assert_eq!(
+            handle_sort(&args(&["SORT", "l"]), &mut store),
+            Ok(bulks(&["1", "2", "3", "10"]))
+        );
+        assert_eq!(
+            handle_sort(&args(&["SORT", "l", "ALPHA", "DESC"]), &mut store),
+            Ok(bulks(&["3", "2", "10", "1"]))
+        );
+        assert_eq!(
+            handle_sort(&args(&["SORT", "l", "LIMIT", "1", "2"]), &mut store),
+            Ok(bulks(&["2", "3"]))
+        );
+        assert_eq!(
+            handle_sort(&args(&["SORT", "l", "LIMIT", "10", "2"]), &mut store),
+            Ok(bulks(&[]))
+        );
+        assert_eq!(
+            handle_sort(&args(&["SORT", "missing"]), &mut store),
+            Ok(bulks(&[]))
+        );
+
+        handle_sadd(&args(&["SADD", "s", "a", "b"]), &mut store).unwrap();
+        assert_eq!(
+            handle_sort(&args(&["SORT", "s"]), &mut store),
+            Err(CommandError::Custom(String::from(
+                "ERR One or more scores can't be converted into double"
+            )))
+        );
+        assert_eq!(
+            handle_sort(&args(&["SORT", "s", "ALPHA"]), &mut store),
+            Ok(bulks(&["a", "b"]))
+        );
+
+        store.set_key_val(Bytes::from("str"), Bytes::from("v"));
+        assert_eq!(
+            handle_sort(&args(&["SORT", "str"]), &mut store),
+            Err(CommandError::WrongType)
+        );
+        assert_eq!(
+            handle_sort(&args(&["SORT", "l", "LIMIT", "1"]), &mut store),
+            Err(CommandError::Syntax)
+        );
+    }
+
+    #[test]
+    fn test_sort_by_and_get() {
+        let mut store = Store::init();
+        handle_rpush(&args(&["RPUSH", "l", "a", "b", "c"]), &mut store).unwrap();
+        store.set_key_val(Bytes::from("w_a"), Bytes::from("3"));
+        store.set_key_val(Bytes::from("w_b"), Bytes::from("1"));
+        store.set_key_val(Bytes::from("w_c"), Bytes::from("2"));
+        handle_hset(&args(&["HSET", "h_a", "name", "Ann"]), &mut store).unwrap();
+        handle_hset(&args(&["HSET", "h_b", "name", "Bob"]), &mut store).unwrap();
+
+        assert_eq!(
+            handle_sort(&args(&["SORT", "l", "BY", "w_*"]), &mut store),
+            Ok(bulks(&["b", "c", "a"]))
+        );
+        assert_eq!(
+            handle_sort(
+                &args(&["SORT", "l", "BY", "h_*->name", "ALPHA", "DESC"]),
+                &mut store
+            ),
+            Ok(bulks(&["b", "a", "c"]))
+        );
+        assert_eq!(
+            handle_sort(
+                &args(&["SORT", "l", "BY", "nosort", "GET", "#", "GET", "h_*->name"]),
+                &mut store
+            ),
+            Ok(RESPDataType::Array(vec![
+                RESPDataType::BulkString(Bytes::from("a")),
+                RESPDataType::BulkString(Bytes::from("Ann")),
+                RESPDataType::BulkString(Bytes::from("b")),
+                RESPDataType::BulkString(Bytes::from("Bob")),
+                RESPDataType::BulkString(Bytes::from("c")),
+                RESPDataType::NullBulkString,
+            ]))
+        );
+
+        handle_zadd(&args(&["ZADD", "z", "1", "x", "2", "y"]), &mut store).unwrap();
+        assert_eq!(
+            handle_sort(&args(&["SORT", "z", "BY", "nosort", "DESC"]), &mut store),
+            Ok(bulks(&["y", "x"]))
+        );
+    }
+
+    #[test]
+    fn test_sort_store() {
+        let mut store = Store::init();
+        handle_rpush(&args(&["RPUSH", "l", "2", "1"]), &mut store).unwrap();
+        assert_eq!(
+            handle_sort(
+                &args(&["SORT", "l", "GET", "none_*", "STORE", "dst"]),
+                &mut store
+            ),
+            Ok(RESPDataType::Integer(2))
+        );
+        assert_eq!(
+            handle_lrange(&args(&["LRANGE", "dst", "0", "-1"]), &mut store),
+            Ok(bulks(&["", ""]))
+        );
+        assert_eq!(
+            handle_sort(&args(&["SORT", "l", "STORE", "dst"]), &mut store),
+            Ok(RESPDataType::Integer(2))
+        );
+        assert_eq!(
+            handle_lrange(&args(&["LRANGE", "dst", "0", "-1"]), &mut store),
+            Ok(bulks(&["1", "2"]))
+        );
+        assert_eq!(
+            handle_sort_ro(&args(&["SORT_RO", "l", "STORE", "dst"]), &mut store),
+            Err(CommandError::Syntax)
+        );
+        assert_eq!(
+            handle_sort(&args(&["SORT", "missing", "STORE", "dst"]), &mut store),
+            Ok(RESPDataType::Integer(0))
+        );
+        assert!(!store.contains_key(b"dst"));
+    }
+}
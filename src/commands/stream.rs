@@ -0,0 +1,1805 @@
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+
+use super::{
+    check_arity, check_subcommand_arity, help_reply, is_keyword, ok, parse_int, unknown_subcommand,
+    wrong_number_of_arguments, CommandError, CommandResult,
+};
+use crate::blocking::{reply_or_block, CommandOutcome};
+use crate::resp::data::RESPDataType;
+use crate::store::{Store, Value};
+use crate::types::stream::{ConsumerGroup, Stream, StreamEntry, StreamId};
+use crate::util::mstime;
+
+/// Default LIMIT of approximate trimming: 100 times the entries of a block.
+const DEFAULT_TRIM_LIMIT: usize = 100 * 100;
+
+pub fn get_stream<'a>(store: &'a Store, key: &[u8]) -> Result<Option<&'a Stream>, CommandError> {
+    match store.get_from_key_val_store(key) {
+        None => Ok(None),
+        Some(Value::Stream(stream)) => Ok(Some(stream)),
+        Some(_) => Err(CommandError::WrongType),
+    }
+}
+
+pub fn get_stream_mut<'a>(
+    store: &'a mut Store,
+    key: &[u8],
+) -> Result<Option<&'a mut Stream>, CommandError> {
+    match store.get_mut_from_key_val_store(key) {
+        None => Ok(None),
+        Some(Value::Stream(stream)) => Ok(Some(stream)),
+        Some(_) => Err(CommandError::WrongType),
+    }
+}
+
+fn invalid_stream_id() -> CommandError {
+    CommandError::Custom(String::from(
+        "ERR Invalid stream ID specified as stream command argument",
+    ))
+}
+
+/// Parse an unsigned integer made of digits only.
+fn parse_u64(arg: &[u8]) -> Option<u64> {
+    if arg.is_empty() || !arg.iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+    std::str::from_utf8(arg).ok()?.parse().ok()
+}
+
+/// Parse `ms-seq`, or just `ms` in which case the sequence is `missing_seq`.
+pub fn parse_stream_id(arg: &[u8], missing_seq: u64) -> Result<StreamId, CommandError> {
+    let (ms, seq) = match arg.iter().position(|byte| *byte == b'-') {
+        Some(dash) => (parse_u64(&arg[..dash]), parse_u64(&arg[dash + 1..])),
+        None => (parse_u64(arg), Some(missing_seq)),
+    };
+    match (ms, seq) {
+        (Some(ms), Some(seq)) => Ok(StreamId::new(ms, seq)),
+        _ => Err(invalid_stream_id()),
+    }
+}
+
+/// Parse an XRANGE interval bound: `-`, `+`, an ID, or an ID prefixed by `(`
+/// to exclude it. A missing sequence defaults to the lowest one for the start
+/// of the interval and to the highest one for its end.
+pub fn parse_range_bound(arg: &[u8], is_start: bool) -> Result<StreamId, CommandError> {
+    match arg {
+        b"-" => return Ok(StreamId::MIN),
+        b"+" => return Ok(StreamId::MAX),
+        _ => {}
+    }
+    let missing_seq = if is_start { 0 } else { u64::MAX };
+    let Some(id) = arg.strip_prefix(b"(") else {
+        return parse_stream_id(arg, missing_seq);
+    };
+    let id = parse_stream_id(id, missing_seq)?;
+    let (id, which) = if is_start {
+        (id.next(), "start")
+    } else {
+        (id.prev(), "end")
+    };
+    id.ok_or(CommandError::Custom(format!(
+        "ERR invalid {} ID for the interval",
+        which
+    )))
+}
+
+pub fn id_reply(id: StreamId) -> RESPDataType {
+    RESPDataType::BulkString(Bytes::from(id.to_string()))
+}
+
+pub fn entry_reply(entry: &StreamEntry) -> RESPDataType {
+    RESPDataType::Array(vec![
+        id_reply(entry.id),
+        RESPDataType::Array(
+            entry
+                .fields
+                .iter()
+                .flat_map(|(field, value)| {
+                    [
+                        RESPDataType::BulkString(field.clone()),
+                        RESPDataType::BulkString(value.clone()),
+                    ]
+                })
+                .collect(),
+        ),
+    ])
+}
+
+pub fn entries_reply(entries: &[&StreamEntry]) -> RESPDataType {
+    RESPDataType::Array(entries.iter().map(|entry| entry_reply(entry)).collect())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TrimStrategy {
+    MaxLen(usize),
+    MinId(StreamId),
+}
+
+/// The trimming options shared by XADD and XTRIM.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Trim {
+    strategy: TrimStrategy,
+    approximate: bool,
+    /// Largest number of entries to remove, zero meaning no limit.
+    limit: usize,
+}
+
+impl Trim {
+    fn apply(&self, stream: &mut Stream) -> usize {
+        match self.strategy {
+            TrimStrategy::MaxLen(maxlen) => {
+                stream.trim_to_len(maxlen, self.approximate, self.limit)
+            }
+            TrimStrategy::MinId(minid) => {
+                stream.trim_to_min_id(minid, self.approximate, self.limit)
+            }
+        }
+    }
+
+    /// Whether applying the trim to `stream` would remove any entry.
+    fn removes_any(&self, stream: &Stream) -> bool {
+        match self.strategy {
+            TrimStrategy::MaxLen(maxlen) => {
+                stream.trims_to_len(maxlen, self.approximate, self.limit)
+            }
+            TrimStrategy::MinId(minid) => {
+                stream.trims_to_min_id(minid, self.approximate, self.limit)
+            }
+        }
+    }
+}
+
+/// Parse the options of XADD (when `is_xadd`) or XTRIM, starting at `index`.
+/// Returns the options and the index of the first argument that is not one.
+fn parse_trim_options(
+    args: &[Bytes],
+    mut index: usize,
+    is_xadd: bool,
+) -> Result<(Option<Trim>, bool, usize), CommandError> {
+    let mut strategy = None;
+    let mut approximate = false;
+    let mut limit = None;
+    let mut nomkstream = false;
+    while let Some(arg) = args.get(index) {
+        let remaining = args.len() - index - 1;
+        let keyword = arg.to_ascii_uppercase();
+        match keyword.as_slice() {
+            b"NOMKSTREAM" if is_xadd => nomkstream = true,
+            b"MAXLEN" | b"MINID" if remaining >= 1 => {
+                let mut threshold = &args[index + 1];
+                if (threshold.as_ref() == b"~" || threshold.as_ref() == b"=") && remaining >= 2 {
+                    approximate = threshold.as_ref() == b"~";
+                    index += 1;
+                    threshold = &args[index + 1];
+                }
+                strategy = Some(if keyword == b"MAXLEN" {
+                    let maxlen = parse_int(threshold)?;
+                    if maxlen < 0 {
+                        return Err(CommandError::Custom(String::from(
+                            "ERR The MAXLEN argument must be >= 0.",
+                        )));
+                    }
+                    TrimStrategy::MaxLen(maxlen as usize)
+                } else {
+                    TrimStrategy::MinId(parse_stream_id(threshold, 0)?)
+                });
+                index += 1;
+            }
+            b"LIMIT" if remaining >= 1 => {
+                let count = parse_int(&args[index + 1])?;
+                if count < 0 {
+                    return Err(CommandError::Custom(String::from(
+                        "ERR The LIMIT argument must be >= 0.",
+                    )));
+                }
+                limit = Some(count as usize);
+                index += 1;
+            }
+            _ if is_xadd => break,
+            _ => return Err(CommandError::Syntax),
+        }
+        index += 1;
+    }
+    if limit.is_some() && !approximate {
+        return Err(CommandError::Custom(String::from(
+            "ERR syntax error, LIMIT cannot be used without the special ~ option",
+        )));
+    }
+    let trim = strategy.map(|strategy| Trim {
+        strategy,
+        approximate,
+        limit: match limit {
+            Some(limit) => limit,
+            None if approximate => DEFAULT_TRIM_LIMIT,
+            None => 0,
+        },
+    });
+    Ok((trim, nomkstream, index))
+}
+
+/// Resolve the ID argument of XADD against the stream's last ID.
+fn resolve_xadd_id(arg: &[u8], stream: Option<&Stream>) -> Result<StreamId, CommandError> {
+    let last_id = stream.map_or(StreamId::MIN, Stream::last_id);
+    let id = if arg == b"*" {
+        let now = mstime().max(0) as u64;
+        stream.map_or(Some(StreamId::new(now, 0)), |stream| stream.next_id(now))
+    } else if let Some(ms) = arg.strip_suffix(b"-*") {
+        let ms = parse_u64(ms).ok_or_else(invalid_stream_id)?;
+        if ms == last_id.ms && stream.is_some() {
+            last_id.seq.checked_add(1).map(|seq| StreamId::new(ms, seq))
+        } else if ms > last_id.ms || stream.is_none() {
+            Some(StreamId::new(ms, if ms == 0 { 1 } else { 0 }))
+        } else {
+            None
+        }
+    } else {
+        let id = parse_stream_id(arg, 0)?;
+        if id == StreamId::MIN {
+            return Err(CommandError::Custom(String::from(
+                "ERR The ID specified in XADD must be greater than 0-0",
+            )));
+        }
+        Some(id)
+    };
+    match id {
+        None if arg == b"*" => Err(CommandError::Custom(String::from(
+            "ERR The stream has exhausted the last possible ID, unable to add more items",
+        ))),
+        Some(id) if id > last_id => Ok(id),
+        _ => Err(CommandError::Custom(String::from(
+            "ERR The ID specified in XADD is equal or smaller than the target stream top item",
+        ))),
+    }
+}
+
+/// XADD key [NOMKSTREAM] [MAXLEN|MINID [=|~] threshold [LIMIT count]] *|id field value [field value ...]
+pub fn handle_xadd(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, -5)?;
+    let (trim, nomkstream, id_index) = parse_trim_options(args, 2, true)?;
+    let pairs = args.get(id_index + 1..).unwrap_or_default();
+    if pairs.is_empty() || !pairs.len().is_multiple_of(2) {
+        return Err(wrong_number_of_arguments(args));
+    }
+
+    let key = &args[1];
+    let stream = get_stream(store, key)?;
+    if stream.is_none() && nomkstream {
+        return Ok(RESPDataType::NullBulkString);
+    }
+    let id = resolve_xadd_id(&args[id_index], stream)?;
+    if stream.is_none() {
+        store.insert_key_val(key.clone(), Value::Stream(Stream::new()));
+    }
+    let stream = get_stream_mut(store, key)?.unwrap();
+    stream.add(
+        id,
+        pairs
+            .chunks(2)
+            .map(|pair| (pair[0].clone(), pair[1].clone()))
+            .collect(),
+    );
+    let trimmed = trim.map_or(0, |trim| trim.apply(stream));
+    store.add_dirty(1 + trimmed);
+    store.signal_key_as_ready(key);
+    Ok(id_reply(id))
+}
+
+/// XLEN key
+pub fn handle_xlen(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, 2)?;
+    let len = get_stream(store, &args[1])?.map_or(0, Stream::len);
+    Ok(RESPDataType::Integer(len as i64))
+}
+
+fn range(args: &[Bytes], store: &mut Store, rev: bool) -> CommandResult {
+    check_arity(args, -4)?;
+    let (start, end) = if rev {
+        (&args[3], &args[2])
+    } else {
+        (&args[2], &args[3])
+    };
+    let start = parse_range_bound(start, true)?;
+    let end = parse_range_bound(end, false)?;
+    let count = match &args[4..] {
+        [] => usize::MAX,
+        [option, count] if is_keyword(option, "COUNT") => parse_int(count)?.max(0) as usize,
+        _ => return Err(CommandError::Syntax),
+    };
+    let entries = get_stream(store, &args[1])?
+        .map(|stream| stream.range(start, end, rev, count))
+        .unwrap_or_default();
+    Ok(entries_reply(&entries))
+}
+
+/// XRANGE key start end [COUNT count]
+pub fn handle_xrange(args: &[Bytes], store: &mut Store) -> CommandResult {
+    range(args, store, false)
+}
+
+/// XREVRANGE key end start [COUNT count]
+pub fn handle_xrevrange(args: &[Bytes], store: &mut Store) -> CommandResult {
+    range(args, store, true)
+}
+
+/// XDEL key id [id ...]
+pub fn handle_xdel(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, -3)?;
+    let ids = args[2..]
+        .iter()
+        .map(|id| parse_stream_id(id, 0))
+        .collect::<Result<Vec<_>, CommandError>>()?;
+    if !get_stream(store, &args[1])?
+        .is_some_and(|stream| ids.iter().any(|id| stream.get(*id).is_some()))
+    {
+        return Ok(RESPDataType::Integer(0));
+    }
+    let stream = get_stream_mut(store, &args[1])?.unwrap();
+    let deleted = ids.into_iter().filter(|id| stream.delete(*id)).count();
+    store.add_dirty(deleted);
+    Ok(RESPDataType::Integer(deleted as i64))
+}
+
+/// XTRIM key MAXLEN|MINID [=|~] threshold [LIMIT count]
+pub fn handle_xtrim(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, -4)?;
+    let (trim, _, _) = parse_trim_options(args, 2, false)?;
+    let trim = trim.ok_or(CommandError::Syntax)?;
+    if !get_stream(store, &args[1])?.is_some_and(|stream| trim.removes_any(stream)) {
+        return Ok(RESPDataType::Integer(0));
+    }
+    let removed = trim.apply(get_stream_mut(store, &args[1])?.unwrap());
+    store.add_dirty(removed);
+    Ok(RESPDataType::Integer(removed as i64))
+}
+
+/// The options of XREAD and XREADGROUP.
+struct ReadOptions {
+    /// The group and consumer names of XREADGROUP.
+    group: Option<(Bytes, Bytes)>,
+    count: usize,
+    blocking: bool,
+    deadline: Option<Instant>,
+    noack: bool,
+    /// Index of the first key, right after STREAMS.
+    streams_index: usize,
+}
+
+fn parse_read_options(args: &[Bytes], is_group: bool) -> Result<ReadOptions, CommandError> {
+    let mut options = ReadOptions {
+        group: None,
+        count: usize::MAX,
+        blocking: false,
+        deadline: None,
+        noack: false,
+        streams_index: 0,
+    };
+    let mut index = 1;
+    loop {
+        let Some(arg) = args.get(index) else {
+            return Err(CommandError::Syntax);
+        };
+        let value = args.get(index + 1);
+        match (arg.to_ascii_uppercase().as_slice(), value) {
+            (b"STREAMS", Some(_)) => {
+                options.streams_index = index + 1;
+                break;
+            }
+            (b"COUNT", Some(value)) => {
+                options.count = match parse_int(value)? {
+                    count if count <= 0 => usize::MAX,
+                    count => count as usize,
+                }
+            }
+            (b"BLOCK", Some(value)) => {
+                let timeout = parse_int(value).map_err(|_| {
+                    CommandError::Custom(String::from(
+                        "ERR timeout is not an integer or out of range",
+                    ))
+                })?;
+                if timeout < 0 {
+                    return Err(CommandError::Custom(String::from(
+                        "ERR timeout is negative",
+                    )));
+                }
+                options.blocking = true;
+                options.deadline =
+                    (timeout > 0).then(|| Instant::now() + Duration::from_millis(timeout as u64));
+            }
+            (b"GROUP", Some(group)) if is_group && args.len() > index + 2 => {
+                options.group = Some((group.clone(), args[index + 2].clone()));
+                index += 1;
+            }
+            (b"NOACK", _) if is_group => {
+                options.noack = true;
+                index -= 1;
+            }
+            _ => return Err(CommandError::Syntax),
+        }
+        index += 2;
+    }
+    if is_group && options.group.is_none() {
+        return Err(CommandError::Custom(String::from(
+            "ERR Missing GROUP option for XREADGROUP",
+        )));
+    }
+    if !(args.len() - options.streams_index).is_multiple_of(2) {
+        return Err(CommandError::Custom(format!(
+            "ERR Unbalanced '{}' list of streams: for each stream key an ID or '{}' must be specified.",
+            if is_group { "xreadgroup" } else { "xread" },
+            if is_group { ">" } else { "$" },
+        )));
+    }
+    Ok(options)
+}
+
+/// XREAD [COUNT count] [BLOCK milliseconds] STREAMS key [key ...] id [id ...]
+pub fn handle_xread(
+    args: &[Bytes],
+    store: &mut Store,
+    may_block: bool,
+) -> Result<CommandOutcome, CommandError> {
+    check_arity(args, -4)?;
+    let options = parse_read_options(args, false)?;
+    let rest = &args[options.streams_index..];
+    let (keys, id_args) = rest.split_at(rest.len() / 2);
+    let mut ids = Vec::with_capacity(keys.len());
+    for (key, id) in keys.iter().zip(id_args) {
+        let stream = get_stream(store, key)?;
+        ids.push(match id.as_ref() {
+            b"$" => stream.map_or(StreamId::MIN, Stream::last_id),
+            b">" => {
+                return Err(CommandError::Custom(String::from(
+                    "ERR The > ID can be specified only when calling XREADGROUP using the GROUP <group> <consumer> option.",
+                )))
+            }
+            _ => parse_stream_id(id, 0)?,
+        });
+    }
+
+    let mut replies = Vec::new();
+    for (key, id) in keys.iter().zip(&ids) {
+        let Some(stream) = get_stream(store, key)? else {
+            continue;
+        };
+        let Some(start) = id.next() else {
+            continue;
+        };
+        let entries = stream.range(start, StreamId::MAX, false, options.count);
+        if !entries.is_empty() {
+            replies.push(RESPDataType::Array(vec![
+                RESPDataType::BulkString(key.clone()),
+                entries_reply(&entries),
+            ]));
+        }
+    }
+    let reply = (!replies.is_empty()).then_some(RESPDataType::Array(replies));
+    if reply.is_none() && !options.blocking {
+        return Ok(CommandOutcome::Reply(RESPDataType::NullArray));
+    }
+
+    // Pin `$` to the IDs resolved now, so that re-running the command once
+    // the client is woken up returns what was added in the meantime.
+    let mut pinned = args.to_vec();
+    for (arg, id) in pinned[options.streams_index + keys.len()..]
+        .iter_mut()
+        .zip(&ids)
+    {
+        *arg = Bytes::from(id.to_string());
+    }
+    reply_or_block(
+        reply,
+        RESPDataType::NullArray,
+        &pinned,
+        keys,
+        options.deadline,
+        may_block,
+    )
+}
+
+fn no_such_group(key: &[u8], group: &[u8]) -> CommandError {
+    CommandError::Custom(format!(
+        "NOGROUP No such key '{}' or consumer group '{}'",
+        String::from_utf8_lossy(key),
+        String::from_utf8_lossy(group)
+    ))
+}
+
+fn no_such_group_for_key(key: &[u8], group: &[u8]) -> CommandError {
+    CommandError::Custom(format!(
+        "NOGROUP No such consumer group '{}' for key name '{}'",
+        String::from_utf8_lossy(group),
+        String::from_utf8_lossy(key)
+    ))
+}
+
+/// Whether `consumer` is missing from `group` of the stream at `key`, so
+/// that reading or claiming as it creates it.
+fn consumer_created(store: &Store, key: &[u8], group: &[u8], consumer: &[u8]) -> bool {
+    get_stream(store, key)
+        .ok()
+        .flatten()
+        .and_then(|stream| stream.group(group))
+        .is_some_and(|group| !group.consumers.contains_key(consumer))
+}
+
+/// The stream at `key` if it has a group called `group`.
+fn get_group_stream<'a>(
+    store: &'a mut Store,
+    key: &[u8],
+    group: &[u8],
+) -> Result<Option<&'a mut Stream>, CommandError> {
+    Ok(get_stream_mut(store, key)?.filter(|stream| stream.group(group).is_some()))
+}
+
+fn optional_integer(value: Option<u64>) -> RESPDataType {
+    match value {
+        Some(value) => RESPDataType::Integer(value as i64),
+        None => RESPDataType::NullBulkString,
+    }
+}
+
+/// The ID a group starts from: `$` for the last entry, or an explicit ID.
+fn parse_group_id(arg: &[u8], stream: Option<&Stream>) -> Result<StreamId, CommandError> {
+    match arg {
+        b"$" => Ok(stream.map_or(StreamId::MIN, Stream::last_id)),
+        _ => parse_stream_id(arg, 0),
+    }
+}
+
+fn parse_entries_read(arg: &[u8]) -> Result<Option<u64>, CommandError> {
+    match parse_int(arg)? {
+        -1 => Ok(None),
+        read if read >= 0 => Ok(Some(read as u64)),
+        _ => Err(CommandError::Custom(String::from(
+            "ERR value for ENTRIESREAD must be positive or -1",
+        ))),
+    }
+}
+
+const XGROUP_HELP: &[&str] = &[
+    "XGROUP <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+    "CREATE <key> <groupname> <id|$> [option]",
+    "    Create a new consumer group. Options are:",
+    "    * MKSTREAM",
+    "      Create the empty stream if it does not exist.",
+    "    * ENTRIESREAD entries_read",
+    "      Set the group's entries_read counter (internal use).",
+    "CREATECONSUMER <key> <groupname> <consumer>",
+    "    Create a new consumer in the specified group.",
+    "DELCONSUMER <key> <groupname> <consumer>",
+    "    Remove the specified consumer.",
+    "DESTROY <key> <groupname>",
+    "    Remove the specified group.",
+    "SETID <key> <groupname> <id|$> [ENTRIESREAD entries_read]",
+    "    Set the current group ID and entries_read counter.",
+    "HELP",
+    "    Print this help.",
+];
+
+/// XGROUP CREATE|SETID|DESTROY|CREATECONSUMER|DELCONSUMER|HELP ...
+pub fn handle_xgroup(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, -2)?;
+    let subcommand = args[1].to_ascii_uppercase();
+    if subcommand == b"HELP" {
+        return Ok(help_reply(XGROUP_HELP));
+    }
+    let arity = match subcommand.as_slice() {
+        b"CREATE" | b"SETID" => -5,
+        b"DESTROY" => 4,
+        b"CREATECONSUMER" | b"DELCONSUMER" => 5,
+        _ => return Err(unknown_subcommand(args)),
+    };
+    check_subcommand_arity(args, arity)?;
+    let (key, group) = (&args[2], &args[3]);
+
+    let mut mkstream = false;
+    let mut entries_read = None;
+    if arity < 0 {
+        let mut options = args[5..].iter();
+        while let Some(option) = options.next() {
+            if subcommand == b"CREATE" && is_keyword(option, "MKSTREAM") {
+                mkstream = true;
+            } else if is_keyword(option, "ENTRIESREAD") {
+                let value = options.next().ok_or(CommandError::Syntax)?;
+                entries_read = parse_entries_read(value)?;
+            } else {
+                return Err(CommandError::Syntax);
+            }
+        }
+    }
+
+    let stream = get_stream(store, key)?;
+    if stream.is_none() && !mkstream {
+        return Err(CommandError::Custom(String::from(
+            "ERR The XGROUP subcommand requires the key to exist. Note that for CREATE you may want to use the MKSTREAM option to create an empty stream automatically.",
+        )));
+    }
+    let existing_group = stream.and_then(|stream| stream.group(group));
+    let now = mstime();
+    match subcommand.as_slice() {
+        b"CREATE" => {
+            let id = parse_group_id(&args[4], stream)?;
+            if existing_group.is_some() {
+                return Err(CommandError::Custom(String::from(
+                    "BUSYGROUP Consumer Group name already exists",
+                )));
+            }
+            if stream.is_none() {
+                store.insert_key_val(key.clone(), Value::Stream(Stream::new()));
+            }
+            let stream = get_stream_mut(store, key)?.unwrap();
+            stream.create_group(group.clone(), ConsumerGroup::new(id, entries_read));
+            store.add_dirty(1);
+            Ok(ok())
+        }
+        b"SETID" => {
+            if existing_group.is_none() {
+                return Err(no_such_group_for_key(key, group));
+            }
+            let id = parse_group_id(&args[4], stream)?;
+            let stream = get_stream_mut(store, key)?.unwrap();
+            let group = stream.group_mut(group).unwrap();
+            group.last_id = id;
+            group.entries_read = entries_read;
+            store.add_dirty(1);
+            Ok(ok())
+        }
+        b"DESTROY" => {
+            if existing_group.is_none() {
+                return Ok(RESPDataType::Integer(0));
+            }
+            get_stream_mut(store, key)?.unwrap().destroy_group(group);
+            store.add_dirty(1);
+            // Consumers blocked on the group get to find out it is gone.
+            store.signal_key_as_ready(key);
+            Ok(RESPDataType::Integer(1))
+        }
+        _ => {
+            let existing_group = existing_group.ok_or_else(|| no_such_group_for_key(key, group))?;
+            let consumer = &args[4];
+            let create = subcommand == b"CREATECONSUMER";
+            if existing_group.consumers.contains_key(consumer) == create {
+                return Ok(RESPDataType::Integer(0));
+            }
+            let stream = get_stream_mut(store, key)?.unwrap();
+            let group = stream.group_mut(group).unwrap();
+            let reply = if create {
+                group.create_consumer(consumer, now) as i64
+            } else {
+                group.delete_consumer(consumer).unwrap_or(0) as i64
+            };
+            store.add_dirty(1);
+            Ok(RESPDataType::Integer(reply))
+        }
+    }
+}
+
+/// Where an XREADGROUP starts reading a stream from.
+enum GroupRead {
+    /// `>`: entries never delivered to the group.
+    New,
+    /// The consumer's own pending entries after this ID.
+    History(StreamId),
+}
+
+/// The pending entries of `consumer` after `start`, marked as delivered once
+/// more. Entries deleted from the stream are replied with a null body.
+fn read_history(
+    stream: &mut Stream,
+    group: &[u8],
+    consumer: &Bytes,
+    start: Option<StreamId>,
+    count: usize,
+    now: i64,
+) -> RESPDataType {
+    let replies = stream.with_group(group, |stream, group| {
+        let pending: Vec<StreamId> = match start {
+            Some(start) => group
+                .consumer_mut(consumer, now)
+                .pending
+                .range(start..)
+                .take(count)
+                .copied()
+                .collect(),
+            None => Vec::new(),
+        };
+        pending
+            .into_iter()
+            .map(|id| match stream.get(id) {
+                Some(entry) => {
+                    let pending = group.pending.get_mut(&id).unwrap();
+                    pending.delivery_time = now;
+                    pending.delivery_count += 1;
+                    entry_reply(entry)
+                }
+                None => RESPDataType::Array(vec![id_reply(id), RESPDataType::NullArray]),
+            })
+            .collect()
+    });
+    RESPDataType::Array(replies.unwrap_or_default())
+}
+
+/// XREADGROUP GROUP group consumer [COUNT count] [BLOCK milliseconds] [NOACK] STREAMS key [key ...] id [id ...]
+pub fn handle_xreadgroup(
+    args: &[Bytes],
+    store: &mut Store,
+    may_block: bool,
+) -> Result<CommandOutcome, CommandError> {
+    check_arity(args, -7)?;
+    let options = parse_read_options(args, true)?;
+    let (group, consumer) = options.group.clone().unwrap();
+    let rest = &args[options.streams_index..];
+    let (keys, id_args) = rest.split_at(rest.len() / 2);
+    let mut reads = Vec::with_capacity(keys.len());
+    for (key, id) in keys.iter().zip(id_args) {
+        reads.push(match id.as_ref() {
+            b">" => GroupRead::New,
+            b"$" => {
+                return Err(CommandError::Custom(String::from(
+                    "ERR The $ ID is meaningful only for XREAD command",
+                )))
+            }
+            _ => GroupRead::History(parse_stream_id(id, 0)?),
+        });
+        if get_stream(store, key)?
+            .and_then(|stream| stream.group(&group))
+            .is_none()
+        {
+            return Err(CommandError::Custom(format!(
+                "NOGROUP No such key '{}' or consumer group '{}' in XREADGROUP with GROUP option",
+                String::from_utf8_lossy(key),
+                String::from_utf8_lossy(&group)
+            )));
+        }
+    }
+
+    let now = mstime();
+    let mut replies = Vec::new();
+    for (key, read) in keys.iter().zip(reads) {
+        let created = consumer_created(store, key, &group, &consumer);
+        // Reading always marks the consumer as seen, so the stream changes
+        // even when nothing is delivered.
+        let stream = get_stream_mut(store, key)?.unwrap();
+        let (entries, delivered) = match read {
+            GroupRead::New => {
+                let entries = stream
+                    .read_group(&group, &consumer, options.count, options.noack, now)
+                    .unwrap_or_default();
+                let delivered = entries.len();
+                (
+                    RESPDataType::Array(entries.iter().map(entry_reply).collect()),
+                    delivered,
+                )
+            }
+            GroupRead::History(id) => {
+                let entries =
+                    read_history(stream, &group, &consumer, id.next(), options.count, now);
+                let delivered = match &entries {
+                    RESPDataType::Array(entries) => entries.len(),
+                    _ => 0,
+                };
+                (entries, delivered)
+            }
+        };
+        store.add_dirty(delivered + created as usize);
+        if delivered == 0 && matches!(read, GroupRead::New) {
+            continue;
+        }
+        replies.push(RESPDataType::Array(vec![
+            RESPDataType::BulkString(key.clone()),
+            entries,
+        ]));
+    }
+    let reply = (!replies.is_empty()).then_some(RESPDataType::Array(replies));
+    if reply.is_none() && !options.blocking {
+        return Ok(CommandOutcome::Reply(RESPDataType::NullArray));
+    }
+    reply_or_block(
+        reply,
+        RESPDataType::NullArray,
+        args,
+        keys,
+        options.deadline,
+        may_block,
+    )
+}
+
+/// XACK key group id [id ...]
+pub fn handle_xack(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, -4)?;
+    let ids = args[3..]
+        .iter()
+        .map(|id| parse_stream_id(id, 0))
+        .collect::<Result<Vec<_>, CommandError>>()?;
+    let pending = get_stream(store, &args[1])?
+        .and_then(|stream| stream.group(&args[2]))
+        .is_some_and(|group| ids.iter().any(|id| group.pending.contains_key(id)));
+    if !pending {
+        return Ok(RESPDataType::Integer(0));
+    }
+    let group = get_stream_mut(store, &args[1])?
+        .unwrap()
+        .group_mut(&args[2])
+        .unwrap();
+    let acked = ids.into_iter().filter(|id| group.ack(*id)).count();
+    store.add_dirty(acked);
+    Ok(RESPDataType::Integer(acked as i64))
+}
+
+/// XPENDING key group [[IDLE min-idle-time] start end count [consumer]]
+pub fn handle_xpending(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, -3)?;
+    let (key, group_name) = (&args[1], &args[2]);
+    let mut extended = &args[3..];
+    let mut min_idle = 0;
+    if extended.first().is_some_and(|arg| is_keyword(arg, "IDLE")) && extended.len() > 1 {
+        min_idle = parse_int(&extended[1])?;
+        extended = &extended[2..];
+    }
+    let range = match extended {
+        [] if min_idle == 0 && args.len() == 3 => None,
+        [start, end, count, consumer @ ..] if consumer.len() <= 1 => Some((
+            parse_range_bound(start, true)?,
+            parse_range_bound(end, false)?,
+            parse_int(count)?.max(0) as usize,
+            consumer.first(),
+        )),
+        _ => return Err(CommandError::Syntax),
+    };
+
+    let stream = get_stream(store, key)?;
+    let group = stream
+        .and_then(|stream| stream.group(group_name))
+        .ok_or_else(|| no_such_group(key, group_name))?;
+
+    let Some((start, end, count, consumer)) = range else {
+        let (Some((first, _)), Some((last, _))) = (
+            group.pending.first_key_value(),
+            group.pending.last_key_value(),
+        ) else {
+            return Ok(RESPDataType::Array(vec![
+                RESPDataType::Integer(0),
+                RESPDataType::NullBulkString,
+                RESPDataType::NullBulkString,
+                RESPDataType::NullArray,
+            ]));
+        };
+        let consumers = group
+            .consumers
+            .iter()
+            .filter(|(_, consumer)| !consumer.pending.is_empty())
+            .map(|(name, consumer)| {
+                RESPDataType::Array(vec![
+                    RESPDataType::BulkString(name.clone()),
+                    RESPDataType::BulkString(Bytes::from(consumer.pending.len().to_string())),
+                ])
+            })
+            .collect();
+        return Ok(RESPDataType::Array(vec![
+            RESPDataType::Integer(group.pending.len() as i64),
+            id_reply(*first),
+            id_reply(*last),
+            RESPDataType::Array(consumers),
+        ]));
+    };
+
+    if start > end {
+        return Ok(RESPDataType::Array(Vec::new()));
+    }
+    let ids: Box<dyn Iterator<Item = &StreamId>> = match consumer {
+        Some(consumer) => match group.consumers.get(consumer) {
+            Some(consumer) => Box::new(consumer.pending.range(start..=end)),
+            None => Box::new(std::iter::empty()),
+        },
+        None => Box::new(group.pending.range(start..=end).map(|(id, _)| id)),
+    };
+    let now = mstime();
+    let replies = ids
+        .map(|id| (id, &group.pending[id]))
+        .filter(|(_, pending)| now - pending.delivery_time >= min_idle)
+        .take(count)
+        .map(|(id, pending)| {
+            RESPDataType::Array(vec![
+                id_reply(*id),
+                RESPDataType::BulkString(pending.consumer.clone()),
+                RESPDataType::Integer(now - pending.delivery_time),
+                RESPDataType::Integer(pending.delivery_count as i64),
+            ])
+        })
+        .collect();
+    Ok(RESPDataType::Array(replies))
+}
+
+/// Parse the min-idle-time argument of XCLAIM and XAUTOCLAIM.
+fn parse_min_idle(arg: &[u8], command: &str) -> Result<i64, CommandError> {
+    parse_int(arg).map(|idle| idle.max(0)).map_err(|_| {
+        CommandError::Custom(format!(
+            "ERR Invalid min-idle-time argument for {}",
+            command
+        ))
+    })
+}
+
+/// Hand the pending entry `id` over to `consumer`, as XCLAIM and XAUTOCLAIM do.
+fn claim(
+    group: &mut ConsumerGroup,
+    id: StreamId,
+    consumer: &Bytes,
+    delivery_time: i64,
+    delivery_count: u64,
+    now: i64,
+) {
+    group.assign(id, consumer, delivery_time, delivery_count);
+    let consumer = group.consumer_mut(consumer, now);
+    consumer.active_time = now;
+}
+
+/// XCLAIM key group consumer min-idle-time id [id ...] [IDLE ms] [TIME unix-time-milliseconds] [RETRYCOUNT count] [FORCE] [JUSTID] [LASTID lastid]
+pub fn handle_xclaim(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, -6)?;
+    let (key, group_name, consumer) = (&args[1], &args[2], &args[3]);
+    let min_idle = parse_min_idle(&args[4], "XCLAIM")?;
+    let mut ids = Vec::new();
+    let mut index = 5;
+    while let Some(Ok(id)) = args.get(index).map(|arg| parse_stream_id(arg, 0)) {
+        ids.push(id);
+        index += 1;
+    }
+
+    let now = mstime();
+    let mut delivery_time = now;
+    let mut retry_count = None;
+    let mut force = false;
+    let mut justid = false;
+    let mut last_id = None;
+    while let Some(option) = args.get(index) {
+        let value = args.get(index + 1);
+        let invalid = |name: &str| {
+            CommandError::Custom(format!("ERR Invalid {} option argument for XCLAIM", name))
+        };
+        match (option.to_ascii_uppercase().as_slice(), value) {
+            (b"FORCE", _) => force = true,
+            (b"JUSTID", _) => justid = true,
+            (b"IDLE", Some(value)) => {
+                delivery_time = now - parse_int(value).map_err(|_| invalid("IDLE"))?;
+                index += 1;
+            }
+            (b"TIME", Some(value)) => {
+                delivery_time = parse_int(value).map_err(|_| invalid("TIME"))?;
+                index += 1;
+            }
+            (b"RETRYCOUNT", Some(value)) => {
+                let count = parse_int(value).map_err(|_| invalid("RETRYCOUNT"))?;
+                retry_count = Some(count.max(0) as u64);
+                index += 1;
+            }
+            (b"LASTID", Some(value)) => {
+                last_id = Some(parse_stream_id(value, 0)?);
+                index += 1;
+            }
+            _ => {
+                return Err(CommandError::Custom(format!(
+                    "ERR Unrecognized XCLAIM option '{}'",
+                    String::from_utf8_lossy(option)
+                )))
+            }
+        }
+        index += 1;
+    }
+    if delivery_time < 0 || delivery_time > now {
+        delivery_time = now;
+    }
+
+    let created = consumer_created(store, key, group_name, consumer);
+    let stream =
+        get_group_stream(store, key, group_name)?.ok_or_else(|| no_such_group(key, group_name))?;
+    let mut changes = created as usize;
+    let replies = stream.with_group(group_name, |stream, group| {
+        if let Some(last_id) = last_id.filter(|id| *id > group.last_id) {
+            group.last_id = last_id;
+            changes += 1;
+        }
+        let mut replies = Vec::new();
+        for id in ids {
+            let entry = stream.get(id);
+            let delivery_count = match group.pending.get(&id) {
+                Some(_) if entry.is_none() => {
+                    // The entry was deleted, so there is nothing left to claim.
+                    group.ack(id);
+                    changes += 1;
+                    continue;
+                }
+                Some(pending) if now - pending.delivery_time < min_idle => continue,
+                Some(pending) => pending.delivery_count,
+                None if force && entry.is_some() => 1,
+                None => continue,
+            };
+            let delivery_count = match retry_count {
+                Some(count) => count,
+                None if justid => delivery_count,
+                None => delivery_count + 1,
+            };
+            claim(group, id, consumer, delivery_time, delivery_count, now);
+            changes += 1;
+            replies.push(match entry {
+                Some(entry) if !justid => entry_reply(entry),
+                _ => id_reply(id),
+            });
+        }
+        group.consumer_mut(consumer, now);
+        replies
+    });
+    store.add_dirty(changes);
+    Ok(RESPDataType::Array(replies.unwrap_or_default()))
+}
+
+/// XAUTOCLAIM key group consumer min-idle-time start [COUNT count] [JUSTID]
+pub fn handle_xautoclaim(args: &[Bytes], store: &mut Store) -> CommandResult {
+    /// Pending entries looked at per entry to claim.
+    const ATTEMPTS_FACTOR: i64 = 10;
+
+    check_arity(args, -6)?;
+    let (key, group_name, consumer) = (&args[1], &args[2], &args[3]);
+    let min_idle = parse_min_idle(&args[4], "XAUTOCLAIM")?;
+    let start = parse_range_bound(&args[5], true)?;
+    let mut count = 100;
+    let mut justid = false;
+    let mut options = args[6..].iter();
+    while let Some(option) = options.next() {
+        if is_keyword(option, "JUSTID") {
+            justid = true;
+        } else if is_keyword(option, "COUNT") {
+            let value = options.next().ok_or(CommandError::Syntax)?;
+            count = parse_int(value)?;
+            if !(1..=i64::MAX / ATTEMPTS_FACTOR).contains(&count) {
+                return Err(CommandError::Custom(String::from("ERR COUNT must be > 0")));
+            }
+        } else {
+            return Err(CommandError::Syntax);
+        }
+    }
+
+    let now = mstime();
+    let created = consumer_created(store, key, group_name, consumer);
+    let stream =
+        get_group_stream(store, key, group_name)?.ok_or_else(|| no_such_group(key, group_name))?;
+    let mut changes = created as usize;
+    let reply = stream.with_group(group_name, |stream, group| {
+        let mut attempts = count * ATTEMPTS_FACTOR;
+        let mut claimed = Vec::new();
+        let mut deleted = Vec::new();
+        let candidates: Vec<StreamId> = group
+            .pending
+            .range(start..)
+            .map(|(id, _)| *id)
+            .take(attempts as usize + 1)
+            .collect();
+        let mut cursor = StreamId::MIN;
+        for id in candidates {
+            if attempts == 0 || claimed.len() as i64 == count {
+                cursor = id;
+                break;
+            }
+            attempts -= 1;
+            let Some(entry) = stream.get(id) else {
+                group.ack(id);
+                deleted.push(id_reply(id));
+                continue;
+            };
+            let pending = &group.pending[&id];
+            if now - pending.delivery_time < min_idle {
+                continue;
+            }
+            let delivery_count = pending.delivery_count + if justid { 0 } else { 1 };
+            claim(group, id, consumer, now, delivery_count, now);
+            claimed.push(if justid {
+                id_reply(id)
+            } else {
+                entry_reply(entry)
+            });
+        }
+        group.consumer_mut(consumer, now);
+        changes += claimed.len() + deleted.len();
+        RESPDataType::Array(vec![
+            id_reply(cursor),
+            RESPDataType::Array(claimed),
+            RESPDataType::Array(deleted),
+        ])
+    });
+    store.add_dirty(changes);
+    Ok(reply.unwrap())
+}
+
+fn field(name: &str, value: RESPDataType) -> [RESPDataType; 2] {
+    [
+        RESPDataType::BulkString(Bytes::copy_from_slice(name.as_bytes())),
+        value,
+    ]
+}
+
+fn optional_entry_reply(entry: Option<&StreamEntry>) -> RESPDataType {
+    entry.map_or(RESPDataType::NullBulkString, entry_reply)
+}
+
+fn stream_info(stream: &Stream, full: Option<usize>) -> RESPDataType {
+    let first_id = stream.first_entry().map_or(StreamId::MIN, |entry| entry.id);
+    let mut reply = [
+        field("length", RESPDataType::Integer(stream.len() as i64)),
+        field(
+            "radix-tree-keys",
+            RESPDataType::Integer(stream.radix_tree_keys() as i64),
+        ),
+        field(
+            "radix-tree-nodes",
+            RESPDataType::Integer(stream.radix_tree_nodes() as i64),
+        ),
+        field("last-generated-id", id_reply(stream.last_id())),
+        field("max-deleted-entry-id", id_reply(stream.max_deleted_id())),
+        field(
+            "entries-added",
+            RESPDataType::Integer(stream.entries_added() as i64),
+        ),
+        field("recorded-first-entry-id", id_reply(first_id)),
+    ]
+    .concat();
+
+    let Some(count) = full else {
+        reply.extend(field(
+            "groups",
+            RESPDataType::Integer(stream.groups().len() as i64),
+        ));
+        reply.extend(field(
+            "first-entry",
+            optional_entry_reply(stream.first_entry()),
+        ));
+        reply.extend(field(
+            "last-entry",
+            optional_entry_reply(stream.last_entry()),
+        ));
+        return RESPDataType::Array(reply);
+    };
+
+    let entries = stream.range(StreamId::MIN, StreamId::MAX, false, count);
+    reply.extend(field("entries", entries_reply(&entries)));
+    let groups = stream
+        .groups()
+        .iter()
+        .map(|(name, group)| {
+            let pending = group
+                .pending
+                .iter()
+                .take(count)
+                .map(|(id, pending)| {
+                    RESPDataType::Array(vec![
+                        id_reply(*id),
+                        RESPDataType::BulkString(pending.consumer.clone()),
+                        RESPDataType::Integer(pending.delivery_time),
+                        RESPDataType::Integer(pending.delivery_count as i64),
+                    ])
+                })
+                .collect();
+            let consumers = group
+                .consumers
+                .iter()
+                .map(|(name, consumer)| {
+                    let pending = consumer
+                        .pending
+                        .iter()
+                        .take(count)
+                        .map(|id| {
+                            let pending = &group.pending[id];
+                            RESPDataType::Array(vec![
+                                id_reply(*id),
+                                RESPDataType::Integer(pending.delivery_time),
+                                RESPDataType::Integer(pending.delivery_count as i64),
+                            ])
+                        })
+                        .collect();
+                    RESPDataType::Array(
+                        [
+                            field("name", RESPDataType::BulkString(name.clone())),
+                            field("seen-time", RESPDataType::Integer(consumer.seen_time)),
+                            field("active-time", RESPDataType::Integer(consumer.active_time)),
+                            field(
+                                "pel-count",
+                                RESPDataType::Integer(consumer.pending.len() as i64),
+                            ),
+                            field("pending", RESPDataType::Array(pending)),
+                        ]
+                        .concat(),
+                    )
+                })
+                .collect();
+            RESPDataType::Array(
+                [
+                    field("name", RESPDataType::BulkString(name.clone())),
+                    field("last-delivered-id", id_reply(group.last_id)),
+                    field("entries-read", optional_integer(group.entries_read)),
+                    field("lag", optional_integer(stream.group_lag(group))),
+                    field(
+                        "pel-count",
+                        RESPDataType::Integer(group.pending.len() as i64),
+                    ),
+                    field("pending", RESPDataType::Array(pending)),
+                    field("consumers", RESPDataType::Array(consumers)),
+                ]
+                .concat(),
+            )
+        })
+        .collect();
+    reply.extend(field("groups", RESPDataType::Array(groups)));
+    RESPDataType::Array(reply)
+}
+
+const XINFO_HELP: &[&str] = &[
+    "XINFO <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+    "CONSUMERS <key> <groupname>",
+    "    Show consumers of <groupname>.",
+    "GROUPS <key>",
+    "    Show the stream consumer groups.",
+    "STREAM <key> [FULL [COUNT <count>]",
+    "    Show information about the stream.",
+    "HELP",
+    "    Print this help.",
+];
+
+/// XINFO STREAM|GROUPS|CONSUMERS|HELP ...
+pub fn handle_xinfo(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, -2)?;
+    let subcommand = args[1].to_ascii_uppercase();
+    let arity = match subcommand.as_slice() {
+        b"HELP" => return Ok(help_reply(XINFO_HELP)),
+        b"STREAM" => -3,
+        b"GROUPS" => 3,
+        b"CONSUMERS" => 4,
+        _ => return Err(unknown_subcommand(args)),
+    };
+    check_subcommand_arity(args, arity)?;
+    let key = &args[2];
+    let stream = get_stream(store, key)?.ok_or(CommandError::NoSuchKey)?;
+    let now = mstime();
+    match subcommand.as_slice() {
+        b"STREAM" => {
+            let full = match &args[3..] {
+                [] => None,
+                [option] if is_keyword(option, "FULL") => Some(10),
+                [option, count_option, count]
+                    if is_keyword(option, "FULL") && is_keyword(count_option, "COUNT") =>
+                {
+                    Some(match parse_int(count)? {
+                        count if count <= 0 => usize::MAX,
+                        count => count as usize,
+                    })
+                }
+                _ => return Err(CommandError::Syntax),
+            };
+            Ok(stream_info(stream, full))
+        }
+        b"GROUPS" => Ok(RESPDataType::Array(
+            stream
+                .groups()
+                .iter()
+                .map(|(name, group)| {
+                    RESPDataType::Array(
+                        [
+                            field("name", RESPDataType::BulkString(name.clone())),
+                            field(
+                                "consumers",
+                                RESPDataType::Integer(group.consumers.len() as i64),
+                            ),
+                            field("pending", RESPDataType::Integer(group.pending.len() as i64)),
+                            field("last-delivered-id", id_reply(group.last_id)),
+                            field("entries-read", optional_integer(group.entries_read)),
+                            field("lag", optional_integer(stream.group_lag(group))),
+                        ]
+                        .concat(),
+                    )
+                })
+                .collect(),
+        )),
+        _ => {
+            let group = stream
+                .group(&args[3])
+                .ok_or_else(|| no_such_group_for_key(key, &args[3]))?;
+            Ok(RESPDataType::Array(
+                group
+                    .consumers
+                    .iter()
+                    .map(|(name, consumer)| {
+                        let inactive = match consumer.active_time {
+                            -1 => -1,
+                            active_time => now - active_time,
+                        };
+                        RESPDataType::Array(
+                            [
+                                field("name", RESPDataType::BulkString(name.clone())),
+                                field(
+                                    "pending",
+                                    RESPDataType::Integer(consumer.pending.len() as i64),
+                                ),
+                                field("idle", RESPDataType::Integer(now - consumer.seen_time)),
+                                field("inactive", RESPDataType::Integer(inactive)),
+                            ]
+                            .concat(),
+                        )
+                    })
+                    .collect(),
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::test_helpers::{args, bulk};
+
+    fn entry(id: &str, fields: &[&str]) -> RESPDataType {
+        RESPDataType::Array(vec![
+            bulk(id),
+            RESPDataType::Array(fields.iter().map(|field| bulk(field)).collect()),
+        ])
+    }
+
+    fn event_log() -> Store {
+        let mut store = Store::init();
+        for id in ["1-1", "1-2", "2-0", "3-0"] {
+            handle_xadd(&args(&["XADD", "s", id, "id", id]), &mut store).unwrap();
+        }
+        store
+    }
+
+    #[test]
+    fn test_xadd_ids() {
+        let mut store = event_log();
+        assert_eq!(
+            handle_xadd(&args(&["XADD", "s", "3-*", "f", "v"]), &mut store),
+            Ok(bulk("3-1"))
+        );
+        assert_eq!(
+            handle_xadd(&args(&["XADD", "s", "5", "f", "v"]), &mut store),
+            Ok(bulk("5-0"))
+        );
+        assert!(handle_xadd(&args(&["XADD", "s", "5-0", "f", "v"]), &mut store).is_err());
+        assert!(handle_xadd(&args(&["XADD", "s", "4-*", "f", "v"]), &mut store).is_err());
+        assert!(handle_xadd(&args(&["XADD", "new", "0-0", "f", "v"]), &mut store).is_err());
+        assert!(handle_xadd(&args(&["XADD", "new", "1-x", "f", "v"]), &mut store).is_err());
+        assert_eq!(
+            handle_xadd(&args(&["XADD", "new", "0-*", "f", "v"]), &mut store),
+            Ok(bulk("0-1"))
+        );
+        assert!(matches!(
+            handle_xadd(&args(&["XADD", "s", "*", "f", "v"]), &mut store),
+            Ok(RESPDataType::BulkString(_))
+        ));
+        assert_eq!(
+            handle_xadd(&args(&["XADD", "s", "*", "f"]), &mut store),
+            Err(CommandError::WrongNumberOfArguments(String::from("xadd")))
+        );
+        assert_eq!(
+            handle_xadd(
+                &args(&["XADD", "none", "NOMKSTREAM", "*", "f", "v"]),
+                &mut store
+            ),
+            Ok(RESPDataType::NullBulkString)
+        );
+        assert!(store.get_from_key_val_store(b"none").is_none());
+    }
+
+    #[test]
+    fn test_xrange_bounds() {
+        let mut store = event_log();
+        assert_eq!(
+            handle_xrange(&args(&["XRANGE", "s", "-", "+", "COUNT", "2"]), &mut store),
+            Ok(RESPDataType::Array(vec![
+                entry("1-1", &["id", "1-1"]),
+                entry("1-2", &["id", "1-2"])
+            ]))
+        );
+        assert_eq!(
+            handle_xrange(&args(&["XRANGE", "s", "(1-1", "2"]), &mut store),
+            Ok(RESPDataType::Array(vec![
+                entry("1-2", &["id", "1-2"]),
+                entry("2-0", &["id", "2-0"])
+            ]))
+        );
+        assert_eq!(
+            handle_xrevrange(&args(&["XREVRANGE", "s", "+", "(2-0"]), &mut store),
+            Ok(RESPDataType::Array(vec![entry("3-0", &["id", "3-0"])]))
+        );
+        assert_eq!(
+            handle_xrange(&args(&["XRANGE", "s", "1", "1"]), &mut store),
+            Ok(RESPDataType::Array(vec![
+                entry("1-1", &["id", "1-1"]),
+                entry("1-2", &["id", "1-2"])
+            ]))
+        );
+        assert!(handle_xrange(
+            &args(&[
+                "XRANGE",
+                "s",
+                "(18446744073709551615-18446744073709551615",
+                "+"
+            ]),
+            &mut store
+        )
+        .is_err());
+        assert!(handle_xrange(&args(&["XRANGE", "s", "x", "+"]), &mut store).is_err());
+    }
+
+    #[test]
+    fn test_xdel_xtrim_xlen() {
+        let mut store = event_log();
+        assert_eq!(
+            handle_xdel(&args(&["XDEL", "s", "1-2", "9-9"]), &mut store),
+            Ok(RESPDataType::Integer(1))
+        );
+        assert_eq!(
+            handle_xlen(&args(&["XLEN", "s"]), &mut store),
+            Ok(RESPDataType::Integer(3))
+        );
+        assert_eq!(
+            handle_xtrim(&args(&["XTRIM", "s", "MAXLEN", "2"]), &mut store),
+            Ok(RESPDataType::Integer(1))
+        );
+        assert_eq!(
+            handle_xtrim(&args(&["XTRIM", "s", "MINID", "=", "3"]), &mut store),
+            Ok(RESPDataType::Integer(1))
+        );
+        assert_eq!(
+            handle_xtrim(&args(&["XTRIM", "s", "MAXLEN", "~", "0"]), &mut store),
+            Ok(RESPDataType::Integer(1))
+        );
+        assert_eq!(
+            handle_xlen(&args(&["XLEN", "s"]), &mut store),
+            Ok(RESPDataType::Integer(0))
+        );
+        assert!(store.get_from_key_val_store(b"s").is_some());
+        assert!(handle_xtrim(
+            &args(&["XTRIM", "s", "MAXLEN", "1", "LIMIT", "1"]),
+            &mut store
+        )
+        .is_err());
+        assert_eq!(
+            handle_xadd(
+                &args(&["XADD", "s", "MAXLEN", "1", "9-0", "f", "v"]),
+                &mut store
+            ),
+            Ok(bulk("9-0"))
+        );
+        assert_eq!(
+            handle_xadd(
+                &args(&["XADD", "s", "MAXLEN", "1", "10-0", "f", "v"]),
+                &mut store
+            ),
+            Ok(bulk("10-0"))
+        );
+        assert_eq!(
+            handle_xlen(&args(&["XLEN", "s"]), &mut store),
+            Ok(RESPDataType::Integer(1))
+        );
+    }
+
+    #[test]
+    fn test_xread() {
+        let mut store = event_log();
+        let reply = |outcome: Result<CommandOutcome, CommandError>| match outcome {
+            Ok(CommandOutcome::Reply(reply)) => Some(reply),
+            Ok(CommandOutcome::Block(_)) => None,
+            Err(e) => panic!("unexpected error {}", e),
+        };
+        assert_eq!(
+            reply(handle_xread(
+                &args(&["XREAD", "COUNT", "1", "STREAMS", "s", "missing", "2", "0"]),
+                &mut store,
+                true
+            )),
+            Some(RESPDataType::Array(vec![RESPDataType::Array(vec![
+                bulk("s"),
+                RESPDataType::Array(vec![entry("3-0", &["id", "3-0"])])
+            ])]))
+        );
+        assert_eq!(
+            reply(handle_xread(
+                &args(&["XREAD", "STREAMS", "s", "$"]),
+                &mut store,
+                true
+            )),
+            Some(RESPDataType::NullArray)
+        );
+        match handle_xread(
+            &args(&["XREAD", "BLOCK", "0", "STREAMS", "s", "$"]),
+            &mut store,
+            true,
+        ) {
+            Ok(CommandOutcome::Block(request)) => {
+                assert_eq!(request.keys, args(&["s"]));
+                assert_eq!(request.args.last(), Some(&Bytes::from("3-0")));
+            }
+            _ => panic!("expected to block"),
+        }
+        assert!(handle_xread(&args(&["XREAD", "STREAMS", "s"]), &mut store, true).is_err());
+        assert!(handle_xread(
+            &args(&["XREAD", "BLOCK", "-1", "STREAMS", "s", "$"]),
+            &mut store,
+            true
+        )
+        .is_err());
+    }
+
+    fn reply(outcome: Result<CommandOutcome, CommandError>) -> RESPDataType {
+        match outcome {
+            Ok(CommandOutcome::Reply(reply)) => reply,
+            Ok(CommandOutcome::Block(_)) => panic!("unexpected block"),
+            Err(e) => panic!("unexpected error {}", e),
+        }
+    }
+
+    fn grouped_log() -> Store {
+        let mut store = event_log();
+        handle_xgroup(&args(&["XGROUP", "CREATE", "s", "g", "0"]), &mut store).unwrap();
+        store
+    }
+
+    #[test]
+    fn test_xgroup() {
+        let mut store = grouped_log();
+        assert!(handle_xgroup(&args(&["XGROUP", "CREATE", "s", "g", "$"]), &mut store).is_err());
+        assert!(handle_xgroup(&args(&["XGROUP", "CREATE", "none", "g", "$"]), &mut store).is_err());
+        assert_eq!(
+            handle_xgroup(
+                &args(&["XGROUP", "CREATE", "none", "g", "$", "MKSTREAM"]),
+                &mut store
+            ),
+            Ok(ok())
+        );
+        assert_eq!(
+            handle_xlen(&args(&["XLEN", "none"]), &mut store),
+            Ok(RESPDataType::Integer(0))
+        );
+        assert_eq!(
+            handle_xgroup(
+                &args(&["XGROUP", "CREATECONSUMER", "s", "g", "alice"]),
+                &mut store
+            ),
+            Ok(RESPDataType::Integer(1))
+        );
+        assert_eq!(
+            handle_xgroup(
+                &args(&["XGROUP", "CREATECONSUMER", "s", "g", "alice"]),
+                &mut store
+            ),
+            Ok(RESPDataType::Integer(0))
+        );
+        assert!(handle_xgroup(&args(&["XGROUP", "SETID", "s", "nope", "$"]), &mut store).is_err());
+        assert_eq!(
+            handle_xgroup(
+                &args(&["XGROUP", "SETID", "s", "g", "$", "ENTRIESREAD", "4"]),
+                &mut store
+            ),
+            Ok(ok())
+        );
+        assert_eq!(
+            handle_xgroup(&args(&["XGROUP", "DESTROY", "s", "g"]), &mut store),
+            Ok(RESPDataType::Integer(1))
+        );
+        assert_eq!(
+            handle_xgroup(&args(&["XGROUP", "FOO"]), &mut store),
+            Err(CommandError::Custom(String::from(
+                "ERR unknown subcommand 'FOO'. Try XGROUP HELP."
+            )))
+        );
+    }
+
+    #[test]
+    fn test_xreadgroup_and_pending() {
+        let mut store = grouped_log();
+        let read = |store: &mut Store, consumer: &str, id: &str| {
+            reply(handle_xreadgroup(
+                &args(&[
+                    "XREADGROUP",
+                    "GROUP",
+                    "g",
+                    consumer,
+                    "COUNT",
+                    "2",
+                    "STREAMS",
+                    "s",
+                    id,
+                ]),
+                store,
+                true,
+            ))
+        };
+        assert_eq!(
+            read(&mut store, "alice", ">"),
+            RESPDataType::Array(vec![RESPDataType::Array(vec![
+                bulk("s"),
+                RESPDataType::Array(vec![
+                    entry("1-1", &["id", "1-1"]),
+                    entry("1-2", &["id", "1-2"])
+                ])
+            ])])
+        );
+        read(&mut store, "bob", ">");
+        assert_eq!(read(&mut store, "alice", ">"), RESPDataType::NullArray);
+        assert_eq!(
+            read(&mut store, "alice", "1-1"),
+            RESPDataType::Array(vec![RESPDataType::Array(vec![
+                bulk("s"),
+                RESPDataType::Array(vec![entry("1-2", &["id", "1-2"])])
+            ])])
+        );
+        assert!(handle_xreadgroup(
+            &args(&["XREADGROUP", "GROUP", "nope", "c", "STREAMS", "s", ">"]),
+            &mut store,
+            true
+        )
+        .is_err());
+        assert!(matches!(
+            handle_xreadgroup(
+                &args(&[
+                    "XREADGROUP",
+                    "GROUP",
+                    "g",
+                    "c",
+                    "BLOCK",
+                    "0",
+                    "STREAMS",
+                    "s",
+                    ">"
+                ]),
+                &mut store,
+                true
+            ),
+            Ok(CommandOutcome::Block(_))
+        ));
+
+        assert_eq!(
+            handle_xpending(&args(&["XPENDING", "s", "g"]), &mut store),
+            Ok(RESPDataType::Array(vec![
+                RESPDataType::Integer(4),
+                bulk("1-1"),
+                bulk("3-0"),
+                RESPDataType::Array(vec![
+                    RESPDataType::Array(vec![bulk("alice"), bulk("2")]),
+                    RESPDataType::Array(vec![bulk("bob"), bulk("2")]),
+                ])
+            ]))
+        );
+        assert_eq!(
+            handle_xack(&args(&["XACK", "s", "g", "1-1", "1-1", "9-9"]), &mut store),
+            Ok(RESPDataType::Integer(1))
+        );
+        match handle_xpending(
+            &args(&["XPENDING", "s", "g", "-", "+", "10", "alice"]),
+            &mut store,
+        ) {
+            Ok(RESPDataType::Array(entries)) => match &entries[..] {
+                [RESPDataType::Array(fields)] => {
+                    assert_eq!(fields[0], bulk("1-2"));
+                    assert_eq!(fields[1], bulk("alice"));
+                    assert_eq!(fields[3], RESPDataType::Integer(2));
+                }
+                _ => panic!("expected a single pending entry"),
+            },
+            other => panic!("unexpected reply {:?}", other),
+        }
+        assert_eq!(
+            handle_xpending(
+                &args(&["XPENDING", "s", "g", "IDLE", "100000", "-", "+", "10"]),
+                &mut store
+            ),
+            Ok(RESPDataType::Array(Vec::new()))
+        );
+    }
+
+    #[test]
+    fn test_xclaim_and_xautoclaim() {
+        let mut store = grouped_log();
+        reply(handle_xreadgroup(
+            &args(&["XREADGROUP", "GROUP", "g", "alice", "STREAMS", "s", ">"]),
+            &mut store,
+            true,
+        ));
+        assert_eq!(
+            handle_xclaim(
+                &args(&["XCLAIM", "s", "g", "bob", "100000", "1-1"]),
+                &mut store
+            ),
+            Ok(RESPDataType::Array(Vec::new()))
+        );
+        assert_eq!(
+            handle_xclaim(
+                &args(&["XCLAIM", "s", "g", "bob", "0", "1-1", "1-2", "JUSTID"]),
+                &mut store
+            ),
+            Ok(RESPDataType::Array(vec![bulk("1-1"), bulk("1-2")]))
+        );
+        handle_xdel(&args(&["XDEL", "s", "2-0"]), &mut store).unwrap();
+        assert_eq!(
+            handle_xautoclaim(
+                &args(&["XAUTOCLAIM", "s", "g", "carol", "0", "0", "COUNT", "1"]),
+                &mut store
+            ),
+            Ok(RESPDataType::Array(vec![
+                bulk("1-2"),
+                RESPDataType::Array(vec![entry("1-1", &["id", "1-1"])]),
+                RESPDataType::Array(Vec::new()),
+            ]))
+        );
+        assert_eq!(
+            handle_xautoclaim(
+                &args(&["XAUTOCLAIM", "s", "g", "carol", "0", "(1-1", "JUSTID"]),
+                &mut store
+            ),
+            Ok(RESPDataType::Array(vec![
+                bulk("0-0"),
+                RESPDataType::Array(vec![bulk("1-2"), bulk("3-0")]),
+                RESPDataType::Array(vec![bulk("2-0")]),
+            ]))
+        );
+        let stream = get_stream(&store, b"s").unwrap().unwrap();
+        let group = stream.group(b"g").unwrap();
+        assert_eq!(group.pending.len(), 3);
+        assert_eq!(group.consumers[&Bytes::from("carol")].pending.len(), 3);
+        assert_eq!(group.pending[&StreamId::new(1, 1)].delivery_count, 2);
+        assert!(handle_xautoclaim(
+            &args(&["XAUTOCLAIM", "s", "g", "carol", "0", "0", "COUNT", "0"]),
+            &mut store
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_xinfo() {
+        let mut store = grouped_log();
+        reply(handle_xreadgroup(
+            &args(&[
+                "XREADGROUP",
+                "GROUP",
+                "g",
+                "alice",
+                "COUNT",
+                "1",
+                "STREAMS",
+                "s",
+                ">",
+            ]),
+            &mut store,
+            true,
+        ));
+        let Ok(RESPDataType::Array(info)) =
+            handle_xinfo(&args(&["XINFO", "STREAM", "s"]), &mut store)
+        else {
+            panic!("expected an array");
+        };
+        assert_eq!(&info[..2], [bulk("length"), RESPDataType::Integer(4)]);
+        assert_eq!(info[19], entry("3-0", &["id", "3-0"]));
+        assert_eq!(
+            handle_xinfo(&args(&["XINFO", "GROUPS", "s"]), &mut store),
+            Ok(RESPDataType::Array(vec![RESPDataType::Array(vec![
+                bulk("name"),
+                bulk("g"),
+                bulk("consumers"),
+                RESPDataType::Integer(1),
+                bulk("pending"),
+                RESPDataType::Integer(1),
+                bulk("last-delivered-id"),
+                bulk("1-1"),
+                bulk("entries-read"),
+                RESPDataType::Integer(1),
+                bulk("lag"),
+                RESPDataType::Integer(3),
+            ])]))
+        );
+        assert!(handle_xinfo(&args(&["XINFO", "CONSUMERS", "s", "g"]), &mut store).is_ok());
+        assert!(handle_xinfo(&args(&["XINFO", "STREAM", "s", "FULL"]), &mut store).is_ok());
+        assert_eq!(
+            handle_xinfo(&args(&["XINFO", "GROUPS", "none"]), &mut store),
+            Err(CommandError::NoSuchKey)
+        );
+    }
+}
@@ -0,0 +1,1596 @@
+use std::collections::HashMap;
+
+use bytes::Bytes;
+
+use super::{
+    check_arity, format_double, is_keyword, normalize_range, parse_float, parse_int,
+    parse_random_count, parse_scan_options, random_repeated_indexes, scan_reply, CommandError,
+    CommandResult, ScanTarget,
+};
+use crate::blocking::{parse_timeout, reply_or_block, CommandOutcome};
+use crate::resp::data::RESPDataType;
+use crate::store::{Store, Value};
+use crate::types::set::Set;
+use crate::types::zset::{LexBound, LexRange, ScoreRange, ZSet};
+use crate::util::{random_distinct_indexes, random_index};
+
+pub fn get_zset<'a>(store: &'a Store, key: &[u8]) -> Result<Option<&'a ZSet>, CommandError> {
+    match store.get_from_key_val_store(key) {
+        None => Ok(None),
+        Some(Value::ZSet(zset)) => Ok(Some(zset)),
+        Some(_) => Err(CommandError::WrongType),
+    }
+}
+
+pub fn get_zset_mut<'a>(
+    store: &'a mut Store,
+    key: &[u8],
+) -> Result<Option<&'a mut ZSet>, CommandError> {
+    match store.get_mut_from_key_val_store(key) {
+        None => Ok(None),
+        Some(Value::ZSet(zset)) => Ok(Some(zset)),
+        Some(_) => Err(CommandError::WrongType),
+    }
+}
+
+/// Get the sorted set at `key`, creating an empty one if the key does not exist.
+/// Callers must delete the key again if they end up adding nothing.
+fn get_or_create_zset<'a>(store: &'a mut Store, key: &Bytes) -> Result<&'a mut ZSet, CommandError> {
+    if get_zset(store, key)?.is_none() {
+        store.insert_key_val(key.clone(), Value::ZSet(ZSet::new()));
+    }
+    Ok(get_zset_mut(store, key)?.unwrap())
+}
+
+/// Replace whatever `destination` holds with `zset`, deleting it if the set is
+/// empty, and reply with the set's cardinality as the STORE commands do.
+pub fn store_zset(store: &mut Store, destination: &Bytes, zset: ZSet) -> CommandResult {
+    let len = zset.len();
+    store.delete_key(destination, store.lazyfree.server_del);
+    if len > 0 {
+        store.insert_key_val(destination.clone(), Value::ZSet(zset));
+    }
+    Ok(RESPDataType::Integer(len as i64))
+}
+
+fn score_reply(score: Option<f64>) -> RESPDataType {
+    score
+        .map(|score| RESPDataType::BulkString(format_double(score)))
+        .unwrap_or(RESPDataType::NullBulkString)
+}
+
+/// Reply with the members, each followed by its score if `with_scores`.
+pub fn elements_reply(elements: &[(Bytes, f64)], with_scores: bool) -> RESPDataType {
+    RESPDataType::Array(
+        elements
+            .iter()
+            .flat_map(|(member, score)| {
+                let member = RESPDataType::BulkString(member.clone());
+                if with_scores {
+                    vec![member, RESPDataType::BulkString(format_double(*score))]
+                } else {
+                    vec![member]
+                }
+            })
+            .collect(),
+    )
+}
+
+/// Parse a score interval bound: a float, optionally prefixed by `(` to exclude it.
+fn parse_score_bound(arg: &[u8]) -> Result<(f64, bool), CommandError> {
+    let (value, exclusive) = match arg.strip_prefix(b"(") {
+        Some(value) => (value, true),
+        None => (arg, false),
+    };
+    parse_float(value)
+        .map(|value| (value, exclusive))
+        .map_err(|_| CommandError::Custom(String::from("ERR min or max is not a float")))
+}
+
+pub fn parse_score_range(min: &[u8], max: &[u8]) -> Result<ScoreRange, CommandError> {
+    let (min, min_exclusive) = parse_score_bound(min)?;
+    let (max, max_exclusive) = parse_score_bound(max)?;
+    Ok(ScoreRange {
+        min,
+        max,
+        min_exclusive,
+        max_exclusive,
+    })
+}
+
+/// Parse a lexicographical interval bound: `-`, `+`, or a member prefixed by
+/// `[` to include it or `(` to exclude it.
+fn parse_lex_bound(arg: &Bytes) -> Result<LexBound, CommandError> {
+    match arg.first() {
+        Some(b'-') if arg.len() == 1 => Ok(LexBound::Min),
+        Some(b'+') if arg.len() == 1 => Ok(LexBound::Max),
+        Some(b'[') => Ok(LexBound::Inclusive(arg.slice(1..))),
+        Some(b'(') => Ok(LexBound::Exclusive(arg.slice(1..))),
+        _ => Err(CommandError::Custom(String::from(
+            "ERR min or max not valid string range item",
+        ))),
+    }
+}
+
+pub fn parse_lex_range(min: &Bytes, max: &Bytes) -> Result<LexRange, CommandError> {
+    Ok(LexRange {
+        min: parse_lex_bound(min)?,
+        max: parse_lex_bound(max)?,
+    })
+}
+
+#[derive(Debug, Default)]
+struct ZAddFlags {
+    nx: bool,
+    xx: bool,
+    gt: bool,
+    lt: bool,
+    ch: bool,
+    incr: bool,
+}
+
+/// Outcome of adding a single element with ZADD's flags.
+enum ZAddResult {
+    Added(f64),
+    Updated(f64),
+    Unchanged(f64),
+    /// The flags prevented the operation.
+    Skipped,
+}
+
+/// What adding a single element would do, without doing it.
+fn zadd_outcome(
+    zset: &ZSet,
+    member: &Bytes,
+    score: f64,
+    flags: &ZAddFlags,
+) -> Result<ZAddResult, CommandError> {
+    let Some(current) = zset.score(member) else {
+        if flags.xx {
+            return Ok(ZAddResult::Skipped);
+        }
+        return Ok(ZAddResult::Added(score));
+    };
+    if flags.nx {
+        return Ok(ZAddResult::Skipped);
+    }
+    let score = if flags.incr {
+        let score = current + score;
+        if score.is_nan() {
+            return Err(CommandError::Custom(String::from(
+                "ERR resulting score is not a number (NaN)",
+            )));
+        }
+        score
+    } else {
+        score
+    };
+    if (flags.gt && score <= current) || (flags.lt && score >= current) {
+        return Ok(ZAddResult::Skipped);
+    }
+    if score == current {
+        return Ok(ZAddResult::Unchanged(score));
+    }
+    Ok(ZAddResult::Updated(score))
+}
+
+fn zadd_element(
+    zset: &mut ZSet,
+    member: &Bytes,
+    score: f64,
+    flags: &ZAddFlags,
+) -> Result<ZAddResult, CommandError> {
+    let outcome = zadd_outcome(zset, member, score, flags)?;
+    if let ZAddResult::Added(score) | ZAddResult::Updated(score) = outcome {
+        zset.insert(member.clone(), score);
+    }
+    Ok(outcome)
+}
+
+fn zadd_reply(flags: &ZAddFlags, last: Option<f64>, added: i64, updated: i64) -> RESPDataType {
+    if flags.incr {
+        score_reply(last)
+    } else if flags.ch {
+        RESPDataType::Integer(added + updated)
+    } else {
+        RESPDataType::Integer(added)
+    }
+}
+
+/// ZADD key [NX|XX] [GT|LT] [CH] [INCR] score member [score member ...]
+pub fn handle_zadd(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, -4)?;
+    let mut flags = ZAddFlags::default();
+    let mut index = 2;
+    while let Some(arg) = args.get(index) {
+        match arg.to_ascii_uppercase().as_slice() {
+            b"NX" => flags.nx = true,
+            b"XX" => flags.xx = true,
+            b"GT" => flags.gt = true,
+            b"LT" => flags.lt = true,
+            b"CH" => flags.ch = true,
+            b"INCR" => flags.incr = true,
+            _ => break,
+        }
+        index += 1;
+    }
+
+    let pairs = &args[index..];
+    if pairs.is_empty() || !pairs.len().is_multiple_of(2) {
+        return Err(CommandError::Syntax);
+    }
+    if flags.nx && flags.xx {
+        return Err(CommandError::Custom(String::from(
+            "ERR XX and NX options at the same time are not compatible",
+        )));
+    }
+    if (flags.nx && (flags.gt || flags.lt)) || (flags.gt && flags.lt) {
+        return Err(CommandError::Custom(String::from(
+            "ERR GT, LT, and/or NX options at the same time are not compatible",
+        )));
+    }
+    if flags.incr && pairs.len() > 2 {
+        return Err(CommandError::Custom(String::from(
+            "ERR INCR option supports a single increment-element pair",
+        )));
+    }
+    let elements = pairs
+        .chunks(2)
+        .map(|pair| Ok((parse_float(&pair[0])?, &pair[1])))
+        .collect::<Result<Vec<_>, CommandError>>()?;
+
+    let key = &args[1];
+    match get_zset(store, key)? {
+        None if flags.xx => return Ok(zadd_reply(&flags, None, 0, 0)),
+        None => {}
+        Some(zset) => {
+            // Only look the set up for modification once an element changes
+            // it, as that copies a set a snapshot still holds.
+            let mut last = None;
+            let mut changes = false;
+            for (score, member) in &elements {
+                match zadd_outcome(zset, member, *score, &flags)? {
+                    ZAddResult::Added(_) | ZAddResult::Updated(_) => {
+                        changes = true;
+                        break;
+                    }
+                    ZAddResult::Unchanged(score) => last = Some(score),
+                    ZAddResult::Skipped => last = None,
+                }
+            }
+            if !changes {
+                return Ok(zadd_reply(&flags, last, 0, 0));
+            }
+        }
+    }
+    let zset = get_or_create_zset(store, key)?;
+    let mut added = 0;
+    let mut updated = 0;
+    let mut last = None;
+    let mut outcome = Ok(());
+    for (score, member) in elements {
+        match zadd_element(zset, member, score, &flags) {
+            Ok(ZAddResult::Added(score)) => {
+                added += 1;
+                last = Some(score);
+            }
+            Ok(ZAddResult::Updated(score)) => {
+                updated += 1;
+                last = Some(score);
+            }
+            Ok(ZAddResult::Unchanged(score)) => last = Some(score),
+            Ok(ZAddResult::Skipped) => last = None,
+            Err(e) => {
+                outcome = Err(e);
+                break;
+            }
+        }
+    }
+    store.add_dirty((added + updated) as usize);
+    store.remove_if_empty(key);
+    outcome?;
+
+    Ok(zadd_reply(&flags, last, added, updated))
+}
+
+/// ZINCRBY key increment member
+pub fn handle_zincrby(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, 4)?;
+    let zadd_args = [
+        Bytes::from("ZADD"),
+        args[1].clone(),
+        Bytes::from("INCR"),
+        args[2].clone(),
+        args[3].clone(),
+    ];
+    handle_zadd(&zadd_args, store)
+}
+
+/// ZSCORE key member
+pub fn handle_zscore(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, 3)?;
+    let zset = get_zset(store, &args[1])?;
+    Ok(score_reply(zset.and_then(|zset| zset.score(&args[2]))))
+}
+
+/// ZMSCORE key member [member ...]
+pub fn handle_zmscore(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, -3)?;
+    let zset = get_zset(store, &args[1])?;
+    Ok(RESPDataType::Array(
+        args[2..]
+            .iter()
+            .map(|member| score_reply(zset.and_then(|zset| zset.score(member))))
+            .collect(),
+    ))
+}
+
+/// ZCARD key
+pub fn handle_zcard(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, 2)?;
+    let len = get_zset(store, &args[1])?.map_or(0, |zset| zset.len());
+    Ok(RESPDataType::Integer(len as i64))
+}
+
+/// ZSCAN key cursor [MATCH pattern] [COUNT count] [NOSCORES]
+pub fn handle_zscan(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, -3)?;
+    let options = parse_scan_options(args, 2, ScanTarget::ZSet)?;
+    let Some(zset) = get_zset(store, &args[1])? else {
+        return Ok(scan_reply(0, vec![]));
+    };
+    let mut elements = Vec::new();
+    let cursor = zset.scan(options.cursor, options.count, |member, score| {
+        if options.matches(member) {
+            elements.push(Bytes::copy_from_slice(member));
+            if !options.no_values {
+                elements.push(format_double(score));
+            }
+        }
+    });
+    Ok(scan_reply(cursor, elements))
+}
+
+/// ZCOUNT key min max
+pub fn handle_zcount(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, 4)?;
+    let range = parse_score_range(&args[2], &args[3])?;
+    let count = get_zset(store, &args[1])?.map_or(0, |zset| {
+        let (start, end) = zset.score_range_ranks(&range);
+        end - start
+    });
+    Ok(RESPDataType::Integer(count as i64))
+}
+
+fn rank(args: &[Bytes], store: &mut Store, rev: bool) -> CommandResult {
+    check_arity(args, -3)?;
+    let with_score = match &args[3..] {
+        [] => false,
+        [option] if is_keyword(option, "WITHSCORE") => true,
+        _ => return Err(CommandError::Syntax),
+    };
+    let zset = get_zset(store, &args[1])?;
+    let found = zset.and_then(|zset| {
+        let rank = zset.rank(&args[2])?;
+        let rank = if rev { zset.len() - 1 - rank } else { rank };
+        Some((rank, zset.score(&args[2]).unwrap()))
+    });
+    Ok(match (found, with_score) {
+        (None, false) => RESPDataType::NullBulkString,
+        (None, true) => RESPDataType::NullArray,
+        (Some((rank, _)), false) => RESPDataType::Integer(rank as i64),
+        (Some((rank, score)), true) => RESPDataType::Array(vec![
+            RESPDataType::Integer(rank as i64),
+            RESPDataType::BulkString(format_double(score)),
+        ]),
+    })
+}
+
+/// ZRANK key member [WITHSCORE]
+pub fn handle_zrank(args: &[Bytes], store: &mut Store) -> CommandResult {
+    rank(args, store, false)
+}
+
+/// ZREVRANK key member [WITHSCORE]
+pub fn handle_zrevrank(args: &[Bytes], store: &mut Store) -> CommandResult {
+    rank(args, store, true)
+}
+
+/// ZREM key member [member ...]
+pub fn handle_zrem(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, -3)?;
+    let key = &args[1];
+    if !get_zset(store, key)?
+        .is_some_and(|zset| args[2..].iter().any(|member| zset.score(member).is_some()))
+    {
+        return Ok(RESPDataType::Integer(0));
+    }
+    let zset = get_zset_mut(store, key)?.unwrap();
+    let removed = args[2..]
+        .iter()
+        .filter(|member| zset.remove(member))
+        .count();
+    store.add_dirty(removed);
+    store.remove_if_empty(key);
+    Ok(RESPDataType::Integer(removed as i64))
+}
+
+/// How ZRANGE interprets its start and stop arguments.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RangeBy {
+    Rank,
+    Score,
+    Lex,
+}
+
+/// The arguments shared by ZRANGE and ZRANGESTORE, following the key.
+struct RangeSpec<'a> {
+    start: &'a Bytes,
+    stop: &'a Bytes,
+    by: RangeBy,
+    rev: bool,
+    /// Offset and count; a negative count means no limit.
+    limit: Option<(i64, i64)>,
+    with_scores: bool,
+}
+
+fn parse_range_spec(
+    args: &[Bytes],
+    allow_with_scores: bool,
+) -> Result<RangeSpec<'_>, CommandError> {
+    let mut spec = RangeSpec {
+        start: &args[0],
+        stop: &args[1],
+        by: RangeBy::Rank,
+        rev: false,
+        limit: None,
+        with_scores: false,
+    };
+    let mut index = 2;
+    while let Some(arg) = args.get(index) {
+        match arg.to_ascii_uppercase().as_slice() {
+            b"BYSCORE" => spec.by = RangeBy::Score,
+            b"BYLEX" => spec.by = RangeBy::Lex,
+            b"REV" => spec.rev = true,
+            b"WITHSCORES" if allow_with_scores => spec.with_scores = true,
+            b"LIMIT" if index + 2 < args.len() => {
+                let offset = parse_int(&args[index + 1])?;
+                let count = parse_int(&args[index + 2])?;
+                spec.limit = Some((offset, count));
+                index += 2;
+            }
+            _ => return Err(CommandError::Syntax),
+        }
+        index += 1;
+    }
+    if spec.limit.is_some() && spec.by == RangeBy::Rank {
+        return Err(CommandError::Custom(String::from(
+            "ERR syntax error, LIMIT is only supported in combination with either BYSCORE or BYLEX",
+        )));
+    }
+    if spec.with_scores && spec.by == RangeBy::Lex {
+        return Err(CommandError::Custom(String::from(
+            "ERR syntax error, WITHSCORES not supported in combination with BYLEX",
+        )));
+    }
+    Ok(spec)
+}
+
+/// Resolve a range spec against a sorted set, returning the elements in reply order.
+fn select_range(zset: &ZSet, spec: &RangeSpec) -> Result<Vec<(Bytes, f64)>, CommandError> {
+    let (start, end) = match spec.by {
+        RangeBy::Rank => {
+            let start = parse_int(spec.start)?;
+            let stop = parse_int(spec.stop)?;
+            let len = zset.len();
+            match normalize_range(start, stop, len) {
+                None => (0, 0),
+                Some((start, stop)) if spec.rev => (len - 1 - stop, len - start),
+                Some((start, stop)) => (start, stop + 1),
+            }
+        }
+        RangeBy::Score => {
+            let (min, max) = if spec.rev {
+                (spec.stop, spec.start)
+            } else {
+                (spec.start, spec.stop)
+            };
+            zset.score_range_ranks(&parse_score_range(min, max)?)
+        }
+        RangeBy::Lex => {
+            let (min, max) = if spec.rev {
+                (spec.stop, spec.start)
+            } else {
+                (spec.start, spec.stop)
+            };
+            zset.lex_range_ranks(&parse_lex_range(min, max)?)
+        }
+    };
+    let (offset, count) = match spec.limit {
+        Some((offset, _)) if offset < 0 => return Ok(Vec::new()),
+        Some((offset, count)) if count < 0 => (offset as usize, usize::MAX),
+        Some((offset, count)) => (offset as usize, count as usize),
+        None => (0, usize::MAX),
+    };
+    Ok(zset
+        .range(start, end, spec.rev)
+        .skip(offset)
+        .take(count)
+        .map(|(member, score)| (Bytes::copy_from_slice(member), score))
+        .collect())
+}
+
+/// ZRANGE key start stop [BYSCORE|BYLEX] [REV] [LIMIT offset count] [WITHSCORES]
+pub fn handle_zrange(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, -4)?;
+    let spec = parse_range_spec(&args[2..], true)?;
+    let elements = match get_zset(store, &args[1])? {
+        Some(zset) => select_range(zset, &spec)?,
+        None => Vec::new(),
+    };
+    Ok(elements_reply(&elements, spec.with_scores))
+}
+
+/// ZRANGESTORE destination source start stop [BYSCORE|BYLEX] [REV] [LIMIT offset count]
+pub fn handle_zrangestore(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, -5)?;
+    let spec = parse_range_spec(&args[3..], false)?;
+    let elements = match get_zset(store, &args[2])? {
+        Some(zset) => select_range(zset, &spec)?,
+        None => Vec::new(),
+    };
+    let mut zset = ZSet::new();
+    for (member, score) in elements {
+        zset.insert(member, score);
+    }
+    store_zset(store, &args[1], zset)
+}
+
+/// Which end of a sorted set a pop applies to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PopEnd {
+    Min,
+    Max,
+}
+
+impl PopEnd {
+    fn parse(arg: &[u8]) -> Result<PopEnd, CommandError> {
+        if is_keyword(arg, "MIN") {
+            Ok(PopEnd::Min)
+        } else if is_keyword(arg, "MAX") {
+            Ok(PopEnd::Max)
+        } else {
+            Err(CommandError::Syntax)
+        }
+    }
+}
+
+/// Pop up to `count` elements from the sorted set at `key`, deleting the key if
+/// it ends up empty. Returns None if the key does not exist.
+fn pop(
+    store: &mut Store,
+    key: &Bytes,
+    end: PopEnd,
+    count: usize,
+) -> Result<Option<Vec<(Bytes, f64)>>, CommandError> {
+    if get_zset(store, key)?.is_none() {
+        return Ok(None);
+    }
+    if count == 0 {
+        return Ok(Some(vec![]));
+    }
+    let zset = get_zset_mut(store, key)?.unwrap();
+    let (start, end, rev) = match end {
+        PopEnd::Min => (0, count.min(zset.len()), false),
+        PopEnd::Max => (zset.len().saturating_sub(count), zset.len(), true),
+    };
+    let popped: Vec<(Bytes, f64)> = zset
+        .range(start, end, rev)
+        .map(|(member, score)| (Bytes::copy_from_slice(member), score))
+        .collect();
+    for (member, _) in &popped {
+        zset.remove(member);
+    }
+    store.add_dirty(popped.len());
+    store.remove_if_empty(key);
+    Ok(Some(popped))
+}
+
+/// The key popped from and the elements popped, as replied by ZMPOP.
+type Popped = (Bytes, Vec<(Bytes, f64)>);
+
+/// Pop from the first of `keys` holding a sorted set.
+fn pop_from_first(
+    store: &mut Store,
+    keys: &[Bytes],
+    end: PopEnd,
+    count: usize,
+) -> Result<Option<Popped>, CommandError> {
+    for key in keys {
+        if let Some(popped) = pop(store, key, end, count)? {
+            return Ok(Some((key.clone(), popped)));
+        }
+    }
+    Ok(None)
+}
+
+fn zpop(args: &[Bytes], store: &mut Store, end: PopEnd) -> CommandResult {
+    check_arity(args, -2)?;
+    if args.len() > 3 {
+        return Err(CommandError::Syntax);
+    }
+    let count = match args.get(2) {
+        Some(count) => match parse_int(count)? {
+            count if count < 0 => return Err(CommandError::OutOfRange),
+            count => count as usize,
+        },
+        None => 1,
+    };
+    let popped = pop(store, &args[1], end, count)?.unwrap_or_default();
+    Ok(elements_reply(&popped, true))
+}
+
+/// ZPOPMIN key [count]
+pub fn handle_zpopmin(args: &[Bytes], store: &mut Store) -> CommandResult {
+    zpop(args, store, PopEnd::Min)
+}
+
+/// ZPOPMAX key [count]
+pub fn handle_zpopmax(args: &[Bytes], store: &mut Store) -> CommandResult {
+    zpop(args, store, PopEnd::Max)
+}
+
+/// Parse `numkeys key [key ...] MIN|MAX [COUNT count]` starting at `numkeys_index`.
+fn parse_mpop_args(
+    args: &[Bytes],
+    numkeys_index: usize,
+) -> Result<(&[Bytes], PopEnd, usize), CommandError> {
+    let numkeys = parse_int(&args[numkeys_index])?;
+    if numkeys <= 0 {
+        return Err(CommandError::Custom(String::from(
+            "ERR numkeys should be greater than 0",
+        )));
+    }
+    let keys_start = numkeys_index + 1;
+    let keys_end = keys_start.saturating_add(numkeys as usize);
+    if keys_end >= args.len() {
+        return Err(CommandError::Syntax);
+    }
+    let end = PopEnd::parse(&args[keys_end])?;
+    let count = match &args[keys_end + 1..] {
+        [] => 1,
+        [option, value] if is_keyword(option, "COUNT") => {
+            let count = parse_int(value)?;
+            if count <= 0 {
+                return Err(CommandError::Custom(String::from(
+                    "ERR count should be greater than 0",
+                )));
+            }
+            count as usize
+        }
+        _ => return Err(CommandError::Syntax),
+    };
+    Ok((&args[keys_start..keys_end], end, count))
+}
+
+fn mpop_reply(popped: Option<Popped>) -> RESPDataType {
+    match popped {
+        Some((key, elements)) => RESPDataType::Array(vec![
+            RESPDataType::BulkString(key),
+            RESPDataType::Array(
+                elements
+                    .into_iter()
+                    .map(|(member, score)| {
+                        RESPDataType::Array(vec![
+                            RESPDataType::BulkString(member),
+                            RESPDataType::BulkString(format_double(score)),
+                        ])
+                    })
+                    .collect(),
+            ),
+        ]),
+        None => RESPDataType::NullArray,
+    }
+}
+
+/// ZMPOP numkeys key [key ...] MIN|MAX [COUNT count]
+pub fn handle_zmpop(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, -4)?;
+    let (keys, end, count) = parse_mpop_args(args, 1)?;
+    Ok(mpop_reply(pop_from_first(store, keys, end, count)?))
+}
+
+fn blocking_pop(
+    args: &[Bytes],
+    store: &mut Store,
+    end: PopEnd,
+    may_block: bool,
+) -> Result<CommandOutcome, CommandError> {
+    check_arity(args, -3)?;
+    let keys = &args[1..args.len() - 1];
+    let deadline = parse_timeout(&args[args.len() - 1])?;
+    let reply = pop_from_first(store, keys, end, 1)?.map(|(key, popped)| {
+        let (member, score) = popped.into_iter().next().unwrap();
+        RESPDataType::Array(vec![
+            RESPDataType::BulkString(key),
+            RESPDataType::BulkString(member),
+            RESPDataType::BulkString(format_double(score)),
+        ])
+    });
+    reply_or_block(
+        reply,
+        RESPDataType::NullArray,
+        args,
+        keys,
+        deadline,
+        may_block,
+    )
+}
+
+/// BZPOPMIN key [key ...] timeout
+pub fn handle_bzpopmin(
+    args: &[Bytes],
+    store: &mut Store,
+    may_block: bool,
+) -> Result<CommandOutcome, CommandError> {
+    blocking_pop(args, store, PopEnd::Min, may_block)
+}
+
+/// BZPOPMAX key [key ...] timeout
+pub fn handle_bzpopmax(
+    args: &[Bytes],
+    store: &mut Store,
+    may_block: bool,
+) -> Result<CommandOutcome, CommandError> {
+    blocking_pop(args, store, PopEnd::Max, may_block)
+}
+
+/// BZMPOP timeout numkeys key [key ...] MIN|MAX [COUNT count]
+pub fn handle_bzmpop(
+    args: &[Bytes],
+    store: &mut Store,
+    may_block: bool,
+) -> Result<CommandOutcome, CommandError> {
+    check_arity(args, -5)?;
+    let deadline = parse_timeout(&args[1])?;
+    let (keys, end, count) = parse_mpop_args(args, 2)?;
+    let reply = pop_from_first(store, keys, end, count)?.map(|popped| mpop_reply(Some(popped)));
+    reply_or_block(
+        reply,
+        RESPDataType::NullArray,
+        args,
+        keys,
+        deadline,
+        may_block,
+    )
+}
+
+/// ZRANDMEMBER key [count [WITHSCORES]]
+pub fn handle_zrandmember(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, -2)?;
+    if args.len() > 4 || (args.len() == 4 && !is_keyword(&args[3], "WITHSCORES")) {
+        return Err(CommandError::Syntax);
+    }
+    let count = args
+        .get(2)
+        .map(|count| parse_random_count(count))
+        .transpose()?;
+    let with_scores = args.len() == 4;
+    let zset = get_zset(store, &args[1])?;
+    let element_at = |zset: &ZSet, index: usize| {
+        let (member, score) = zset.range(index, index + 1, false).next().unwrap();
+        (Bytes::copy_from_slice(member), score)
+    };
+
+    let Some(count) = count else {
+        return Ok(zset
+            .map(|zset| RESPDataType::BulkString(element_at(zset, random_index(zset.len())).0))
+            .unwrap_or(RESPDataType::NullBulkString));
+    };
+    let Some(zset) = zset else {
+        return Ok(RESPDataType::Array(vec![]));
+    };
+    let indexes: Box<dyn Iterator<Item = usize>> = if count >= 0 {
+        Box::new(random_distinct_indexes(zset.len(), count as usize).into_iter())
+    } else {
+        Box::new(random_repeated_indexes(zset.len(), count))
+    };
+    let elements: Vec<(Bytes, f64)> = indexes.map(|index| element_at(zset, index)).collect();
+    Ok(elements_reply(&elements, with_scores))
+}
+
+/// Remove the elements with a rank in `start..end`, replying with their number.
+fn remove_rank_range(
+    store: &mut Store,
+    key: &Bytes,
+    ranks: impl Fn(&ZSet) -> (usize, usize),
+) -> CommandResult {
+    let Some(zset) = get_zset(store, key)? else {
+        return Ok(RESPDataType::Integer(0));
+    };
+    let (start, end) = ranks(zset);
+    if start >= end {
+        return Ok(RESPDataType::Integer(0));
+    }
+    let zset = get_zset_mut(store, key)?.unwrap();
+    let members: Vec<Bytes> = zset
+        .range(start, end, false)
+        .map(|(member, _)| Bytes::copy_from_slice(member))
+        .collect();
+    for member in &members {
+        zset.remove(member);
+    }
+    store.add_dirty(members.len());
+    store.remove_if_empty(key);
+    Ok(RESPDataType::Integer(members.len() as i64))
+}
+
+/// ZREMRANGEBYRANK key start stop
+pub fn handle_zremrangebyrank(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, 4)?;
+    let start = parse_int(&args[2])?;
+    let stop = parse_int(&args[3])?;
+    remove_rank_range(store, &args[1], |zset| {
+        normalize_range(start, stop, zset.len()).map_or((0, 0), |(start, stop)| (start, stop + 1))
+    })
+}
+
+/// ZREMRANGEBYSCORE key min max
+pub fn handle_zremrangebyscore(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, 4)?;
+    let range = parse_score_range(&args[2], &args[3])?;
+    remove_rank_range(store, &args[1], |zset| zset.score_range_ranks(&range))
+}
+
+/// ZREMRANGEBYLEX key min max
+pub fn handle_zremrangebylex(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, 4)?;
+    let range = parse_lex_range(&args[2], &args[3])?;
+    remove_rank_range(store, &args[1], |zset| zset.lex_range_ranks(&range))
+}
+
+/// An input of ZUNION and friends, which also accept plain sets, whose
+/// members all count as having a score of 1.
+#[derive(Clone, Copy)]
+enum Source<'a> {
+    ZSet(&'a ZSet),
+    Set(&'a Set),
+}
+
+impl Source<'_> {
+    fn len(&self) -> usize {
+        match self {
+            Source::ZSet(zset) => zset.len(),
+            Source::Set(set) => set.len(),
+        }
+    }
+
+    fn score(&self, member: &[u8]) -> Option<f64> {
+        match self {
+            Source::ZSet(zset) => zset.score(member),
+            Source::Set(set) => set.contains(member).then_some(1.0),
+        }
+    }
+
+    fn elements(&self) -> Vec<(Bytes, f64)> {
+        match self {
+            Source::ZSet(zset) => zset
+                .iter()
+                .map(|(member, score)| (Bytes::copy_from_slice(member), score))
+                .collect(),
+            Source::Set(set) => set.iter().map(|member| (member, 1.0)).collect(),
+        }
+    }
+}
+
+fn get_sources<'a>(
+    store: &'a Store,
+    keys: &[Bytes],
+) -> Result<Vec<Option<Source<'a>>>, CommandError> {
+    keys.iter()
+        .map(|key| match store.get_from_key_val_store(key) {
+            None => Ok(None),
+            Some(Value::ZSet(zset)) => Ok(Some(Source::ZSet(zset))),
+            Some(Value::Set(set)) => Ok(Some(Source::Set(set))),
+            Some(_) => Err(CommandError::WrongType),
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Aggregate {
+    Sum,
+    Min,
+    Max,
+}
+
+impl Aggregate {
+    fn apply(&self, current: f64, score: f64) -> f64 {
+        match self {
+            // inf + -inf is NaN, which redis turns into 0.
+            Aggregate::Sum => Some(current + score)
+                .filter(|sum| !sum.is_nan())
+                .unwrap_or(0.0),
+            Aggregate::Min => current.min(score),
+            Aggregate::Max => current.max(score),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SetOperation {
+    Union,
+    Inter,
+    Diff,
+}
+
+/// Weight a score, where 0 * inf counts as 0 rather than NaN.
+fn weigh(score: f64, weight: f64) -> f64 {
+    Some(score * weight)
+        .filter(|score| !score.is_nan())
+        .unwrap_or(0.0)
+}
+
+/// Combine the inputs of ZUNION, ZINTER or ZDIFF into a new sorted set.
+fn combine(
+    sources: &[Option<Source>],
+    operation: SetOperation,
+    weights: &[f64],
+    aggregate: Aggregate,
+) -> ZSet {
+    let mut result = ZSet::new();
+    match operation {
+        SetOperation::Union => {
+            let mut scores: HashMap<Bytes, f64> = HashMap::new();
+            let mut order = Vec::new();
+            for (source, weight) in sources.iter().zip(weights) {
+                for (member, score) in source.iter().flat_map(Source::elements) {
+                    let score = weigh(score, *weight);
+                    match scores.get_mut(&member) {
+                        Some(current) => *current = aggregate.apply(*current, score),
+                        None => {
+                            scores.insert(member.clone(), score);
+                            order.push(member);
+                        }
+                    }
+                }
+            }
+            for member in order {
+                let score = scores[&member];
+                result.insert(member, score);
+            }
+        }
+        SetOperation::Inter => {
+            let Some(sources) = sources.iter().copied().collect::<Option<Vec<Source>>>() else {
+                return result;
+            };
+            let mut by_size: Vec<usize> = (0..sources.len()).collect();
+            by_size.sort_by_key(|index| sources[*index].len());
+            let (smallest, others) = by_size.split_first().unwrap();
+            'members: for (member, score) in sources[*smallest].elements() {
+                let mut total = weigh(score, weights[*smallest]);
+                for other in others {
+                    let Some(score) = sources[*other].score(&member) else {
+                        continue 'members;
+                    };
+                    total = aggregate.apply(total, weigh(score, weights[*other]));
+                }
+                result.insert(member, total);
+            }
+        }
+        SetOperation::Diff => {
+            let Some(first) = sources[0] else {
+                return result;
+            };
+            for (member, score) in first.elements() {
+                if !sources[1..]
+                    .iter()
+                    .flatten()
+                    .any(|source| source.score(&member).is_some())
+                {
+                    result.insert(member, score);
+                }
+            }
+        }
+    }
+    result
+}
+
+/// Shared implementation of ZUNION, ZINTER and ZDIFF and their STORE variants,
+/// whose `numkeys` argument is at `numkeys_index`.
+fn combine_command(
+    args: &[Bytes],
+    store: &mut Store,
+    operation: SetOperation,
+    numkeys_index: usize,
+) -> Result<(ZSet, bool), CommandError> {
+    let numkeys = parse_int(&args[numkeys_index])?;
+    if numkeys < 1 {
+        return Err(CommandError::Custom(format!(
+            "ERR at least 1 input key is needed for '{}' command",
+            String::from_utf8_lossy(&args[0]).to_lowercase()
+        )));
+    }
+    let keys_start = numkeys_index + 1;
+    let keys_end = keys_start.saturating_add(numkeys as usize);
+    if keys_end > args.len() {
+        return Err(CommandError::Syntax);
+    }
+    let keys = &args[keys_start..keys_end];
+    let is_store = numkeys_index == 2;
+
+    let mut weights = vec![1.0; keys.len()];
+    let mut aggregate = Aggregate::Sum;
+    let mut with_scores = false;
+    let mut index = keys_end;
+    while let Some(arg) = args.get(index) {
+        let remaining = args.len() - index - 1;
+        match arg.to_ascii_uppercase().as_slice() {
+            b"WEIGHTS" if operation != SetOperation::Diff && remaining >= keys.len() => {
+                for (i, weight) in weights.iter_mut().enumerate() {
+                    *weight = parse_float(&args[index + 1 + i]).map_err(|_| {
+                        CommandError::Custom(String::from("ERR weight value is not a float"))
+                    })?;
+                }
+                index += keys.len();
+            }
+            b"AGGREGATE" if operation != SetOperation::Diff && remaining >= 1 => {
+                aggregate = match args[index + 1].to_ascii_uppercase().as_slice() {
+                    b"SUM" => Aggregate::Sum,
+                    b"MIN" => Aggregate::Min,
+                    b"MAX" => Aggregate::Max,
+                    _ => return Err(CommandError::Syntax),
+                };
+                index += 1;
+            }
+            b"WITHSCORES" if !is_store => with_scores = true,
+            _ => return Err(CommandError::Syntax),
+        }
+        index += 1;
+    }
+
+    let sources = get_sources(store, keys)?;
+    Ok((
+        combine(&sources, operation, &weights, aggregate),
+        with_scores,
+    ))
+}
+
+fn combine_reply(args: &[Bytes], store: &mut Store, operation: SetOperation) -> CommandResult {
+    check_arity(args, -3)?;
+    let (zset, with_scores) = combine_command(args, store, operation, 1)?;
+    let elements: Vec<(Bytes, f64)> = zset
+        .iter()
+        .map(|(member, score)| (Bytes::copy_from_slice(member), score))
+        .collect();
+    Ok(elements_reply(&elements, with_scores))
+}
+
+fn combine_store(args: &[Bytes], store: &mut Store, operation: SetOperation) -> CommandResult {
+    check_arity(args, -4)?;
+    let (zset, _) = combine_command(args, store, operation, 2)?;
+    store_zset(store, &args[1], zset)
+}
+
+/// ZUNION numkeys key [key ...] [WEIGHTS weight [weight ...]] [AGGREGATE SUM|MIN|MAX] [WITHSCORES]
+pub fn handle_zunion(args: &[Bytes], store: &mut Store) -> CommandResult {
+    combine_reply(args, store, SetOperation::Union)
+}
+
+/// ZINTER numkeys key [key ...] [WEIGHTS weight [weight ...]] [AGGREGATE SUM|MIN|MAX] [WITHSCORES]
+pub fn handle_zinter(args: &[Bytes], store: &mut Store) -> CommandResult {
+    combine_reply(args, store, SetOperation::Inter)
+}
+
+/// ZDIFF numkeys key [key ...] [WITHSCORES]
+pub fn handle_zdiff(args: &[Bytes], store: &mut Store) -> CommandResult {
+    combine_reply(args, store, SetOperation::Diff)
+}
+
+/// ZUNIONSTORE destination numkeys key [key ...] [WEIGHTS weight [weight ...]] [AGGREGATE SUM|MIN|MAX]
+pub fn handle_zunionstore(args: &[Bytes], store: &mut Store) -> CommandResult {
+    combine_store(args, store, SetOperation::Union)
+}
+
+/// ZINTERSTORE destination numkeys key [key ...] [WEIGHTS weight [weight ...]] [AGGREGATE SUM|MIN|MAX]
+pub fn handle_zinterstore(args: &[Bytes], store: &mut Store) -> CommandResult {
+    combine_store(args, store, SetOperation::Inter)
+}
+
+/// ZDIFFSTORE destination numkeys key [key ...]
+pub fn handle_zdiffstore(args: &[Bytes], store: &mut Store) -> CommandResult {
+    combine_store(args, store, SetOperation::Diff)
+}
+
+/// ZINTERCARD numkeys key [key ...] [LIMIT limit]
+pub fn handle_zintercard(args: &[Bytes], store: &mut Store) -> CommandResult {
+    check_arity(args, -3)?;
+    let numkeys = parse_int(&args[1])?;
+    if numkeys <= 0 {
+        return Err(CommandError::Custom(String::from(
+            "ERR numkeys should be greater than 0",
+        )));
+    }
+    let keys_end = 2usize.saturating_add(numkeys as usize);
+    if keys_end > args.len() {
+        return Err(CommandError::Custom(String::from(
+            "ERR Number of keys can't be greater than number of args",
+        )));
+    }
+    let limit = match &args[keys_end..] {
+        [] => 0,
+        [option, limit] if is_keyword(option, "LIMIT") => match parse_int(limit) {
+            Ok(limit) if limit >= 0 => limit as usize,
+            _ => {
+                return Err(CommandError::Custom(String::from(
+                    "ERR LIMIT can't be negative",
+                )))
+            }
+        },
+        _ => return Err(CommandError::Syntax),
+    };
+    let limit = if limit == 0 { usize::MAX } else { limit };
+    let Some(mut sources) = get_sources(store, &args[2..keys_end])?
+        .into_iter()
+        .collect::<Option<Vec<Source>>>()
+    else {
+        return Ok(RESPDataType::Integer(0));
+    };
+    sources.sort_by_key(|source| source.len());
+    let (smallest, others) = sources.split_first().unwrap();
+    let count = smallest
+        .elements()
+        .iter()
+        .filter(|(member, _)| others.iter().all(|other| other.score(member).is_some()))
+        .take(limit)
+        .count();
+    Ok(RESPDataType::Integer(count as i64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::test_helpers::{args, bulk, bulks};
+    use crate::commands::RANDOM_REPLY_MAX;
+
+    fn leaderboard() -> Store {
+        let mut store = Store::init();
+        handle_zadd(
+            &args(&["ZADD", "z", "1", "a", "2", "b", "3", "c", "4", "d"]),
+            &mut store,
+        )
+        .unwrap();
+        store
+    }
+
+    #[test]
+    fn test_zscan() {
+        let mut store = leaderboard();
+        assert_eq!(
+            handle_zscan(&args(&["ZSCAN", "z", "0", "MATCH", "[bc]"]), &mut store),
+            Ok(RESPDataType::Array(vec![
+                bulk("0"),
+                bulks(&["b", "2", "c", "3"]),
+            ]))
+        );
+        assert_eq!(
+            handle_zscan(
+                &args(&["ZSCAN", "z", "0", "NOSCORES", "COUNT", "2"]),
+                &mut store
+            ),
+            Ok(RESPDataType::Array(vec![
+                bulk("0"),
+                bulks(&["a", "b", "c", "d"]),
+            ]))
+        );
+        assert_eq!(
+            handle_zscan(&args(&["ZSCAN", "z", "0", "COUNT", "0"]), &mut store),
+            Err(CommandError::Syntax)
+        );
+    }
+
+    #[test]
+    fn test_zadd_flags() {
+        let mut store = leaderboard();
+        assert_eq!(
+            handle_zadd(&args(&["ZADD", "z", "NX", "9", "a", "5", "e"]), &mut store),
+            Ok(RESPDataType::Integer(1))
+        );
+        assert_eq!(
+            handle_zadd(
+                &args(&["ZADD", "z", "XX", "CH", "9", "a", "6", "f"]),
+                &mut store
+            ),
+            Ok(RESPDataType::Integer(1))
+        );
+        assert_eq!(
+            handle_zadd(
+                &args(&["ZADD", "z", "GT", "CH", "1", "a", "10", "b"]),
+                &mut store
+            ),
+            Ok(RESPDataType::Integer(1))
+        );
+        assert_eq!(
+            handle_zscore(&args(&["ZSCORE", "z", "a"]), &mut store),
+            Ok(bulk("9"))
+        );
+        assert_eq!(
+            handle_zadd(&args(&["ZADD", "z", "LT", "INCR", "1", "a"]), &mut store),
+            Ok(RESPDataType::NullBulkString)
+        );
+        assert_eq!(
+            handle_zadd(&args(&["ZADD", "z", "INCR", "-0.5", "a"]), &mut store),
+            Ok(bulk("8.5"))
+        );
+        assert_eq!(
+            handle_zincrby(&args(&["ZINCRBY", "z", "2", "new"]), &mut store),
+            Ok(bulk("2"))
+        );
+        assert_eq!(
+            handle_zadd(&args(&["ZADD", "missing", "XX", "1", "a"]), &mut store),
+            Ok(RESPDataType::Integer(0))
+        );
+        assert!(store.get_from_key_val_store(b"missing").is_none());
+    }
+
+    #[test]
+    fn test_zadd_errors() {
+        let mut store = Store::init();
+        assert_eq!(
+            handle_zadd(&args(&["ZADD", "z", "1", "a", "2"]), &mut store),
+            Err(CommandError::Syntax)
+        );
+        assert_eq!(
+            handle_zadd(&args(&["ZADD", "z", "x", "a"]), &mut store),
+            Err(CommandError::NotFloat)
+        );
+        assert!(handle_zadd(&args(&["ZADD", "z", "NX", "XX", "1", "a"]), &mut store).is_err());
+        assert!(handle_zadd(&args(&["ZADD", "z", "GT", "LT", "1", "a"]), &mut store).is_err());
+        assert!(handle_zadd(
+            &args(&["ZADD", "z", "INCR", "1", "a", "2", "b"]),
+            &mut store
+        )
+        .is_err());
+        handle_zadd(&args(&["ZADD", "z", "inf", "a"]), &mut store).unwrap();
+        assert!(handle_zadd(&args(&["ZADD", "z", "INCR", "-inf", "a"]), &mut store).is_err());
+        assert_eq!(
+            handle_zscore(&args(&["ZSCORE", "z", "a"]), &mut store),
+            Ok(bulk("inf"))
+        );
+    }
+
+    #[test]
+    fn test_zrank_zcount_zrem() {
+        let mut store = leaderboard();
+        assert_eq!(
+            handle_zrank(&args(&["ZRANK", "z", "c"]), &mut store),
+            Ok(RESPDataType::Integer(2))
+        );
+        assert_eq!(
+            handle_zrevrank(&args(&["ZREVRANK", "z", "c", "WITHSCORE"]), &mut store),
+            Ok(RESPDataType::Array(vec![
+                RESPDataType::Integer(1),
+                bulk("3")
+            ]))
+        );
+        assert_eq!(
+            handle_zrank(&args(&["ZRANK", "z", "x"]), &mut store),
+            Ok(RESPDataType::NullBulkString)
+        );
+        assert_eq!(
+            handle_zcount(&args(&["ZCOUNT", "z", "(1", "3"]), &mut store),
+            Ok(RESPDataType::Integer(2))
+        );
+        assert_eq!(
+            handle_zcount(&args(&["ZCOUNT", "z", "-inf", "+inf"]), &mut store),
+            Ok(RESPDataType::Integer(4))
+        );
+        assert!(handle_zcount(&args(&["ZCOUNT", "z", "x", "1"]), &mut store).is_err());
+        assert_eq!(
+            handle_zmscore(&args(&["ZMSCORE", "z", "a", "x"]), &mut store),
+            Ok(RESPDataType::Array(vec![
+                bulk("1"),
+                RESPDataType::NullBulkString
+            ]))
+        );
+        assert_eq!(
+            handle_zrem(&args(&["ZREM", "z", "a", "b", "c", "d", "x"]), &mut store),
+            Ok(RESPDataType::Integer(4))
+        );
+        assert!(store.get_from_key_val_store(b"z").is_none());
+    }
+
+    #[test]
+    fn test_zrange_by_rank_and_score() {
+        let mut store = leaderboard();
+        assert_eq!(
+            handle_zrange(&args(&["ZRANGE", "z", "0", "-1"]), &mut store),
+            Ok(bulks(&["a", "b", "c", "d"]))
+        );
+        assert_eq!(
+            handle_zrange(
+                &args(&["ZRANGE", "z", "0", "1", "REV", "WITHSCORES"]),
+                &mut store
+            ),
+            Ok(bulks(&["d", "4", "c", "3"]))
+        );
+        assert_eq!(
+            handle_zrange(&args(&["ZRANGE", "z", "(1", "+inf", "BYSCORE"]), &mut store),
+            Ok(bulks(&["b", "c", "d"]))
+        );
+        assert_eq!(
+            handle_zrange(
+                &args(&["ZRANGE", "z", "+inf", "-inf", "BYSCORE", "REV", "LIMIT", "1", "2"]),
+                &mut store
+            ),
+            Ok(bulks(&["c", "b"]))
+        );
+        assert!(handle_zrange(
+            &args(&["ZRANGE", "z", "0", "-1", "LIMIT", "0", "1"]),
+            &mut store
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_zrange_by_lex_and_store() {
+        let mut store = Store::init();
+        handle_zadd(
+            &args(&["ZADD", "z", "0", "a", "0", "b", "0", "c", "0", "d"]),
+            &mut store,
+        )
+        .unwrap();
+        assert_eq!(
+            handle_zrange(&args(&["ZRANGE", "z", "[b", "+", "BYLEX"]), &mut store),
+            Ok(bulks(&["b", "c", "d"]))
+        );
+        assert_eq!(
+            handle_zrange(
+                &args(&["ZRANGE", "z", "(d", "-", "BYLEX", "REV", "LIMIT", "0", "2"]),
+                &mut store
+            ),
+            Ok(bulks(&["c", "b"]))
+        );
+        assert!(handle_zrange(&args(&["ZRANGE", "z", "b", "+", "BYLEX"]), &mut store).is_err());
+        assert!(handle_zrange(
+            &args(&["ZRANGE", "z", "-", "+", "BYLEX", "WITHSCORES"]),
+            &mut store
+        )
+        .is_err());
+
+        assert_eq!(
+            handle_zrangestore(&args(&["ZRANGESTORE", "dst", "z", "1", "2"]), &mut store),
+            Ok(RESPDataType::Integer(2))
+        );
+        assert_eq!(
+            handle_zrange(&args(&["ZRANGE", "dst", "0", "-1"]), &mut store),
+            Ok(bulks(&["b", "c"]))
+        );
+        assert_eq!(
+            handle_zrangestore(&args(&["ZRANGESTORE", "dst", "z", "5", "6"]), &mut store),
+            Ok(RESPDataType::Integer(0))
+        );
+        assert!(store.get_from_key_val_store(b"dst").is_none());
+    }
+
+    #[test]
+    fn test_zpop_and_zmpop() {
+        let mut store = leaderboard();
+        assert_eq!(
+            handle_zpopmin(&args(&["ZPOPMIN", "z"]), &mut store),
+            Ok(bulks(&["a", "1"]))
+        );
+        assert_eq!(
+            handle_zpopmax(&args(&["ZPOPMAX", "z", "2"]), &mut store),
+            Ok(bulks(&["d", "4", "c", "3"]))
+        );
+        assert_eq!(
+            handle_zmpop(
+                &args(&["ZMPOP", "2", "missing", "z", "MIN", "COUNT", "5"]),
+                &mut store
+            ),
+            Ok(RESPDataType::Array(vec![
+                bulk("z"),
+                RESPDataType::Array(vec![bulks(&["b", "2"])])
+            ]))
+        );
+        assert!(store.get_from_key_val_store(b"z").is_none());
+        assert_eq!(
+            handle_zmpop(&args(&["ZMPOP", "1", "z", "MAX"]), &mut store),
+            Ok(RESPDataType::NullArray)
+        );
+        assert_eq!(
+            handle_zpopmin(&args(&["ZPOPMIN", "z"]), &mut store),
+            Ok(RESPDataType::Array(vec![]))
+        );
+    }
+
+    #[test]
+    fn test_bzpop_replies_or_blocks() {
+        let mut store = leaderboard();
+        match handle_bzpopmax(&args(&["BZPOPMAX", "missing", "z", "0"]), &mut store, true) {
+            Ok(CommandOutcome::Reply(reply)) => assert_eq!(reply, bulks(&["z", "d", "4"])),
+            _ => panic!("expected a reply"),
+        }
+        assert!(matches!(
+            handle_bzpopmin(&args(&["BZPOPMIN", "missing", "0"]), &mut store, true),
+            Ok(CommandOutcome::Block(_))
+        ));
+        assert!(matches!(
+            handle_bzmpop(
+                &args(&["BZMPOP", "0", "1", "missing", "MIN"]),
+                &mut store,
+                false
+            ),
+            Ok(CommandOutcome::Reply(RESPDataType::NullArray))
+        ));
+    }
+
+    #[test]
+    fn test_zremrange() {
+        let mut store = leaderboard();
+        assert_eq!(
+            handle_zremrangebyrank(&args(&["ZREMRANGEBYRANK", "z", "0", "0"]), &mut store),
+            Ok(RESPDataType::Integer(1))
+        );
+        assert_eq!(
+            handle_zremrangebyscore(&args(&["ZREMRANGEBYSCORE", "z", "(2", "3"]), &mut store),
+            Ok(RESPDataType::Integer(1))
+        );
+        assert_eq!(
+            handle_zrange(&args(&["ZRANGE", "z", "0", "-1"]), &mut store),
+            Ok(bulks(&["b", "d"]))
+        );
+        handle_zadd(
+            &args(&["ZADD", "lex", "0", "a", "0", "b", "0", "c"]),
+            &mut store,
+        )
+        .unwrap();
+        assert_eq!(
+            handle_zremrangebylex(&args(&["ZREMRANGEBYLEX", "lex", "-", "[b"]), &mut store),
+            Ok(RESPDataType::Integer(2))
+        );
+        assert_eq!(
+            handle_zremrangebylex(&args(&["ZREMRANGEBYLEX", "lex", "-", "+"]), &mut store),
+            Ok(RESPDataType::Integer(1))
+        );
+        assert!(store.get_from_key_val_store(b"lex").is_none());
+    }
+
+    #[test]
+    fn test_zrandmember() {
+        let mut store = leaderboard();
+        match handle_zrandmember(&args(&["ZRANDMEMBER", "z", "10", "WITHSCORES"]), &mut store) {
+            Ok(RESPDataType::Array(elements)) => assert_eq!(elements.len(), 8),
+            other => panic!("unexpected reply {:?}", other),
+        }
+        match handle_zrandmember(&args(&["ZRANDMEMBER", "z", "-6"]), &mut store) {
+            Ok(RESPDataType::Array(elements)) => assert_eq!(elements.len(), 6),
+            other => panic!("unexpected reply {:?}", other),
+        }
+        for count in [
+            "-9223372036854775808",
+            "-4611686018427387904",
+            "4611686018427387904",
+        ] {
+            assert_eq!(
+                handle_zrandmember(&args(&["ZRANDMEMBER", "z", count]), &mut store),
+                Err(CommandError::Custom(String::from(
+                    "ERR value is out of range"
+                )))
+            );
+        }
+        match handle_zrandmember(
+            &args(&["ZRANDMEMBER", "z", "-4611686018427387903"]),
+            &mut store,
+        ) {
+            Ok(RESPDataType::Array(elements)) => {
+                assert_eq!(elements.len() as u64, RANDOM_REPLY_MAX)
+            }
+            other => panic!("unexpected reply {:?}", other),
+        }
+        assert_eq!(
+            handle_zrandmember(&args(&["ZRANDMEMBER", "missing"]), &mut store),
+            Ok(RESPDataType::NullBulkString)
+        );
+    }
+
+    #[test]
+    fn test_zunion_zinter_zdiff() {
+        let mut store = Store::init();
+        handle_zadd(&args(&["ZADD", "a", "1", "x", "2", "y"]), &mut store).unwrap();
+        handle_zadd(&args(&["ZADD", "b", "10", "y", "20", "z"]), &mut store).unwrap();
+        crate::commands::set::handle_sadd(&args(&["SADD", "s", "y"]), &mut store).unwrap();
+
+        assert_eq!(
+            handle_zunion(&args(&["ZUNION", "2", "a", "b", "WITHSCORES"]), &mut store),
+            Ok(bulks(&["x", "1", "y", "12", "z", "20"]))
+        );
+        assert_eq!(
+            handle_zunion(
+                &args(&[
+                    "ZUNION",
+                    "2",
+                    "a",
+                    "b",
+                    "WEIGHTS",
+                    "2",
+                    "1",
+                    "AGGREGATE",
+                    "MAX",
+                    "WITHSCORES"
+                ]),
+                &mut store
+            ),
+            Ok(bulks(&["x", "2", "y", "10", "z", "20"]))
+        );
+        assert_eq!(
+            handle_zinter(
+                &args(&["ZINTER", "3", "a", "b", "s", "WITHSCORES"]),
+                &mut store
+            ),
+            Ok(bulks(&["y", "13"]))
+        );
+        assert_eq!(
+            handle_zdiff(&args(&["ZDIFF", "2", "a", "b"]), &mut store),
+            Ok(bulks(&["x"]))
+        );
+        assert_eq!(
+            handle_zinterstore(
+                &args(&["ZINTERSTORE", "dst", "2", "a", "b", "AGGREGATE", "MIN"]),
+                &mut store
+            ),
+            Ok(RESPDataType::Integer(1))
+        );
+        assert_eq!(
+            handle_zscore(&args(&["ZSCORE", "dst", "y"]), &mut store),
+            Ok(bulk("2"))
+        );
+        assert_eq!(
+            handle_zdiffstore(&args(&["ZDIFFSTORE", "dst", "2", "a", "a"]), &mut store),
+            Ok(RESPDataType::Integer(0))
+        );
+        assert!(store.get_from_key_val_store(b"dst").is_none());
+        assert_eq!(
+            handle_zunionstore(
+                &args(&["ZUNIONSTORE", "dst", "1", "a", "WITHSCORES"]),
+                &mut store
+            ),
+            Err(CommandError::Syntax)
+        );
+        assert_eq!(
+            handle_zdiff(
+                &args(&["ZDIFF", "2", "a", "b", "WEIGHTS", "1", "1"]),
+                &mut store
+            ),
+            Err(CommandError::Syntax)
+        );
+        assert!(handle_zunion(&args(&["ZUNION", "0", "a"]), &mut store).is_err());
+    }
+
+    #[test]
+    fn test_zintercard() {
+        let mut store = Store::init();
+        handle_zadd(
+            &args(&["ZADD", "a", "1", "x", "2", "y", "3", "z"]),
+            &mut store,
+        )
+        .unwrap();
+        handle_zadd(&args(&["ZADD", "b", "1", "x", "2", "y"]), &mut store).unwrap();
+        assert_eq!(
+            handle_zintercard(&args(&["ZINTERCARD", "2", "a", "b"]), &mut store),
+            Ok(RESPDataType::Integer(2))
+        );
+        assert_eq!(
+            handle_zintercard(
+                &args(&["ZINTERCARD", "2", "a", "b", "LIMIT", "1"]),
+                &mut store
+            ),
+            Ok(RESPDataType::Integer(1))
+        );
+        assert_eq!(
+            handle_zintercard(&args(&["ZINTERCARD", "2", "a", "missing"]), &mut store),
+            Ok(RESPDataType::Integer(0))
+        );
+    }
+}
@@ -0,0 +1,217 @@
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use crate::evict::{MaxMemoryOptions, MaxMemoryPolicy};
+use crate::lazyfree::LazyFreeOptions;
+use crate::memory::parse_memory;
+use crate::persistence::{PersistenceOptions, SaveRule};
+use crate::store::DEFAULT_DATABASES;
+use crate::types::EncodingLimits;
+
+pub const DEFAULT_PORT: u16 = 6379;
+
+/// Server settings, given on the command line as `--name value` pairs the way
+/// redis-server accepts them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Config {
+    /// TCP port to listen on.
+    pub port: u16,
+    /// Number of logical databases selectable with SELECT.
+    pub databases: usize,
+    pub lazyfree: LazyFreeOptions,
+    pub maxmemory: MaxMemoryOptions,
+    pub encoding_limits: EncodingLimits,
+    pub persistence: PersistenceOptions,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            port: DEFAULT_PORT,
+            databases: DEFAULT_DATABASES,
+            lazyfree: LazyFreeOptions::default(),
+            maxmemory: MaxMemoryOptions::default(),
+            encoding_limits: EncodingLimits::default(),
+            persistence: PersistenceOptions::default(),
+        }
+    }
+}
+
+impl Config {
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<Config, String> {
+        let mut config = Config::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let Some(name) = arg.strip_prefix("--") else {
+                return Err(format!("Unexpected argument '{}'", arg));
+            };
+            let value = args
+                .next()
+                .ok_or_else(|| format!("Missing value for '{}'", arg))?;
+            config.set(name, &value)?;
+        }
+        Ok(config)
+    }
+
+    /// Set the option `name` from its textual `value`.
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), String> {
+        match name.to_ascii_lowercase().as_str() {
+            "port" => {
+                self.port = value.parse().map_err(|_| String::from("Invalid port"))?;
+            }
+            "databases" => {
+                self.databases = value
+                    .parse()
+                    .ok()
+                    .filter(|databases| *databases >= 1)
+                    .ok_or_else(|| String::from("Invalid number of databases"))?;
+            }
+            "lazyfree-lazy-eviction" => self.lazyfree.eviction = parse_bool(value)?,
+            "lazyfree-lazy-expire" => self.lazyfree.expire = parse_bool(value)?,
+            "lazyfree-lazy-server-del" => self.lazyfree.server_del = parse_bool(value)?,
+            "lazyfree-lazy-user-del" => self.lazyfree.user_del = parse_bool(value)?,
+            "lazyfree-lazy-user-flush" => self.lazyfree.user_flush = parse_bool(value)?,
+            "maxmemory" => {
+                self.maxmemory.maxmemory = parse_memory(value)
+                    .ok_or_else(|| String::from("argument must be a memory value"))?;
+            }
+            "maxmemory-policy" => {
+                self.maxmemory.policy = MaxMemoryPolicy::parse(value)
+                    .ok_or_else(|| String::from("Invalid maxmemory policy"))?;
+            }
+            "maxmemory-samples" => {
+                self.maxmemory.samples = value
+                    .parse()
+                    .ok()
+                    .filter(|samples| (1..=64).contains(samples))
+                    .ok_or_else(|| String::from("Invalid maxmemory samples"))?;
+            }
+            "lfu-log-factor" => {
+                self.maxmemory.lfu_log_factor = value
+                    .parse()
+                    .map_err(|_| String::from("Invalid lfu-log-factor"))?;
+            }
+            "lfu-decay-time" => {
+                self.maxmemory.lfu_decay_time = value
+                    .parse()
+                    .map_err(|_| String::from("Invalid lfu-decay-time"))?;
+            }
+            "hash-max-listpack-entries" | "hash-max-ziplist-entries" => {
+                self.encoding_limits.hash_max_listpack_entries = parse_number(name, value)?;
+            }
+            "hash-max-listpack-value" | "hash-max-ziplist-value" => {
+                self.encoding_limits.hash_max_listpack_value = parse_number(name, value)?;
+            }
+            "set-max-intset-entries" => {
+                self.encoding_limits.set_max_intset_entries = parse_number(name, value)?;
+            }
+            "set-max-listpack-entries" => {
+                self.encoding_limits.set_max_listpack_entries = parse_number(name, value)?;
+            }
+            "set-max-listpack-value" => {
+                self.encoding_limits.set_max_listpack_value = parse_number(name, value)?;
+            }
+            "zset-max-listpack-entries" | "zset-max-ziplist-entries" => {
+                self.encoding_limits.zset_max_listpack_entries = parse_number(name, value)?;
+            }
+            "zset-max-listpack-value" | "zset-max-ziplist-value" => {
+                self.encoding_limits.zset_max_listpack_value = parse_number(name, value)?;
+            }
+            "list-max-listpack-size" | "list-max-ziplist-size" => {
+                self.encoding_limits.list_max_listpack_size = parse_number(name, value)?;
+            }
+            "dir" => self.persistence.dir = PathBuf::from(value),
+            "dbfilename" => {
+                if value.is_empty() || value.contains('/') {
+                    return Err(String::from("dbfilename can't be a path, just a filename"));
+                }
+                self.persistence.dbfilename = value.to_string();
+            }
+            "save" => {
+                self.persistence.save_rules = SaveRule::parse_rules(value)
+                    .ok_or_else(|| String::from("Invalid save parameters"))?;
+            }
+            _ => return Err(format!("Unknown option '{}'", name)),
+        }
+        Ok(())
+    }
+}
+
+fn parse_number<T: FromStr>(name: &str, value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("Invalid {}", name))
+}
+
+fn parse_bool(value: &str) -> Result<bool, String> {
+    match value.to_ascii_lowercase().as_str() {
+        "yes" => Ok(true),
+        "no" => Ok(false),
+        _ => Err(String::from("argument must be 'yes' or 'no'")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(args: &[&str]) -> Result<Config, String> {
+        Config::from_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn test_from_args() {
+        assert_eq!(config(&[]), Ok(Config::default()));
+        assert_eq!(config(&["--databases", "4"]).unwrap().databases, 4);
+        assert!(config(&["--databases", "0"]).is_err());
+        assert!(config(&["--databases"]).is_err());
+        assert_eq!(config(&["--port", "6380"]).unwrap().port, 6380);
+        assert!(config(&["--port", "70000"]).is_err());
+        let lazy = config(&[
+            "--lazyfree-lazy-user-del",
+            "yes",
+            "--lazyfree-lazy-expire",
+            "no",
+        ]);
+        assert!(lazy.unwrap().lazyfree.user_del);
+        assert!(config(&["--lazyfree-lazy-expire", "maybe"]).is_err());
+        let maxmemory = config(&[
+            "--maxmemory",
+            "100mb",
+            "--maxmemory-policy",
+            "allkeys-lfu",
+            "--maxmemory-samples",
+            "10",
+        ])
+        .unwrap()
+        .maxmemory;
+        assert_eq!(maxmemory.maxmemory, 100 * 1024 * 1024);
+        assert_eq!(maxmemory.policy, MaxMemoryPolicy::AllKeysLfu);
+        assert_eq!(maxmemory.samples, 10);
+        assert!(config(&["--maxmemory", "lots"]).is_err());
+        assert!(config(&["--maxmemory-policy", "lru"]).is_err());
+        assert!(config(&["--maxmemory-samples", "0"]).is_err());
+        let limits = config(&[
+            "--hash-max-listpack-entries",
+            "16",
+            "--zset-max-ziplist-value",
+            "32",
+            "--list-max-listpack-size",
+            "-4",
+        ])
+        .unwrap()
+        .encoding_limits;
+        assert_eq!(limits.hash_max_listpack_entries, 16);
+        assert_eq!(limits.zset_max_listpack_value, 32);
+        assert_eq!(limits.list_max_listpack_size, -4);
+        assert_eq!(limits.set_max_intset_entries, 512);
+        assert!(config(&["--set-max-intset-entries", "-1"]).is_err());
+        let persistence = config(&["--dir", "/tmp", "--dbfilename", "x.rdb", "--save", ""])
+            .unwrap()
+            .persistence;
+        assert_eq!(persistence.path(), PathBuf::from("/tmp/x.rdb"));
+        assert!(persistence.save_rules.is_empty());
+        assert!(config(&["--dbfilename", "a/b.rdb"]).is_err());
+        assert!(config(&["--save", "60"]).is_err());
+        assert!(config(&["databases", "4"]).is_err());
+        assert!(config(&["--nope", "4"]).is_err());
+    }
+}
@@ -0,0 +1,368 @@
+use std::time::Duration;
+
+use bytes::Bytes;
+
+use crate::util::{mstime, random_u64};
+
+/// Number of candidates kept between eviction cycles.
+const EVPOOL_SIZE: usize = 16;
+/// Resolution in milliseconds of the LRU clock.
+const LRU_CLOCK_RESOLUTION: i64 = 1000;
+/// The LRU clock wraps around after 24 bits.
+pub const LRU_CLOCK_MAX: u32 = (1 << 24) - 1;
+/// Logarithmic access counter given to new keys under an LFU policy, so
+/// that they get a chance to accumulate accesses before being evicted.
+pub const LFU_INIT_VAL: u8 = 5;
+/// Longest a single eviction cycle runs before letting commands proceed.
+pub const EVICTION_TIME_LIMIT: Duration = Duration::from_micros(500);
+
+/// What to do once memory use goes past `maxmemory`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MaxMemoryPolicy {
+    /// Refuse commands that may use more memory.
+    #[default]
+    NoEviction,
+    AllKeysLru,
+    AllKeysLfu,
+    AllKeysRandom,
+    VolatileLru,
+    VolatileLfu,
+    VolatileRandom,
+    /// Evict the keys closest to expiring first.
+    VolatileTtl,
+}
+
+impl MaxMemoryPolicy {
+    pub fn parse(name: &str) -> Option<MaxMemoryPolicy> {
+        Some(match name.to_ascii_lowercase().as_str() {
+            "noeviction" => MaxMemoryPolicy::NoEviction,
+            "allkeys-lru" => MaxMemoryPolicy::AllKeysLru,
+            "allkeys-lfu" => MaxMemoryPolicy::AllKeysLfu,
+            "allkeys-random" => MaxMemoryPolicy::AllKeysRandom,
+            "volatile-lru" => MaxMemoryPolicy::VolatileLru,
+            "volatile-lfu" => MaxMemoryPolicy::VolatileLfu,
+            "volatile-random" => MaxMemoryPolicy::VolatileRandom,
+            "volatile-ttl" => MaxMemoryPolicy::VolatileTtl,
+            _ => return None,
+        })
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            MaxMemoryPolicy::NoEviction => "noeviction",
+            MaxMemoryPolicy::AllKeysLru => "allkeys-lru",
+            MaxMemoryPolicy::AllKeysLfu => "allkeys-lfu",
+            MaxMemoryPolicy::AllKeysRandom => "allkeys-random",
+            MaxMemoryPolicy::VolatileLru => "volatile-lru",
+            MaxMemoryPolicy::VolatileLfu => "volatile-lfu",
+            MaxMemoryPolicy::VolatileRandom => "volatile-random",
+            MaxMemoryPolicy::VolatileTtl => "volatile-ttl",
+        }
+    }
+
+    /// Whether keys keep an access frequency rather than an access time.
+    pub fn is_lfu(&self) -> bool {
+        matches!(
+            self,
+            MaxMemoryPolicy::AllKeysLfu | MaxMemoryPolicy::VolatileLfu
+        )
+    }
+
+    /// Whether only keys with a TTL may be evicted.
+    pub fn is_volatile(&self) -> bool {
+        matches!(
+            self,
+            MaxMemoryPolicy::VolatileLru
+                | MaxMemoryPolicy::VolatileLfu
+                | MaxMemoryPolicy::VolatileRandom
+                | MaxMemoryPolicy::VolatileTtl
+        )
+    }
+
+    pub fn is_random(&self) -> bool {
+        matches!(
+            self,
+            MaxMemoryPolicy::AllKeysRandom | MaxMemoryPolicy::VolatileRandom
+        )
+    }
+}
+
+/// The maxmemory related settings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MaxMemoryOptions {
+    /// Memory limit in bytes, 0 for no limit.
+    pub maxmemory: usize,
+    pub policy: MaxMemoryPolicy,
+    /// Keys sampled per database to find eviction candidates.
+    pub samples: usize,
+    /// How many accesses it takes to saturate the LFU counter.
+    pub lfu_log_factor: u32,
+    /// Minutes it takes the LFU counter to decay by one, 0 for never.
+    pub lfu_decay_time: u32,
+}
+
+impl Default for MaxMemoryOptions {
+    fn default() -> Self {
+        MaxMemoryOptions {
+            maxmemory: 0,
+            policy: MaxMemoryPolicy::NoEviction,
+            samples: 5,
+            lfu_log_factor: 10,
+            lfu_decay_time: 1,
+        }
+    }
+}
+
+impl MaxMemoryOptions {
+    /// Access metadata of a key just created.
+    pub fn initial_access(&self) -> u32 {
+        if self.policy.is_lfu() {
+            ((lfu_time_in_minutes() as u32) << 8) | LFU_INIT_VAL as u32
+        } else {
+            lru_clock()
+        }
+    }
+
+    /// Access metadata of a key accessed now, given its previous one.
+    pub fn touch(&self, access: u32) -> u32 {
+        if self.policy.is_lfu() {
+            let counter = lfu_decr_and_return(access, self.lfu_decay_time);
+            let counter = lfu_log_incr(counter, self.lfu_log_factor);
+            ((lfu_time_in_minutes() as u32) << 8) | counter as u32
+        } else {
+            lru_clock()
+        }
+    }
+
+    /// How good a candidate for eviction a key is, higher being better.
+    fn eviction_score(&self, access: u32, expire: Option<i64>) -> u64 {
+        match self.policy {
+            MaxMemoryPolicy::VolatileTtl => u64::MAX - expire.unwrap_or(i64::MAX) as u64,
+            policy if policy.is_lfu() => {
+                255 - lfu_decr_and_return(access, self.lfu_decay_time) as u64
+            }
+            _ => estimate_idle_time(access),
+        }
+    }
+}
+
+/// The current time in seconds, wrapped to 24 bits.
+pub fn lru_clock() -> u32 {
+    ((mstime() / LRU_CLOCK_RESOLUTION) as u32) & LRU_CLOCK_MAX
+}
+
+/// Milliseconds since the access recorded as LRU clock `lru`.
+pub fn estimate_idle_time(lru: u32) -> u64 {
+    let now = lru_clock();
+    let elapsed = if now >= lru {
+        now - lru
+    } else {
+        now + (LRU_CLOCK_MAX - lru)
+    };
+    elapsed as u64 * LRU_CLOCK_RESOLUTION as u64
+}
+
+/// The LRU clock value of an access `idle_seconds` ago.
+pub fn lru_clock_for_idle(idle_seconds: u64) -> u32 {
+    let idle = (idle_seconds * 1000 / LRU_CLOCK_RESOLUTION as u64) % LRU_CLOCK_MAX as u64;
+    let now = lru_clock() as i64;
+    let access = now - idle as i64;
+    if access < 0 {
+        (access + LRU_CLOCK_MAX as i64) as u32
+    } else {
+        access as u32
+    }
+}
+
+/// The current time in minutes, wrapped to 16 bits, as stored by LFU.
+fn lfu_time_in_minutes() -> u16 {
+    ((mstime() / 1000 / 60) & 0xffff) as u16
+}
+
+/// Minutes since `ldt`, accounting for a single wrap around.
+fn lfu_time_elapsed(ldt: u16) -> u64 {
+    let now = lfu_time_in_minutes();
+    if now >= ldt {
+        (now - ldt) as u64
+    } else {
+        65535 - ldt as u64 + now as u64
+    }
+}
+
+/// The LFU counter of `access`, decremented once per `decay_time` minutes
+/// since it was last updated.
+pub fn lfu_decr_and_return(access: u32, decay_time: u32) -> u8 {
+    let ldt = (access >> 8) as u16;
+    let counter = (access & 255) as u8;
+    let periods = match decay_time {
+        0 => 0,
+        decay_time => lfu_time_elapsed(ldt) / decay_time as u64,
+    };
+    counter.saturating_sub(periods.min(255) as u8)
+}
+
+/// Increment a logarithmic counter: the larger it is, the less likely.
+pub fn lfu_log_incr(counter: u8, log_factor: u32) -> u8 {
+    if counter == 255 {
+        return 255;
+    }
+    let r = (random_u64() >> 11) as f64 / (1u64 << 53) as f64;
+    let base = counter.saturating_sub(LFU_INIT_VAL) as f64;
+    let p = 1.0 / (base * log_factor as f64 + 1.0);
+    if r < p {
+        counter + 1
+    } else {
+        counter
+    }
+}
+
+/// Packed LFU metadata for a key accessed now with the given frequency.
+pub fn lfu_access_with_frequency(frequency: u8) -> u32 {
+    ((lfu_time_in_minutes() as u32) << 8) | frequency as u32
+}
+
+/// The outcome of trying to get back under `maxmemory`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EvictionResult {
+    /// Memory use is within the limit.
+    Ok,
+    /// Keys were evicted but the time limit hit first, the next cycle
+    /// carries on.
+    Running,
+    /// Nothing could be evicted, or the policy forbids it.
+    Fail,
+}
+
+#[derive(Debug, Clone)]
+struct PoolEntry {
+    score: u64,
+    key: Bytes,
+    db: usize,
+}
+
+/// The best eviction candidates seen across sampling rounds, ordered by
+/// increasing score, so that sampling a few keys at a time approximates
+/// evicting the very best candidate of the whole keyspace.
+#[derive(Debug, Default)]
+pub struct EvictionPool {
+    entries: Vec<PoolEntry>,
+}
+
+impl EvictionPool {
+    /// Offer a sampled key as candidate.
+    pub fn offer(
+        &mut self,
+        options: &MaxMemoryOptions,
+        db: usize,
+        key: &Bytes,
+        access: u32,
+        expire: Option<i64>,
+    ) {
+        let score = options.eviction_score(access, expire);
+        let position = self.entries.partition_point(|entry| entry.score < score);
+        if position == 0 && self.entries.len() == EVPOOL_SIZE {
+            // Worse than every candidate of a full pool.
+            return;
+        }
+        if self.entries.len() == EVPOOL_SIZE {
+            self.entries.remove(0);
+            self.entries.insert(
+                position - 1,
+                PoolEntry {
+                    score,
+                    key: key.clone(),
+                    db,
+                },
+            );
+        } else {
+            self.entries.insert(
+                position,
+                PoolEntry {
+                    score,
+                    key: key.clone(),
+                    db,
+                },
+            );
+        }
+    }
+
+    /// Take out the best candidate, the caller checks it still exists.
+    pub fn pop_best(&mut self) -> Option<(usize, Bytes)> {
+        self.entries.pop().map(|entry| (entry.db, entry.key))
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_policies() {
+        for name in [
+            "noeviction",
+            "allkeys-lru",
+            "allkeys-lfu",
+            "allkeys-random",
+            "volatile-lru",
+            "volatile-lfu",
+            "volatile-random",
+            "volatile-ttl",
+        ] {
+            assert_eq!(MaxMemoryPolicy::parse(name).unwrap().name(), name);
+        }
+        assert_eq!(
+            MaxMemoryPolicy::parse("ALLKEYS-LRU"),
+            Some(MaxMemoryPolicy::AllKeysLru)
+        );
+        assert_eq!(MaxMemoryPolicy::parse("lru"), None);
+        assert!(MaxMemoryPolicy::VolatileTtl.is_volatile());
+        assert!(!MaxMemoryPolicy::AllKeysLfu.is_volatile());
+    }
+
+    #[test]
+    fn test_lru_and_lfu_clocks() {
+        assert!(estimate_idle_time(lru_clock()) <= 1000);
+        let idle = estimate_idle_time(lru_clock_for_idle(3600));
+        assert!((3_600_000..=3_601_000).contains(&idle));
+
+        // The counter only grows logarithmically and never past 255.
+        let mut counter = LFU_INIT_VAL;
+        for _ in 0..1000 {
+            counter = lfu_log_incr(counter, 10);
+        }
+        assert!(counter > LFU_INIT_VAL && counter < 50);
+        assert_eq!(lfu_log_incr(255, 10), 255);
+        assert_eq!(lfu_log_incr(0, 10), 1);
+
+        let access = lfu_access_with_frequency(100);
+        assert_eq!(lfu_decr_and_return(access, 1), 100);
+        // Ten minutes ago, decaying one per minute.
+        let ldt = lfu_time_in_minutes().wrapping_sub(10);
+        let access = ((ldt as u32) << 8) | 100;
+        assert_eq!(lfu_decr_and_return(access, 1), 90);
+        assert_eq!(lfu_decr_and_return(access, 0), 100);
+        assert_eq!(lfu_decr_and_return(((ldt as u32) << 8) | 3, 1), 0);
+    }
+
+    #[test]
+    fn test_eviction_pool() {
+        let options = MaxMemoryOptions {
+            policy: MaxMemoryPolicy::VolatileTtl,
+            ..MaxMemoryOptions::default()
+        };
+        let mut pool = EvictionPool::default();
+        // Keys expiring later are worse candidates, only the 16 best stay.
+        for i in (0..40).rev() {
+            let key = Bytes::from(format!("k{}", i));
+            pool.offer(&options, 0, &key, 0, Some(1000 + i));
+        }
+        for i in 0..16 {
+            assert_eq!(pool.pop_best(), Some((0, Bytes::from(format!("k{}", i)))));
+        }
+        assert_eq!(pool.pop_best(), None);
+    }
+}
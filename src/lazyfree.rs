@@ -0,0 +1,132 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{self, Sender},
+        OnceLock,
+    },
+    thread,
+};
+
+use crate::store::Value;
+
+/// Values whose free effort is above this are freed in the background, below
+/// it handing them to another thread costs more than dropping them inline.
+pub const LAZYFREE_THRESHOLD: usize = 64;
+
+/// Which kinds of deletion free large values in the background, mirroring
+/// redis' lazyfree-lazy-* options. All of them are off by default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LazyFreeOptions {
+    /// Keys evicted to honour maxmemory.
+    pub eviction: bool,
+    /// Keys deleted because their TTL passed.
+    pub expire: bool,
+    /// Values deleted as a side effect of a command, like SET overwriting a key.
+    pub server_del: bool,
+    /// DEL, which then behaves like UNLINK.
+    pub user_del: bool,
+    /// FLUSHDB and FLUSHALL without an explicit SYNC or ASYNC.
+    pub user_flush: bool,
+}
+
+/// Something to drop, along with the number of values it stands for.
+type Job = (Box<dyn Send>, usize);
+
+static FREE_QUEUE: OnceLock<Sender<Job>> = OnceLock::new();
+static PENDING_OBJECTS: AtomicUsize = AtomicUsize::new(0);
+static FREED_OBJECTS: AtomicUsize = AtomicUsize::new(0);
+
+/// The queue of the background thread dropping what it is sent, started on
+/// first use.
+fn free_queue() -> &'static Sender<Job> {
+    FREE_QUEUE.get_or_init(|| {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        thread::Builder::new()
+            .name(String::from("lazyfree"))
+            .spawn(move || {
+                for (job, objects) in receiver {
+                    drop(job);
+                    PENDING_OBJECTS.fetch_sub(objects, Ordering::Relaxed);
+                    FREED_OBJECTS.fetch_add(objects, Ordering::Relaxed);
+                }
+            })
+            .expect("failed to spawn the lazyfree thread");
+        sender
+    })
+}
+
+/// Rough number of allocations released by dropping `value`.
+pub fn free_effort(value: &Value) -> usize {
+    match value {
+        Value::String(_) => 1,
+        Value::List(list) => list.len(),
+        Value::Hash(hash) => hash.len(),
+        Value::Set(set) if set.encoding() == "intset" => 1,
+        Value::Set(set) => set.len(),
+        // The skiplist holds every member twice, in the list and the dict.
+        Value::ZSet(zset) if zset.encoding() == "skiplist" => zset.len() * 2,
+        Value::ZSet(zset) => zset.len(),
+        Value::Stream(stream) => {
+            stream.radix_tree_nodes()
+                + stream
+                    .groups()
+                    .values()
+                    .map(|group| 1 + group.pending.len() + group.consumers.len())
+                    .sum::<usize>()
+        }
+    }
+}
+
+/// Hand `item`, standing for `objects` values, to the background thread.
+pub fn free_in_background(item: impl Send + 'static, objects: usize) {
+    PENDING_OBJECTS.fetch_add(objects, Ordering::Relaxed);
+    free_queue()
+        .send((Box::new(item), objects))
+        .expect("the lazyfree thread exited");
+}
+
+/// Drop `value`, in the background if `lazy` and freeing it is costly enough.
+/// Returns whether it was sent to the background thread.
+pub fn free_value(value: Value, lazy: bool) -> bool {
+    if lazy && free_effort(&value) > LAZYFREE_THRESHOLD {
+        free_in_background(value, 1);
+        return true;
+    }
+    false
+}
+
+/// Number of values waiting to be freed by the background thread.
+pub fn pending_objects() -> usize {
+    PENDING_OBJECTS.load(Ordering::Relaxed)
+}
+
+/// Number of values freed by the background thread since startup.
+pub fn freed_objects() -> usize {
+    FREED_OBJECTS.load(Ordering::Relaxed)
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use super::*;
+    use crate::types::set::Set;
+
+    #[test]
+    fn test_free_value() {
+        let mut set = Set::new();
+        for i in 0..1000 {
+            set.insert(Bytes::from(format!("m{}", i)));
+        }
+        let set = Value::Set(set);
+        assert_eq!(free_effort(&set), 1000);
+        assert!(!free_value(Value::String(Bytes::from("v")), true));
+        assert!(!free_value(set.clone(), false));
+
+        let freed = freed_objects();
+        assert!(free_value(set, true));
+        while freed_objects() == freed {
+            thread::yield_now();
+        }
+    }
+}
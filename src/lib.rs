@@ -1,138 +1,781 @@
+pub mod blocking;
+pub mod client;
+pub mod commands;
+pub mod config;
+pub mod evict;
+pub mod lazyfree;
+pub mod memory;
+pub mod migrate;
+pub mod persistence;
+pub mod rdb;
 pub mod resp;
+pub mod server;
 pub mod store;
 pub mod thread_pool;
+pub mod types;
+pub mod util;
 
-use std::{
-    io::{prelude::*, BufReader},
-    net::TcpStream,
-};
+use std::collections::HashMap;
+use std::sync::OnceLock;
 
-use bytes::{BufMut, BytesMut};
+use bytes::Bytes;
 use log::{error, info};
 
+use blocking::CommandOutcome;
+use client::Client;
+use commands::{
+    geo, hash, hyperloglog, keyspace, list, set, sort, stream, zset, CommandError, CommandResult,
+};
+use evict::EvictionResult;
 use resp::data::RESPDataType;
-use resp::deserializer::RespDeserializer;
-use resp::serializer::RespSerializer;
-use store::Store;
+use server::Server;
+use store::{Store, Value};
 
-pub fn handle_connection(mut stream: TcpStream, store: &mut Store) {
-    info!("Handling new connection.");
+/// Serve a client until it disconnects or blocks. A blocked client is handed over
+/// to the store's blocking registry, freeing this worker until it is resumed.
+pub fn handle_connection(mut client: Client, server: &Server) {
+    info!("Handling connection for client {}.", client.id);
 
-    let command: BytesMut = get_command(&stream);
+    loop {
+        loop {
+            let command = match client.next_command() {
+                Ok(Some(command)) => command,
+                Ok(None) => break,
+                Err(e) => {
+                    error!("Error occured while deserializing command: {:?}", e);
+                    client.add_reply(&handle_error("Protocol error."));
+                    let _ = client.flush();
+                    return;
+                }
+            };
 
-    let resp_deserializer = RespDeserializer::default();
-    let resp_result = resp_deserializer.deserialize(&command, 0);
+            let mut store = server.store.lock().unwrap();
+            if let Some(error) = check_maxmemory(&command, &mut store, &mut client) {
+                client.add_reply(&error);
+                continue;
+            }
+            match handle_resp_command(command, &mut store, &mut client, true) {
+                CommandOutcome::Reply(reply) => client.add_reply(&reply),
+                CommandOutcome::Block(request) => {
+                    // Send the replies to earlier commands without holding the
+                    // store: a client that does not read them yet would stall
+                    // every other client on the write.
+                    drop(store);
+                    if client.flush().is_err() {
+                        return;
+                    }
+                    let mut store = server.store.lock().unwrap();
+                    // The keys may have received data while the store was
+                    // unlocked, so block and check them right away.
+                    let (db, keys) = (client.db, request.keys.clone());
+                    store.blocking.block(client, request);
+                    for key in &keys {
+                        store.blocking.signal_key_as_ready(db, key);
+                    }
+                    handle_clients_blocked_on_keys(&mut store, server);
+                    return;
+                }
+            }
+            handle_clients_blocked_on_keys(&mut store, server);
+        }
 
-    if let Ok(value) = resp_result {
-        if let Some((_, resp_data_type)) = value {
-            let response = handle_resp_command(resp_data_type, store);
-            if response.len() > 0 {
-                stream.write_all(response.as_bytes()).unwrap();
+        if client.flush().is_err() {
+            return;
+        }
+        match client.read_from_socket() {
+            Ok(0) | Err(_) => {
+                info!("Client {} disconnected.", client.id);
+                return;
             }
+            Ok(_) => {}
         }
-    } else {
-        error!("Error occured while deserializing command.");
     }
 }
 
-fn get_command(mut stream: &TcpStream) -> BytesMut {
-    let mut buf_reader = BufReader::new(&mut stream);
-    let mut buffer = [0; 512];
-    if let Ok(command_size) = buf_reader.read(&mut buffer) {
-        let command: String = String::from_utf8(buffer[..command_size].to_vec()).unwrap();
-        let mut bytes_mut = BytesMut::with_capacity(command.len());
-        bytes_mut.put(command.as_bytes());
-        return bytes_mut;
-    } else {
-        panic!("Command could not be deserialized.");
-    }
-}
-
-fn handle_resp_command(resp_command: RESPDataType, store: &mut Store) -> String {
-    if let RESPDataType::Array(resp_data_types) = resp_command {
-        info!("Handling command {:?}", resp_data_types);
-        let first = resp_data_types.get(0);
-        match first.unwrap() {
-            RESPDataType::BulkString(first_command) => {
-                match String::from_utf8(first_command.to_vec()).unwrap().as_str() {
-                    "config" | "CONFIG" => handle_config(),
-                    "ping" | "PING" => handle_ping(),
-                    "echo" | "ECHO" => handle_echo(resp_data_types),
-                    "set" | "SET" => handle_set(resp_data_types, store),
-                    "get" | "GET" => handle_get(resp_data_types, store),
-                    _ => return handle_default(),
+/// Re-run the commands of clients blocked on keys that received data, serving
+/// the longest waiting client first. Serving a client can make further keys
+/// ready (e.g. BLMOVE pushing to another list), so loop until none are left.
+fn handle_clients_blocked_on_keys(store: &mut Store, server: &Server) {
+    loop {
+        let ready_keys = store.blocking.take_ready_keys();
+        if ready_keys.is_empty() {
+            return;
+        }
+        for key in ready_keys {
+            for id in store.blocking.waiters(&key) {
+                let Some(mut blocked) = store.blocking.take(id) else {
+                    continue;
+                };
+                let command = RESPDataType::Array(
+                    blocked
+                        .args
+                        .iter()
+                        .map(|arg| RESPDataType::BulkString(arg.clone()))
+                        .collect(),
+                );
+                match handle_resp_command(command, store, &mut blocked.client, true) {
+                    CommandOutcome::Reply(reply) => {
+                        store.blocking.unblock(&blocked);
+                        blocked.client.add_reply(&reply);
+                        server.resume(blocked.client);
+                    }
+                    CommandOutcome::Block(_) => store.blocking.restore(blocked),
                 }
             }
-            _ => handle_error("First element in command should be a bulk string."),
         }
-    } else {
-        handle_error("Command should be an array.")
     }
 }
 
-fn handle_error(error_str: &str) -> String {
-    let resp_serializer: RespSerializer = RespSerializer::default();
-    return resp_serializer.serialize_error(error_str);
+/// How a command is executed.
+#[derive(Clone, Copy)]
+enum Handler {
+    /// Runs against the store alone.
+    Plain(fn(&[Bytes], &mut Store) -> CommandResult),
+    /// May block the client, which it only does when told it may.
+    Blocking(fn(&[Bytes], &mut Store, bool) -> Result<CommandOutcome, CommandError>),
+    /// Needs the connection state of the client.
+    Client(fn(&[Bytes], &mut Store, &mut Client) -> CommandResult),
+}
+
+/// An entry of the command table.
+struct Command {
+    name: &'static str,
+    handler: Handler,
+    /// Whether the command may make the dataset grow, in which case it is
+    /// refused when memory use is over maxmemory and nothing can be evicted.
+    denyoom: bool,
+    /// Subcommands that are denyoom when the command as a whole is not.
+    denyoom_subcommands: &'static [&'static str],
+}
+
+impl Command {
+    const fn new(name: &'static str, handler: Handler) -> Self {
+        Command {
+            name,
+            handler,
+            denyoom: false,
+            denyoom_subcommands: &[],
+        }
+    }
+
+    const fn plain(name: &'static str, handler: fn(&[Bytes], &mut Store) -> CommandResult) -> Self {
+        Command::new(name, Handler::Plain(handler))
+    }
+
+    const fn blocking(
+        name: &'static str,
+        handler: fn(&[Bytes], &mut Store, bool) -> Result<CommandOutcome, CommandError>,
+    ) -> Self {
+        Command::new(name, Handler::Blocking(handler))
+    }
+
+    const fn client(
+        name: &'static str,
+        handler: fn(&[Bytes], &mut Store, &mut Client) -> CommandResult,
+    ) -> Self {
+        Command::new(name, Handler::Client(handler))
+    }
+
+    const fn denyoom(self) -> Self {
+        Command {
+            denyoom: true,
+            ..self
+        }
+    }
+
+    const fn denyoom_subcommands(self, subcommands: &'static [&'static str]) -> Self {
+        Command {
+            denyoom_subcommands: subcommands,
+            ..self
+        }
+    }
+
+    /// Whether running the command with `args` may use more memory.
+    fn is_denyoom(&self, args: &[Bytes]) -> bool {
+        self.denyoom
+            || args.get(1).is_some_and(|subcommand| {
+                self.denyoom_subcommands
+                    .iter()
+                    .any(|name| commands::is_keyword(subcommand, name))
+            })
+    }
+}
+
+/// Every command the server knows, looked up by its upper case name.
+static COMMAND_TABLE: &[Command] = &[
+    Command::client("MULTI", handle_multi),
+    Command::client("EXEC", handle_exec),
+    Command::client("DISCARD", handle_discard),
+    Command::client("SELECT", handle_select),
+    Command::blocking("BLPOP", list::handle_blpop),
+    Command::blocking("BRPOP", list::handle_brpop),
+    Command::blocking("BLMOVE", list::handle_blmove).denyoom(),
+    Command::blocking("BRPOPLPUSH", list::handle_brpoplpush).denyoom(),
+    Command::blocking("BLMPOP", list::handle_blmpop),
+    Command::blocking("BZPOPMIN", zset::handle_bzpopmin),
+    Command::blocking("BZPOPMAX", zset::handle_bzpopmax),
+    Command::blocking("BZMPOP", zset::handle_bzmpop),
+    Command::blocking("XREAD", stream::handle_xread),
+    Command::blocking("XREADGROUP", stream::handle_xreadgroup),
+    Command::plain("CONFIG", handle_config),
+    Command::plain("PING", handle_ping),
+    Command::plain("ECHO", handle_echo),
+    Command::plain("SET", handle_set).denyoom(),
+    Command::plain("GET", handle_get),
+    Command::plain("DEL", keyspace::handle_del),
+    Command::plain("UNLINK", keyspace::handle_unlink),
+    Command::plain("EXISTS", keyspace::handle_exists),
+    Command::plain("TYPE", keyspace::handle_type),
+    Command::plain("RENAME", keyspace::handle_rename),
+    Command::plain("RENAMENX", keyspace::handle_renamenx),
+    Command::plain("COPY", keyspace::handle_copy).denyoom(),
+    Command::plain("TOUCH", keyspace::handle_touch),
+    Command::plain("RANDOMKEY", keyspace::handle_randomkey),
+    Command::plain("DBSIZE", keyspace::handle_dbsize),
+    Command::plain("KEYS", keyspace::handle_keys),
+    Command::plain("SCAN", keyspace::handle_scan),
+    Command::plain("MOVE", keyspace::handle_move),
+    Command::plain("OBJECT", keyspace::handle_object),
+    Command::plain("MEMORY", commands::memory::handle_memory),
+    Command::plain("DUMP", keyspace::handle_dump),
+    Command::plain("RESTORE", keyspace::handle_restore).denyoom(),
+    Command::plain("MIGRATE", keyspace::handle_migrate),
+    Command::plain("SWAPDB", keyspace::handle_swapdb),
+    Command::plain("FLUSHDB", keyspace::handle_flushdb),
+    Command::plain("FLUSHALL", keyspace::handle_flushall),
+    Command::plain("SAVE", commands::persistence::handle_save),
+    Command::plain("BGSAVE", commands::persistence::handle_bgsave),
+    Command::plain("LASTSAVE", commands::persistence::handle_lastsave),
+    Command::plain("SORT", sort::handle_sort).denyoom(),
+    Command::plain("SORT_RO", sort::handle_sort_ro),
+    Command::plain("EXPIRE", keyspace::handle_expire),
+    Command::plain("PEXPIRE", keyspace::handle_pexpire),
+    Command::plain("EXPIREAT", keyspace::handle_expireat),
+    Command::plain("PEXPIREAT", keyspace::handle_pexpireat),
+    Command::plain("TTL", keyspace::handle_ttl),
+    Command::plain("PTTL", keyspace::handle_pttl),
+    Command::plain("EXPIRETIME", keyspace::handle_expiretime),
+    Command::plain("PEXPIRETIME", keyspace::handle_pexpiretime),
+    Command::plain("PERSIST", keyspace::handle_persist),
+    Command::plain("LPUSH", list::handle_lpush).denyoom(),
+    Command::plain("RPUSH", list::handle_rpush).denyoom(),
+    Command::plain("LPUSHX", list::handle_lpushx).denyoom(),
+    Command::plain("RPUSHX", list::handle_rpushx).denyoom(),
+    Command::plain("LPOP", list::handle_lpop),
+    Command::plain("RPOP", list::handle_rpop),
+    Command::plain("LLEN", list::handle_llen),
+    Command::plain("LRANGE", list::handle_lrange),
+    Command::plain("LINDEX", list::handle_lindex),
+    Command::plain("LSET", list::handle_lset).denyoom(),
+    Command::plain("LINSERT", list::handle_linsert).denyoom(),
+    Command::plain("LREM", list::handle_lrem),
+    Command::plain("LTRIM", list::handle_ltrim),
+    Command::plain("LPOS", list::handle_lpos),
+    Command::plain("LMOVE", list::handle_lmove).denyoom(),
+    Command::plain("RPOPLPUSH", list::handle_rpoplpush).denyoom(),
+    Command::plain("LMPOP", list::handle_lmpop),
+    Command::plain("HSET", hash::handle_hset).denyoom(),
+    Command::plain("HMSET", hash::handle_hmset).denyoom(),
+    Command::plain("HSETNX", hash::handle_hsetnx).denyoom(),
+    Command::plain("HGET", hash::handle_hget),
+    Command::plain("HMGET", hash::handle_hmget),
+    Command::plain("HDEL", hash::handle_hdel),
+    Command::plain("HEXISTS", hash::handle_hexists),
+    Command::plain("HLEN", hash::handle_hlen),
+    Command::plain("HSTRLEN", hash::handle_hstrlen),
+    Command::plain("HKEYS", hash::handle_hkeys),
+    Command::plain("HVALS", hash::handle_hvals),
+    Command::plain("HGETALL", hash::handle_hgetall),
+    Command::plain("HINCRBY", hash::handle_hincrby).denyoom(),
+    Command::plain("HINCRBYFLOAT", hash::handle_hincrbyfloat).denyoom(),
+    Command::plain("HRANDFIELD", hash::handle_hrandfield),
+    Command::plain("HSCAN", hash::handle_hscan),
+    Command::plain("HEXPIRE", hash::handle_hexpire).denyoom(),
+    Command::plain("HPEXPIRE", hash::handle_hpexpire).denyoom(),
+    Command::plain("HEXPIREAT", hash::handle_hexpireat).denyoom(),
+    Command::plain("HPEXPIREAT", hash::handle_hpexpireat).denyoom(),
+    Command::plain("HTTL", hash::handle_httl),
+    Command::plain("HPTTL", hash::handle_hpttl),
+    Command::plain("HEXPIRETIME", hash::handle_hexpiretime),
+    Command::plain("HPEXPIRETIME", hash::handle_hpexpiretime),
+    Command::plain("HPERSIST", hash::handle_hpersist),
+    Command::plain("SADD", set::handle_sadd).denyoom(),
+    Command::plain("SREM", set::handle_srem),
+    Command::plain("SISMEMBER", set::handle_sismember),
+    Command::plain("SMISMEMBER", set::handle_smismember),
+    Command::plain("SCARD", set::handle_scard),
+    Command::plain("SMEMBERS", set::handle_smembers),
+    Command::plain("SPOP", set::handle_spop),
+    Command::plain("SRANDMEMBER", set::handle_srandmember),
+    Command::plain("SMOVE", set::handle_smove),
+    Command::plain("SINTER", set::handle_sinter),
+    Command::plain("SINTERSTORE", set::handle_sinterstore).denyoom(),
+    Command::plain("SUNION", set::handle_sunion),
+    Command::plain("SUNIONSTORE", set::handle_sunionstore).denyoom(),
+    Command::plain("SDIFF", set::handle_sdiff),
+    Command::plain("SDIFFSTORE", set::handle_sdiffstore).denyoom(),
+    Command::plain("SINTERCARD", set::handle_sintercard),
+    Command::plain("SSCAN", set::handle_sscan),
+    Command::plain("ZADD", zset::handle_zadd).denyoom(),
+    Command::plain("ZINCRBY", zset::handle_zincrby).denyoom(),
+    Command::plain("ZSCORE", zset::handle_zscore),
+    Command::plain("ZMSCORE", zset::handle_zmscore),
+    Command::plain("ZCARD", zset::handle_zcard),
+    Command::plain("ZCOUNT", zset::handle_zcount),
+    Command::plain("ZRANK", zset::handle_zrank),
+    Command::plain("ZREVRANK", zset::handle_zrevrank),
+    Command::plain("ZREM", zset::handle_zrem),
+    Command::plain("ZRANGE", zset::handle_zrange),
+    Command::plain("ZRANGESTORE", zset::handle_zrangestore).denyoom(),
+    Command::plain("ZPOPMIN", zset::handle_zpopmin),
+    Command::plain("ZPOPMAX", zset::handle_zpopmax),
+    Command::plain("ZMPOP", zset::handle_zmpop),
+    Command::plain("ZRANDMEMBER", zset::handle_zrandmember),
+    Command::plain("ZREMRANGEBYRANK", zset::handle_zremrangebyrank),
+    Command::plain("ZREMRANGEBYSCORE", zset::handle_zremrangebyscore),
+    Command::plain("ZREMRANGEBYLEX", zset::handle_zremrangebylex),
+    Command::plain("ZUNION", zset::handle_zunion),
+    Command::plain("ZINTER", zset::handle_zinter),
+    Command::plain("ZDIFF", zset::handle_zdiff),
+    Command::plain("ZUNIONSTORE", zset::handle_zunionstore).denyoom(),
+    Command::plain("ZINTERSTORE", zset::handle_zinterstore).denyoom(),
+    Command::plain("ZDIFFSTORE", zset::handle_zdiffstore).denyoom(),
+    Command::plain("ZINTERCARD", zset::handle_zintercard),
+    Command::plain("ZSCAN", zset::handle_zscan),
+    Command::plain("XADD", stream::handle_xadd).denyoom(),
+    Command::plain("XLEN", stream::handle_xlen),
+    Command::plain("XRANGE", stream::handle_xrange),
+    Command::plain("XREVRANGE", stream::handle_xrevrange),
+    Command::plain("XDEL", stream::handle_xdel),
+    Command::plain("XTRIM", stream::handle_xtrim),
+    Command::plain("XGROUP", stream::handle_xgroup)
+        .denyoom_subcommands(&["CREATE", "CREATECONSUMER"]),
+    Command::plain("XACK", stream::handle_xack),
+    Command::plain("XPENDING", stream::handle_xpending),
+    Command::plain("XCLAIM", stream::handle_xclaim),
+    Command::plain("XAUTOCLAIM", stream::handle_xautoclaim),
+    Command::plain("XINFO", stream::handle_xinfo),
+    Command::plain("GEOADD", geo::handle_geoadd).denyoom(),
+    Command::plain("GEOPOS", geo::handle_geopos),
+    Command::plain("GEODIST", geo::handle_geodist),
+    Command::plain("GEOHASH", geo::handle_geohash),
+    Command::plain("GEOSEARCH", geo::handle_geosearch),
+    Command::plain("GEOSEARCHSTORE", geo::handle_geosearchstore).denyoom(),
+    Command::plain("PFADD", hyperloglog::handle_pfadd).denyoom(),
+    Command::plain("PFCOUNT", hyperloglog::handle_pfcount),
+    Command::plain("PFMERGE", hyperloglog::handle_pfmerge).denyoom(),
+    Command::plain("PFDEBUG", hyperloglog::handle_pfdebug).denyoom(),
+    Command::plain("PFSELFTEST", hyperloglog::handle_pfselftest),
+];
+
+/// Look a command up by name, in any case.
+fn lookup_command(name: &[u8]) -> Option<&'static Command> {
+    static COMMANDS: OnceLock<HashMap<&'static str, &'static Command>> = OnceLock::new();
+    let commands = COMMANDS.get_or_init(|| {
+        COMMAND_TABLE
+            .iter()
+            .map(|command| (command.name, command))
+            .collect()
+    });
+    commands
+        .get(String::from_utf8_lossy(name).to_ascii_uppercase().as_str())
+        .copied()
 }
 
-fn handle_default() -> String {
-    return handle_error("Unimplemented command.");
+/// Whether `args` is a command that may use more memory.
+fn is_denyoom(args: &[Bytes]) -> bool {
+    args.first()
+        .and_then(|name| lookup_command(name))
+        .is_some_and(|command| command.is_denyoom(args))
 }
 
-fn handle_config() -> String {
-    return String::from("*2\r\n$4\r\nsave\r\n$0\r\n\r\n");
+/// Evict keys if memory use is over maxmemory, returning the error to reply
+/// with if that fails and the command may use more memory. Refusing a
+/// command queued by MULTI fails the whole transaction.
+fn check_maxmemory(
+    command: &RESPDataType,
+    store: &mut Store,
+    client: &mut Client,
+) -> Option<RESPDataType> {
+    if store.maxmemory.maxmemory == 0 || store.perform_evictions() != EvictionResult::Fail {
+        return None;
+    }
+    let args = command_args(command)?;
+    let is_exec = commands::is_keyword(&args[0], "EXEC");
+    let may_use_memory = if is_exec {
+        client.multi.as_ref().is_some_and(|queued| {
+            queued
+                .iter()
+                .any(|command| command_args(command).is_some_and(|args| is_denyoom(&args)))
+        })
+    } else {
+        is_denyoom(&args)
+    };
+    if !may_use_memory {
+        return None;
+    }
+    let error = "OOM command not allowed when used memory > 'maxmemory'.";
+    if is_exec {
+        client.multi = None;
+        client.multi_failed = false;
+        return Some(handle_error(&format!(
+            "EXECABORT Transaction discarded because of: {}",
+            error
+        )));
+    }
+    if client.multi.is_some() {
+        client.multi_failed = true;
+    }
+    Some(handle_error(error))
 }
 
-fn handle_ping() -> String {
-    let resp_serializer: RespSerializer = RespSerializer::default();
-    return resp_serializer.serialize_ss("pong");
+/// The arguments of a command, None if it is not an array of bulk strings.
+fn command_args(command: &RESPDataType) -> Option<Vec<Bytes>> {
+    match command {
+        RESPDataType::Array(parts) if !parts.is_empty() => get_args(parts),
+        _ => None,
+    }
 }
 
-fn handle_echo(resp_data_types: Vec<RESPDataType>) -> String {
-    let resp_serializer: RespSerializer = RespSerializer::default();
-    if let Some(msg) = resp_data_types.get(1) {
-        if let RESPDataType::BulkString(return_msg) = msg {
-            return resp_serializer
-                .serialize_ss(String::from_utf8(return_msg.to_vec()).unwrap().as_str());
-        } else {
-            return handle_error("Echo should be followed by a string.");
+/// Collect the arguments of a command, which clients always send as bulk strings.
+fn get_args(resp_data_types: &[RESPDataType]) -> Option<Vec<Bytes>> {
+    resp_data_types
+        .iter()
+        .map(|resp_data_type| match resp_data_type {
+            RESPDataType::BulkString(arg) => Some(arg.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Execute a command for `client`. Blocking commands only block when `may_block`
+/// is set, which is not the case for commands run as part of a transaction.
+fn handle_resp_command(
+    resp_command: RESPDataType,
+    store: &mut Store,
+    client: &mut Client,
+    may_block: bool,
+) -> CommandOutcome {
+    let RESPDataType::Array(resp_data_types) = resp_command else {
+        return CommandOutcome::Reply(handle_error("Command should be an array."));
+    };
+    info!("Handling command {:?}", resp_data_types);
+    let Some(args) = get_args(&resp_data_types) else {
+        return CommandOutcome::Reply(handle_error(
+            "First element in command should be a bulk string.",
+        ));
+    };
+    store.select(client.db);
+
+    if let Some(queued) = client.multi.as_mut() {
+        if !["MULTI", "EXEC", "DISCARD"]
+            .iter()
+            .any(|name| commands::is_keyword(&args[0], name))
+        {
+            queued.push(RESPDataType::Array(resp_data_types));
+            return CommandOutcome::Reply(RESPDataType::SimpleString(Bytes::from("QUEUED")));
         }
     }
-    return handle_error("Missing 'message' argument.");
+
+    let Some(command) = lookup_command(&args[0]) else {
+        return CommandOutcome::Reply(handle_default());
+    };
+    let outcome = match command.handler {
+        Handler::Plain(handler) => handler(&args, store).map(CommandOutcome::Reply),
+        Handler::Blocking(handler) => handler(&args, store, may_block),
+        Handler::Client(handler) => handler(&args, store, client).map(CommandOutcome::Reply),
+    };
+    outcome.unwrap_or_else(|e| CommandOutcome::Reply(e.into()))
+}
+
+fn handle_multi(_args: &[Bytes], _store: &mut Store, client: &mut Client) -> CommandResult {
+    if client.multi.is_some() {
+        return Err(CommandError::Custom(String::from(
+            "ERR MULTI calls can not be nested",
+        )));
+    }
+    client.multi = Some(Vec::new());
+    client.multi_failed = false;
+    Ok(commands::ok())
 }
 
-fn handle_set(resp_data_types: Vec<RESPDataType>, store: &mut Store) -> String {
-    let resp_serializer: RespSerializer = RespSerializer::default();
-    if resp_data_types.get(1).is_none() {
-        return handle_error("Missing 'key' argument.");
+/// Run the queued commands back to back. Blocking commands never block here.
+fn handle_exec(_args: &[Bytes], store: &mut Store, client: &mut Client) -> CommandResult {
+    let queued = client
+        .multi
+        .take()
+        .ok_or(CommandError::Custom(String::from("ERR EXEC without MULTI")))?;
+    if std::mem::take(&mut client.multi_failed) {
+        return Err(CommandError::Custom(String::from(
+            "EXECABORT Transaction discarded because of previous errors.",
+        )));
     }
-    if resp_data_types.get(2).is_none() {
-        return handle_error("Missing 'value' argument.");
+    let replies = queued
+        .into_iter()
+        .map(
+            |command| match handle_resp_command(command, store, client, false) {
+                CommandOutcome::Reply(reply) => reply,
+                CommandOutcome::Block(_) => RESPDataType::NullArray,
+            },
+        )
+        .collect();
+    Ok(RESPDataType::Array(replies))
+}
+
+fn handle_discard(_args: &[Bytes], _store: &mut Store, client: &mut Client) -> CommandResult {
+    client
+        .multi
+        .take()
+        .ok_or(CommandError::Custom(String::from(
+            "ERR DISCARD without MULTI",
+        )))?;
+    client.multi_failed = false;
+    Ok(commands::ok())
+}
+
+fn handle_select(args: &[Bytes], store: &mut Store, client: &mut Client) -> CommandResult {
+    keyspace::handle_select(args, store, &mut client.db)
+}
+
+fn handle_error(error_str: &str) -> RESPDataType {
+    RESPDataType::Error(Bytes::copy_from_slice(error_str.as_bytes()))
+}
+
+fn handle_default() -> RESPDataType {
+    handle_error("Unimplemented command.")
+}
+
+fn handle_config(_args: &[Bytes], _store: &mut Store) -> CommandResult {
+    Ok(RESPDataType::Array(vec![
+        RESPDataType::BulkString(Bytes::from("save")),
+        RESPDataType::BulkString(Bytes::new()),
+    ]))
+}
+
+fn handle_ping(_args: &[Bytes], _store: &mut Store) -> CommandResult {
+    Ok(RESPDataType::SimpleString(Bytes::from("pong")))
+}
+
+fn handle_echo(args: &[Bytes], _store: &mut Store) -> CommandResult {
+    match args.get(1) {
+        Some(msg) => Ok(RESPDataType::SimpleString(msg.clone())),
+        None => Ok(handle_error("Missing 'message' argument.")),
     }
-    let key_resp = resp_data_types.get(1).unwrap();
-    let val_resp = resp_data_types.get(2).unwrap();
-    match (key_resp, val_resp) {
-        (RESPDataType::BulkString(key), RESPDataType::BulkString(val)) => {
-            store.set_key_val(key.clone(), val.clone());
-            return resp_serializer.serialize_ss("OK");
-        }
-        _ => return handle_error("Set should be followed by 2 bulk strings."),
+}
+
+fn handle_set(args: &[Bytes], store: &mut Store) -> CommandResult {
+    let Some(key) = args.get(1) else {
+        return Ok(handle_error("Missing 'key' argument."));
+    };
+    let Some(val) = args.get(2) else {
+        return Ok(handle_error("Missing 'value' argument."));
+    };
+    store.set_key_val(key.clone(), val.clone());
+    Ok(RESPDataType::SimpleString(Bytes::from("OK")))
+}
+
+fn handle_get(args: &[Bytes], store: &mut Store) -> CommandResult {
+    let Some(key) = args.get(1) else {
+        return Ok(handle_error("Missing 'key' argument."));
+    };
+    match store.get_from_key_val_store(key) {
+        Some(Value::String(result)) => Ok(RESPDataType::BulkString(result.clone())),
+        Some(_) => Err(CommandError::WrongType),
+        None => Ok(RESPDataType::NullBulkString),
     }
 }
 
-fn handle_get(resp_data_types: Vec<RESPDataType>, store: &mut Store) -> String {
-    let resp_serializer: RespSerializer = RespSerializer::default();
-    if let Some(key_resp) = resp_data_types.get(1) {
-        if let RESPDataType::BulkString(key) = key_resp {
-            let value = store.get_from_key_val_store(key.clone());
-            if let Some(result) = value {
-                return resp_serializer
-                    .serialize_ss(String::from_utf8(result.to_vec()).unwrap().as_str());
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+    use std::time::Duration;
+
+    use bytes::BytesMut;
+
+    use super::*;
+    use resp::deserializer::RespDeserializer;
+    use resp::serializer::RespSerializer;
+    use thread_pool::ThreadPool;
+
+    fn command(args: &[&str]) -> RESPDataType {
+        RESPDataType::Array(
+            args.iter()
+                .map(|arg| RESPDataType::BulkString(Bytes::copy_from_slice(arg.as_bytes())))
+                .collect(),
+        )
+    }
+
+    fn client() -> Client {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        Client::new(TcpStream::connect(listener.local_addr().unwrap()).unwrap())
+    }
+
+    /// Start a server on an ephemeral port and connect to it.
+    fn connect_to_server() -> TcpStream {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(10)))
+            .unwrap();
+        let server = Server::new(Store::init(), ThreadPool::new(2));
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                server.accept(stream.unwrap());
+            }
+        });
+        stream
+    }
+
+    /// Send `args` to the server and wait for its reply.
+    fn request(stream: &mut TcpStream, args: &[&str]) -> RESPDataType {
+        stream
+            .write_all(&RespSerializer.serialize(&command(args)))
+            .unwrap();
+        let mut buffer = BytesMut::new();
+        loop {
+            if let Ok(Some((_, reply))) = RespDeserializer.deserialize(&buffer, 0) {
+                return reply;
             }
-            return resp_serializer.serialize_nil();
-        } else {
-            return handle_error("Echo should be followed by a string.");
+            let mut chunk = [0; 4096];
+            let size = stream.read(&mut chunk).unwrap();
+            assert!(size > 0, "the server closed the connection");
+            buffer.extend_from_slice(&chunk[..size]);
+        }
+    }
+
+    #[test]
+    fn test_huge_random_counts_keep_the_server_up() {
+        let mut stream = connect_to_server();
+        request(&mut stream, &["HSET", "h", "f", "v"]);
+        assert_eq!(
+            request(&mut stream, &["HRANDFIELD", "h", "-9223372036854775808"]),
+            RESPDataType::Error(Bytes::from("ERR value is out of range"))
+        );
+        assert_eq!(
+            request(&mut stream, &["HGET", "h", "f"]),
+            RESPDataType::BulkString(Bytes::from("v"))
+        );
+        request(&mut stream, &["SADD", "s", "m"]);
+        assert_eq!(
+            request(&mut stream, &["SRANDMEMBER", "s", "-9223372036854775808"]),
+            RESPDataType::Error(Bytes::from("ERR value is out of range"))
+        );
+        assert_eq!(
+            request(&mut stream, &["SISMEMBER", "s", "m"]),
+            RESPDataType::Integer(1)
+        );
+        request(&mut stream, &["ZADD", "z", "1", "m"]);
+        assert_eq!(
+            request(&mut stream, &["ZRANDMEMBER", "z", "-9223372036854775808"]),
+            RESPDataType::Error(Bytes::from("ERR value is out of range"))
+        );
+        assert_eq!(
+            request(&mut stream, &["ZSCORE", "z", "m"]),
+            RESPDataType::BulkString(Bytes::from("1"))
+        );
+    }
+
+    #[test]
+    fn test_blocking_commands_inside_multi() {
+        let mut stream = connect_to_server();
+        request(&mut stream, &["MULTI"]);
+        request(
+            &mut stream,
+            &["BLMOVE", "missing", "dst", "LEFT", "LEFT", "0"],
+        );
+        request(&mut stream, &["BRPOPLPUSH", "missing", "dst", "0"]);
+        request(&mut stream, &["BLPOP", "missing", "0"]);
+        assert_eq!(
+            request(&mut stream, &["EXEC"]),
+            RESPDataType::Array(vec![
+                RESPDataType::NullBulkString,
+                RESPDataType::NullBulkString,
+                RESPDataType::NullArray,
+            ])
+        );
+    }
+
+    #[test]
+    fn test_check_maxmemory_noeviction() {
+        let mut store = Store::init();
+        store.maxmemory.maxmemory = 1;
+        let mut client = client();
+        let writes: &[&[&str]] = &[
+            &["SET", "string", "v"],
+            &["COPY", "string", "copy"],
+            &["RESTORE", "restored", "0", "payload"],
+            &["SORT", "list", "STORE", "sorted"],
+            &["LPUSH", "list", "a"],
+            &["BLMOVE", "list", "other", "LEFT", "LEFT", "0"],
+            &["HSET", "hash", "f", "v"],
+            &["HINCRBY", "hash", "n", "1"],
+            &["HEXPIRE", "hash", "10", "FIELDS", "1", "f"],
+            &["HPEXPIREAT", "hash", "10", "FIELDS", "1", "f"],
+            &["SADD", "set", "a"],
+            &["SUNIONSTORE", "dest", "set"],
+            &["ZADD", "zset", "1", "a"],
+            &["ZRANGESTORE", "dest", "zset", "0", "-1"],
+            &["XADD", "stream", "*", "f", "v"],
+            &["XGROUP", "CREATE", "stream", "group", "$"],
+            &["GEOADD", "geo", "13.36", "38.11", "Palermo"],
+            &["PFADD", "hll", "a"],
+            &["PFMERGE", "hll", "other"],
+        ];
+        for args in writes {
+            let error = check_maxmemory(&command(args), &mut store, &mut client);
+            assert!(
+                matches!(&error, Some(RESPDataType::Error(e)) if e.starts_with(b"OOM")),
+                "{:?} was not refused",
+                args
+            );
+        }
+
+        let allowed: &[&[&str]] = &[
+            &["GET", "string"],
+            &["DEL", "string"],
+            &["LPOP", "list"],
+            &["HPERSIST", "hash", "FIELDS", "1", "f"],
+            &["XGROUP", "DESTROY", "stream", "group"],
+            &["UNKNOWN"],
+        ];
+        for args in allowed {
+            assert_eq!(
+                check_maxmemory(&command(args), &mut store, &mut client),
+                None
+            );
+        }
+    }
+
+    #[test]
+    fn test_check_maxmemory_exec() {
+        let mut store = Store::init();
+        store.maxmemory.maxmemory = 1;
+        let mut client = client();
+        client.multi = Some(vec![command(&["GET", "a"])]);
+        assert_eq!(
+            check_maxmemory(&command(&["exec"]), &mut store, &mut client),
+            None
+        );
+
+        client.multi = Some(vec![
+            command(&["GET", "a"]),
+            command(&["hexpire", "h", "1"]),
+        ]);
+        let error = check_maxmemory(&command(&["exec"]), &mut store, &mut client);
+        assert!(matches!(error, Some(RESPDataType::Error(e)) if e.starts_with(b"EXECABORT")));
+        assert!(client.multi.is_none());
+    }
+
+    #[test]
+    fn test_command_table_names() {
+        for command in COMMAND_TABLE {
+            assert_eq!(command.name, command.name.to_ascii_uppercase());
+            assert!(std::ptr::eq(
+                lookup_command(command.name.to_ascii_lowercase().as_bytes()).unwrap(),
+                command
+            ));
         }
     }
-    return handle_error("Missing 'key' argument.");
 }
@@ -1,29 +1,52 @@
+use std::env;
 use std::net::TcpListener;
-use std::sync::{Arc, Mutex};
+use std::process;
+use std::time::Instant;
 
 use env_logger::Env;
-use log::error;
+use log::{error, info};
 
-use redis_server::handle_connection;
+use redis_server::config::Config;
+use redis_server::memory;
+use redis_server::rdb;
+use redis_server::server::Server;
 use redis_server::store::Store;
 use redis_server::thread_pool::ThreadPool;
+use redis_server::types;
 
 fn main() {
     env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
 
-    let listener = TcpListener::bind("127.0.0.1:6379").unwrap();
-    let pool = ThreadPool::new(15000);
-    let state_store = Arc::new(Mutex::new(Store::init()));
+    let config = Config::from_args(env::args().skip(1)).unwrap_or_else(|e| {
+        error!("Bad configuration: {}", e);
+        process::exit(1);
+    });
+
+    types::set_encoding_limits(config.encoding_limits);
+    let listener = TcpListener::bind(("127.0.0.1", config.port)).unwrap();
+    let mut store = Store::new(&config);
+    let start = Instant::now();
+    match rdb::file::load(&config.persistence.path(), config.databases) {
+        Ok(Some(snapshot)) => {
+            store.load_snapshot(snapshot);
+            info!(
+                "DB loaded from disk: {:.3} seconds",
+                start.elapsed().as_secs_f64()
+            );
+        }
+        Ok(None) => {}
+        Err(e) => {
+            error!("Failed loading the RDB file: {}", e);
+            process::exit(1);
+        }
+    }
+    let server = Server::new(store, ThreadPool::new(15000));
+    memory::record_startup_memory();
+    server.start_cron();
 
     for stream in listener.incoming() {
         match stream {
-            Ok(stream) => {
-                let state_store = Arc::clone(&state_store);
-                pool.execute(move || {
-                    let mut store = state_store.lock().unwrap();
-                    handle_connection(stream, &mut store);
-                })
-            }
+            Ok(stream) => server.accept(stream),
             Err(e) => {
                 error!("error: {}", e);
             }
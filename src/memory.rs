@@ -0,0 +1,250 @@
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    fs,
+    mem::size_of,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use bytes::Bytes;
+
+use crate::store::Value;
+use crate::types::listpack::ListPack;
+use crate::types::stream::{PendingEntry, StreamEntry, StreamId};
+
+static USED_MEMORY: AtomicUsize = AtomicUsize::new(0);
+/// Largest `USED_MEMORY` seen by `update_peak_memory`.
+static PEAK_MEMORY: AtomicUsize = AtomicUsize::new(0);
+/// Memory in use once the server was initialized, before serving clients.
+static STARTUP_MEMORY: AtomicUsize = AtomicUsize::new(0);
+
+/// Elements looked at by MEMORY USAGE unless told otherwise.
+pub const DEFAULT_USAGE_SAMPLES: usize = 5;
+/// Approximate cost of an entry of a hash table besides its key and value:
+/// its share of the buckets and the bucket's own bookkeeping.
+const TABLE_ENTRY_OVERHEAD: usize = 2 * size_of::<usize>();
+/// Approximate cost of a skiplist node besides its member: the score, the
+/// backward pointer and an average of two levels.
+const SKIPLIST_NODE_OVERHEAD: usize = size_of::<f64>() + 3 * size_of::<usize>() * 2;
+
+/// The system allocator, keeping count of the bytes allocated through it the
+/// way redis' zmalloc does, so that maxmemory can be enforced.
+struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            USED_MEMORY.fetch_add(layout.size(), Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc_zeroed(layout);
+        if !ptr.is_null() {
+            USED_MEMORY.fetch_add(layout.size(), Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        USED_MEMORY.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = System.realloc(ptr, layout, new_size);
+        if !new_ptr.is_null() {
+            USED_MEMORY.fetch_add(new_size, Ordering::Relaxed);
+            USED_MEMORY.fetch_sub(layout.size(), Ordering::Relaxed);
+        }
+        new_ptr
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Number of bytes currently allocated by the process.
+pub fn used_memory() -> usize {
+    USED_MEMORY.load(Ordering::Relaxed)
+}
+
+/// Record the current memory use as the peak if it is the highest yet, and
+/// return the peak.
+pub fn update_peak_memory() -> usize {
+    let used = used_memory();
+    PEAK_MEMORY.fetch_max(used, Ordering::Relaxed).max(used)
+}
+
+/// Record the current memory use as the baseline of an empty server.
+pub fn record_startup_memory() {
+    STARTUP_MEMORY.store(used_memory(), Ordering::Relaxed);
+}
+
+pub fn startup_memory() -> usize {
+    STARTUP_MEMORY.load(Ordering::Relaxed)
+}
+
+/// Resident set size of the process, when the platform reports it.
+pub fn resident_memory() -> Option<usize> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    let kilobytes: usize = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kilobytes * 1024)
+}
+
+/// Ask the allocator to return the free memory it holds to the system.
+pub fn purge() {
+    #[cfg(all(target_os = "linux", target_env = "gnu"))]
+    {
+        extern "C" {
+            fn malloc_trim(pad: usize) -> i32;
+        }
+        // SAFETY: malloc_trim only releases memory glibc holds unused.
+        unsafe {
+            malloc_trim(0);
+        }
+    }
+}
+
+/// Estimated bytes allocated for the contents of `value`, beyond the value
+/// itself. Collections are measured on their first `samples` elements, or
+/// all of them when 0, and extrapolated to their length.
+pub fn value_usage(value: &Value, samples: usize) -> usize {
+    let limit = if samples == 0 { usize::MAX } else { samples };
+    let extrapolate = |len: usize, sizes: &mut dyn Iterator<Item = usize>| {
+        let (count, total) = sizes
+            .take(limit)
+            .fold((0, 0), |(count, total), size| (count + 1, total + size));
+        match count {
+            0 => 0,
+            count => (total as f64 / count as f64 * len as f64) as usize,
+        }
+    };
+    match value {
+        Value::String(_) if value.encoding() == "int" => 0,
+        Value::String(string) => string.len(),
+        Value::List(list) => extrapolate(list.len(), &mut list.iter().map(ListPack::entry_size)),
+        Value::Hash(hash) if hash.encoding() == "listpack" => extrapolate(
+            hash.len(),
+            &mut hash
+                .iter()
+                .map(|(field, value)| ListPack::entry_size(field) + ListPack::entry_size(value)),
+        ),
+        Value::Hash(hash) => extrapolate(
+            hash.len(),
+            &mut hash.iter().map(|(field, value)| {
+                2 * size_of::<Bytes>() + field.len() + value.len() + TABLE_ENTRY_OVERHEAD
+            }),
+        ),
+        Value::Set(set) if set.encoding() == "intset" => set.len() * size_of::<i64>(),
+        Value::Set(set) if set.encoding() == "listpack" => extrapolate(
+            set.len(),
+            &mut set.iter().map(|member| ListPack::entry_size(&member)),
+        ),
+        Value::Set(set) => extrapolate(
+            set.len(),
+            &mut set
+                .iter()
+                .map(|member| size_of::<Bytes>() + member.len() + TABLE_ENTRY_OVERHEAD),
+        ),
+        Value::ZSet(zset) if zset.encoding() == "listpack" => extrapolate(
+            zset.len(),
+            &mut zset.iter().map(|(member, score)| {
+                ListPack::entry_size(member) + ListPack::entry_size(&score.to_le_bytes())
+            }),
+        ),
+        Value::ZSet(zset) => extrapolate(
+            zset.len(),
+            &mut zset.iter().map(|(member, _)| {
+                size_of::<(Bytes, f64)>()
+                    + member.len()
+                    + size_of::<Bytes>()
+                    + TABLE_ENTRY_OVERHEAD
+                    + SKIPLIST_NODE_OVERHEAD
+            }),
+        ),
+        Value::Stream(stream) => {
+            let entries = stream.range(StreamId::MIN, StreamId::MAX, false, limit);
+            let entries_size = extrapolate(
+                stream.len(),
+                &mut entries.into_iter().map(|entry| {
+                    size_of::<StreamEntry>()
+                        + entry
+                            .fields
+                            .iter()
+                            .map(|(field, value)| {
+                                2 * size_of::<Bytes>() + field.len() + value.len()
+                            })
+                            .sum::<usize>()
+                }),
+            );
+            let groups_size: usize = stream
+                .groups()
+                .iter()
+                .map(|(name, group)| {
+                    let pending = group.pending.len()
+                        * (size_of::<StreamId>()
+                            + size_of::<PendingEntry>()
+                            + TABLE_ENTRY_OVERHEAD);
+                    let consumers: usize = group
+                        .consumers
+                        .iter()
+                        .map(|(name, consumer)| {
+                            name.len() + consumer.pending.len() * size_of::<StreamId>()
+                        })
+                        .sum();
+                    name.len() + pending + consumers
+                })
+                .sum();
+            entries_size + groups_size
+        }
+    }
+}
+
+/// Parse a memory amount the way redis does: a number optionally followed
+/// by a unit, k, m and g being powers of 1000 and kb, mb and gb of 1024.
+pub fn parse_memory(value: &str) -> Option<usize> {
+    let value = value.to_ascii_lowercase();
+    let digits = value.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    let multiplier: usize = match &value[digits.len()..] {
+        "" | "b" => 1,
+        "k" => 1000,
+        "kb" => 1024,
+        "m" => 1000 * 1000,
+        "mb" => 1024 * 1024,
+        "g" => 1000 * 1000 * 1000,
+        "gb" => 1024 * 1024 * 1024,
+        _ => return None,
+    };
+    digits.parse::<usize>().ok()?.checked_mul(multiplier)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_memory() {
+        assert_eq!(parse_memory("0"), Some(0));
+        assert_eq!(parse_memory("100"), Some(100));
+        assert_eq!(parse_memory("1k"), Some(1000));
+        assert_eq!(parse_memory("1KB"), Some(1024));
+        assert_eq!(parse_memory("2mb"), Some(2 * 1024 * 1024));
+        assert_eq!(parse_memory("3g"), Some(3_000_000_000));
+        assert_eq!(parse_memory("1tb"), None);
+        assert_eq!(parse_memory("-1"), None);
+        assert_eq!(parse_memory("mb"), None);
+    }
+
+    #[test]
+    fn test_used_memory() {
+        // Other tests allocate concurrently, only check for a large change.
+        let before = used_memory();
+        let block = vec![0u8; 64 << 20];
+        assert!(used_memory() > before + (32 << 20));
+        drop(block);
+        assert!(used_memory() < before + (32 << 20));
+    }
+}
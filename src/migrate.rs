@@ -0,0 +1,120 @@
+use std::{
+    collections::HashMap,
+    io::{self, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    time::{Duration, Instant},
+};
+
+use bytes::{Buf, BytesMut};
+
+use crate::resp::data::RESPDataType;
+use crate::resp::deserializer::RespDeserializer;
+use crate::resp::serializer::RespSerializer;
+
+/// Most connections kept open to other instances.
+const MIGRATE_SOCKET_CACHE_ITEMS: usize = 64;
+/// Connections unused for this long are closed.
+pub const MIGRATE_SOCKET_CACHE_TTL: Duration = Duration::from_secs(10);
+
+/// A connection MIGRATE uses to talk to another instance.
+pub struct MigrateSocket {
+    stream: TcpStream,
+    replies: BytesMut,
+    /// Database selected on the other end, None when unknown.
+    pub last_db: Option<i64>,
+    last_use: Instant,
+}
+
+impl MigrateSocket {
+    fn connect(address: &str, timeout: Duration) -> io::Result<MigrateSocket> {
+        let mut last_error = io::Error::new(io::ErrorKind::NotFound, "no address to connect to");
+        for addr in address.to_socket_addrs()? {
+            match TcpStream::connect_timeout(&addr, timeout) {
+                Ok(stream) => {
+                    stream.set_nodelay(true)?;
+                    return Ok(MigrateSocket {
+                        stream,
+                        replies: BytesMut::new(),
+                        last_db: None,
+                        last_use: Instant::now(),
+                    });
+                }
+                Err(e) => last_error = e,
+            }
+        }
+        Err(last_error)
+    }
+
+    /// Send a batch of commands in one write.
+    pub fn send(&mut self, commands: &[RESPDataType], timeout: Duration) -> io::Result<()> {
+        let mut buffer = BytesMut::new();
+        for command in commands {
+            buffer.extend_from_slice(&RespSerializer.serialize(command));
+        }
+        self.stream.set_write_timeout(Some(timeout))?;
+        self.stream.write_all(&buffer)
+    }
+
+    /// Wait for the next reply, for at most `timeout` between reads.
+    pub fn read_reply(&mut self, timeout: Duration) -> io::Result<RESPDataType> {
+        self.stream.set_read_timeout(Some(timeout))?;
+        loop {
+            let parsed = RespDeserializer
+                .deserialize(&self.replies, 0)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{:?}", e)))?;
+            if let Some((len, reply)) = parsed {
+                self.replies.advance(len);
+                return Ok(reply);
+            }
+            let mut buffer = [0; 16 * 1024];
+            let size = self.stream.read(&mut buffer)?;
+            if size == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            self.replies.extend_from_slice(&buffer[..size]);
+        }
+    }
+}
+
+/// Connections to other instances kept open between MIGRATE calls, keyed by
+/// "host:port".
+#[derive(Default)]
+pub struct MigrateSockets {
+    sockets: HashMap<String, MigrateSocket>,
+}
+
+impl MigrateSockets {
+    pub fn len(&self) -> usize {
+        self.sockets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sockets.is_empty()
+    }
+
+    /// Take the cached connection to `address` out of the cache, or open a
+    /// new one. Hand it back with `put` once it is known to be healthy,
+    /// dropping it closes it.
+    pub fn take(&mut self, address: &str, timeout: Duration) -> io::Result<MigrateSocket> {
+        match self.sockets.remove(address) {
+            Some(socket) => Ok(socket),
+            None => MigrateSocket::connect(address, timeout),
+        }
+    }
+
+    pub fn put(&mut self, address: String, mut socket: MigrateSocket) {
+        if self.sockets.len() >= MIGRATE_SOCKET_CACHE_ITEMS {
+            // Too many connections, close any one of them.
+            let evicted = self.sockets.keys().next().cloned().unwrap();
+            self.sockets.remove(&evicted);
+        }
+        socket.last_use = Instant::now();
+        self.sockets.insert(address, socket);
+    }
+
+    /// Close the connections unused since `MIGRATE_SOCKET_CACHE_TTL`.
+    pub fn close_idle(&mut self, now: Instant) {
+        self.sockets
+            .retain(|_, socket| now.duration_since(socket.last_use) < MIGRATE_SOCKET_CACHE_TTL);
+    }
+}
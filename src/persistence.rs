@@ -0,0 +1,258 @@
+use std::io;
+use std::path::PathBuf;
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+
+use log::{error, info, warn};
+
+use crate::rdb::file::{self, Snapshot};
+use crate::util::mstime;
+
+/// How long to wait before retrying a background save that failed, in
+/// seconds.
+const BGSAVE_RETRY_DELAY: i64 = 5;
+
+/// Save once `changes` changes were made within `seconds` of the last save,
+/// like a `save <seconds> <changes>` line of redis.conf.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SaveRule {
+    pub seconds: i64,
+    pub changes: u64,
+}
+
+impl SaveRule {
+    /// Parse `seconds changes` pairs, an empty string disabling saving.
+    pub fn parse_rules(value: &str) -> Option<Vec<SaveRule>> {
+        let numbers: Vec<&str> = value.split_whitespace().collect();
+        if !numbers.len().is_multiple_of(2) {
+            return None;
+        }
+        numbers
+            .chunks(2)
+            .map(|pair| {
+                Some(SaveRule {
+                    seconds: pair[0].parse().ok().filter(|seconds| *seconds >= 0)?,
+                    changes: pair[1].parse().ok()?,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Where snapshots go and when they are taken.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PersistenceOptions {
+    pub dir: PathBuf,
+    pub dbfilename: String,
+    pub save_rules: Vec<SaveRule>,
+}
+
+impl Default for PersistenceOptions {
+    fn default() -> Self {
+        PersistenceOptions {
+            dir: PathBuf::from("."),
+            dbfilename: String::from("dump.rdb"),
+            save_rules: vec![
+                SaveRule {
+                    seconds: 3600,
+                    changes: 1,
+                },
+                SaveRule {
+                    seconds: 300,
+                    changes: 100,
+                },
+                SaveRule {
+                    seconds: 60,
+                    changes: 10000,
+                },
+            ],
+        }
+    }
+}
+
+impl PersistenceOptions {
+    pub fn path(&self) -> PathBuf {
+        self.dir.join(&self.dbfilename)
+    }
+}
+
+/// A snapshot being written by another thread.
+struct BackgroundSave {
+    thread: JoinHandle<io::Result<()>>,
+    /// Changes made before the snapshot was taken, which it saves.
+    dirty: u64,
+    start: Instant,
+}
+
+/// The state of snapshotting: changes not saved yet, when the last save
+/// happened and the save in progress, if any.
+pub struct Persistence {
+    pub options: PersistenceOptions,
+    /// Changes to the keyspace since the last successful save.
+    pub dirty: u64,
+    /// Unix time in seconds of the last successful save, or of the start.
+    last_save: i64,
+    last_bgsave_try: i64,
+    last_bgsave_ok: bool,
+    /// A BGSAVE asked for while another save was in progress.
+    bgsave_scheduled: bool,
+    background_save: Option<BackgroundSave>,
+}
+
+fn unix_time() -> i64 {
+    mstime() / 1000
+}
+
+impl Persistence {
+    pub fn new(options: PersistenceOptions) -> Self {
+        Persistence {
+            options,
+            dirty: 0,
+            last_save: unix_time(),
+            last_bgsave_try: 0,
+            last_bgsave_ok: true,
+            bgsave_scheduled: false,
+            background_save: None,
+        }
+    }
+
+    pub fn last_save(&self) -> i64 {
+        self.last_save
+    }
+
+    pub fn is_saving(&self) -> bool {
+        self.background_save.is_some()
+    }
+
+    /// Save `snapshot`, blocking until it is on disk.
+    pub fn save(&mut self, snapshot: &Snapshot) -> io::Result<()> {
+        if let Err(e) = file::save(&self.options.path(), snapshot) {
+            warn!("Failed saving the DB: {}", e);
+            return Err(e);
+        }
+        info!("DB saved on disk");
+        self.dirty = 0;
+        self.last_save = unix_time();
+        self.last_bgsave_ok = true;
+        Ok(())
+    }
+
+    /// Save `snapshot` from another thread. There must be no save in
+    /// progress.
+    pub fn background_save(&mut self, snapshot: Snapshot) -> io::Result<()> {
+        assert!(!self.is_saving(), "a background save is in progress");
+        let path = self.options.path();
+        self.last_bgsave_try = unix_time();
+        let thread = thread::Builder::new()
+            .name(String::from("bgsave"))
+            .spawn(move || file::save(&path, &snapshot))
+            .inspect_err(|_| self.last_bgsave_ok = false)?;
+        info!("Background saving started");
+        self.background_save = Some(BackgroundSave {
+            thread,
+            dirty: self.dirty,
+            start: Instant::now(),
+        });
+        Ok(())
+    }
+
+    /// Save once the save in progress is done.
+    pub fn schedule_background_save(&mut self) {
+        self.bgsave_scheduled = true;
+    }
+
+    /// Reap a finished background save, then tell whether a background save
+    /// should start: because one was scheduled or a save rule is met.
+    pub fn cron(&mut self) -> bool {
+        if self
+            .background_save
+            .as_ref()
+            .is_some_and(|save| save.thread.is_finished())
+        {
+            let save = self.background_save.take().unwrap();
+            match save.thread.join() {
+                Ok(Ok(())) => {
+                    info!(
+                        "Background saving terminated with success in {:?}",
+                        save.start.elapsed()
+                    );
+                    self.dirty = self.dirty.saturating_sub(save.dirty);
+                    self.last_save = unix_time();
+                    self.last_bgsave_ok = true;
+                }
+                Ok(Err(e)) => {
+                    error!("Background saving error: {}", e);
+                    self.last_bgsave_ok = false;
+                }
+                Err(_) => {
+                    error!("Background saving terminated by a panic");
+                    self.last_bgsave_ok = false;
+                }
+            }
+        }
+        if self.is_saving() {
+            return false;
+        }
+        if self.bgsave_scheduled {
+            self.bgsave_scheduled = false;
+            return true;
+        }
+        let now = unix_time();
+        // Retry after a failure only once some time passed.
+        if !self.last_bgsave_ok && now - self.last_bgsave_try <= BGSAVE_RETRY_DELAY {
+            return false;
+        }
+        let rule = self
+            .options
+            .save_rules
+            .iter()
+            .find(|rule| self.dirty >= rule.changes && now - self.last_save > rule.seconds);
+        if let Some(rule) = rule {
+            info!(
+                "{} changes in {} seconds. Saving...",
+                rule.changes, rule.seconds
+            );
+        }
+        rule.is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_save_rules() {
+        assert_eq!(
+            SaveRule::parse_rules("900 1 300 10"),
+            Some(vec![
+                SaveRule {
+                    seconds: 900,
+                    changes: 1
+                },
+                SaveRule {
+                    seconds: 300,
+                    changes: 10
+                }
+            ])
+        );
+        assert_eq!(SaveRule::parse_rules(""), Some(vec![]));
+        assert_eq!(SaveRule::parse_rules("900"), None);
+        assert_eq!(SaveRule::parse_rules("-1 1"), None);
+
+        let mut persistence = Persistence::new(PersistenceOptions {
+            save_rules: SaveRule::parse_rules("0 2").unwrap(),
+            ..PersistenceOptions::default()
+        });
+        persistence.dirty = 1;
+        persistence.last_save -= 1;
+        assert!(!persistence.cron());
+        persistence.dirty = 2;
+        assert!(persistence.cron());
+        persistence.options.save_rules.clear();
+        assert!(!persistence.cron());
+        persistence.schedule_background_save();
+        assert!(persistence.cron());
+        assert!(!persistence.cron());
+    }
+}
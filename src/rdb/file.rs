@@ -0,0 +1,524 @@
+//! Snapshots of the whole keyspace in the RDB file format: a header, then the
+//! keys of each database, then a CRC64 of everything before it.
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter, ErrorKind, Write};
+use std::path::Path;
+use std::process;
+use std::sync::Arc;
+
+use bytes::Bytes;
+use log::warn;
+
+use super::crc64::crc64;
+use super::{rdb_version, Decoder, Encoder, RDB_VERSION, RDB_VERSION_WITHOUT_FIELD_TTLS};
+use crate::memory;
+use crate::store::Value;
+use crate::util::mstime;
+
+/// What files start with, followed by their version as four digits.
+const RDB_MAGIC: &[u8] = b"REDIS";
+const RDB_HEADER_SIZE: usize = 9;
+/// Versions of redis that introduced the versions of the format we write,
+/// reported in the header.
+const REDIS_VERSION: &str = "7.4.0";
+const REDIS_VERSION_WITHOUT_FIELD_TTLS: &str = "7.2.0";
+/// Files older than this have no checksum.
+const RDB_FIRST_VERSION_WITH_CHECKSUM: u16 = 5;
+
+/// Opcodes that stand where the type of a value would otherwise be.
+const RDB_OPCODE_SLOT_INFO: u8 = 244;
+const RDB_OPCODE_FUNCTION2: u8 = 245;
+const RDB_OPCODE_FUNCTION_PRE_GA: u8 = 246;
+const RDB_OPCODE_MODULE_AUX: u8 = 247;
+const RDB_OPCODE_IDLE: u8 = 248;
+const RDB_OPCODE_FREQ: u8 = 249;
+const RDB_OPCODE_AUX: u8 = 250;
+const RDB_OPCODE_RESIZEDB: u8 = 251;
+const RDB_OPCODE_EXPIRETIME_MS: u8 = 252;
+const RDB_OPCODE_EXPIRETIME: u8 = 253;
+const RDB_OPCODE_SELECTDB: u8 = 254;
+const RDB_OPCODE_EOF: u8 = 255;
+
+/// A key of a snapshot.
+#[derive(Debug, Clone)]
+pub struct SnapshotEntry {
+    pub key: Bytes,
+    pub value: Arc<Value>,
+    /// Unix time in milliseconds at which the key expires.
+    pub expire: Option<i64>,
+}
+
+/// The keys of every database at some point in time, by database.
+pub type Snapshot = Vec<Vec<SnapshotEntry>>;
+
+/// Hands encoded data to a writer, keeping the checksum of all of it.
+struct ChecksumWriter<W> {
+    out: W,
+    crc: u64,
+}
+
+impl<W: Write> ChecksumWriter<W> {
+    fn write(&mut self, encoder: Encoder) -> io::Result<()> {
+        let data = encoder.into_inner();
+        self.crc = crc64(self.crc, &data);
+        self.out.write_all(&data)
+    }
+}
+
+/// Write `snapshot` to `out` in the RDB format, in the oldest version that
+/// holds all of it so that as many versions of redis as possible load it:
+/// 11 unless a hash has field TTLs, which need 12 and so redis 7.4 or later.
+pub fn write_snapshot(out: impl Write, snapshot: &Snapshot) -> io::Result<()> {
+    let version = snapshot
+        .iter()
+        .flatten()
+        .map(|entry| rdb_version(&entry.value))
+        .max()
+        .unwrap_or(RDB_VERSION_WITHOUT_FIELD_TTLS);
+    let redis_version = match version {
+        RDB_VERSION_WITHOUT_FIELD_TTLS => REDIS_VERSION_WITHOUT_FIELD_TTLS,
+        _ => REDIS_VERSION,
+    };
+
+    let mut out = ChecksumWriter { out, crc: 0 };
+    let mut header = Encoder::new();
+    header.write_bytes(RDB_MAGIC);
+    header.write_bytes(format!("{:04}", version).as_bytes());
+    let aux_fields = [
+        ("redis-ver", String::from(redis_version)),
+        ("redis-bits", (usize::BITS).to_string()),
+        ("ctime", (mstime() / 1000).to_string()),
+        ("used-mem", memory::used_memory().to_string()),
+        ("aof-base", String::from("0")),
+    ];
+    for (name, value) in aux_fields {
+        header.write_u8(RDB_OPCODE_AUX);
+        header.write_string(name.as_bytes());
+        header.write_string(value.as_bytes());
+    }
+    out.write(header)?;
+
+    for (index, entries) in snapshot.iter().enumerate() {
+        if entries.is_empty() {
+            continue;
+        }
+        let mut encoder = Encoder::new();
+        encoder.write_u8(RDB_OPCODE_SELECTDB);
+        encoder.write_len(index as u64);
+        encoder.write_u8(RDB_OPCODE_RESIZEDB);
+        encoder.write_len(entries.len() as u64);
+        let volatile = entries.iter().filter(|entry| entry.expire.is_some());
+        encoder.write_len(volatile.count() as u64);
+        out.write(encoder)?;
+        for entry in entries {
+            let mut encoder = Encoder::new();
+            if let Some(when) = entry.expire {
+                encoder.write_u8(RDB_OPCODE_EXPIRETIME_MS);
+                encoder.write_millis(when);
+            }
+            encoder.write_key_value(&entry.key, &entry.value);
+            out.write(encoder)?;
+        }
+    }
+
+    let mut footer = Encoder::new();
+    footer.write_u8(RDB_OPCODE_EOF);
+    out.write(footer)?;
+    let crc = out.crc;
+    out.out.write_all(&crc.to_le_bytes())?;
+    out.out.flush()
+}
+
+/// Read a snapshot in the RDB format for a server with `databases`
+/// databases, dropping the keys that already expired. The access time or
+/// frequency of keys is not kept, and functions are skipped as there is
+/// nothing to run them.
+pub fn read_snapshot(data: &[u8], databases: usize) -> Result<Snapshot, String> {
+    if data.len() < RDB_HEADER_SIZE || !data.starts_with(RDB_MAGIC) {
+        return Err(String::from("Wrong signature trying to load DB from file"));
+    }
+    let version = std::str::from_utf8(&data[RDB_MAGIC.len()..RDB_HEADER_SIZE])
+        .ok()
+        .and_then(|digits| digits.parse::<u16>().ok())
+        .filter(|version| (1..=RDB_VERSION).contains(version))
+        .ok_or_else(|| String::from("Can't handle RDB format version"))?;
+
+    let mut decoder = Decoder::new(data);
+    decoder.read_bytes(RDB_HEADER_SIZE)?;
+    let mut snapshot: Snapshot = vec![Vec::new(); databases];
+    let mut db = 0;
+    let mut expire = None;
+    let now = mstime();
+    loop {
+        match decoder.read_u8()? {
+            RDB_OPCODE_AUX => {
+                decoder.read_string()?;
+                decoder.read_string()?;
+            }
+            RDB_OPCODE_RESIZEDB => {
+                decoder.read_len()?;
+                decoder.read_len()?;
+            }
+            RDB_OPCODE_SLOT_INFO => {
+                for _ in 0..3 {
+                    decoder.read_len()?;
+                }
+            }
+            RDB_OPCODE_IDLE => {
+                decoder.read_len()?;
+            }
+            RDB_OPCODE_FREQ => {
+                decoder.read_u8()?;
+            }
+            RDB_OPCODE_FUNCTION2 => {
+                decoder.read_string()?;
+                warn!("Skipping a library of functions, which are not supported");
+            }
+            RDB_OPCODE_FUNCTION_PRE_GA => {
+                return Err(String::from("Pre-release function format not supported"));
+            }
+            RDB_OPCODE_MODULE_AUX => {
+                return Err(String::from(
+                    "Module data found but modules are not supported",
+                ));
+            }
+            RDB_OPCODE_SELECTDB => {
+                db = decoder.read_size()?;
+                if db >= databases {
+                    return Err(format!(
+                        "Data file was created with a server configured to handle more than {} databases",
+                        databases
+                    ));
+                }
+            }
+            RDB_OPCODE_EXPIRETIME_MS => expire = Some(decoder.read_millis()?),
+            RDB_OPCODE_EXPIRETIME => {
+                let seconds = i32::from_le_bytes(decoder.read_bytes(4)?.try_into().unwrap());
+                expire = Some(seconds as i64 * 1000);
+            }
+            RDB_OPCODE_EOF => break,
+            rdb_type => {
+                let key = decoder.read_string()?;
+                let value = decoder.read_value(rdb_type)?;
+                if expire.is_none_or(|when| when > now) {
+                    snapshot[db].push(SnapshotEntry {
+                        key,
+                        value: Arc::new(value),
+                        expire,
+                    });
+                }
+                expire = None;
+            }
+        }
+    }
+
+    if version >= RDB_FIRST_VERSION_WITH_CHECKSUM {
+        let body = &data[..decoder.pos];
+        let crc = u64::from_le_bytes(decoder.read_bytes(8)?.try_into().unwrap());
+        // A zero checksum means the file was written with checksums disabled.
+        if crc != 0 && crc != crc64(0, body) {
+            return Err(String::from("Wrong RDB checksum"));
+        }
+    }
+    Ok(snapshot)
+}
+
+/// Save `snapshot` to `path` through a temporary file renamed over it once
+/// complete, so that `path` always holds a whole snapshot.
+pub fn save(path: &Path, snapshot: &Snapshot) -> io::Result<()> {
+    let temp = path.with_file_name(format!("temp-{}.rdb", process::id()));
+    let result = File::create(&temp).and_then(|file| {
+        let mut out = BufWriter::new(file);
+        write_snapshot(&mut out, snapshot)?;
+        out.into_inner()
+            .map_err(|error| error.into_error())?
+            .sync_all()?;
+        fs::rename(&temp, path)
+    });
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+/// Load the snapshot saved at `path`, None if there is none.
+pub fn load(path: &Path, databases: usize) -> Result<Option<Snapshot>, String> {
+    match fs::read(path) {
+        Ok(data) => read_snapshot(&data, databases).map(Some),
+        Err(error) if error.kind() == ErrorKind::NotFound => Ok(None),
+        Err(error) => Err(error.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rdb;
+    use crate::types::hash::Hash;
+    use crate::types::quicklist::QuickList;
+    use crate::types::stream::StreamId;
+    use crate::types::zset::ZSet;
+
+    fn entry(key: &str, value: Value, expire: Option<i64>) -> SnapshotEntry {
+        SnapshotEntry {
+            key: Bytes::copy_from_slice(key.as_bytes()),
+            value: Arc::new(value),
+            expire,
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut list = QuickList::new();
+        list.push_back(Bytes::from("a"));
+        let mut hash = Hash::new();
+        hash.insert(Bytes::from("f"), Bytes::from("v"));
+        let mut zset = ZSet::new();
+        zset.insert(Bytes::from("m"), 1.5);
+        let later = mstime() + 60_000;
+        let snapshot = vec![
+            vec![
+                entry("string", Value::String(Bytes::from("12")), None),
+                entry("list", Value::List(list), Some(later)),
+                entry("expired", Value::String(Bytes::from("x")), Some(1)),
+            ],
+            vec![],
+            vec![
+                entry("hash", Value::Hash(hash), None),
+                entry("zset", Value::ZSet(zset), None),
+            ],
+        ];
+        let mut data = Vec::new();
+        write_snapshot(&mut data, &snapshot).unwrap();
+        assert!(data.starts_with(b"REDIS0011"));
+
+        let loaded = read_snapshot(&data, 3).unwrap();
+        let keys: Vec<Vec<&[u8]>> = loaded
+            .iter()
+            .map(|entries| entries.iter().map(|entry| entry.key.as_ref()).collect())
+            .collect();
+        assert_eq!(
+            keys,
+            vec![
+                vec![&b"string"[..], b"list"],
+                vec![],
+                vec![b"hash", b"zset"]
+            ]
+        );
+        assert_eq!(loaded[0][1].expire, Some(later));
+        assert!(matches!(&*loaded[2][1].value, Value::ZSet(zset) if zset.score(b"m") == Some(1.5)));
+
+        assert!(read_snapshot(&data, 2).is_err());
+        let mut corrupted = data.clone();
+        corrupted[20] ^= 1;
+        assert!(read_snapshot(&corrupted, 3).is_err());
+        let mut newer = data.clone();
+        newer[5..9].copy_from_slice(b"0099");
+        assert!(read_snapshot(&newer, 3).is_err());
+        assert!(read_snapshot(&data[..data.len() - 1], 3).is_err());
+
+        // Only field TTLs need the latest version.
+        let mut hash = Hash::new();
+        hash.insert(Bytes::from("f"), Bytes::from("v"));
+        hash.set_field_expire_time(&Bytes::from("f"), later);
+        let snapshot = vec![vec![entry("hash", Value::Hash(hash), None)]];
+        let mut data = Vec::new();
+        write_snapshot(&mut data, &snapshot).unwrap();
+        assert!(data.starts_with(b"REDIS0012"));
+        let loaded = read_snapshot(&data, 1).unwrap();
+        assert!(matches!(
+            &*loaded[0][0].value,
+            Value::Hash(hash) if hash.field_expire_time(b"f") == Some(later)
+        ));
+    }
+
+    #[test]
+    fn test_read_redis_file() {
+        // A file as redis 7.0 writes it, with compact encodings, metadata of
+        // keys and a library of functions.
+        let mut encoder = Encoder::new();
+        encoder.write_bytes(b"REDIS0010");
+        encoder.write_u8(RDB_OPCODE_AUX);
+        encoder.write_string(b"redis-ver");
+        encoder.write_string(b"7.0.15");
+        encoder.write_u8(RDB_OPCODE_FUNCTION2);
+        encoder.write_string(b"#!lua name=lib\nredis.register_function('f', function() end)");
+        encoder.write_u8(RDB_OPCODE_SELECTDB);
+        encoder.write_len(0);
+        encoder.write_u8(RDB_OPCODE_RESIZEDB);
+        encoder.write_len(3);
+        encoder.write_len(1);
+        encoder.write_u8(RDB_OPCODE_FREQ);
+        encoder.write_u8(5);
+        encoder.write_u8(rdb::RDB_TYPE_STRING);
+        encoder.write_string(b"long");
+        encoder.write_string("abc".repeat(100).as_bytes());
+        encoder.write_u8(RDB_OPCODE_EXPIRETIME);
+        encoder.write_bytes(&i32::MAX.to_le_bytes());
+        encoder.write_u8(RDB_OPCODE_IDLE);
+        encoder.write_len(100);
+        encoder.write_u8(rdb::RDB_TYPE_LIST_QUICKLIST);
+        encoder.write_string(b"list");
+        encoder.write_len(1);
+        encoder.write_string(b"\x11\0\0\0\x0d\0\0\0\x02\0\x00\x01f\x03\x01v\xff");
+        encoder.write_u8(rdb::RDB_TYPE_SET_INTSET);
+        encoder.write_string(b"set");
+        encoder.write_string(b"\x02\0\0\0\x02\0\0\0\x01\0\x02\0");
+        encoder.write_u8(RDB_OPCODE_EOF);
+        let mut data = encoder.into_inner();
+        data.extend(crc64(0, &data).to_le_bytes());
+
+        let loaded = read_snapshot(&data, 1).unwrap();
+        let keys: Vec<&[u8]> = loaded[0].iter().map(|entry| entry.key.as_ref()).collect();
+        assert_eq!(keys, vec![&b"long"[..], b"list", b"set"]);
+        assert!(matches!(
+            &*loaded[0][0].value,
+            Value::String(value) if *value == "abc".repeat(100)
+        ));
+        assert_eq!(loaded[0][1].expire, Some(i32::MAX as i64 * 1000));
+        assert!(matches!(
+            &*loaded[0][1].value,
+            Value::List(list) if list.iter().eq([&b"f"[..], b"v"])
+        ));
+        assert!(matches!(&*loaded[0][2].value, Value::Set(set) if set.len() == 2));
+
+        let mut module_data = data[..RDB_HEADER_SIZE].to_vec();
+        module_data.push(RDB_OPCODE_MODULE_AUX);
+        assert_eq!(
+            read_snapshot(&module_data, 1).unwrap_err(),
+            "Module data found but modules are not supported"
+        );
+    }
+
+    /// The value of `key` in the first database of `snapshot`.
+    fn value_of<'a>(snapshot: &'a Snapshot, key: &str) -> &'a Value {
+        let entry = snapshot[0].iter().find(|entry| entry.key == key.as_bytes());
+        &entry.unwrap().value
+    }
+
+    /// Checks what both fixtures hold: the same keys, in the encodings of
+    /// redis 5.0 and 7.2 respectively.
+    fn check_fixture(snapshot: &Snapshot) {
+        let t: u64 = 1_700_000_000_000;
+        let ms = t as i64;
+        let long = "abcdefghij".repeat(8);
+        assert!(matches!(value_of(snapshot, "lzf"), Value::String(value) if *value == long));
+        let Value::List(list) = value_of(snapshot, "list") else {
+            panic!()
+        };
+        let items = ["a", "1024", "-5", "hello", "70000", &long[..40]];
+        assert!(list.iter().eq(items.map(str::as_bytes)));
+        let list = snapshot[0]
+            .iter()
+            .find(|entry| entry.key == "list")
+            .unwrap();
+        assert_eq!(list.expire, Some(4_102_444_800_000));
+        let Value::Hash(hash) = value_of(snapshot, "hash") else {
+            panic!()
+        };
+        assert_eq!(hash.len(), 2);
+        assert_eq!(hash.get(b"f1"), Some(&b"v1"[..]));
+        assert_eq!(hash.get(b"n"), Some(&b"12"[..]));
+        let Value::ZSet(zset) = value_of(snapshot, "zset") else {
+            panic!()
+        };
+        let scores: Vec<(&[u8], f64)> = zset.iter().collect();
+        assert_eq!(scores, [(&b"c"[..], -300.0), (b"a", 1.5), (b"b", 2.0)]);
+
+        let Value::Stream(stream) = value_of(snapshot, "stream") else {
+            panic!()
+        };
+        let entries: Vec<_> = stream
+            .range(
+                StreamId::default(),
+                StreamId::new(u64::MAX, u64::MAX),
+                false,
+                usize::MAX,
+            )
+            .into_iter()
+            .map(|entry| (entry.id, entry.fields.clone()))
+            .collect();
+        let fields = |pairs: &[(&'static str, &'static str)]| -> Vec<(Bytes, Bytes)> {
+            pairs
+                .iter()
+                .map(|(field, value)| (Bytes::from(*field), Bytes::from(*value)))
+                .collect()
+        };
+        assert_eq!(
+            entries,
+            [
+                (StreamId::new(t, 0), fields(&[("f", "v")])),
+                (StreamId::new(t + 5, 0), fields(&[("g", "x"), ("h", "7")])),
+            ]
+        );
+        assert_eq!(stream.last_id(), StreamId::new(t + 5, 0));
+        let group = stream.group(b"grp").unwrap();
+        assert_eq!(group.last_id, StreamId::new(t + 5, 0));
+        let pending = &group.pending[&StreamId::new(t, 0)];
+        assert_eq!(pending.consumer, "alice");
+        assert_eq!(
+            (pending.delivery_time, pending.delivery_count),
+            (ms + 100, 2)
+        );
+        assert_eq!(group.consumers[&Bytes::from("alice")].seen_time, ms + 200);
+    }
+
+    #[test]
+    fn test_read_redis_fixtures() {
+        let t: u64 = 1_700_000_000_000;
+        let ms = t as i64;
+        let snapshot = read_snapshot(include_bytes!("fixtures/redis-5.0.rdb"), 1).unwrap();
+        check_fixture(&snapshot);
+        assert!(matches!(value_of(&snapshot, "int"), Value::String(value) if value == "-70000"));
+        let Value::Set(set) = value_of(&snapshot, "set") else {
+            panic!()
+        };
+        assert!(
+            set.len() == 3
+                && ["-70000", "1", "300"]
+                    .iter()
+                    .all(|m| set.contains(m.as_bytes()))
+        );
+        let Value::Stream(stream) = value_of(&snapshot, "stream") else {
+            panic!()
+        };
+        assert_eq!(stream.entries_added(), 2);
+        assert_eq!(stream.max_deleted_id(), StreamId::default());
+        let consumer = &stream.group(b"grp").unwrap().consumers[&Bytes::from("alice")];
+        assert_eq!(consumer.active_time, ms + 200);
+
+        let snapshot = read_snapshot(include_bytes!("fixtures/redis-7.2.rdb"), 1).unwrap();
+        check_fixture(&snapshot);
+        let Value::Set(set) = value_of(&snapshot, "set") else {
+            panic!()
+        };
+        assert!(set.len() == 2 && set.contains(b"x") && set.contains(b"7"));
+        let Value::Set(set) = value_of(&snapshot, "intset") else {
+            panic!()
+        };
+        assert!(set.len() == 3 && ["1", "2", "300"].iter().all(|m| set.contains(m.as_bytes())));
+        let Value::Stream(stream) = value_of(&snapshot, "stream") else {
+            panic!()
+        };
+        assert_eq!(stream.entries_added(), 3);
+        assert_eq!(stream.max_deleted_id(), StreamId::new(t, 1));
+        let group = stream.group(b"grp").unwrap();
+        assert_eq!(group.entries_read, Some(2));
+        assert_eq!(group.consumers[&Bytes::from("alice")].active_time, ms + 150);
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let dir = std::env::temp_dir().join(format!("rdb-file-test-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("dump.rdb");
+        assert_eq!(load(&path, 1).map(|snapshot| snapshot.is_none()), Ok(true));
+        let snapshot = vec![vec![entry("key", Value::String(Bytes::from("v")), None)]];
+        save(&path, &snapshot).unwrap();
+        let loaded = load(&path, 1).unwrap().unwrap();
+        assert_eq!(loaded[0][0].key, Bytes::from("key"));
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,47 @@
+//! The intset, the serialization of small sets of integers: the width of its
+//! integers and their count, then the integers in increasing order.
+
+/// Size of the header: the width and the count as u32s.
+const HEADER_SIZE: usize = 8;
+
+/// Decode the integers of an intset, validating its structure.
+pub fn parse(data: &[u8]) -> Result<Vec<i64>, String> {
+    let corrupt = || String::from("corrupt intset");
+    if data.len() < HEADER_SIZE {
+        return Err(corrupt());
+    }
+    let width = u32::from_le_bytes(data[..4].try_into().unwrap()) as usize;
+    let count = u32::from_le_bytes(data[4..HEADER_SIZE].try_into().unwrap()) as usize;
+    if ![2, 4, 8].contains(&width) || Some(data.len() - HEADER_SIZE) != count.checked_mul(width) {
+        return Err(corrupt());
+    }
+    let values: Vec<i64> = data[HEADER_SIZE..]
+        .chunks(width)
+        .map(|bytes| match width {
+            2 => i16::from_le_bytes(bytes.try_into().unwrap()) as i64,
+            4 => i32::from_le_bytes(bytes.try_into().unwrap()) as i64,
+            _ => i64::from_le_bytes(bytes.try_into().unwrap()),
+        })
+        .collect();
+    if values.windows(2).any(|pair| pair[0] >= pair[1]) {
+        return Err(corrupt());
+    }
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let data = b"\x04\0\0\0\x03\0\0\0\x00\x00\x00\x80\xff\xff\xff\xff\x10\x27\x00\x00";
+        assert_eq!(parse(data).unwrap(), vec![i32::MIN as i64, -1, 10000]);
+        assert_eq!(parse(b"\x02\0\0\0\0\0\0\0").unwrap(), vec![]);
+        assert!(parse(&data[..data.len() - 1]).is_err());
+        let mut unsorted = data.to_vec();
+        unsorted[16..].copy_from_slice(b"\xff\xff\xff\xff");
+        assert!(parse(&unsorted).is_err());
+        assert!(parse(b"\x03\0\0\0\x01\0\0\0abc").is_err());
+    }
+}
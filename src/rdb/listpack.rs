@@ -0,0 +1,248 @@
+use bytes::Bytes;
+
+use crate::util::canonical_int;
+
+/// Size of the header: total bytes as a u32 and element count as a u16.
+const HEADER_SIZE: usize = 6;
+/// Element count stored once the real count no longer fits in the header.
+const UNKNOWN_COUNT: u16 = u16::MAX;
+const EOF: u8 = 0xff;
+
+/// An element of a listpack, which stores integers in a compact form.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Entry {
+    Int(i64),
+    Str(Bytes),
+}
+
+impl Entry {
+    pub fn into_bytes(self) -> Bytes {
+        match self {
+            Entry::Int(value) => Bytes::from(value.to_string()),
+            Entry::Str(bytes) => bytes,
+        }
+    }
+
+    /// The element as an integer, whether it was stored as one or as its
+    /// canonical representation.
+    pub fn as_int(&self) -> Option<i64> {
+        match self {
+            Entry::Int(value) => Some(*value),
+            Entry::Str(bytes) => canonical_int(bytes),
+        }
+    }
+}
+
+/// Builds a listpack, the flat serialization redis uses for small collections
+/// and the nodes of streams.
+#[derive(Debug, Default)]
+pub struct ListPackWriter {
+    buf: Vec<u8>,
+    count: usize,
+}
+
+impl ListPackWriter {
+    pub fn new() -> Self {
+        ListPackWriter {
+            buf: vec![0; HEADER_SIZE],
+            count: 0,
+        }
+    }
+
+    pub fn push_int(&mut self, value: i64) {
+        let start = self.buf.len();
+        match value {
+            0..=127 => self.buf.push(value as u8),
+            -4096..=4095 => {
+                let value = (value as u16) & 0x1fff;
+                self.buf.extend([0xc0 | (value >> 8) as u8, value as u8]);
+            }
+            -32768..=32767 => {
+                self.buf.push(0xf1);
+                self.buf.extend((value as i16).to_le_bytes());
+            }
+            -8_388_608..=8_388_607 => {
+                self.buf.push(0xf2);
+                self.buf.extend(&(value as i32).to_le_bytes()[..3]);
+            }
+            _ if i32::try_from(value).is_ok() => {
+                self.buf.push(0xf3);
+                self.buf.extend((value as i32).to_le_bytes());
+            }
+            _ => {
+                self.buf.push(0xf4);
+                self.buf.extend(value.to_le_bytes());
+            }
+        }
+        self.finish_entry(start);
+    }
+
+    /// Append a string, stored as an integer when it is the canonical
+    /// representation of one.
+    pub fn push_str(&mut self, value: &[u8]) {
+        if let Some(value) = canonical_int(value) {
+            return self.push_int(value);
+        }
+        let start = self.buf.len();
+        let len = value.len();
+        if len < 64 {
+            self.buf.push(0x80 | len as u8);
+        } else if len < 4096 {
+            self.buf.extend([0xe0 | (len >> 8) as u8, len as u8]);
+        } else {
+            self.buf.push(0xf0);
+            self.buf.extend((len as u32).to_le_bytes());
+        }
+        self.buf.extend_from_slice(value);
+        self.finish_entry(start);
+    }
+
+    /// Append the back length of the entry starting at `start`, which lets
+    /// redis walk the listpack backwards.
+    fn finish_entry(&mut self, start: usize) {
+        let len = self.buf.len() - start;
+        let size = backlen_size(len);
+        self.buf.push((len >> (7 * (size - 1))) as u8);
+        for i in (0..size - 1).rev() {
+            self.buf.push(((len >> (7 * i)) & 127) as u8 | 128);
+        }
+        self.count += 1;
+    }
+
+    pub fn finish(mut self) -> Vec<u8> {
+        self.buf.push(EOF);
+        let total = self.buf.len() as u32;
+        let count = u16::try_from(self.count).unwrap_or(UNKNOWN_COUNT);
+        self.buf[..4].copy_from_slice(&total.to_le_bytes());
+        self.buf[4..HEADER_SIZE].copy_from_slice(&count.to_le_bytes());
+        self.buf
+    }
+}
+
+/// Number of bytes of the back length of an entry of `len` bytes.
+fn backlen_size(len: usize) -> usize {
+    match len {
+        0..=127 => 1,
+        128..=16382 => 2,
+        16383..=2_097_150 => 3,
+        2_097_151..=268_435_454 => 4,
+        _ => 5,
+    }
+}
+
+/// Decode every element of a listpack, validating its structure.
+pub fn parse(data: &[u8]) -> Result<Vec<Entry>, String> {
+    let corrupt = || String::from("corrupt listpack");
+    if data.len() < HEADER_SIZE + 1 {
+        return Err(corrupt());
+    }
+    let total = u32::from_le_bytes(data[..4].try_into().unwrap()) as usize;
+    let count = u16::from_le_bytes(data[4..HEADER_SIZE].try_into().unwrap());
+    if total != data.len() || data[total - 1] != EOF {
+        return Err(corrupt());
+    }
+
+    let bytes = |start: usize, len: usize| data.get(start..start + len).ok_or_else(corrupt);
+    let mut entries = Vec::new();
+    let mut pos = HEADER_SIZE;
+    while data[pos] != EOF {
+        let first = data[pos];
+        let (entry, len) = match first {
+            0x00..=0x7f => (Entry::Int(first as i64), 1),
+            0x80..=0xbf => {
+                let len = (first & 0x3f) as usize;
+                (
+                    Entry::Str(Bytes::copy_from_slice(bytes(pos + 1, len)?)),
+                    1 + len,
+                )
+            }
+            0xc0..=0xdf => {
+                let value = ((first as i64 & 0x1f) << 8) | bytes(pos + 1, 1)?[0] as i64;
+                (Entry::Int((value << 51) >> 51), 2)
+            }
+            0xe0..=0xef => {
+                let len = ((first as usize & 0x0f) << 8) | bytes(pos + 1, 1)?[0] as usize;
+                (
+                    Entry::Str(Bytes::copy_from_slice(bytes(pos + 2, len)?)),
+                    2 + len,
+                )
+            }
+            0xf0 => {
+                let len = u32::from_le_bytes(bytes(pos + 1, 4)?.try_into().unwrap()) as usize;
+                (
+                    Entry::Str(Bytes::copy_from_slice(bytes(pos + 5, len)?)),
+                    5 + len,
+                )
+            }
+            0xf1..=0xf4 => {
+                let width = [2, 3, 4, 8][(first - 0xf1) as usize];
+                let mut buf = [0; 8];
+                buf[..width].copy_from_slice(bytes(pos + 1, width)?);
+                let shift = 64 - 8 * width as u32;
+                let value = (i64::from_le_bytes(buf) << shift) >> shift;
+                (Entry::Int(value), 1 + width)
+            }
+            _ => return Err(corrupt()),
+        };
+        pos += len + backlen_size(len);
+        if pos >= total {
+            return Err(corrupt());
+        }
+        entries.push(entry);
+    }
+    if pos != total - 1 || (count != UNKNOWN_COUNT && count as usize != entries.len()) {
+        return Err(corrupt());
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let ints = [
+            0, 127, 128, -1, -4096, 4095, 4096, -32768, 70000, -8_388_608,
+        ];
+        let ints = ints
+            .into_iter()
+            .chain([i32::MIN as i64, i64::MAX, i64::MIN]);
+        let long = "x".repeat(5000);
+        let strings = ["", "a", "007", "+1", &"y".repeat(100), &long];
+
+        let mut writer = ListPackWriter::new();
+        for value in ints.clone() {
+            writer.push_int(value);
+        }
+        for value in strings {
+            writer.push_str(value.as_bytes());
+        }
+        writer.push_str(b"-42");
+        let data = writer.finish();
+
+        let mut expected: Vec<Entry> = ints.map(Entry::Int).collect();
+        expected.extend(strings.map(|s| Entry::Str(Bytes::from(s.to_string()))));
+        expected.push(Entry::Int(-42));
+        assert_eq!(parse(&data).unwrap(), expected);
+
+        // A short string: header, encoding byte, the string and its back length.
+        let mut writer = ListPackWriter::new();
+        writer.push_str(b"ab");
+        assert_eq!(writer.finish(), b"\x0b\0\0\0\x01\0\x82ab\x03\xff");
+    }
+
+    #[test]
+    fn test_rejects_corrupt_data() {
+        let mut writer = ListPackWriter::new();
+        writer.push_str(b"hello");
+        let data = writer.finish();
+        assert!(parse(&data[..data.len() - 1]).is_err());
+        let mut bad = data.clone();
+        bad[6] = 0x90;
+        assert!(parse(&bad).is_err());
+        let mut bad = data;
+        bad[4] = 2;
+        assert!(parse(&bad).is_err());
+    }
+}
@@ -0,0 +1,142 @@
+//! The LZF compression redis applies to long strings of RDB files: a sequence
+//! of literal runs and back references into the output produced so far.
+
+/// Longest literal run a single control byte describes.
+const MAX_LIT: usize = 32;
+/// Farthest and longest a back reference reaches.
+const MAX_OFF: usize = 1 << 13;
+const MAX_REF: usize = (1 << 8) + (1 << 3);
+/// Most bits of the hash of three bytes indexing the table of previous
+/// positions, fewer for short inputs which would not fill the table.
+const HASH_LOG: u32 = 14;
+
+fn hash(bytes: &[u8], bits: u32) -> usize {
+    let value = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], 0]);
+    (value.wrapping_mul(2_654_435_761) >> (32 - bits)) as usize
+}
+
+fn push_literals(out: &mut Vec<u8>, literals: &[u8]) {
+    for run in literals.chunks(MAX_LIT) {
+        out.push((run.len() - 1) as u8);
+        out.extend_from_slice(run);
+    }
+}
+
+/// Compress `input`, finding repeats of at least three bytes through a hash
+/// table of where each sequence of three bytes was last seen.
+pub fn compress(input: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(input.len());
+    let bits = input
+        .len()
+        .next_power_of_two()
+        .trailing_zeros()
+        .min(HASH_LOG);
+    let mut table = vec![usize::MAX; 1 << bits];
+    let mut literals = 0;
+    let mut pos = 0;
+    while pos + 2 < input.len() {
+        let slot = &mut table[hash(&input[pos..], bits)];
+        let candidate = *slot;
+        *slot = pos;
+        if candidate >= pos
+            || pos - candidate > MAX_OFF
+            || input[candidate..candidate + 3] != input[pos..pos + 3]
+        {
+            pos += 1;
+            continue;
+        }
+        let max = (input.len() - pos).min(MAX_REF);
+        let mut len = 3;
+        while len < max && input[candidate + len] == input[pos + len] {
+            len += 1;
+        }
+        push_literals(&mut out, &input[literals..pos]);
+        let offset = pos - candidate - 1;
+        let extra = len - 2;
+        if extra < 7 {
+            out.push(((extra << 5) | (offset >> 8)) as u8);
+        } else {
+            out.push(((7 << 5) | (offset >> 8)) as u8);
+            out.push((extra - 7) as u8);
+        }
+        out.push(offset as u8);
+        pos += len;
+        literals = pos;
+    }
+    push_literals(&mut out, &input[literals..]);
+    out
+}
+
+/// Decompress `input` into the `len` bytes it was compressed from.
+pub fn decompress(input: &[u8], len: usize) -> Result<Vec<u8>, String> {
+    let corrupt = || String::from("corrupt LZF data");
+    let mut out = Vec::new();
+    let mut pos = 0;
+    while pos < input.len() {
+        let ctrl = input[pos] as usize;
+        pos += 1;
+        if ctrl < MAX_LIT {
+            let run = input.get(pos..pos + ctrl + 1).ok_or_else(corrupt)?;
+            out.extend_from_slice(run);
+            pos += run.len();
+        } else {
+            let mut extra = ctrl >> 5;
+            if extra == 7 {
+                extra += *input.get(pos).ok_or_else(corrupt)? as usize;
+                pos += 1;
+            }
+            let offset = ((ctrl & 0x1f) << 8) | *input.get(pos).ok_or_else(corrupt)? as usize;
+            pos += 1;
+            let start = out.len().checked_sub(offset + 1).ok_or_else(corrupt)?;
+            // The reference may overlap the bytes it produces.
+            for i in start..start + extra + 2 {
+                out.push(out[i]);
+            }
+        }
+        if out.len() > len {
+            return Err(corrupt());
+        }
+    }
+    if out.len() != len {
+        return Err(corrupt());
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let text = "the quick brown fox jumps over the lazy dog ".repeat(50);
+        let runs = "a".repeat(1000);
+        let mut noise = Vec::new();
+        let mut state = 7u32;
+        for _ in 0..20_000 {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            noise.push((state >> 24) as u8 % 8);
+        }
+        for input in [
+            b"".as_slice(),
+            b"ab",
+            text.as_bytes(),
+            runs.as_bytes(),
+            &noise,
+        ] {
+            let compressed = compress(input);
+            assert_eq!(decompress(&compressed, input.len()).unwrap(), input);
+        }
+        assert!(compress(text.as_bytes()).len() < text.len() / 10);
+    }
+
+    #[test]
+    fn test_decompress() {
+        // A literal run of three bytes, then nine bytes from three back.
+        let data = b"\x02abc\xe0\x00\x02";
+        assert_eq!(decompress(data, 12).unwrap(), b"abcabcabcabc");
+        assert!(decompress(data, 11).is_err());
+        assert!(decompress(b"\x02ab", 3).is_err());
+        assert!(decompress(b"\x00a\x20\x05", 4).is_err());
+    }
+}
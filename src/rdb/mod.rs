@@ -0,0 +1,1037 @@
+//! The RDB serialization of values, shared by DUMP and RESTORE and by the
+//! snapshots of the keyspace saved to disk.
+
+pub mod crc64;
+pub mod file;
+pub mod intset;
+pub mod listpack;
+pub mod lzf;
+pub mod ziplist;
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use bytes::Bytes;
+
+use crate::store::Value;
+use crate::types::hash::Hash;
+use crate::types::quicklist::QuickList;
+use crate::types::set::Set;
+use crate::types::stream::{
+    Consumer, ConsumerGroup, Fields, PendingEntry, Stream, StreamId, STREAM_NODE_MAX_ENTRIES,
+};
+use crate::types::zset::ZSet;
+use crate::util::canonical_int;
+use crc64::crc64;
+use listpack::{Entry, ListPackWriter};
+
+/// Latest version of the format, payloads of later versions are refused.
+pub const RDB_VERSION: u16 = 12;
+/// Version written when nothing needs the latest one, so that redis 7.2 and
+/// later load what we write.
+pub const RDB_VERSION_WITHOUT_FIELD_TTLS: u16 = 11;
+
+const RDB_TYPE_STRING: u8 = 0;
+const RDB_TYPE_LIST: u8 = 1;
+const RDB_TYPE_SET: u8 = 2;
+/// A sorted set with scores stored as strings.
+const RDB_TYPE_ZSET: u8 = 3;
+const RDB_TYPE_HASH: u8 = 4;
+const RDB_TYPE_ZSET_2: u8 = 5;
+const RDB_TYPE_MODULE_PRE_GA: u8 = 6;
+const RDB_TYPE_MODULE_2: u8 = 7;
+/// Collections serialized the way redis holds them in memory.
+const RDB_TYPE_LIST_ZIPLIST: u8 = 10;
+const RDB_TYPE_SET_INTSET: u8 = 11;
+const RDB_TYPE_ZSET_ZIPLIST: u8 = 12;
+const RDB_TYPE_HASH_ZIPLIST: u8 = 13;
+const RDB_TYPE_LIST_QUICKLIST: u8 = 14;
+const RDB_TYPE_STREAM_LISTPACKS: u8 = 15;
+const RDB_TYPE_HASH_LISTPACK: u8 = 16;
+const RDB_TYPE_ZSET_LISTPACK: u8 = 17;
+const RDB_TYPE_LIST_QUICKLIST_2: u8 = 18;
+const RDB_TYPE_STREAM_LISTPACKS_2: u8 = 19;
+const RDB_TYPE_SET_LISTPACK: u8 = 20;
+const RDB_TYPE_STREAM_LISTPACKS_3: u8 = 21;
+/// A hash with fields that have a TTL.
+const RDB_TYPE_HASH_METADATA: u8 = 24;
+
+/// The two high bits of the first byte of a length tell how it is encoded.
+const RDB_6BITLEN: u8 = 0;
+const RDB_14BITLEN: u8 = 1;
+const RDB_32BITLEN: u8 = 0x80;
+const RDB_64BITLEN: u8 = 0x81;
+/// Not a length but a string stored in a special encoding.
+const RDB_ENCVAL: u8 = 3;
+const RDB_ENC_INT8: u8 = 0;
+const RDB_ENC_INT16: u8 = 1;
+const RDB_ENC_INT32: u8 = 2;
+const RDB_ENC_LZF: u8 = 3;
+/// Strings up to this long are never worth compressing.
+const LZF_MIN_LEN: usize = 20;
+
+/// Lengths of string encoded doubles that stand for special values instead.
+const RDB_DOUBLE_NAN: u8 = 253;
+const RDB_DOUBLE_POS_INF: u8 = 254;
+const RDB_DOUBLE_NEG_INF: u8 = 255;
+
+/// Containers of the nodes of a quicklist.
+const QUICKLIST_NODE_CONTAINER_PLAIN: u64 = 1;
+const QUICKLIST_NODE_CONTAINER_PACKED: u64 = 2;
+
+/// Flags of the entries of a stream listpack.
+const STREAM_ITEM_FLAG_DELETED: i64 = 1;
+const STREAM_ITEM_FLAG_SAMEFIELDS: i64 = 2;
+
+/// Size of the trailer of a DUMP payload: the RDB version and a CRC64.
+const DUMP_FOOTER_SIZE: usize = 10;
+
+/// Serializes values in the RDB format.
+#[derive(Debug, Default)]
+pub struct Encoder {
+    buf: Vec<u8>,
+}
+
+impl Encoder {
+    pub fn new() -> Self {
+        Encoder::default()
+    }
+
+    pub fn into_inner(self) -> Vec<u8> {
+        self.buf
+    }
+
+    pub fn write_u8(&mut self, byte: u8) {
+        self.buf.push(byte);
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    pub fn write_len(&mut self, len: u64) {
+        if len < 1 << 6 {
+            self.buf.push((RDB_6BITLEN << 6) | len as u8);
+        } else if len < 1 << 14 {
+            self.buf
+                .extend([(RDB_14BITLEN << 6) | (len >> 8) as u8, len as u8]);
+        } else if let Ok(len) = u32::try_from(len) {
+            self.buf.push(RDB_32BITLEN);
+            self.buf.extend(len.to_be_bytes());
+        } else {
+            self.buf.push(RDB_64BITLEN);
+            self.buf.extend(len.to_be_bytes());
+        }
+    }
+
+    /// Write a string, as an integer when it is the canonical representation
+    /// of one that fits 32 bits, compressed when that saves space.
+    pub fn write_string(&mut self, string: &[u8]) {
+        if let Some(value) = canonical_int(string) {
+            let special = RDB_ENCVAL << 6;
+            if let Ok(value) = i8::try_from(value) {
+                self.buf.extend([special | RDB_ENC_INT8, value as u8]);
+                return;
+            } else if let Ok(value) = i16::try_from(value) {
+                self.buf.push(special | RDB_ENC_INT16);
+                self.buf.extend(value.to_le_bytes());
+                return;
+            } else if let Ok(value) = i32::try_from(value) {
+                self.buf.push(special | RDB_ENC_INT32);
+                self.buf.extend(value.to_le_bytes());
+                return;
+            }
+        }
+        if string.len() > LZF_MIN_LEN {
+            let compressed = lzf::compress(string);
+            if compressed.len() + 4 <= string.len() {
+                self.write_u8((RDB_ENCVAL << 6) | RDB_ENC_LZF);
+                self.write_len(compressed.len() as u64);
+                self.write_len(string.len() as u64);
+                self.buf.extend(compressed);
+                return;
+            }
+        }
+        self.write_len(string.len() as u64);
+        self.buf.extend_from_slice(string);
+    }
+
+    pub fn write_double(&mut self, value: f64) {
+        self.buf.extend(value.to_le_bytes());
+    }
+
+    pub fn write_millis(&mut self, time: i64) {
+        self.buf.extend(time.to_le_bytes());
+    }
+
+    /// Write the type of `value` followed by the value itself.
+    pub fn write_value(&mut self, value: &Value) {
+        match value {
+            Value::String(string) => {
+                self.write_u8(RDB_TYPE_STRING);
+                self.write_string(string);
+            }
+            Value::List(list) => {
+                self.write_u8(RDB_TYPE_LIST);
+                self.write_len(list.len() as u64);
+                for element in list.iter() {
+                    self.write_string(element);
+                }
+            }
+            Value::Set(set) => {
+                self.write_u8(RDB_TYPE_SET);
+                self.write_len(set.len() as u64);
+                for member in set.iter() {
+                    self.write_string(&member);
+                }
+            }
+            Value::ZSet(zset) => {
+                self.write_u8(RDB_TYPE_ZSET_2);
+                self.write_len(zset.len() as u64);
+                for (member, score) in zset.iter() {
+                    self.write_string(member);
+                    self.write_double(score);
+                }
+            }
+            Value::Hash(hash) => self.write_hash(hash),
+            Value::Stream(stream) => self.write_stream(stream),
+        }
+    }
+
+    /// Field TTLs are stored relative to the earliest one, plus one so that
+    /// zero can stand for no TTL.
+    fn write_hash(&mut self, hash: &Hash) {
+        let min_expire = hash.next_field_expire_time();
+        match min_expire {
+            Some(min_expire) => {
+                self.write_u8(RDB_TYPE_HASH_METADATA);
+                self.write_millis(min_expire);
+            }
+            None => self.write_u8(RDB_TYPE_HASH),
+        }
+        self.write_len(hash.len() as u64);
+        for (field, value) in hash.iter() {
+            if let Some(min_expire) = min_expire {
+                let ttl = hash
+                    .field_expire_time(field)
+                    .map_or(0, |when| (when - min_expire) as u64 + 1);
+                self.write_len(ttl);
+            }
+            self.write_string(field);
+            self.write_string(value);
+        }
+    }
+
+    fn write_stream(&mut self, stream: &Stream) {
+        self.write_u8(RDB_TYPE_STREAM_LISTPACKS_3);
+        let entries = stream.range(
+            StreamId::default(),
+            StreamId::new(u64::MAX, u64::MAX),
+            false,
+            usize::MAX,
+        );
+        let nodes: Vec<_> = entries.chunks(STREAM_NODE_MAX_ENTRIES).collect();
+        self.write_len(nodes.len() as u64);
+        for node in nodes {
+            let master = node[0];
+            // The master entry: entry counts, then the fields entries
+            // usually share so that they only store their values.
+            let mut lp = ListPackWriter::new();
+            lp.push_int(node.len() as i64);
+            lp.push_int(0);
+            lp.push_int(master.fields.len() as i64);
+            for (field, _) in &master.fields {
+                lp.push_str(field);
+            }
+            lp.push_int(0);
+            for entry in node {
+                let same_fields = entry.fields.len() == master.fields.len()
+                    && entry
+                        .fields
+                        .iter()
+                        .zip(&master.fields)
+                        .all(|((a, _), (b, _))| a == b);
+                let flags = if same_fields {
+                    STREAM_ITEM_FLAG_SAMEFIELDS
+                } else {
+                    0
+                };
+                lp.push_int(flags);
+                lp.push_int(entry.id.ms.wrapping_sub(master.id.ms) as i64);
+                lp.push_int(entry.id.seq.wrapping_sub(master.id.seq) as i64);
+                let mut lp_count = entry.fields.len() + 3;
+                if !same_fields {
+                    lp.push_int(entry.fields.len() as i64);
+                    lp_count += entry.fields.len() + 1;
+                }
+                for (field, value) in &entry.fields {
+                    if !same_fields {
+                        lp.push_str(field);
+                    }
+                    lp.push_str(value);
+                }
+                lp.push_int(lp_count as i64);
+            }
+            self.write_string(&master.id.to_be_bytes());
+            self.write_string(&lp.finish());
+        }
+
+        self.write_len(stream.len() as u64);
+        self.write_stream_id(stream.last_id());
+        let first_id = stream
+            .first_entry()
+            .map(|entry| entry.id)
+            .unwrap_or_default();
+        self.write_stream_id(first_id);
+        self.write_stream_id(stream.max_deleted_id());
+        self.write_len(stream.entries_added());
+
+        self.write_len(stream.groups().len() as u64);
+        for (name, group) in stream.groups() {
+            self.write_string(name);
+            self.write_stream_id(group.last_id);
+            self.write_len(group.entries_read.unwrap_or(u64::MAX));
+            self.write_len(group.pending.len() as u64);
+            for (id, pending) in &group.pending {
+                self.buf.extend(id.to_be_bytes());
+                self.write_millis(pending.delivery_time);
+                self.write_len(pending.delivery_count);
+            }
+            self.write_len(group.consumers.len() as u64);
+            for (name, consumer) in &group.consumers {
+                self.write_string(name);
+                self.write_millis(consumer.seen_time);
+                self.write_millis(consumer.active_time);
+                self.write_len(consumer.pending.len() as u64);
+                for id in &consumer.pending {
+                    self.buf.extend(id.to_be_bytes());
+                }
+            }
+        }
+    }
+
+    /// Write a key and its value the way RDB files hold them: the type of
+    /// the value, then the key, then the value.
+    pub fn write_key_value(&mut self, key: &[u8], value: &Value) {
+        let mut encoder = Encoder::new();
+        encoder.write_value(value);
+        let (rdb_type, body) = encoder.buf.split_first().expect("values have a type");
+        self.write_u8(*rdb_type);
+        self.write_string(key);
+        self.write_bytes(body);
+    }
+
+    fn write_stream_id(&mut self, id: StreamId) {
+        self.write_len(id.ms);
+        self.write_len(id.seq);
+    }
+}
+
+/// Reads values serialized in the RDB format, failing on anything malformed.
+pub struct Decoder<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Decoder<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Decoder { data, pos: 0 }
+    }
+
+    pub fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos.saturating_add(len))
+            .ok_or_else(|| String::from("unexpected end of data"))?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, String> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    /// Read a length, or the special encoding of a string when the second
+    /// element is true.
+    fn read_len_or_encoding(&mut self) -> Result<(u64, bool), String> {
+        let first = self.read_u8()?;
+        match first >> 6 {
+            RDB_6BITLEN => Ok(((first & 0x3f) as u64, false)),
+            RDB_14BITLEN => Ok((
+                (((first & 0x3f) as u64) << 8) | self.read_u8()? as u64,
+                false,
+            )),
+            RDB_ENCVAL => Ok(((first & 0x3f) as u64, true)),
+            _ => match first {
+                RDB_32BITLEN => {
+                    let bytes = self.read_bytes(4)?.try_into().unwrap();
+                    Ok((u32::from_be_bytes(bytes) as u64, false))
+                }
+                RDB_64BITLEN => {
+                    let bytes = self.read_bytes(8)?.try_into().unwrap();
+                    Ok((u64::from_be_bytes(bytes), false))
+                }
+                _ => Err(format!("unknown length encoding {}", first)),
+            },
+        }
+    }
+
+    pub fn read_len(&mut self) -> Result<u64, String> {
+        match self.read_len_or_encoding()? {
+            (len, false) => Ok(len),
+            _ => Err(String::from("unexpected string encoding")),
+        }
+    }
+
+    /// Read a length that sizes something in memory.
+    fn read_size(&mut self) -> Result<usize, String> {
+        let len = self.read_len()?;
+        usize::try_from(len).map_err(|_| format!("length {} is too large", len))
+    }
+
+    pub fn read_string(&mut self) -> Result<Bytes, String> {
+        let (len, encoded) = self.read_len_or_encoding()?;
+        if !encoded {
+            let len = usize::try_from(len).map_err(|_| String::from("string too large"))?;
+            return Ok(Bytes::copy_from_slice(self.read_bytes(len)?));
+        }
+        let value = match len as u8 {
+            RDB_ENC_INT8 => self.read_u8()? as i8 as i64,
+            RDB_ENC_INT16 => i16::from_le_bytes(self.read_bytes(2)?.try_into().unwrap()) as i64,
+            RDB_ENC_INT32 => i32::from_le_bytes(self.read_bytes(4)?.try_into().unwrap()) as i64,
+            RDB_ENC_LZF => {
+                let compressed_len = self.read_size()?;
+                let len = self.read_size()?;
+                let compressed = self.read_bytes(compressed_len)?;
+                return Ok(Bytes::from(lzf::decompress(compressed, len)?));
+            }
+            encoding => return Err(format!("unknown string encoding {}", encoding)),
+        };
+        Ok(Bytes::from(value.to_string()))
+    }
+
+    pub fn read_double(&mut self) -> Result<f64, String> {
+        Ok(f64::from_le_bytes(self.read_bytes(8)?.try_into().unwrap()))
+    }
+
+    pub fn read_millis(&mut self) -> Result<i64, String> {
+        Ok(i64::from_le_bytes(self.read_bytes(8)?.try_into().unwrap()))
+    }
+
+    /// Read a double stored as a string, as redis did before it stored them
+    /// in binary.
+    fn read_string_double(&mut self) -> Result<f64, String> {
+        match self.read_u8()? {
+            RDB_DOUBLE_NAN => Ok(f64::NAN),
+            RDB_DOUBLE_POS_INF => Ok(f64::INFINITY),
+            RDB_DOUBLE_NEG_INF => Ok(f64::NEG_INFINITY),
+            len => parse_double(self.read_bytes(len as usize)?),
+        }
+    }
+
+    fn read_ziplist(&mut self) -> Result<Vec<Entry>, String> {
+        ziplist::parse(&self.read_string()?)
+    }
+
+    fn read_listpack(&mut self) -> Result<Vec<Entry>, String> {
+        listpack::parse(&self.read_string()?)
+    }
+
+    /// Read a value of the given RDB type. Empty collections are refused,
+    /// as redis never stores them.
+    pub fn read_value(&mut self, rdb_type: u8) -> Result<Value, String> {
+        let value = match rdb_type {
+            RDB_TYPE_STRING => Value::String(self.read_string()?),
+            RDB_TYPE_LIST => {
+                let mut list = QuickList::new();
+                for _ in 0..self.read_len()? {
+                    list.push_back(self.read_string()?);
+                }
+                Value::List(list)
+            }
+            RDB_TYPE_LIST_ZIPLIST => {
+                let mut list = QuickList::new();
+                for entry in self.read_ziplist()? {
+                    list.push_back(entry.into_bytes());
+                }
+                Value::List(list)
+            }
+            RDB_TYPE_LIST_QUICKLIST | RDB_TYPE_LIST_QUICKLIST_2 => {
+                Value::List(self.read_quicklist(rdb_type)?)
+            }
+            RDB_TYPE_SET => {
+                let mut members = Vec::new();
+                for _ in 0..self.read_len()? {
+                    members.push(self.read_string()?);
+                }
+                Value::Set(set_from(members)?)
+            }
+            RDB_TYPE_SET_INTSET => {
+                let members = intset::parse(&self.read_string()?)?
+                    .into_iter()
+                    .map(|member| Bytes::from(member.to_string()));
+                Value::Set(set_from(members)?)
+            }
+            RDB_TYPE_SET_LISTPACK => {
+                let members = self.read_listpack()?.into_iter().map(Entry::into_bytes);
+                Value::Set(set_from(members)?)
+            }
+            RDB_TYPE_ZSET | RDB_TYPE_ZSET_2 => {
+                let mut pairs = Vec::new();
+                for _ in 0..self.read_len()? {
+                    let member = self.read_string()?;
+                    let score = match rdb_type {
+                        RDB_TYPE_ZSET => self.read_string_double()?,
+                        _ => self.read_double()?,
+                    };
+                    pairs.push((member, score));
+                }
+                Value::ZSet(zset_from(pairs)?)
+            }
+            RDB_TYPE_ZSET_ZIPLIST | RDB_TYPE_ZSET_LISTPACK => {
+                let entries = match rdb_type {
+                    RDB_TYPE_ZSET_ZIPLIST => self.read_ziplist()?,
+                    _ => self.read_listpack()?,
+                };
+                if entries.len() % 2 != 0 {
+                    return Err(String::from("zset without a score"));
+                }
+                let mut entries = entries.into_iter();
+                let mut pairs = Vec::new();
+                while let (Some(member), Some(score)) = (entries.next(), entries.next()) {
+                    let score = match score {
+                        Entry::Int(score) => score as f64,
+                        Entry::Str(score) => parse_double(&score)?,
+                    };
+                    pairs.push((member.into_bytes(), score));
+                }
+                Value::ZSet(zset_from(pairs)?)
+            }
+            RDB_TYPE_HASH | RDB_TYPE_HASH_METADATA => Value::Hash(self.read_hash(rdb_type)?),
+            RDB_TYPE_HASH_ZIPLIST | RDB_TYPE_HASH_LISTPACK => {
+                let entries = match rdb_type {
+                    RDB_TYPE_HASH_ZIPLIST => self.read_ziplist()?,
+                    _ => self.read_listpack()?,
+                };
+                if entries.len() % 2 != 0 {
+                    return Err(String::from("hash field without a value"));
+                }
+                let mut entries = entries.into_iter();
+                let mut hash = Hash::new();
+                while let (Some(field), Some(value)) = (entries.next(), entries.next()) {
+                    if !hash.insert(field.into_bytes(), value.into_bytes()) {
+                        return Err(String::from("duplicate hash fields"));
+                    }
+                }
+                Value::Hash(hash)
+            }
+            RDB_TYPE_STREAM_LISTPACKS
+            | RDB_TYPE_STREAM_LISTPACKS_2
+            | RDB_TYPE_STREAM_LISTPACKS_3 => Value::Stream(self.read_stream(rdb_type)?),
+            RDB_TYPE_MODULE_PRE_GA | RDB_TYPE_MODULE_2 => {
+                return Err(String::from("module values are not supported"))
+            }
+            _ => return Err(format!("unknown value type {}", rdb_type)),
+        };
+        if value.is_empty_collection() {
+            return Err(String::from("empty collection"));
+        }
+        Ok(value)
+    }
+
+    /// Read a list stored as a sequence of nodes, ziplists in files of
+    /// redis 6.2 and earlier, listpacks or single large elements after.
+    fn read_quicklist(&mut self, rdb_type: u8) -> Result<QuickList, String> {
+        let mut list = QuickList::new();
+        for _ in 0..self.read_len()? {
+            let container = match rdb_type {
+                RDB_TYPE_LIST_QUICKLIST_2 => self.read_len()?,
+                _ => QUICKLIST_NODE_CONTAINER_PACKED,
+            };
+            let node = self.read_string()?;
+            let entries = match container {
+                QUICKLIST_NODE_CONTAINER_PLAIN if rdb_type == RDB_TYPE_LIST_QUICKLIST_2 => {
+                    list.push_back(node);
+                    continue;
+                }
+                QUICKLIST_NODE_CONTAINER_PACKED if rdb_type == RDB_TYPE_LIST_QUICKLIST_2 => {
+                    listpack::parse(&node)?
+                }
+                QUICKLIST_NODE_CONTAINER_PACKED => ziplist::parse(&node)?,
+                _ => return Err(format!("unknown quicklist node container {}", container)),
+            };
+            for entry in entries {
+                list.push_back(entry.into_bytes());
+            }
+        }
+        Ok(list)
+    }
+
+    fn read_hash(&mut self, rdb_type: u8) -> Result<Hash, String> {
+        let min_expire = match rdb_type {
+            RDB_TYPE_HASH_METADATA => Some(self.read_millis()?),
+            _ => None,
+        };
+        let mut hash = Hash::new();
+        for _ in 0..self.read_len()? {
+            let ttl = match min_expire {
+                Some(min_expire) => match self.read_len()? {
+                    0 => None,
+                    ttl => Some(min_expire.saturating_add(ttl as i64 - 1)),
+                },
+                None => None,
+            };
+            let field = self.read_string()?;
+            let value = self.read_string()?;
+            if !hash.insert(field.clone(), value) {
+                return Err(String::from("duplicate hash fields"));
+            }
+            if let Some(when) = ttl {
+                hash.set_field_expire_time(&field, when);
+            }
+        }
+        Ok(hash)
+    }
+
+    fn read_stream(&mut self, rdb_type: u8) -> Result<Stream, String> {
+        let mut stream = Stream::new();
+        for _ in 0..self.read_len()? {
+            let key = self.read_string()?;
+            if key.len() != 16 {
+                return Err(corrupt());
+            }
+            let master_id = StreamId::from_be_bytes(&key);
+            let mut lp = listpack::parse(&self.read_string()?)?.into_iter();
+            let count = next_int(&mut lp)?;
+            let deleted = next_int(&mut lp)?;
+            let master_fields: Vec<Bytes> = (0..next_int(&mut lp)?)
+                .map(|_| next_bytes(&mut lp))
+                .collect::<Result<_, _>>()?;
+            if next_int(&mut lp)? != 0 || count + deleted == 0 {
+                return Err(corrupt());
+            }
+            for _ in 0..count + deleted {
+                let flags = next_int(&mut lp)?;
+                let id = StreamId::new(
+                    master_id.ms.wrapping_add(next_int(&mut lp)? as u64),
+                    master_id.seq.wrapping_add(next_int(&mut lp)? as u64),
+                );
+                let mut fields = Fields::new();
+                if flags & STREAM_ITEM_FLAG_SAMEFIELDS != 0 {
+                    for field in &master_fields {
+                        fields.push((field.clone(), next_bytes(&mut lp)?));
+                    }
+                } else {
+                    for _ in 0..next_int(&mut lp)? {
+                        fields.push((next_bytes(&mut lp)?, next_bytes(&mut lp)?));
+                    }
+                }
+                next_int(&mut lp)?;
+                if flags & STREAM_ITEM_FLAG_DELETED != 0 {
+                    continue;
+                }
+                if stream.last_entry().is_some_and(|last| last.id >= id) {
+                    return Err(corrupt());
+                }
+                stream.add(id, fields);
+            }
+            if lp.next().is_some() {
+                return Err(corrupt());
+            }
+        }
+
+        let len = self.read_size()?;
+        let last_id = self.read_stream_id()?;
+        let (max_deleted_id, entries_added) = if rdb_type >= RDB_TYPE_STREAM_LISTPACKS_2 {
+            self.read_stream_id()?;
+            (self.read_stream_id()?, self.read_len()?)
+        } else {
+            (StreamId::default(), len as u64)
+        };
+        if len != stream.len() || stream.last_entry().is_some_and(|last| last.id > last_id) {
+            return Err(corrupt());
+        }
+        stream.set_metadata(last_id, max_deleted_id, entries_added);
+
+        for _ in 0..self.read_len()? {
+            let name = self.read_string()?;
+            let last_id = self.read_stream_id()?;
+            let entries_read = if rdb_type >= RDB_TYPE_STREAM_LISTPACKS_2 {
+                Some(self.read_len()?).filter(|read| *read != u64::MAX)
+            } else {
+                stream.estimate_entries_read(last_id)
+            };
+            let mut group = ConsumerGroup::new(last_id, entries_read);
+            // Entries of the group's list, until a consumer's list claims them.
+            let mut unowned = BTreeSet::new();
+            for _ in 0..self.read_len()? {
+                let id = StreamId::from_be_bytes(self.read_bytes(16)?);
+                let pending = PendingEntry {
+                    consumer: Bytes::new(),
+                    delivery_time: self.read_millis()?,
+                    delivery_count: self.read_len()?,
+                };
+                if group.pending.insert(id, pending).is_some() {
+                    return Err(corrupt());
+                }
+                unowned.insert(id);
+            }
+            let mut consumers = BTreeMap::new();
+            for _ in 0..self.read_len()? {
+                let consumer_name = self.read_string()?;
+                let seen_time = self.read_millis()?;
+                let active_time = if rdb_type >= RDB_TYPE_STREAM_LISTPACKS_3 {
+                    self.read_millis()?
+                } else {
+                    seen_time
+                };
+                let mut consumer = Consumer {
+                    seen_time,
+                    active_time,
+                    pending: BTreeSet::new(),
+                };
+                for _ in 0..self.read_len()? {
+                    let id = StreamId::from_be_bytes(self.read_bytes(16)?);
+                    if !unowned.remove(&id) {
+                        return Err(corrupt());
+                    }
+                    group.pending.get_mut(&id).unwrap().consumer = consumer_name.clone();
+                    consumer.pending.insert(id);
+                }
+                consumers.insert(consumer_name, consumer);
+            }
+            if !unowned.is_empty() {
+                return Err(corrupt());
+            }
+            group.consumers = consumers;
+            if !stream.create_group(name, group) {
+                return Err(String::from("duplicate stream consumer groups"));
+            }
+        }
+        Ok(stream)
+    }
+
+    fn read_stream_id(&mut self) -> Result<StreamId, String> {
+        Ok(StreamId::new(self.read_len()?, self.read_len()?))
+    }
+}
+
+fn parse_double(bytes: &[u8]) -> Result<f64, String> {
+    std::str::from_utf8(bytes)
+        .ok()
+        .and_then(|string| string.parse().ok())
+        .ok_or_else(|| String::from("invalid double"))
+}
+
+fn set_from(members: impl IntoIterator<Item = Bytes>) -> Result<Set, String> {
+    let mut set = Set::new();
+    for member in members {
+        if !set.insert(member) {
+            return Err(String::from("duplicate set members"));
+        }
+    }
+    Ok(set)
+}
+
+fn zset_from(pairs: Vec<(Bytes, f64)>) -> Result<ZSet, String> {
+    let mut zset = ZSet::new();
+    for (member, score) in pairs {
+        if score.is_nan() {
+            return Err(String::from("zset score is NaN"));
+        }
+        if !zset.insert(member, score) {
+            return Err(String::from("duplicate zset members"));
+        }
+    }
+    Ok(zset)
+}
+
+fn corrupt() -> String {
+    String::from("corrupt stream")
+}
+
+fn next_bytes(lp: &mut impl Iterator<Item = Entry>) -> Result<Bytes, String> {
+    lp.next().map(Entry::into_bytes).ok_or_else(corrupt)
+}
+
+fn next_int(lp: &mut impl Iterator<Item = Entry>) -> Result<i64, String> {
+    lp.next()
+        .and_then(|entry| entry.as_int())
+        .ok_or_else(corrupt)
+}
+
+/// Version of the format `value` needs: the latest one only for hashes with
+/// field TTLs, which older versions cannot hold.
+pub fn rdb_version(value: &Value) -> u16 {
+    match value {
+        Value::Hash(hash) if hash.next_field_expire_time().is_some() => RDB_VERSION,
+        _ => RDB_VERSION_WITHOUT_FIELD_TTLS,
+    }
+}
+
+/// Serialize `value` for DUMP: its RDB encoding followed by the RDB version
+/// and a CRC64 of everything before it, both little endian.
+pub fn dump(value: &Value) -> Vec<u8> {
+    let mut encoder = Encoder::new();
+    encoder.write_value(value);
+    let mut payload = encoder.into_inner();
+    payload.extend(rdb_version(value).to_le_bytes());
+    let crc = crc64(0, &payload);
+    payload.extend(crc.to_le_bytes());
+    payload
+}
+
+/// Whether a DUMP payload has a known RDB version and a valid checksum.
+pub fn verify_dump_payload(payload: &[u8]) -> bool {
+    if payload.len() < DUMP_FOOTER_SIZE {
+        return false;
+    }
+    let (body, crc) = payload.split_at(payload.len() - 8);
+    let version = u16::from_le_bytes([body[body.len() - 2], body[body.len() - 1]]);
+    version <= RDB_VERSION && crc64(0, body).to_le_bytes() == crc
+}
+
+/// Deserialize the value of a DUMP payload that passed `verify_dump_payload`.
+pub fn restore(payload: &[u8]) -> Result<Value, String> {
+    let mut decoder = Decoder::new(&payload[..payload.len() - DUMP_FOOTER_SIZE]);
+    let rdb_type = decoder.read_u8()?;
+    decoder.read_value(rdb_type)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(value: &Value) -> Value {
+        let payload = dump(value);
+        assert!(verify_dump_payload(&payload));
+        restore(&payload).unwrap()
+    }
+
+    #[test]
+    fn test_lengths_and_strings() {
+        for len in [
+            0,
+            63,
+            64,
+            16383,
+            16384,
+            u32::MAX as u64,
+            u32::MAX as u64 + 1,
+        ] {
+            let mut encoder = Encoder::new();
+            encoder.write_len(len);
+            let data = encoder.into_inner();
+            assert_eq!(Decoder::new(&data).read_len(), Ok(len));
+        }
+        for string in ["", "0", "-128", "300", "-70000", "2147483648", "007", "abc"] {
+            let mut encoder = Encoder::new();
+            encoder.write_string(string.as_bytes());
+            let data = encoder.into_inner();
+            assert_eq!(Decoder::new(&data).read_string(), Ok(Bytes::from(string)));
+        }
+        let mut encoder = Encoder::new();
+        encoder.write_string(b"-70000");
+        assert_eq!(encoder.into_inner(), b"\xc2\x90\xee\xfe\xff");
+        assert!(Decoder::new(b"\x05abc").read_string().is_err());
+
+        let long = "abc".repeat(100);
+        let mut encoder = Encoder::new();
+        encoder.write_string(long.as_bytes());
+        let data = encoder.into_inner();
+        assert_eq!(data[0], (RDB_ENCVAL << 6) | RDB_ENC_LZF);
+        assert!(data.len() < 20);
+        assert_eq!(Decoder::new(&data).read_string(), Ok(Bytes::from(long)));
+    }
+
+    #[test]
+    fn test_dump_payload() {
+        let payload = dump(&Value::String(Bytes::from("hello")));
+        assert_eq!(&payload[..8], b"\x00\x05hello\x0b");
+        assert!(verify_dump_payload(&payload));
+
+        let mut corrupt = payload.clone();
+        corrupt[3] ^= 1;
+        assert!(!verify_dump_payload(&corrupt));
+        let mut future = payload[..payload.len() - 10].to_vec();
+        future.extend((RDB_VERSION + 1).to_le_bytes());
+        future.extend(crc64(0, &future).to_le_bytes());
+        assert!(!verify_dump_payload(&future));
+        assert!(!verify_dump_payload(b"short"));
+    }
+
+    #[test]
+    fn test_round_trip_collections() {
+        let mut list = QuickList::new();
+        let mut set = Set::new();
+        let mut zset = ZSet::new();
+        let mut hash = Hash::new();
+        for i in 0..300 {
+            list.push_back(Bytes::from(format!("e{}", i)));
+            set.insert(Bytes::from(i.to_string()));
+            zset.insert(Bytes::from(format!("m{}", i)), i as f64 / 3.0);
+            hash.insert(Bytes::from(format!("f{}", i)), Bytes::from(i.to_string()));
+        }
+        hash.set_field_expire_time(&Bytes::from("f7"), 1_000_000);
+        hash.set_field_expire_time(&Bytes::from("f9"), 2_000_000);
+
+        let Value::List(restored) = round_trip(&Value::List(list.clone())) else {
+            panic!()
+        };
+        assert!(restored.iter().eq(list.iter()));
+        let Value::Set(restored) = round_trip(&Value::Set(set.clone())) else {
+            panic!()
+        };
+        assert!(restored.len() == 300 && set.iter().all(|m| restored.contains(&m)));
+        let Value::ZSet(restored) = round_trip(&Value::ZSet(zset.clone())) else {
+            panic!()
+        };
+        assert!(restored.iter().eq(zset.iter()));
+        let Value::Hash(restored) = round_trip(&Value::Hash(hash)) else {
+            panic!()
+        };
+        assert_eq!(restored.len(), 300);
+        assert_eq!(restored.get(b"f299"), Some(&b"299"[..]));
+        assert_eq!(restored.field_expire_time(b"f7"), Some(1_000_000));
+        assert_eq!(restored.field_expire_time(b"f9"), Some(2_000_000));
+        assert_eq!(restored.field_expire_time(b"f8"), None);
+
+        let empty = dump(&Value::List(QuickList::new()));
+        assert!(restore(&empty).is_err());
+    }
+
+    #[test]
+    fn test_compact_encodings() {
+        let read = |rdb_type: u8, blob: &[u8]| {
+            let mut encoder = Encoder::new();
+            encoder.write_string(blob);
+            let data = encoder.into_inner();
+            Decoder::new(&data).read_value(rdb_type)
+        };
+        let listpack = |entries: &[&str]| {
+            let mut writer = ListPackWriter::new();
+            for entry in entries {
+                writer.push_str(entry.as_bytes());
+            }
+            writer.finish()
+        };
+        // Ziplists of "f", "v" and of "m", 5.
+        let strings = b"\x11\0\0\0\x0d\0\0\0\x02\0\x00\x01f\x03\x01v\xff";
+        let member_and_score = b"\x10\0\0\0\x0d\0\0\0\x02\0\x00\x01m\x03\xf6\xff";
+
+        let Ok(Value::Hash(hash)) = read(RDB_TYPE_HASH_LISTPACK, &listpack(&["f", "v", "n", "12"]))
+        else {
+            panic!()
+        };
+        assert_eq!(hash.get(b"n"), Some(&b"12"[..]));
+        let Ok(Value::Hash(hash)) = read(RDB_TYPE_HASH_ZIPLIST, strings) else {
+            panic!()
+        };
+        assert_eq!(hash.get(b"f"), Some(&b"v"[..]));
+        let scores = listpack(&["a", "1.5", "b", "-3", "c", "inf"]);
+        let Ok(Value::ZSet(zset)) = read(RDB_TYPE_ZSET_LISTPACK, &scores) else {
+            panic!()
+        };
+        assert_eq!(zset.score(b"a"), Some(1.5));
+        assert_eq!(zset.score(b"b"), Some(-3.0));
+        assert_eq!(zset.score(b"c"), Some(f64::INFINITY));
+        let Ok(Value::ZSet(zset)) = read(RDB_TYPE_ZSET_ZIPLIST, member_and_score) else {
+            panic!()
+        };
+        assert_eq!(zset.score(b"m"), Some(5.0));
+        let Ok(Value::Set(set)) = read(RDB_TYPE_SET_LISTPACK, &listpack(&["x", "7"])) else {
+            panic!()
+        };
+        assert!(set.contains(b"x") && set.contains(b"7"));
+        let Ok(Value::Set(set)) = read(RDB_TYPE_SET_INTSET, b"\x02\0\0\0\x02\0\0\0\x01\0\x02\0")
+        else {
+            panic!()
+        };
+        assert!(set.len() == 2 && set.contains(b"1") && set.contains(b"2"));
+        let Ok(Value::List(list)) = read(RDB_TYPE_LIST_ZIPLIST, strings) else {
+            panic!()
+        };
+        assert!(list.iter().eq([&b"f"[..], b"v"]));
+
+        let mut encoder = Encoder::new();
+        encoder.write_len(2);
+        encoder.write_len(QUICKLIST_NODE_CONTAINER_PACKED);
+        encoder.write_string(&listpack(&["a", "b"]));
+        encoder.write_len(QUICKLIST_NODE_CONTAINER_PLAIN);
+        encoder.write_string(b"large");
+        let data = encoder.into_inner();
+        let Ok(Value::List(list)) = Decoder::new(&data).read_value(RDB_TYPE_LIST_QUICKLIST_2)
+        else {
+            panic!()
+        };
+        assert!(list.iter().eq([&b"a"[..], b"b", b"large"]));
+        let mut encoder = Encoder::new();
+        encoder.write_len(1);
+        encoder.write_string(strings);
+        let data = encoder.into_inner();
+        let Ok(Value::List(list)) = Decoder::new(&data).read_value(RDB_TYPE_LIST_QUICKLIST) else {
+            panic!()
+        };
+        assert_eq!(list.len(), 2);
+
+        let mut encoder = Encoder::new();
+        encoder.write_len(2);
+        encoder.write_string(b"a");
+        encoder.write_bytes(b"\x032.5");
+        encoder.write_string(b"b");
+        encoder.write_u8(RDB_DOUBLE_NEG_INF);
+        let data = encoder.into_inner();
+        let Ok(Value::ZSet(zset)) = Decoder::new(&data).read_value(RDB_TYPE_ZSET) else {
+            panic!()
+        };
+        assert_eq!(zset.score(b"a"), Some(2.5));
+        assert_eq!(zset.score(b"b"), Some(f64::NEG_INFINITY));
+
+        assert!(read(RDB_TYPE_SET_LISTPACK, &listpack(&["x", "x"])).is_err());
+        assert!(read(RDB_TYPE_HASH_LISTPACK, &listpack(&["f"])).is_err());
+        assert!(read(RDB_TYPE_ZSET_LISTPACK, &listpack(&["m", "x"])).is_err());
+        assert!(read(RDB_TYPE_SET_INTSET, b"\x02\0\0\0\0\0\0\0").is_err());
+        assert!(read(RDB_TYPE_MODULE_2, b"").is_err());
+    }
+
+    #[test]
+    fn test_round_trip_stream() {
+        let mut stream = Stream::new();
+        for i in 0..250u64 {
+            let mut fields = vec![(Bytes::from("f"), Bytes::from(i.to_string()))];
+            if i % 7 == 0 {
+                fields.push((Bytes::from("extra"), Bytes::from("x")));
+            }
+            stream.add(StreamId::new(1000 + i / 3, i % 3), fields);
+        }
+        stream.delete(StreamId::new(1001, 0));
+        let mut group = ConsumerGroup::new(StreamId::new(1002, 0), Some(7));
+        group.assign(StreamId::new(1000, 1), &Bytes::from("alice"), 5000, 1);
+        group.assign(StreamId::new(1002, 0), &Bytes::from("bob"), 6000, 2);
+        stream.create_group(Bytes::from("g"), group);
+
+        let Value::Stream(restored) = round_trip(&Value::Stream(stream.clone())) else {
+            panic!()
+        };
+        assert_eq!(restored.len(), 249);
+        assert_eq!(restored.last_id(), stream.last_id());
+        assert_eq!(restored.max_deleted_id(), StreamId::new(1001, 0));
+        assert_eq!(restored.entries_added(), 250);
+        let all = |s: &Stream| -> Vec<(StreamId, Fields)> {
+            s.range(
+                StreamId::default(),
+                StreamId::new(u64::MAX, u64::MAX),
+                false,
+                usize::MAX,
+            )
+            .into_iter()
+            .map(|entry| (entry.id, entry.fields.clone()))
+            .collect()
+        };
+        assert_eq!(all(&restored), all(&stream));
+        let group = restored.group(b"g").unwrap();
+        assert_eq!(group.entries_read, Some(7));
+        assert_eq!(group.pending.len(), 2);
+        assert_eq!(group.pending[&StreamId::new(1002, 0)].consumer, "bob");
+        assert_eq!(group.consumers, stream.group(b"g").unwrap().consumers);
+    }
+}
@@ -0,0 +1,161 @@
+//! The ziplist, the flat serialization of small collections that preceded the
+//! listpack, found in files of redis 6.2 and earlier.
+
+use bytes::Bytes;
+
+use super::listpack::Entry;
+
+/// Size of the header: total bytes and offset of the last entry as u32s, then
+/// the element count as a u16.
+const HEADER_SIZE: usize = 10;
+/// Element count stored once the real count no longer fits in the header.
+const UNKNOWN_COUNT: u16 = u16::MAX;
+/// First byte of the length of the previous entry when it takes four more.
+const BIG_PREVLEN: u8 = 254;
+const END: u8 = 0xff;
+
+/// Decode every element of a ziplist, validating its structure.
+pub fn parse(data: &[u8]) -> Result<Vec<Entry>, String> {
+    let corrupt = || String::from("corrupt ziplist");
+    if data.len() < HEADER_SIZE + 1 {
+        return Err(corrupt());
+    }
+    let total = u32::from_le_bytes(data[..4].try_into().unwrap()) as usize;
+    let count = u16::from_le_bytes(data[8..HEADER_SIZE].try_into().unwrap());
+    if total != data.len() || data[total - 1] != END {
+        return Err(corrupt());
+    }
+
+    let bytes = |start: usize, len: usize| data.get(start..start + len).ok_or_else(corrupt);
+    let mut entries = Vec::new();
+    let mut pos = HEADER_SIZE;
+    let mut prev_len = 0;
+    while data[pos] != END {
+        let start = pos;
+        let (stored_prev_len, size) = match data[pos] {
+            BIG_PREVLEN => (
+                u32::from_le_bytes(bytes(pos + 1, 4)?.try_into().unwrap()) as usize,
+                5,
+            ),
+            len => (len as usize, 1),
+        };
+        if stored_prev_len != prev_len {
+            return Err(corrupt());
+        }
+        pos += size;
+        let first = *data.get(pos).ok_or_else(corrupt)?;
+        let string = |offset: usize, len: usize| -> Result<(Entry, usize), String> {
+            let value = bytes(pos + offset, len)?;
+            Ok((Entry::Str(Bytes::copy_from_slice(value)), offset + len))
+        };
+        let (entry, len) = match first >> 6 {
+            0 => string(1, (first & 0x3f) as usize)?,
+            1 => string(
+                2,
+                ((first as usize & 0x3f) << 8) | bytes(pos + 1, 1)?[0] as usize,
+            )?,
+            2 if first == 0x80 => string(
+                5,
+                u32::from_be_bytes(bytes(pos + 1, 4)?.try_into().unwrap()) as usize,
+            )?,
+            _ => {
+                let (value, len) = match first {
+                    0xc0 => (int(bytes(pos + 1, 2)?), 3),
+                    0xd0 => (int(bytes(pos + 1, 4)?), 5),
+                    0xe0 => (int(bytes(pos + 1, 8)?), 9),
+                    0xf0 => (int(bytes(pos + 1, 3)?), 4),
+                    0xfe => (int(bytes(pos + 1, 1)?), 2),
+                    0xf1..=0xfd => ((first & 0x0f) as i64 - 1, 1),
+                    _ => return Err(corrupt()),
+                };
+                (Entry::Int(value), len)
+            }
+        };
+        entries.push(entry);
+        pos += len;
+        prev_len = pos - start;
+        if pos >= total {
+            return Err(corrupt());
+        }
+    }
+    if pos != total - 1 || (count != UNKNOWN_COUNT && count as usize != entries.len()) {
+        return Err(corrupt());
+    }
+    Ok(entries)
+}
+
+/// A little endian signed integer of up to eight bytes.
+fn int(bytes: &[u8]) -> i64 {
+    let mut buf = [0; 8];
+    buf[..bytes.len()].copy_from_slice(bytes);
+    let shift = 64 - 8 * bytes.len() as u32;
+    (i64::from_le_bytes(buf) << shift) >> shift
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Lay out entries given as their encoding and content into a ziplist.
+    fn ziplist(entries: &[&[u8]]) -> Vec<u8> {
+        let mut data = vec![0; HEADER_SIZE];
+        let mut prev_len = 0;
+        let mut tail = HEADER_SIZE;
+        for entry in entries {
+            tail = data.len();
+            if prev_len < BIG_PREVLEN as usize {
+                data.push(prev_len as u8);
+            } else {
+                data.push(BIG_PREVLEN);
+                data.extend((prev_len as u32).to_le_bytes());
+            }
+            data.extend_from_slice(entry);
+            prev_len = data.len() - tail;
+        }
+        data.push(END);
+        let total = data.len() as u32;
+        data[..4].copy_from_slice(&total.to_le_bytes());
+        data[4..8].copy_from_slice(&(tail as u32).to_le_bytes());
+        data[8..10].copy_from_slice(&(entries.len() as u16).to_le_bytes());
+        data
+    }
+
+    #[test]
+    fn test_parse() {
+        let long = [&b"\x41\x2c"[..], &[b'x'; 300]].concat();
+        let data = ziplist(&[
+            b"\x02ab",
+            b"\xf6",
+            b"\xfe\xfe",
+            b"\xc0\xe8\x03",
+            &long,
+            b"\xf0\x00\x00\x80",
+            b"\xd0\x00\x00\x00\x80",
+            b"\xe0\xff\xff\xff\xff\xff\xff\xff\x7f",
+            b"\x80\x00\x00\x00\x01y",
+        ]);
+        assert_eq!(
+            parse(&data).unwrap(),
+            vec![
+                Entry::Str(Bytes::from("ab")),
+                Entry::Int(5),
+                Entry::Int(-2),
+                Entry::Int(1000),
+                Entry::Str(Bytes::from("x".repeat(300))),
+                Entry::Int(-8_388_608),
+                Entry::Int(i32::MIN as i64),
+                Entry::Int(i64::MAX),
+                Entry::Str(Bytes::from("y")),
+            ]
+        );
+
+        let mut bad = data.clone();
+        bad[HEADER_SIZE + 5] = 0x07;
+        assert!(parse(&bad).is_err());
+        let mut bad = data.clone();
+        bad[8] = 3;
+        assert!(parse(&bad).is_err());
+        assert!(parse(&data[..data.len() - 1]).is_err());
+        assert!(parse(&ziplist(&[b"\x05ab"])).is_err());
+    }
+}
@@ -1,6 +1,6 @@
-use bytes::{Bytes, BytesMut};
+use bytes::BytesMut;
 
-use super::data::{RESPDataType, RESPError, RESPResult};
+use super::data::{RESPError, RESPResult};
 use super::parser::{from_array, from_bulk_string, from_error, from_int, from_simple_string};
 
 #[derive(Default)]
@@ -24,9 +24,11 @@ impl RespDeserializer {
 }
 
 #[cfg(test)]
+#[allow(clippy::unnecessary_cast, clippy::default_constructed_unit_structs)]
 mod tests {
-    use bytes::BufMut;
+    use bytes::{BufMut, Bytes};
 
+    use super::super::data::RESPDataType;
     use super::*;
 
     #[test]
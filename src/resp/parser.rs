@@ -65,62 +65,58 @@ pub fn from_int(buffer: &BytesMut, pos: usize) -> RESPResult {
 pub fn from_bulk_string(buffer: &BytesMut, pos: usize) -> RESPResult {
     match from_int(buffer, pos)? {
         Some((pos, RESPDataType::Integer(-1))) => Ok(Some((pos, RESPDataType::NullBulkString))),
-        Some((pos, res)) => match res {
-            RESPDataType::Integer(size) => {
-                if size >= 0 {
-                    let total_size = pos + size as usize;
-                    if buffer.len() < total_size + 2 {
-                        Ok(None)
-                    } else {
-                        match parse_word(buffer, pos) {
-                            Some((_, slice)) => Ok(Some((
-                                total_size + 2,
-                                RESPDataType::BulkString(Bytes::copy_from_slice(slice)),
-                            ))),
-                            None => Ok(None),
-                        }
-                    }
-                } else {
+        Some((pos, RESPDataType::Integer(size))) => {
+            if size >= 0 {
+                // The payload is binary safe: take exactly `size` bytes rather
+                // than scanning for the next CRLF.
+                let total_size = pos + size as usize;
+                if buffer.len() < total_size + 2 {
+                    Ok(None)
+                } else if &buffer[total_size..total_size + 2] != b"\r\n" {
                     Err(RESPError::InvalidBulkStringSize)
+                } else {
+                    Ok(Some((
+                        total_size + 2,
+                        RESPDataType::BulkString(Bytes::copy_from_slice(&buffer[pos..total_size])),
+                    )))
                 }
+            } else {
+                Err(RESPError::InvalidBulkStringSize)
             }
-            _ => Ok(None),
-        },
-        None => Ok(None),
+        }
+        _ => Ok(None),
     }
 }
 
 /// Get array RESPResult from buffer, starting at `pos`.
 pub fn from_array(buffer: &BytesMut, pos: usize) -> RESPResult {
     match from_int(buffer, pos)? {
-        None => Ok(None),
         Some((pos, RESPDataType::Integer(-1))) => Ok(Some((pos, RESPDataType::NullArray))),
-        Some((pos, res)) => match res {
-            RESPDataType::Integer(num_elements) => {
-                if num_elements > 0 {
-                    let mut resp_data_types = Vec::with_capacity(num_elements as usize);
-                    let mut curr_pos = pos;
-                    for _ in 0..num_elements {
-                        let deserializer = RespDeserializer::default();
-                        match deserializer.deserialize(buffer, curr_pos)? {
-                            Some((new_pos, resp_data_type)) => {
-                                curr_pos = new_pos;
-                                resp_data_types.push(resp_data_type)
-                            }
-                            None => return Ok(None),
-                        };
-                    }
-                    Ok(Some((curr_pos, RESPDataType::Array(resp_data_types))))
-                } else {
-                    Err(RESPError::InvalidArrayElementSize)
+        Some((pos, RESPDataType::Integer(num_elements))) => {
+            if num_elements > 0 {
+                let mut resp_data_types = Vec::with_capacity(num_elements as usize);
+                let mut curr_pos = pos;
+                for _ in 0..num_elements {
+                    let deserializer = RespDeserializer;
+                    match deserializer.deserialize(buffer, curr_pos)? {
+                        Some((new_pos, resp_data_type)) => {
+                            curr_pos = new_pos;
+                            resp_data_types.push(resp_data_type)
+                        }
+                        None => return Ok(None),
+                    };
                 }
+                Ok(Some((curr_pos, RESPDataType::Array(resp_data_types))))
+            } else {
+                Err(RESPError::InvalidArrayElementSize)
             }
-            _ => Ok(None),
-        },
+        }
+        _ => Ok(None),
     }
 }
 
 #[cfg(test)]
+#[allow(clippy::unnecessary_cast, clippy::default_constructed_unit_structs)]
 mod tests {
     use bytes::BufMut;
 
@@ -259,4 +255,15 @@ mod tests {
             (31 as usize, RESPDataType::Array(expected_vec))
         );
     }
+
+    #[test]
+    fn test_bulk_str_binary() {
+        let mut buf = BytesMut::with_capacity(20);
+        buf.put(&b"6\r\na\r\n\0bc\r\n"[..]);
+        let result = from_bulk_string(&buf, 0);
+        assert_eq!(
+            result.unwrap().unwrap(),
+            (11, RESPDataType::BulkString(Bytes::from(&b"a\r\n\0bc"[..])))
+        );
+    }
 }
@@ -1,3 +1,7 @@
+use bytes::{BufMut, BytesMut};
+
+use super::data::RESPDataType;
+
 #[derive(Default)]
 pub struct RespSerializer;
 
@@ -11,12 +15,54 @@ impl RespSerializer {
     }
 
     pub fn serialize_nil(self) -> String {
-        return String::from("*-1\r\n");
+        String::from("*-1\r\n")
+    }
+
+    /// Serialize any RESP data type into its wire representation.
+    pub fn serialize(self, data: &RESPDataType) -> BytesMut {
+        let mut buffer = BytesMut::new();
+        write_resp(&mut buffer, data);
+        buffer
+    }
+}
+
+/// Append the wire representation of `data` to `buffer`.
+fn write_resp(buffer: &mut BytesMut, data: &RESPDataType) {
+    match data {
+        RESPDataType::SimpleString(s) => {
+            buffer.put_u8(b'+');
+            buffer.put_slice(s);
+            buffer.put_slice(b"\r\n");
+        }
+        RESPDataType::Error(e) => {
+            buffer.put_u8(b'-');
+            buffer.put_slice(e);
+            buffer.put_slice(b"\r\n");
+        }
+        RESPDataType::Integer(i) => {
+            buffer.put_slice(format!(":{}\r\n", i).as_bytes());
+        }
+        RESPDataType::BulkString(s) => {
+            buffer.put_slice(format!("${}\r\n", s.len()).as_bytes());
+            buffer.put_slice(s);
+            buffer.put_slice(b"\r\n");
+        }
+        RESPDataType::NullBulkString => buffer.put_slice(b"$-1\r\n"),
+        RESPDataType::Array(elements) => {
+            buffer.put_slice(format!("*{}\r\n", elements.len()).as_bytes());
+            for element in elements {
+                write_resp(buffer, element);
+            }
+        }
+        RESPDataType::NullArray => buffer.put_slice(b"*-1\r\n"),
     }
 }
 
 #[cfg(test)]
+#[allow(clippy::default_constructed_unit_structs)]
 mod tests {
+    use bytes::Bytes;
+
     use super::*;
 
     #[test]
@@ -30,4 +76,36 @@ mod tests {
         let resp_serializer = RespSerializer::default();
         assert_eq!(resp_serializer.serialize_error("error"), "-error\r\n")
     }
+
+    #[test]
+    fn test_serialize_int() {
+        let resp_serializer = RespSerializer::default();
+        assert_eq!(
+            resp_serializer.serialize(&RESPDataType::Integer(-42)),
+            &b":-42\r\n"[..]
+        )
+    }
+
+    #[test]
+    fn test_serialize_bulk_str() {
+        let resp_serializer = RespSerializer::default();
+        assert_eq!(
+            resp_serializer.serialize(&RESPDataType::BulkString(Bytes::from("a\r\nb"))),
+            &b"$4\r\na\r\nb\r\n"[..]
+        )
+    }
+
+    #[test]
+    fn test_serialize_nested_array() {
+        let resp_serializer = RespSerializer::default();
+        let data = RESPDataType::Array(vec![
+            RESPDataType::Integer(1),
+            RESPDataType::NullBulkString,
+            RESPDataType::Array(vec![RESPDataType::SimpleString(Bytes::from("OK"))]),
+        ]);
+        assert_eq!(
+            resp_serializer.serialize(&data),
+            &b"*3\r\n:1\r\n$-1\r\n*1\r\n+OK\r\n"[..]
+        )
+    }
 }
@@ -0,0 +1,111 @@
+use std::{
+    net::TcpStream,
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use log::{error, info};
+
+use crate::client::Client;
+use crate::handle_connection;
+use crate::memory;
+use crate::resp::data::RESPDataType;
+use crate::store::Store;
+use crate::thread_pool::ThreadPool;
+use crate::util::mstime;
+
+/// How often background housekeeping runs.
+const CRON_INTERVAL: Duration = Duration::from_millis(10);
+/// Number of cron iterations between checks for blocked clients that hung up.
+const DISCONNECT_CHECK_PERIOD: u64 = 10;
+/// Number of cron iterations between active expiry cycles.
+const ACTIVE_EXPIRE_PERIOD: u64 = 10;
+/// Most keys looked at by a single active expiry cycle.
+const ACTIVE_EXPIRE_KEYS_PER_CYCLE: usize = 200;
+/// Number of cron iterations between active rehashing of the keyspace.
+const ACTIVE_REHASH_PERIOD: u64 = 10;
+/// Time spent rehashing by a single cron iteration.
+const ACTIVE_REHASH_BUDGET: Duration = Duration::from_millis(1);
+/// Number of cron iterations between closing idle MIGRATE connections.
+const MIGRATE_SOCKETS_CHECK_PERIOD: u64 = 100;
+/// Number of cron iterations between checks of the background save and of
+/// the save rules.
+const SAVE_CHECK_PERIOD: u64 = 10;
+
+/// State shared by every connection: the store and the pool serving clients.
+#[derive(Clone)]
+pub struct Server {
+    pub store: Arc<Mutex<Store>>,
+    pool: Arc<ThreadPool>,
+}
+
+impl Server {
+    pub fn new(store: Store, pool: ThreadPool) -> Self {
+        Server {
+            store: Arc::new(Mutex::new(store)),
+            pool: Arc::new(pool),
+        }
+    }
+
+    pub fn accept(&self, stream: TcpStream) {
+        self.resume(Client::new(stream));
+    }
+
+    /// Hand a client to a worker, which flushes its pending replies and keeps serving it.
+    pub fn resume(&self, client: Client) {
+        let server = self.clone();
+        self.pool
+            .execute(move || handle_connection(client, &server));
+    }
+
+    /// Start the background thread running periodic housekeeping.
+    pub fn start_cron(&self) {
+        let server = self.clone();
+        thread::spawn(move || {
+            for iteration in 0.. {
+                thread::sleep(CRON_INTERVAL);
+                server.cron(iteration);
+            }
+        });
+    }
+
+    fn cron(&self, iteration: u64) {
+        let mut store = self.store.lock().unwrap();
+        memory::update_peak_memory();
+        if iteration.is_multiple_of(ACTIVE_EXPIRE_PERIOD) {
+            store.active_expire_keys(mstime(), ACTIVE_EXPIRE_KEYS_PER_CYCLE);
+            store.active_expire_hash_fields(mstime(), ACTIVE_EXPIRE_KEYS_PER_CYCLE);
+        }
+        if iteration.is_multiple_of(ACTIVE_REHASH_PERIOD) {
+            store.active_rehash(ACTIVE_REHASH_BUDGET);
+        }
+        // Keep evicting between commands when a cycle ran out of time.
+        if store.maxmemory.maxmemory > 0 {
+            store.perform_evictions();
+        }
+        if iteration.is_multiple_of(SAVE_CHECK_PERIOD) && store.persistence.cron() {
+            let snapshot = store.snapshot();
+            if let Err(e) = store.persistence.background_save(snapshot) {
+                error!("Can't start the background save: {}", e);
+            }
+        }
+        if iteration.is_multiple_of(MIGRATE_SOCKETS_CHECK_PERIOD)
+            && !store.migrate_sockets.is_empty()
+        {
+            store.migrate_sockets.close_idle(Instant::now());
+        }
+        if store.blocking.blocked_count() == 0 {
+            return;
+        }
+        for mut blocked in store.blocking.take_timed_out(Instant::now()) {
+            blocked.client.add_reply(&RESPDataType::NullArray);
+            self.resume(blocked.client);
+        }
+        if iteration.is_multiple_of(DISCONNECT_CHECK_PERIOD) {
+            for blocked in store.blocking.take_disconnected() {
+                info!("Blocked client {} disconnected.", blocked.client.id);
+            }
+        }
+    }
+}
@@ -1,24 +1,1000 @@
 use bytes::Bytes;
 
-use std::collections::HashMap;
+use std::cell::Cell;
+use std::collections::BTreeSet;
+use std::mem;
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+
+use crate::blocking::BlockingState;
+use crate::config::Config;
+use crate::evict::{self, EvictionPool, EvictionResult, MaxMemoryOptions, MaxMemoryPolicy};
+use crate::lazyfree::{self, LazyFreeOptions};
+use crate::memory;
+use crate::migrate::MigrateSockets;
+use crate::persistence::Persistence;
+use crate::rdb::file::{Snapshot, SnapshotEntry};
+use crate::types::dict::Dict;
+use crate::types::hash::Hash;
+use crate::types::quicklist::QuickList;
+use crate::types::set::Set;
+use crate::types::stream::Stream;
+use crate::types::zset::ZSet;
+use crate::util::{canonical_int, mstime};
+
+/// Longest string redis allocates along with its object header.
+const OBJ_ENCODING_EMBSTR_SIZE_LIMIT: usize = 44;
+/// Integers from 0 up to this are stored once and shared by every key
+/// holding them.
+const OBJ_SHARED_INTEGERS: i64 = 10000;
+
+fn shared_integers() -> &'static [Bytes] {
+    static SHARED_INTEGERS: OnceLock<Vec<Bytes>> = OnceLock::new();
+    SHARED_INTEGERS.get_or_init(|| {
+        (0..OBJ_SHARED_INTEGERS)
+            .map(|int| Bytes::from(int.to_string()))
+            .collect()
+    })
+}
+
+/// The shared copy of `string` if it is a small integer.
+fn shared_integer(string: &[u8]) -> Option<&'static Bytes> {
+    let int = canonical_int(string).filter(|int| (0..OBJ_SHARED_INTEGERS).contains(int))?;
+    Some(&shared_integers()[int as usize])
+}
+
+/// Whether `string` is the shared copy of a small integer rather than a copy
+/// of its own.
+pub fn is_shared_integer(string: &Bytes) -> bool {
+    shared_integer(string).is_some_and(|shared| shared.as_ptr() == string.as_ptr())
+}
+
+/// A value held by a key in the store.
+#[derive(Debug, Clone)]
+pub enum Value {
+    String(Bytes),
+    List(QuickList),
+    Hash(Hash),
+    Set(Set),
+    ZSet(ZSet),
+    Stream(Stream),
+}
+
+impl Value {
+    /// Name of the value's type, as reported by the TYPE command.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::String(_) => "string",
+            Value::List(_) => "list",
+            Value::Hash(_) => "hash",
+            Value::Set(_) => "set",
+            Value::ZSet(_) => "zset",
+            Value::Stream(_) => "stream",
+        }
+    }
+
+    /// Name of the internal representation, as reported by OBJECT ENCODING.
+    pub fn encoding(&self) -> &'static str {
+        match self {
+            Value::String(string) if string.len() <= 20 && canonical_int(string).is_some() => "int",
+            Value::String(string) if string.len() <= OBJ_ENCODING_EMBSTR_SIZE_LIMIT => "embstr",
+            Value::String(_) => "raw",
+            Value::List(list) => list.encoding(),
+            Value::Hash(hash) => hash.encoding(),
+            Value::Set(set) => set.encoding(),
+            Value::ZSet(zset) => zset.encoding(),
+            Value::Stream(_) => "stream",
+        }
+    }
+
+    /// Whether the value is an empty collection, which redis never keeps around.
+    /// Streams are the exception: an empty stream keeps its last ID and groups.
+    pub fn is_empty_collection(&self) -> bool {
+        match self {
+            Value::String(_) => false,
+            Value::List(list) => list.is_empty(),
+            Value::Hash(hash) => hash.is_empty(),
+            Value::Set(set) => set.is_empty(),
+            Value::ZSet(zset) => zset.is_empty(),
+            Value::Stream(_) => false,
+        }
+    }
+}
+
+/// A value along with the access metadata eviction ranks keys by.
+#[derive(Debug)]
+struct Object {
+    /// Shared with the snapshots being saved, which keep the value as it was
+    /// when they were taken: modifying a shared value copies it first.
+    value: Arc<Value>,
+    /// The LRU clock of the last access or, under an LFU policy, the minute
+    /// the counter was last decremented in the upper 16 bits and the
+    /// logarithmic access counter in the lower 8. Reads update it through
+    /// shared references.
+    access: Cell<u32>,
+}
+
+impl Object {
+    /// Free the value, unless a snapshot still holds it.
+    fn free(self, lazy: bool) {
+        if let Ok(value) = Arc::try_unwrap(self.value) {
+            lazyfree::free_value(value, lazy);
+        }
+    }
+}
+
+/// Number of databases unless configured otherwise.
+pub const DEFAULT_DATABASES: usize = 16;
+
+/// One of the logical databases clients switch between with SELECT, each
+/// with its own keyspace and expiry state.
+#[derive(Default)]
+pub struct Db {
+    key_val_store: Dict<Bytes, Object>,
+    /// Unix time in milliseconds at which volatile keys expire.
+    expires: Dict<Bytes, i64>,
+    /// Volatile keys ordered by expiry time, for active expiry. Entries can be
+    /// stale and are checked against `expires` when they come due.
+    expire_queue: BTreeSet<(i64, Bytes)>,
+    /// Hashes with fields that have a TTL, ordered by when their next field
+    /// expires. Entries can be stale and are re-validated when they come due.
+    hash_field_expires: BTreeSet<(i64, Bytes)>,
+}
+
+impl Db {
+    /// Number of keys, including expired keys that were not reclaimed yet.
+    pub fn len(&self) -> usize {
+        self.key_val_store.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.key_val_store.is_empty()
+    }
+
+    fn is_expired(&self, key: &[u8]) -> bool {
+        self.expires.get(key).is_some_and(|when| *when <= mstime())
+    }
+
+    fn get(&self, key: &[u8]) -> Option<&Object> {
+        if self.is_expired(key) {
+            return None;
+        }
+        self.key_val_store.get(key)
+    }
+
+    fn get_mut(&mut self, key: &[u8], lazy_expire: bool) -> Option<&mut Object> {
+        self.expire_if_needed(key, lazy_expire);
+        self.key_val_store.get_mut(key)
+    }
+
+    /// Insert a value, freeing the value it replaces in the background when
+    /// `lazy_overwrite` and that value is large.
+    fn insert(&mut self, key: Bytes, object: Object, lazy_overwrite: bool) {
+        self.expires.remove(&key);
+        if let Value::Hash(hash) = &*object.value {
+            if let Some(when) = hash.next_field_expire_time() {
+                self.hash_field_expires.insert((when, key.clone()));
+            }
+        }
+        if let Some(old) = self.key_val_store.insert(key, object) {
+            old.free(lazy_overwrite);
+        }
+    }
+
+    /// Take `key` out, leaving its value shared with any snapshot holding it
+    /// so that the caller decides whether it is freed or moved elsewhere.
+    fn remove(&mut self, key: &[u8], lazy_expire: bool) -> Option<Object> {
+        let expired = self.expire_if_needed(key, lazy_expire);
+        self.expires.remove(key);
+        self.key_val_store.remove(key).filter(|_| !expired)
+    }
+
+    /// Delete `key` if it is past its TTL, freeing its value in the background
+    /// when `lazy` and the value is large. Returns whether it was deleted.
+    fn expire_if_needed(&mut self, key: &[u8], lazy: bool) -> bool {
+        if !self.is_expired(key) {
+            return false;
+        }
+        self.expires.remove(key);
+        if let Some(object) = self.key_val_store.remove(key) {
+            object.free(lazy);
+        }
+        true
+    }
+
+    fn random_key(&mut self, lazy_expire: bool) -> Option<Bytes> {
+        while let Some((key, _)) = self.key_val_store.random_entry() {
+            let key = key.clone();
+            if !self.expire_if_needed(&key, lazy_expire) {
+                return Some(key);
+            }
+        }
+        None
+    }
+
+    fn set_expire(&mut self, key: Bytes, when: i64) {
+        self.expire_queue.insert((when, key.clone()));
+        self.expires.insert(key, when);
+    }
+
+    fn active_expire_keys(&mut self, now: i64, limit: usize, lazy: bool) -> usize {
+        let mut expired = 0;
+        for _ in 0..limit {
+            match self.expire_queue.first() {
+                Some((when, _)) if *when <= now => {}
+                _ => break,
+            }
+            let (when, key) = self.expire_queue.pop_first().unwrap();
+            if self.expires.get(&key) == Some(&when) {
+                self.expires.remove(&key);
+                if let Some(object) = self.key_val_store.remove(&key) {
+                    object.free(lazy);
+                }
+                expired += 1;
+            }
+        }
+        expired
+    }
+
+    fn remove_if_empty(&mut self, key: &[u8]) {
+        if self
+            .key_val_store
+            .get(key)
+            .is_some_and(|object| object.value.is_empty_collection())
+        {
+            self.remove(key, false);
+        }
+    }
+
+    fn expire_hash_fields(&mut self, key: &[u8], now: i64) -> usize {
+        // Check before `make_mut`, which copies a hash a snapshot still shares.
+        let due = match self.key_val_store.get(key).map(|o| &*o.value) {
+            Some(Value::Hash(hash)) => hash
+                .next_field_expire_time()
+                .is_some_and(|when| when <= now),
+            _ => false,
+        };
+        if !due {
+            return 0;
+        }
+        let expired = match self
+            .key_val_store
+            .get_mut(key)
+            .map(|o| Arc::make_mut(&mut o.value))
+        {
+            Some(Value::Hash(hash)) => hash.expire_fields(now),
+            _ => 0,
+        };
+        self.remove_if_empty(key);
+        expired
+    }
+
+    fn active_expire_hash_fields(&mut self, now: i64, limit: usize) -> usize {
+        let mut expired = 0;
+        for _ in 0..limit {
+            match self.hash_field_expires.first() {
+                Some((when, _)) if *when <= now => {}
+                _ => break,
+            }
+            let (_, key) = self.hash_field_expires.pop_first().unwrap();
+            expired += self.expire_hash_fields(&key, now);
+            if let Some(Value::Hash(hash)) = self.key_val_store.get(&key).map(|o| &*o.value) {
+                if let Some(when) = hash.next_field_expire_time() {
+                    self.hash_field_expires.insert((when, key));
+                }
+            }
+        }
+        expired
+    }
+
+    fn active_rehash(&mut self, budget: Duration) -> bool {
+        if self.key_val_store.is_rehashing() {
+            self.key_val_store.rehash_for(budget);
+            return true;
+        }
+        if self.expires.is_rehashing() {
+            self.expires.rehash_for(budget);
+            return true;
+        }
+        false
+    }
+}
 
 pub struct Store {
-    key_val_store: HashMap<Bytes, Bytes>,
+    dbs: Vec<Db>,
+    /// The database commands operate on, switched to the database of each
+    /// client before running its commands.
+    selected: usize,
+    pub blocking: BlockingState,
+    /// Which deletions free large values in the background.
+    pub lazyfree: LazyFreeOptions,
+    /// Connections to other instances kept open by MIGRATE.
+    pub migrate_sockets: MigrateSockets,
+    pub maxmemory: MaxMemoryOptions,
+    eviction_pool: EvictionPool,
+    /// Database the random policies evict from next, so that they take
+    /// turns.
+    next_random_eviction_db: usize,
+    pub persistence: Persistence,
 }
 
 impl Store {
     pub fn init() -> Self {
-        let key_val_store = HashMap::new();
-        return Store {
-            key_val_store: key_val_store,
-        };
+        Store::new(&Config::default())
+    }
+
+    pub fn new(config: &Config) -> Self {
+        Store {
+            dbs: (0..config.databases.max(1))
+                .map(|_| Db::default())
+                .collect(),
+            selected: 0,
+            blocking: BlockingState::default(),
+            lazyfree: config.lazyfree,
+            migrate_sockets: MigrateSockets::default(),
+            maxmemory: config.maxmemory,
+            eviction_pool: EvictionPool::default(),
+            next_random_eviction_db: 0,
+            persistence: Persistence::new(config.persistence.clone()),
+        }
+    }
+
+    pub fn db_count(&self) -> usize {
+        self.dbs.len()
+    }
+
+    pub fn selected_db(&self) -> usize {
+        self.selected
+    }
+
+    /// Make the database at `index`, which must exist, the one commands operate on.
+    pub fn select(&mut self, index: usize) {
+        assert!(index < self.dbs.len(), "DB index {} is out of range", index);
+        self.selected = index;
+    }
+
+    fn db(&self) -> &Db {
+        &self.dbs[self.selected]
     }
 
+    fn db_mut(&mut self) -> &mut Db {
+        &mut self.dbs[self.selected]
+    }
+
+    /// Exchange the contents of two databases. Clients blocked on keys of
+    /// either database are woken up if their keys now hold data.
+    pub fn swap_dbs(&mut self, first: usize, second: usize) {
+        self.dbs.swap(first, second);
+        self.persistence.dirty += 1;
+        for db in [first, second] {
+            for key in self.blocking.waited_keys(db) {
+                if self.dbs[db].get(&key).is_some() {
+                    self.blocking.signal_key_as_ready(db, &key);
+                }
+            }
+        }
+    }
+
+    /// Empty the database at `index`, returning its former contents so that
+    /// the caller decides where the memory is released.
+    pub fn flush_db(&mut self, index: usize) -> Db {
+        self.persistence.dirty += self.dbs[index].len() as u64;
+        std::mem::take(&mut self.dbs[index])
+    }
+
+    /// Empty every database, returning their former contents.
+    pub fn flush_all(&mut self) -> Vec<Db> {
+        self.persistence.dirty += self.key_count() as u64;
+        self.dbs.iter_mut().map(std::mem::take).collect()
+    }
+
+    /// The keys of every database as they are now. Values are shared with
+    /// the keyspace rather than copied, a value being copied only if it is
+    /// modified while the snapshot is alive.
+    pub fn snapshot(&self) -> Snapshot {
+        let now = mstime();
+        self.dbs
+            .iter()
+            .map(|db| {
+                db.key_val_store
+                    .iter()
+                    .filter_map(|(key, object)| {
+                        let expire = db.expires.get(key).copied();
+                        expire.is_none_or(|when| when > now).then(|| SnapshotEntry {
+                            key: key.clone(),
+                            value: Arc::clone(&object.value),
+                            expire,
+                        })
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Add the keys of `snapshot`, which must not have more databases than
+    /// the store, as loaded at startup.
+    pub fn load_snapshot(&mut self, snapshot: Snapshot) {
+        let access = self.maxmemory.initial_access();
+        for (db, entries) in self.dbs.iter_mut().zip(snapshot) {
+            for entry in entries {
+                let object = Object {
+                    value: entry.value,
+                    access: Cell::new(access),
+                };
+                db.insert(entry.key.clone(), object, false);
+                if let Some(when) = entry.expire {
+                    db.set_expire(entry.key, when);
+                }
+            }
+        }
+    }
+
+    /// Set `key` to a string, discarding any TTL it had.
     pub fn set_key_val(&mut self, key: Bytes, val: Bytes) {
-        self.key_val_store.insert(key, val);
+        self.insert_key_val(key, Value::String(val));
+    }
+
+    /// Insert a value, discarding any TTL the key had, and wake up clients
+    /// blocked on `key` if it now holds data they wait for.
+    pub fn insert_key_val(&mut self, key: Bytes, val: Value) {
+        if matches!(val, Value::List(_) | Value::ZSet(_)) {
+            self.signal_key_as_ready(&key);
+        }
+        let val = match val {
+            Value::String(string) if self.shares_integers() => {
+                Value::String(shared_integer(&string).cloned().unwrap_or(string))
+            }
+            val => val,
+        };
+        // Overwriting a key keeps its access frequency, but is an access.
+        let access = match self.db().key_val_store.get(&key) {
+            Some(old) if self.maxmemory.policy.is_lfu() => self.maxmemory.touch(old.access.get()),
+            _ => self.maxmemory.initial_access(),
+        };
+        let lazy = self.lazyfree.server_del;
+        self.persistence.dirty += 1;
+        let object = Object {
+            value: Arc::new(val),
+            access: Cell::new(access),
+        };
+        self.db_mut().insert(key, object, lazy);
+    }
+
+    /// Whether small integer values are shared between keys. They are not
+    /// when evicting by access, as a shared value has no access time of its
+    /// own in redis.
+    fn shares_integers(&self) -> bool {
+        self.maxmemory.maxmemory == 0
+            || !matches!(
+                self.maxmemory.policy,
+                MaxMemoryPolicy::AllKeysLru
+                    | MaxMemoryPolicy::VolatileLru
+                    | MaxMemoryPolicy::AllKeysLfu
+                    | MaxMemoryPolicy::VolatileLfu
+            )
+    }
+
+    /// Wake up clients blocked on `key` in the selected database, if any.
+    pub fn signal_key_as_ready(&mut self, key: &Bytes) {
+        self.blocking.signal_key_as_ready(self.selected, key);
+    }
+
+    /// The value of `key`, treating a key past its TTL as missing. Counts as
+    /// an access of the key.
+    pub fn get_from_key_val_store(&self, key: &[u8]) -> Option<&Value> {
+        let object = self.db().get(key)?;
+        object.access.set(self.maxmemory.touch(object.access.get()));
+        Some(&object.value)
     }
 
-    pub fn get_from_key_val_store(&self, key: Bytes) -> Option<&Bytes> {
-        self.key_val_store.get(&key)
+    /// The value of `key` without counting as an access, for commands that
+    /// only inspect keys.
+    pub fn peek(&self, key: &[u8]) -> Option<&Value> {
+        self.db().get(key).map(|object| &*object.value)
+    }
+
+    /// The access metadata of `key`: an LRU clock or, under an LFU policy, a
+    /// decrement time and counter. Does not count as an access.
+    pub fn get_access(&self, key: &[u8]) -> Option<u32> {
+        self.db().get(key).map(|object| object.access.get())
+    }
+
+    /// The value of `key` for modification, deleting the key if it is past its TTL.
+    /// A value a snapshot still holds is copied first, so commands only ask
+    /// for it once they know they will change it, and count their changes
+    /// with `add_dirty`.
+    pub fn get_mut_from_key_val_store(&mut self, key: &[u8]) -> Option<&mut Value> {
+        let lazy = self.lazyfree.expire;
+        let options = self.maxmemory;
+        let object = self.dbs[self.selected].get_mut(key, lazy)?;
+        object.access.set(options.touch(object.access.get()));
+        Some(Arc::make_mut(&mut object.value))
+    }
+
+    /// Count `changes` made to the dataset towards the save rules.
+    pub fn add_dirty(&mut self, changes: usize) {
+        self.persistence.dirty += changes as u64;
+    }
+
+    /// Set the access metadata of the existing `key` the way RESTORE asks
+    /// for: `frequency` only matters under an LFU policy and `idle_seconds`
+    /// under any other.
+    pub fn set_access(&mut self, key: &[u8], frequency: Option<u8>, idle_seconds: Option<u64>) {
+        let access = match (self.maxmemory.policy.is_lfu(), frequency, idle_seconds) {
+            (true, Some(frequency), _) => evict::lfu_access_with_frequency(frequency),
+            (false, _, Some(idle_seconds)) => evict::lru_clock_for_idle(idle_seconds),
+            _ => return,
+        };
+        if let Some(object) = self.db().get(key) {
+            object.access.set(access);
+        }
+    }
+
+    /// Take `key` out of the selected database along with its access
+    /// metadata. The value is not copied even if a snapshot still holds it.
+    fn remove_object(&mut self, key: &[u8]) -> Option<Object> {
+        let lazy = self.lazyfree.expire;
+        let object = self.db_mut().remove(key, lazy)?;
+        self.persistence.dirty += 1;
+        Some(object)
+    }
+
+    /// Add an object taken out by `remove_object` under `key`, waking up
+    /// clients blocked on `key` if it now holds data they wait for.
+    fn insert_object(&mut self, key: Bytes, object: Object) {
+        if matches!(*object.value, Value::List(_) | Value::ZSet(_)) {
+            self.signal_key_as_ready(&key);
+        }
+        let lazy = self.lazyfree.server_del;
+        self.persistence.dirty += 1;
+        self.db_mut().insert(key, object, lazy);
+    }
+
+    /// Delete `key`, freeing its value in the background when `lazy` and the
+    /// value is large. Returns whether the key existed.
+    pub fn delete_key(&mut self, key: &[u8], lazy: bool) -> bool {
+        match self.remove_object(key) {
+            Some(object) => {
+                object.free(lazy);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn contains_key(&self, key: &[u8]) -> bool {
+        self.peek(key).is_some()
+    }
+
+    /// Number of keys, including expired keys that were not reclaimed yet.
+    pub fn dbsize(&self) -> usize {
+        self.db().len()
+    }
+
+    /// Number of keys across all databases.
+    pub fn key_count(&self) -> usize {
+        self.dbs.iter().map(Db::len).sum()
+    }
+
+    /// Bytes used by the keyspace and expires tables of each database that
+    /// holds keys, as (database, keyspace, expires).
+    pub fn keyspace_overheads(&self) -> Vec<(usize, usize, usize)> {
+        self.dbs
+            .iter()
+            .enumerate()
+            .filter(|(_, db)| !db.is_empty())
+            .map(|(index, db)| (index, db.key_val_store.overhead(), db.expires.overhead()))
+            .collect()
+    }
+
+    /// Estimated bytes held by `key`: its value, looking at `samples`
+    /// elements of collections, the key itself and its entry in the keyspace.
+    pub fn memory_usage(&self, key: &[u8], samples: usize) -> Option<usize> {
+        let object = self.db().get(key)?;
+        let entry = mem::size_of::<(Bytes, Object)>() + key.len();
+        Some(entry + memory::value_usage(&object.value, samples))
+    }
+
+    /// Visit a batch of about `count` keys starting at `cursor`, returning the
+    /// cursor to continue from, 0 once done. Expired keys are visited too.
+    pub fn scan(&self, cursor: u64, count: usize, mut visit: impl FnMut(&Bytes, &Value)) -> u64 {
+        self.db()
+            .key_val_store
+            .scan_batch(cursor, count, |key, object| visit(key, &object.value))
+    }
+
+    /// Every key, including expired keys that were not reclaimed yet.
+    pub fn keys(&self) -> impl Iterator<Item = &Bytes> {
+        self.db().key_val_store.keys()
+    }
+
+    /// A random key that has not expired, None if there is none.
+    pub fn random_key(&mut self) -> Option<Bytes> {
+        let lazy = self.lazyfree.expire;
+        self.db_mut().random_key(lazy)
+    }
+
+    /// Evict keys as the maxmemory policy says until memory use is back
+    /// under the limit, or the time limit of a cycle hits.
+    pub fn perform_evictions(&mut self) -> EvictionResult {
+        self.perform_evictions_with(|_| memory::used_memory())
+    }
+
+    fn perform_evictions_with(&mut self, used_memory: impl Fn(&Store) -> usize) -> EvictionResult {
+        let maxmemory = self.maxmemory.maxmemory;
+        if maxmemory == 0 || used_memory(self) <= maxmemory {
+            return EvictionResult::Ok;
+        }
+        if self.maxmemory.policy == MaxMemoryPolicy::NoEviction {
+            return EvictionResult::Fail;
+        }
+        let start = Instant::now();
+        let lazy = self.lazyfree.eviction;
+        let mut evicted = 0;
+        while used_memory(self) > maxmemory {
+            let Some((index, key)) = self.next_eviction_candidate() else {
+                // Values still being freed in the background may be enough.
+                if lazyfree::pending_objects() > 0 {
+                    return EvictionResult::Running;
+                }
+                return EvictionResult::Fail;
+            };
+            let db = &mut self.dbs[index];
+            db.expires.remove(&key);
+            if let Some(object) = db.key_val_store.remove(&key) {
+                object.free(lazy);
+            }
+            evicted += 1;
+            if evicted % 16 == 0 && start.elapsed() > evict::EVICTION_TIME_LIMIT {
+                return EvictionResult::Running;
+            }
+        }
+        EvictionResult::Ok
+    }
+
+    /// The database and key to evict next, None if no key may be evicted.
+    fn next_eviction_candidate(&mut self) -> Option<(usize, Bytes)> {
+        let options = self.maxmemory;
+        let volatile = options.policy.is_volatile();
+        if options.policy.is_random() {
+            for _ in 0..self.dbs.len() {
+                let index = self.next_random_eviction_db;
+                self.next_random_eviction_db = (index + 1) % self.dbs.len();
+                let db = &self.dbs[index];
+                let key = if volatile {
+                    db.expires.random_entry().map(|(key, _)| key)
+                } else {
+                    db.key_val_store.random_entry().map(|(key, _)| key)
+                };
+                if let Some(key) = key {
+                    return Some((index, key.clone()));
+                }
+            }
+            return None;
+        }
+        let evictable = |db: &Db| {
+            if volatile {
+                !db.expires.is_empty()
+            } else {
+                !db.is_empty()
+            }
+        };
+        if !self.dbs.iter().any(evictable) {
+            return None;
+        }
+        loop {
+            for (index, db) in self.dbs.iter().enumerate() {
+                let keys: Vec<&Bytes> = if volatile {
+                    db.expires
+                        .sample(options.samples)
+                        .into_iter()
+                        .map(|(key, _)| key)
+                        .collect()
+                } else {
+                    db.key_val_store
+                        .sample(options.samples)
+                        .into_iter()
+                        .map(|(key, _)| key)
+                        .collect()
+                };
+                for key in keys {
+                    let Some(object) = db.key_val_store.get(key) else {
+                        continue;
+                    };
+                    let expire = db.expires.get(key).copied();
+                    self.eviction_pool
+                        .offer(&options, index, key, object.access.get(), expire);
+                }
+            }
+            // Candidates from earlier cycles may be gone by now.
+            while let Some((index, key)) = self.eviction_pool.pop_best() {
+                if self.dbs[index].key_val_store.contains_key(&key) {
+                    return Some((index, key));
+                }
+            }
+        }
+    }
+
+    /// Spend about `budget` migrating buckets of the keyspace tables that are
+    /// being resized, returning whether any work was done.
+    pub fn active_rehash(&mut self, budget: Duration) -> bool {
+        self.dbs.iter_mut().any(|db| db.active_rehash(budget))
+    }
+
+    /// Move the value and TTL of `key` to `new_key`, replacing whatever
+    /// `new_key` held. Returns false if `key` does not exist.
+    pub fn rename(&mut self, key: &[u8], new_key: Bytes) -> bool {
+        let expire = self.get_expire(key);
+        let Some(object) = self.remove_object(key) else {
+            return false;
+        };
+        self.delete_key(&new_key, self.lazyfree.server_del);
+        self.insert_object(new_key.clone(), object);
+        if let Some(when) = expire {
+            self.set_expire(new_key, when);
+        }
+        true
+    }
+
+    /// Move `key` and its TTL to the database at `index`, where the caller
+    /// made sure it does not exist. Returns false if `key` does not exist.
+    pub fn move_key(&mut self, key: &Bytes, index: usize) -> bool {
+        let expire = self.get_expire(key);
+        let Some(object) = self.remove_object(key) else {
+            return false;
+        };
+        let selected = self.selected;
+        self.select(index);
+        self.insert_object(key.clone(), object);
+        if let Some(when) = expire {
+            self.set_expire(key.clone(), when);
+        }
+        self.select(selected);
+        true
+    }
+
+    /// The unix time in milliseconds at which `key` expires, if it is volatile.
+    pub fn get_expire(&self, key: &[u8]) -> Option<i64> {
+        self.db().expires.get(key).copied()
+    }
+
+    /// Make the existing `key` expire at `when`, in unix milliseconds.
+    pub fn set_expire(&mut self, key: Bytes, when: i64) {
+        self.persistence.dirty += 1;
+        self.db_mut().set_expire(key, when);
+    }
+
+    /// Make `key` persistent, returning whether it had a TTL.
+    pub fn remove_expire(&mut self, key: &[u8]) -> bool {
+        let removed = self.db_mut().expires.remove(key).is_some();
+        self.persistence.dirty += removed as u64;
+        removed
+    }
+
+    /// Delete `key` if it is past its TTL, returning whether it was.
+    pub fn expire_if_needed(&mut self, key: &[u8]) -> bool {
+        let lazy = self.lazyfree.expire;
+        self.db_mut().expire_if_needed(key, lazy)
+    }
+
+    /// Delete keys whose TTL passed by `now`, looking at most at `limit` of
+    /// them in each database so a large backlog is worked through over several
+    /// calls. Returns the number of keys deleted.
+    pub fn active_expire_keys(&mut self, now: i64, limit: usize) -> usize {
+        self.dbs
+            .iter_mut()
+            .map(|db| db.active_expire_keys(now, limit, self.lazyfree.expire))
+            .sum()
+    }
+
+    /// Delete `key` if a command left it holding an empty collection.
+    pub fn remove_if_empty(&mut self, key: &[u8]) {
+        self.db_mut().remove_if_empty(key);
+    }
+
+    /// Make sure active expiry looks at the hash at `key` no later than `when`.
+    pub fn schedule_hash_field_expiry(&mut self, key: &Bytes, when: i64) {
+        self.db_mut().hash_field_expires.insert((when, key.clone()));
+    }
+
+    /// Delete the fields of the hash at `key` that expired by `now`, deleting
+    /// the key too if no field is left. Returns the number of fields deleted.
+    pub fn expire_hash_fields(&mut self, key: &[u8], now: i64) -> usize {
+        let expired = self.db_mut().expire_hash_fields(key, now);
+        self.persistence.dirty += expired as u64;
+        expired
+    }
+
+    /// Expire due hash fields, visiting at most `limit` hashes in each database
+    /// so that a large backlog is worked through over several calls. Returns
+    /// the fields deleted.
+    pub fn active_expire_hash_fields(&mut self, now: i64, limit: usize) -> usize {
+        let expired: usize = self
+            .dbs
+            .iter_mut()
+            .map(|db| db.active_expire_hash_fields(now, limit))
+            .sum();
+        self.persistence.dirty += expired as u64;
+        expired
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store(policy: MaxMemoryPolicy) -> Store {
+        let mut store = Store::init();
+        store.maxmemory = MaxMemoryOptions {
+            maxmemory: 10,
+            policy,
+            ..MaxMemoryOptions::default()
+        };
+        store
+    }
+
+    // The limits of these tests are a number of keys rather than bytes.
+    fn fill(store: &mut Store, count: usize) {
+        for i in 0..count {
+            store.set_key_val(Bytes::from(format!("key:{}", i)), Bytes::from("value"));
+        }
+    }
+
+    #[test]
+    fn test_noeviction() {
+        let mut store = store(MaxMemoryPolicy::NoEviction);
+        fill(&mut store, 10);
+        assert_eq!(
+            store.perform_evictions_with(Store::key_count),
+            EvictionResult::Ok
+        );
+        fill(&mut store, 20);
+        assert_eq!(
+            store.perform_evictions_with(Store::key_count),
+            EvictionResult::Fail
+        );
+        assert_eq!(store.dbsize(), 20);
+    }
+
+    #[test]
+    fn test_allkeys_eviction() {
+        for policy in [
+            MaxMemoryPolicy::AllKeysLru,
+            MaxMemoryPolicy::AllKeysLfu,
+            MaxMemoryPolicy::AllKeysRandom,
+        ] {
+            let mut store = store(policy);
+            fill(&mut store, 15);
+            store.select(1);
+            fill(&mut store, 15);
+            assert_eq!(
+                store.perform_evictions_with(Store::key_count),
+                EvictionResult::Ok
+            );
+            assert_eq!(store.key_count(), 10);
+        }
+    }
+
+    #[test]
+    fn test_volatile_eviction() {
+        for policy in [
+            MaxMemoryPolicy::VolatileLru,
+            MaxMemoryPolicy::VolatileLfu,
+            MaxMemoryPolicy::VolatileRandom,
+            MaxMemoryPolicy::VolatileTtl,
+        ] {
+            let mut store = store(policy);
+            fill(&mut store, 20);
+            for i in 0..5 {
+                store.set_expire(Bytes::from(format!("key:{}", i)), mstime() + 100_000);
+            }
+            // Only the volatile keys may go, which is not enough.
+            assert_eq!(
+                store.perform_evictions_with(Store::key_count),
+                EvictionResult::Fail
+            );
+            assert_eq!(store.dbsize(), 15);
+            assert!(!store.contains_key(b"key:0"));
+            assert!(store.contains_key(b"key:5"));
+        }
+    }
+
+    #[test]
+    fn test_volatile_ttl_evicts_closest_expiry_first() {
+        let mut store = store(MaxMemoryPolicy::VolatileTtl);
+        store.maxmemory.samples = 64;
+        fill(&mut store, 12);
+        for i in 0..12 {
+            store.set_expire(
+                Bytes::from(format!("key:{}", i)),
+                mstime() + 100_000 + i as i64,
+            );
+        }
+        assert_eq!(
+            store.perform_evictions_with(Store::key_count),
+            EvictionResult::Ok
+        );
+        assert!(!store.contains_key(b"key:0"));
+        assert!(!store.contains_key(b"key:1"));
+        assert!(store.contains_key(b"key:2"));
+    }
+
+    #[test]
+    fn test_lru_evicts_idle_keys_first() {
+        let mut store = store(MaxMemoryPolicy::AllKeysLru);
+        store.maxmemory.samples = 64;
+        fill(&mut store, 12);
+        store.set_access(b"key:3", None, Some(3600));
+        store.set_access(b"key:7", None, Some(7200));
+        assert_eq!(
+            store.perform_evictions_with(Store::key_count),
+            EvictionResult::Ok
+        );
+        assert!(!store.contains_key(b"key:3"));
+        assert!(!store.contains_key(b"key:7"));
+    }
+
+    #[test]
+    fn test_lfu_evicts_rarely_used_keys_first() {
+        let mut store = store(MaxMemoryPolicy::AllKeysLfu);
+        store.maxmemory.samples = 64;
+        fill(&mut store, 12);
+        for i in 0..12 {
+            let frequency = if i == 4 || i == 9 { 0 } else { 100 };
+            store.set_access(format!("key:{}", i).as_bytes(), Some(frequency), None);
+        }
+        assert_eq!(
+            store.perform_evictions_with(Store::key_count),
+            EvictionResult::Ok
+        );
+        assert!(!store.contains_key(b"key:4"));
+        assert!(!store.contains_key(b"key:9"));
+    }
+
+    /// The value of `key` in the first database of `snapshot`.
+    fn snapshot_value<'a>(snapshot: &'a Snapshot, key: &[u8]) -> &'a Arc<Value> {
+        &snapshot[0]
+            .iter()
+            .find(|entry| entry.key == key)
+            .unwrap()
+            .value
+    }
+
+    #[test]
+    fn test_removing_keys_keeps_snapshot_values_without_copying() {
+        let mut store = Store::init();
+        for key in ["deleted", "renamed", "moved"] {
+            let mut set = Set::new();
+            set.insert(Bytes::from("member"));
+            store.insert_key_val(Bytes::from(key), Value::Set(set));
+        }
+        let snapshot = store.snapshot();
+
+        assert!(store.delete_key(b"deleted", true));
+        assert!(!store.contains_key(b"deleted"));
+        assert_eq!(Arc::strong_count(snapshot_value(&snapshot, b"deleted")), 1);
+
+        assert!(store.rename(b"renamed", Bytes::from("new")));
+        assert!(std::ptr::eq(
+            store.peek(b"new").unwrap(),
+            &**snapshot_value(&snapshot, b"renamed")
+        ));
+
+        assert!(store.move_key(&Bytes::from("moved"), 1));
+        store.select(1);
+        assert!(std::ptr::eq(
+            store.peek(b"moved").unwrap(),
+            &**snapshot_value(&snapshot, b"moved")
+        ));
+
+        // Modifying the value now copies it, leaving the snapshot's alone.
+        match store.get_mut_from_key_val_store(b"moved") {
+            Some(Value::Set(set)) => set.insert(Bytes::from("other")),
+            _ => unreachable!(),
+        };
+        match &**snapshot_value(&snapshot, b"moved") {
+            Value::Set(set) => assert_eq!(set.len(), 1),
+            _ => unreachable!(),
+        }
     }
 }
@@ -7,7 +7,7 @@ use log::{error, info};
 
 pub struct ThreadPool {
     workers: Vec<Worker>,
-    sender: mpsc::Sender<Job>,
+    sender: Option<mpsc::Sender<Job>>,
 }
 
 type Job = Box<dyn FnOnce() + Send + 'static>;
@@ -26,7 +26,10 @@ impl ThreadPool {
             workers.push(Worker::new(id, Arc::clone(&receiver)).unwrap())
         }
 
-        ThreadPool { workers, sender }
+        ThreadPool {
+            workers,
+            sender: Some(sender),
+        }
     }
 
     pub fn execute<F>(&self, f: F)
@@ -34,27 +37,52 @@ impl ThreadPool {
         F: FnOnce() + Send + 'static,
     {
         let job = Box::new(f);
-        self.sender.send(job).unwrap();
+        self.sender.as_ref().unwrap().send(job).unwrap();
+    }
+}
+
+impl Drop for ThreadPool {
+    fn drop(&mut self) {
+        drop(self.sender.take());
+
+        for worker in &mut self.workers {
+            info!("Shutting down worker {}", worker.id);
+
+            if let Some(thread) = worker.thread.take() {
+                thread.join().unwrap();
+            }
+        }
     }
 }
 
 struct Worker {
     id: usize,
-    thread: thread::JoinHandle<()>,
+    thread: Option<thread::JoinHandle<()>>,
 }
 
 impl Worker {
     fn new(id: usize, receiver: Arc<Mutex<mpsc::Receiver<Job>>>) -> Result<Worker, String> {
         let builder = thread::Builder::new();
         let result = builder.spawn(move || loop {
-            let job = receiver.lock().unwrap().recv().unwrap();
+            let message = receiver.lock().unwrap().recv();
 
-            info!("Worker {id} got a job; executing.");
+            match message {
+                Ok(job) => {
+                    info!("Worker {id} got a job; executing.");
 
-            job();
+                    job();
+                }
+                Err(_) => {
+                    info!("Worker {id} disconnected; shutting down.");
+                    break;
+                }
+            }
         });
         match result {
-            Ok(thread) => Ok(Worker { id, thread }),
+            Ok(thread) => Ok(Worker {
+                id,
+                thread: Some(thread),
+            }),
             Err(e) => {
                 error!("Thread failed: {:?}", e);
                 Err("Error spawning thread".to_string())
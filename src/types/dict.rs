@@ -0,0 +1,590 @@
+use std::borrow::Borrow;
+use std::fmt;
+use std::hash::{BuildHasher, Hash, RandomState};
+use std::mem;
+use std::time::{Duration, Instant};
+
+use crate::util::{random_index, random_u64};
+
+/// Size of a table when the first entry is inserted.
+const DICT_INITIAL_SIZE: usize = 4;
+/// A table shrinks once no more than one bucket in this many is used.
+const DICT_MIN_FILL: usize = 8;
+/// Number of entries sampled to pick a fair random entry.
+const DICT_FAIR_RANDOM_SAMPLES: usize = 20;
+
+type Table<K, V> = Vec<Vec<(K, V)>>;
+
+/// A chained hash table with a power of two number of buckets, modelled on
+/// redis' dict.
+///
+/// Resizing never moves every entry at once: a second table is allocated and
+/// buckets migrate to it a few at a time, on every write and from the server
+/// cron, so that a huge table does not stall the server while it grows or
+/// shrinks. Lookups search both tables while a rehash is in progress.
+///
+/// The table can be scanned with a stateless cursor: a scan returns every
+/// entry present for its whole duration at least once, even if the table is
+/// resized between calls.
+#[derive(Clone)]
+pub struct Dict<K, V> {
+    /// New entries go to the second table while rehashing, and the first one
+    /// is replaced by it once every bucket migrated.
+    tables: [Table<K, V>; 2],
+    /// Number of entries in each table.
+    used: [usize; 2],
+    /// Next bucket of the first table to migrate, None when not rehashing.
+    rehash_index: Option<usize>,
+    hasher: RandomState,
+}
+
+impl<K, V> Default for Dict<K, V> {
+    fn default() -> Self {
+        Dict {
+            tables: [Vec::new(), Vec::new()],
+            used: [0, 0],
+            rehash_index: None,
+            hasher: RandomState::new(),
+        }
+    }
+}
+
+impl<K: fmt::Debug, V: fmt::Debug> fmt::Debug for Dict<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<K: Hash + Eq, V> FromIterator<(K, V)> for Dict<K, V> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut dict = Dict::new();
+        for (key, value) in iter {
+            dict.insert(key, value);
+        }
+        dict
+    }
+}
+
+fn new_table<K, V>(size: usize) -> Table<K, V> {
+    (0..size).map(|_| Vec::new()).collect()
+}
+
+/// Increment the bits of `cursor` under `mask` in reverse binary order, the
+/// high bits first.
+fn next_cursor(cursor: u64, mask: u64) -> u64 {
+    // Set the bits above the mask so that incrementing the reversed cursor
+    // carries into the masked bits.
+    (cursor | !mask)
+        .reverse_bits()
+        .wrapping_add(1)
+        .reverse_bits()
+}
+
+impl<K, V> Dict<K, V> {
+    pub fn new() -> Self {
+        Dict::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.used[0] + self.used[1]
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Number of buckets of the table, or of the table being rehashed to.
+    pub fn size(&self) -> usize {
+        if self.is_rehashing() {
+            self.tables[1].len()
+        } else {
+            self.tables[0].len()
+        }
+    }
+
+    /// Approximate bytes used by the table itself: its buckets and entries,
+    /// not counting what the keys and values own on the heap.
+    pub fn overhead(&self) -> usize {
+        let buckets = self.tables[0].len() + self.tables[1].len();
+        buckets * mem::size_of::<Vec<(K, V)>>() + self.len() * mem::size_of::<(K, V)>()
+    }
+
+    pub fn is_rehashing(&self) -> bool {
+        self.rehash_index.is_some()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.tables
+            .iter()
+            .flatten()
+            .flat_map(|bucket| bucket.iter().map(|(key, value)| (key, value)))
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.iter().map(|(key, _)| key)
+    }
+
+    pub fn clear(&mut self) {
+        *self = Dict {
+            hasher: self.hasher.clone(),
+            ..Dict::default()
+        };
+    }
+
+    /// Visit the entries of the bucket at `cursor` and return the cursor of the
+    /// next bucket to visit, 0 once the scan is complete.
+    ///
+    /// Cursors are incremented in the reverse binary order of their bits, the
+    /// high bits first. A bucket of a table of size 2^n becomes the buckets with
+    /// the same low n bits in a larger table, which the reversed increment only
+    /// moves past after visiting all of them, while the buckets of a smaller
+    /// table merge buckets that the scan either visited already or has yet to
+    /// reach. Either way no entry is missed, although some can repeat.
+    ///
+    /// While rehashing, the bucket of the smaller table is visited along with
+    /// every bucket of the larger table it expands to.
+    pub fn scan(&self, mut cursor: u64, mut visit: impl FnMut(&K, &V)) -> u64 {
+        if self.is_empty() {
+            return 0;
+        }
+        let mut visit_bucket = |bucket: &Vec<(K, V)>| {
+            for (key, value) in bucket {
+                visit(key, value);
+            }
+        };
+        if !self.is_rehashing() {
+            let mask = (self.tables[0].len() - 1) as u64;
+            visit_bucket(&self.tables[0][(cursor & mask) as usize]);
+            return next_cursor(cursor, mask);
+        }
+
+        let (small, large) = if self.tables[0].len() <= self.tables[1].len() {
+            (&self.tables[0], &self.tables[1])
+        } else {
+            (&self.tables[1], &self.tables[0])
+        };
+        let small_mask = (small.len() - 1) as u64;
+        let large_mask = (large.len() - 1) as u64;
+        visit_bucket(&small[(cursor & small_mask) as usize]);
+        loop {
+            visit_bucket(&large[(cursor & large_mask) as usize]);
+            cursor = next_cursor(cursor, large_mask);
+            // Stop once the bits the larger table has over the smaller one
+            // wrapped around, the increment then carried into the smaller mask.
+            if cursor & (small_mask ^ large_mask) == 0 {
+                return cursor;
+            }
+        }
+    }
+
+    /// Scan buckets from `cursor` until `count` entries were visited or the
+    /// scan completed, giving up after ten buckets per entry requested so that
+    /// a sparse table cannot make a single call slow.
+    pub fn scan_batch(&self, mut cursor: u64, count: usize, mut visit: impl FnMut(&K, &V)) -> u64 {
+        let mut visited = 0;
+        let mut max_iterations = count.saturating_mul(10);
+        loop {
+            cursor = self.scan(cursor, |key, value| {
+                visited += 1;
+                visit(key, value);
+            });
+            if cursor == 0 || max_iterations == 0 || visited >= count {
+                return cursor;
+            }
+            max_iterations -= 1;
+        }
+    }
+
+    /// A random entry, picked among a sample of entries since picking a random
+    /// bucket alone favours the entries of short chains.
+    pub fn random_entry(&self) -> Option<(&K, &V)> {
+        let sample = self.sample(DICT_FAIR_RANDOM_SAMPLES);
+        if sample.is_empty() {
+            return self.random_bucket_entry();
+        }
+        Some(sample[random_index(sample.len())])
+    }
+
+    /// A random entry of a random non empty bucket.
+    fn random_bucket_entry(&self) -> Option<(&K, &V)> {
+        if self.is_empty() {
+            return None;
+        }
+        let bucket = match self.rehash_index {
+            Some(rehash_index) => {
+                // Buckets of the first table below the rehash index are empty.
+                let sizes = [self.tables[0].len(), self.tables[1].len()];
+                loop {
+                    let index = rehash_index + random_index(sizes[0] + sizes[1] - rehash_index);
+                    let bucket = if index >= sizes[0] {
+                        &self.tables[1][index - sizes[0]]
+                    } else {
+                        &self.tables[0][index]
+                    };
+                    if !bucket.is_empty() {
+                        break bucket;
+                    }
+                }
+            }
+            None => loop {
+                let bucket = &self.tables[0][random_index(self.tables[0].len())];
+                if !bucket.is_empty() {
+                    break bucket;
+                }
+            },
+        };
+        let (key, value) = &bucket[random_index(bucket.len())];
+        Some((key, value))
+    }
+
+    /// Up to `count` entries taken from consecutive buckets starting at a
+    /// random one, which is much cheaper than `count` random picks but with no
+    /// guarantee that the entries are distinct or well distributed. Used to
+    /// find candidates for eviction and expiry.
+    pub fn sample(&self, count: usize) -> Vec<(&K, &V)> {
+        let count = count.min(self.len());
+        let mut sample = Vec::with_capacity(count);
+        if count == 0 {
+            return sample;
+        }
+        let tables = if self.is_rehashing() { 2 } else { 1 };
+        let max_mask = self.tables[..tables]
+            .iter()
+            .map(|table| table.len() - 1)
+            .max()
+            .unwrap();
+        let rehash_index = self.rehash_index.unwrap_or(0);
+        let mut index = random_u64() as usize & max_mask;
+        let mut empty_run = 0;
+        let mut max_steps = count * 10;
+        while sample.len() < count && max_steps > 0 {
+            max_steps -= 1;
+            for (table_index, table) in self.tables[..tables].iter().enumerate() {
+                // Buckets of the first table below the rehash index are empty.
+                if tables == 2 && table_index == 0 && index < rehash_index {
+                    if index >= self.tables[1].len() {
+                        index = rehash_index;
+                    } else {
+                        continue;
+                    }
+                }
+                let Some(bucket) = table.get(index) else {
+                    continue;
+                };
+                if bucket.is_empty() {
+                    empty_run += 1;
+                    // Jump elsewhere after a long run of empty buckets.
+                    if empty_run >= 5 && empty_run > count {
+                        index = random_u64() as usize & max_mask;
+                        empty_run = 0;
+                    }
+                    continue;
+                }
+                empty_run = 0;
+                for (key, value) in bucket {
+                    sample.push((key, value));
+                    if sample.len() == count {
+                        return sample;
+                    }
+                }
+            }
+            index = (index + 1) & max_mask;
+        }
+        sample
+    }
+}
+
+impl<K: Hash + Eq, V> Dict<K, V> {
+    fn bucket_index<Q: Hash + ?Sized>(&self, table: usize, key: &Q) -> usize {
+        (self.hasher.hash_one(key) as usize) & (self.tables[table].len() - 1)
+    }
+
+    /// The table, bucket and position in the bucket of `key`.
+    fn find<Q>(&self, key: &Q) -> Option<(usize, usize, usize)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if self.is_empty() {
+            return None;
+        }
+        let tables = if self.is_rehashing() { 2 } else { 1 };
+        (0..tables).find_map(|table| {
+            let index = self.bucket_index(table, key);
+            let position = self.tables[table][index]
+                .iter()
+                .position(|(k, _)| k.borrow() == key)?;
+            Some((table, index, position))
+        })
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let (table, index, position) = self.find(key)?;
+        Some(&self.tables[table][index][position].1)
+    }
+
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.rehash_step();
+        let (table, index, position) = self.find(key)?;
+        Some(&mut self.tables[table][index][position].1)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.find(key).is_some()
+    }
+
+    /// Insert or replace the value of `key`, returning the previous value.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        if let Some(existing) = self.get_mut(&key) {
+            return Some(std::mem::replace(existing, value));
+        }
+        self.expand_if_needed();
+        let table = self.is_rehashing() as usize;
+        let index = self.bucket_index(table, &key);
+        self.tables[table][index].push((key, value));
+        self.used[table] += 1;
+        None
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.remove_entry(key).map(|(_, value)| value)
+    }
+
+    pub fn remove_entry<Q>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.rehash_step();
+        let (table, index, position) = self.find(key)?;
+        let entry = self.tables[table][index].swap_remove(position);
+        self.used[table] -= 1;
+        self.shrink_if_needed();
+        Some(entry)
+    }
+
+    /// Migrate one bucket if a rehash is in progress, the share of the work
+    /// done by every write.
+    fn rehash_step(&mut self) {
+        if self.is_rehashing() {
+            self.rehash(1);
+        }
+    }
+
+    fn expand_if_needed(&mut self) {
+        if !self.is_rehashing() && self.len() >= self.tables[0].len() {
+            self.resize(self.len() + 1);
+        }
+    }
+
+    fn shrink_if_needed(&mut self) {
+        let size = self.tables[0].len();
+        if !self.is_rehashing() && size > DICT_INITIAL_SIZE && self.len() * DICT_MIN_FILL <= size {
+            self.resize(self.len());
+        }
+    }
+
+    /// Start rehashing to a table of the smallest power of two size holding
+    /// `len` entries. An empty table is replaced right away.
+    fn resize(&mut self, len: usize) {
+        let size = len.next_power_of_two().max(DICT_INITIAL_SIZE);
+        if size == self.tables[0].len() {
+            return;
+        }
+        if self.used[0] == 0 {
+            self.tables[0] = new_table(size);
+            return;
+        }
+        self.tables[1] = new_table(size);
+        self.rehash_index = Some(0);
+    }
+
+    /// Migrate up to `buckets` non empty buckets to the new table, returning
+    /// whether the rehash still has work left. Visits at most ten empty
+    /// buckets per bucket requested so that a sparse table cannot make a single
+    /// call slow.
+    pub fn rehash(&mut self, buckets: usize) -> bool {
+        let Some(mut index) = self.rehash_index else {
+            return false;
+        };
+        let mut empty_visits = buckets.saturating_mul(10);
+        for _ in 0..buckets {
+            if self.used[0] == 0 {
+                break;
+            }
+            // Entries are left in the first table, so a non empty bucket is
+            // ahead of the index.
+            while self.tables[0][index].is_empty() {
+                index += 1;
+                empty_visits -= 1;
+                if empty_visits == 0 {
+                    self.rehash_index = Some(index);
+                    return true;
+                }
+            }
+            for (key, value) in std::mem::take(&mut self.tables[0][index]) {
+                let new_index = self.bucket_index(1, &key);
+                self.tables[1][new_index].push((key, value));
+                self.used[0] -= 1;
+                self.used[1] += 1;
+            }
+            index += 1;
+        }
+        if self.used[0] == 0 {
+            self.tables[0] = std::mem::take(&mut self.tables[1]);
+            self.used = [self.used[1], 0];
+            self.rehash_index = None;
+            return false;
+        }
+        self.rehash_index = Some(index);
+        true
+    }
+
+    /// Rehash in batches of buckets for about `budget`, returning whether the
+    /// rehash still has work left.
+    pub fn rehash_for(&mut self, budget: Duration) -> bool {
+        let start = Instant::now();
+        while self.rehash(100) {
+            if start.elapsed() >= budget {
+                return true;
+            }
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    /// Scan `dict` to completion, calling `between` after every step.
+    fn scan_all(
+        dict: &mut Dict<u64, ()>,
+        mut between: impl FnMut(&mut Dict<u64, ()>, usize),
+    ) -> HashSet<u64> {
+        let mut seen = HashSet::new();
+        let mut cursor = 0;
+        for step in 0.. {
+            cursor = dict.scan(cursor, |key, _| {
+                seen.insert(*key);
+            });
+            if cursor == 0 {
+                break;
+            }
+            between(dict, step);
+        }
+        seen
+    }
+
+    #[test]
+    fn test_insert_get_remove() {
+        let mut dict = Dict::new();
+        assert_eq!(dict.insert("a", 1), None);
+        assert_eq!(dict.insert("a", 2), Some(1));
+        assert_eq!(dict.get("a"), Some(&2));
+        assert_eq!(dict.len(), 1);
+        for i in 0..100 {
+            dict.insert(Box::leak(i.to_string().into_boxed_str()), i);
+        }
+        assert_eq!(dict.size(), 128);
+        for i in 0..100 {
+            assert_eq!(dict.remove(i.to_string().as_str()), Some(i));
+            while dict.rehash(100) {}
+        }
+        assert_eq!(dict.size(), 4);
+        assert_eq!(dict.remove("a"), Some(2));
+        assert!(dict.is_empty());
+    }
+
+    #[test]
+    fn test_scan_visits_every_entry() {
+        let mut dict: Dict<u64, ()> = (0..1000).map(|i| (i, ())).collect();
+        assert_eq!(scan_all(&mut dict, |_, _| {}).len(), 1000);
+        assert_eq!(Dict::<u64, ()>::new().scan(0, |_, _| {}), 0);
+    }
+
+    #[test]
+    fn test_scan_survives_resizes() {
+        // Grow the table while scanning.
+        let mut dict: Dict<u64, ()> = (0..100).map(|i| (i, ())).collect();
+        let seen = scan_all(&mut dict, |dict, step| {
+            if step < 20 {
+                for i in 0..50 {
+                    dict.insert(1000 + step as u64 * 50 + i, ());
+                }
+            }
+        });
+        assert!((0..100).all(|i| seen.contains(&i)));
+
+        // Shrink it while scanning.
+        let mut dict: Dict<u64, ()> = (0..10_000).map(|i| (i, ())).collect();
+        let seen = scan_all(&mut dict, |dict, step| {
+            for i in 0..200 {
+                dict.remove(&(100 + step as u64 * 200 + i));
+            }
+        });
+        assert!((0..100).all(|i| seen.contains(&i)));
+    }
+
+    #[test]
+    fn test_incremental_rehash() {
+        let mut dict: Dict<u64, u64> = (0..1000).map(|i| (i, i)).collect();
+        while dict.rehash(100) {}
+        assert_eq!(dict.size(), 1024);
+
+        // Growing past the size starts a rehash that every write advances.
+        for i in 1000..1025 {
+            dict.insert(i, i);
+        }
+        assert!(dict.is_rehashing());
+        assert_eq!(dict.size(), 2048);
+        assert!((0..1025).all(|i| dict.get(&i) == Some(&i)));
+        assert_eq!(dict.iter().count(), 1025);
+
+        // A scan in the middle of the rehash still sees everything.
+        let mut keys: Dict<u64, ()> = dict.keys().map(|key| (*key, ())).collect();
+        while keys.rehash(100) {}
+        for i in 0..1100 {
+            keys.insert(10_000 + i, ());
+        }
+        assert!(keys.is_rehashing());
+        let seen = scan_all(&mut keys, |_, _| {});
+        assert!((0..1025).all(|i| seen.contains(&i)));
+
+        assert!(!dict.rehash_for(Duration::from_secs(1)));
+        assert!((0..1025).all(|i| dict.get(&i) == Some(&i)));
+        assert_eq!(dict.len(), 1025);
+    }
+
+    #[test]
+    fn test_random_sampling() {
+        let mut dict: Dict<u64, ()> = Dict::new();
+        assert!(dict.random_entry().is_none());
+        assert!(dict.sample(5).is_empty());
+        for i in 0..100 {
+            dict.insert(i, ());
+        }
+        let seen: HashSet<u64> = (0..1000).map(|_| *dict.random_entry().unwrap().0).collect();
+        assert!(seen.len() > 50);
+        assert_eq!(dict.sample(10).len(), 10);
+        assert_eq!(dict.sample(1000).len(), 100);
+    }
+}
@@ -0,0 +1,439 @@
+//! Geohash encoding as done by redis: longitude and latitude are interleaved
+//! into a 52 bit integer, which is stored as the score of a sorted set member.
+
+/// Number of bits used per coordinate.
+pub const GEO_STEP_MAX: u8 = 26;
+pub const GEO_LAT_MIN: f64 = -85.05112878;
+pub const GEO_LAT_MAX: f64 = 85.05112878;
+pub const GEO_LONG_MIN: f64 = -180.0;
+pub const GEO_LONG_MAX: f64 = 180.0;
+/// Earth's quadratic mean radius for WGS-84.
+const EARTH_RADIUS_IN_METERS: f64 = 6372797.560856;
+const MERCATOR_MAX: f64 = 20037726.37;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Range {
+    pub min: f64,
+    pub max: f64,
+}
+
+/// The ranges covered by the internal encoding, which stops at the latitudes
+/// of the web mercator projection.
+pub const LONG_RANGE: Range = Range {
+    min: GEO_LONG_MIN,
+    max: GEO_LONG_MAX,
+};
+pub const LAT_RANGE: Range = Range {
+    min: GEO_LAT_MIN,
+    max: GEO_LAT_MAX,
+};
+/// The latitude range of standard geohash strings.
+pub const STANDARD_LAT_RANGE: Range = Range {
+    min: -90.0,
+    max: 90.0,
+};
+
+/// A geohash of `step` bits per coordinate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct HashBits {
+    pub bits: u64,
+    pub step: u8,
+}
+
+impl HashBits {
+    fn is_zero(&self) -> bool {
+        self.bits == 0 && self.step == 0
+    }
+
+    /// The sorted set scores `min..max` of the points within this box.
+    pub fn score_range(&self) -> (f64, f64) {
+        let shift = 52 - self.step as u32 * 2;
+        (
+            (self.bits << shift) as f64,
+            ((self.bits + 1) << shift) as f64,
+        )
+    }
+
+    fn move_x(&mut self, forward: bool) {
+        let x = self.bits & 0xaaaaaaaaaaaaaaaa;
+        let y = self.bits & 0x5555555555555555;
+        let zz = 0x5555555555555555u64 >> (64 - self.step as u32 * 2);
+        let x = if forward {
+            x.wrapping_add(zz + 1)
+        } else {
+            (x | zz).wrapping_sub(zz + 1)
+        };
+        self.bits = (x & (0xaaaaaaaaaaaaaaaa >> (64 - self.step as u32 * 2))) | y;
+    }
+
+    fn move_y(&mut self, forward: bool) {
+        let x = self.bits & 0xaaaaaaaaaaaaaaaa;
+        let y = self.bits & 0x5555555555555555;
+        let zz = 0xaaaaaaaaaaaaaaaau64 >> (64 - self.step as u32 * 2);
+        let y = if forward {
+            y.wrapping_add(zz + 1)
+        } else {
+            (y | zz).wrapping_sub(zz + 1)
+        };
+        self.bits = x | (y & (0x5555555555555555 >> (64 - self.step as u32 * 2)));
+    }
+
+    /// Move by `dx` boxes east and `dy` boxes north, each being -1, 0 or 1.
+    fn moved(mut self, dx: i8, dy: i8) -> Self {
+        if dx != 0 {
+            self.move_x(dx > 0);
+        }
+        if dy != 0 {
+            self.move_y(dy > 0);
+        }
+        self
+    }
+}
+
+/// The area covered by a geohash.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Area {
+    pub longitude: Range,
+    pub latitude: Range,
+}
+
+/// Spread the 32 bits of `x` over the even bits and those of `y` over the odd ones.
+fn interleave(x: u32, y: u32) -> u64 {
+    const B: [u64; 5] = [
+        0x5555555555555555,
+        0x3333333333333333,
+        0x0F0F0F0F0F0F0F0F,
+        0x00FF00FF00FF00FF,
+        0x0000FFFF0000FFFF,
+    ];
+    const S: [u32; 5] = [1, 2, 4, 8, 16];
+    let spread = |value: u32| {
+        let mut value = value as u64;
+        for i in (0..5).rev() {
+            value = (value | (value << S[i])) & B[i];
+        }
+        value
+    };
+    spread(x) | (spread(y) << 1)
+}
+
+/// The inverse of `interleave`: even bits in the low half, odd ones in the high half.
+fn deinterleave(interleaved: u64) -> u64 {
+    const B: [u64; 6] = [
+        0x5555555555555555,
+        0x3333333333333333,
+        0x0F0F0F0F0F0F0F0F,
+        0x00FF00FF00FF00FF,
+        0x0000FFFF0000FFFF,
+        0x00000000FFFFFFFF,
+    ];
+    const S: [u32; 6] = [0, 1, 2, 4, 8, 16];
+    let squash = |mut value: u64| {
+        value &= B[0];
+        for i in 1..6 {
+            value = (value | (value >> S[i])) & B[i];
+        }
+        value
+    };
+    squash(interleaved) | (squash(interleaved >> 1) << 32)
+}
+
+/// Whether the coordinates can be indexed.
+pub fn is_valid(longitude: f64, latitude: f64) -> bool {
+    (GEO_LONG_MIN..=GEO_LONG_MAX).contains(&longitude)
+        && (GEO_LAT_MIN..=GEO_LAT_MAX).contains(&latitude)
+}
+
+pub fn encode(
+    long_range: Range,
+    lat_range: Range,
+    longitude: f64,
+    latitude: f64,
+    step: u8,
+) -> Option<HashBits> {
+    if !is_valid(longitude, latitude)
+        || latitude < lat_range.min
+        || latitude > lat_range.max
+        || longitude < long_range.min
+        || longitude > long_range.max
+    {
+        return None;
+    }
+    let scale = (1u64 << step) as f64;
+    let lat_offset = (latitude - lat_range.min) / (lat_range.max - lat_range.min) * scale;
+    let long_offset = (longitude - long_range.min) / (long_range.max - long_range.min) * scale;
+    Some(HashBits {
+        bits: interleave(lat_offset as u32, long_offset as u32),
+        step,
+    })
+}
+
+/// The 52 bit hash stored as the score of a member at these coordinates.
+pub fn encode_wgs84(longitude: f64, latitude: f64) -> Option<u64> {
+    encode(LONG_RANGE, LAT_RANGE, longitude, latitude, GEO_STEP_MAX).map(|hash| hash.bits)
+}
+
+pub fn decode(long_range: Range, lat_range: Range, hash: HashBits) -> Area {
+    let separated = deinterleave(hash.bits);
+    let lat_bits = (separated & 0xffffffff) as f64;
+    let long_bits = (separated >> 32) as f64;
+    let scale = (1u64 << hash.step) as f64;
+    let lat_scale = lat_range.max - lat_range.min;
+    let long_scale = long_range.max - long_range.min;
+    Area {
+        latitude: Range {
+            min: lat_range.min + (lat_bits / scale) * lat_scale,
+            max: lat_range.min + ((lat_bits + 1.0) / scale) * lat_scale,
+        },
+        longitude: Range {
+            min: long_range.min + (long_bits / scale) * long_scale,
+            max: long_range.min + ((long_bits + 1.0) / scale) * long_scale,
+        },
+    }
+}
+
+/// The (longitude, latitude) of the center of the area of a stored score.
+pub fn decode_score(score: f64) -> (f64, f64) {
+    let hash = HashBits {
+        bits: score as u64,
+        step: GEO_STEP_MAX,
+    };
+    let area = decode(LONG_RANGE, LAT_RANGE, hash);
+    let longitude =
+        ((area.longitude.min + area.longitude.max) / 2.0).clamp(GEO_LONG_MIN, GEO_LONG_MAX);
+    let latitude = ((area.latitude.min + area.latitude.max) / 2.0).clamp(GEO_LAT_MIN, GEO_LAT_MAX);
+    (longitude, latitude)
+}
+
+/// The standard 11 character geohash string of a stored score.
+pub fn geohash_string(score: f64) -> String {
+    const ALPHABET: &[u8] = b"0123456789bcdefghjkmnpqrstuvwxyz";
+    let (longitude, latitude) = decode_score(score);
+    let bits = encode(
+        LONG_RANGE,
+        STANDARD_LAT_RANGE,
+        longitude,
+        latitude,
+        GEO_STEP_MAX,
+    )
+    .map_or(0, |hash| hash.bits);
+    (0..11)
+        .map(|i| {
+            // Only 52 bits are available, the last character is always '0'.
+            let index = if i == 10 {
+                0
+            } else {
+                (bits >> (52 - (i + 1) * 5)) & 0x1f
+            };
+            ALPHABET[index as usize] as char
+        })
+        .collect()
+}
+
+fn deg_rad(degrees: f64) -> f64 {
+    degrees * (std::f64::consts::PI / 180.0)
+}
+
+fn rad_deg(radians: f64) -> f64 {
+    radians / (std::f64::consts::PI / 180.0)
+}
+
+pub fn lat_distance(lat1: f64, lat2: f64) -> f64 {
+    EARTH_RADIUS_IN_METERS * (deg_rad(lat2) - deg_rad(lat1)).abs()
+}
+
+/// Great circle distance in meters, using the haversine formula.
+pub fn distance(lon1: f64, lat1: f64, lon2: f64, lat2: f64) -> f64 {
+    let v = ((deg_rad(lon2) - deg_rad(lon1)) / 2.0).sin();
+    if v == 0.0 {
+        return lat_distance(lat1, lat2);
+    }
+    let lat1 = deg_rad(lat1);
+    let lat2 = deg_rad(lat2);
+    let u = ((lat2 - lat1) / 2.0).sin();
+    let a = u * u + lat1.cos() * lat2.cos() * v * v;
+    2.0 * EARTH_RADIUS_IN_METERS * a.sqrt().asin()
+}
+
+/// The area to search around a center, in meters.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Shape {
+    Radius(f64),
+    Box { width: f64, height: f64 },
+}
+
+/// A search area around (longitude, latitude).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Search {
+    pub longitude: f64,
+    pub latitude: f64,
+    pub shape: Shape,
+}
+
+impl Search {
+    /// The distance from the center to a point, if the point is within the shape.
+    pub fn distance_if_within(&self, longitude: f64, latitude: f64) -> Option<f64> {
+        match self.shape {
+            Shape::Radius(radius) => {
+                let distance = distance(self.longitude, self.latitude, longitude, latitude);
+                (distance <= radius).then_some(distance)
+            }
+            Shape::Box { width, height } => {
+                if lat_distance(latitude, self.latitude) > height / 2.0 {
+                    return None;
+                }
+                let long_distance =
+                    distance(longitude, self.latitude, self.longitude, self.latitude);
+                if long_distance > width / 2.0 {
+                    return None;
+                }
+                Some(distance(self.longitude, self.latitude, longitude, latitude))
+            }
+        }
+    }
+
+    /// The (min longitude, min latitude, max longitude, max latitude) of the
+    /// smallest box around the shape.
+    fn bounding_box(&self) -> (f64, f64, f64, f64) {
+        let (width, height) = match self.shape {
+            Shape::Radius(radius) => (radius, radius),
+            Shape::Box { width, height } => (width / 2.0, height / 2.0),
+        };
+        let lat_delta = rad_deg(height / EARTH_RADIUS_IN_METERS);
+        let long_delta_top =
+            rad_deg(width / EARTH_RADIUS_IN_METERS / deg_rad(self.latitude + lat_delta).cos());
+        let long_delta_bottom =
+            rad_deg(width / EARTH_RADIUS_IN_METERS / deg_rad(self.latitude - lat_delta).cos());
+        // The hemispheres are mirrored, so the widest edge is on opposite sides.
+        let long_delta = if self.latitude < 0.0 {
+            long_delta_bottom
+        } else {
+            long_delta_top
+        };
+        (
+            self.longitude - long_delta,
+            self.latitude - lat_delta,
+            self.longitude + long_delta,
+            self.latitude + lat_delta,
+        )
+    }
+
+    /// The boxes to scan to find every point of the shape: the box around the
+    /// center first, then its neighbors north, south, east, west, north east,
+    /// north west, south east and south west. Neighbors that cannot overlap
+    /// the shape are left out.
+    pub fn boxes(&self) -> [Option<HashBits>; 9] {
+        let (min_lon, min_lat, max_lon, max_lat) = self.bounding_box();
+        let radius = match self.shape {
+            Shape::Radius(radius) => radius,
+            Shape::Box { width, height } => ((width / 2.0).powi(2) + (height / 2.0).powi(2)).sqrt(),
+        };
+        let mut step = estimate_steps(radius, self.latitude);
+        let encode = |step| {
+            encode(LONG_RANGE, LAT_RANGE, self.longitude, self.latitude, step).unwrap_or_default()
+        };
+        let mut hash = encode(step);
+
+        // Near the edges of the center box the estimated step may be too
+        // large for the neighbors to cover the whole shape.
+        let area = |dx, dy, hash: HashBits| decode(LONG_RANGE, LAT_RANGE, hash.moved(dx, dy));
+        let decrease_step = area(0, 1, hash).latitude.max < max_lat
+            || area(0, -1, hash).latitude.min > min_lat
+            || area(1, 0, hash).longitude.max < max_lon
+            || area(-1, 0, hash).longitude.min > min_lon;
+        if step > 1 && decrease_step {
+            step -= 1;
+            hash = encode(step);
+        }
+
+        let center = decode(LONG_RANGE, LAT_RANGE, hash);
+        let useful = |dx: i8, dy: i8| {
+            step < 2
+                || !((dy < 0 && center.latitude.min < min_lat)
+                    || (dy > 0 && center.latitude.max > max_lat)
+                    || (dx < 0 && center.longitude.min < min_lon)
+                    || (dx > 0 && center.longitude.max > max_lon))
+        };
+        [
+            (0, 0),
+            (0, 1),
+            (0, -1),
+            (1, 0),
+            (-1, 0),
+            (1, 1),
+            (-1, 1),
+            (1, -1),
+            (-1, -1),
+        ]
+        .map(|(dx, dy)| {
+            let neighbor = hash.moved(dx, dy);
+            (useful(dx, dy) && !neighbor.is_zero()).then_some(neighbor)
+        })
+    }
+}
+
+/// The number of bits per coordinate giving boxes about the size of the radius.
+fn estimate_steps(mut range_meters: f64, latitude: f64) -> u8 {
+    if range_meters == 0.0 {
+        return GEO_STEP_MAX;
+    }
+    let mut step: i32 = 1;
+    while range_meters < MERCATOR_MAX {
+        range_meters *= 2.0;
+        step += 1;
+    }
+    // Make sure the range is included in most of the base cases.
+    step -= 2;
+    // Boxes get narrower towards the poles.
+    if !(-66.0..=66.0).contains(&latitude) {
+        step -= 1;
+        if !(-80.0..=80.0).contains(&latitude) {
+            step -= 1;
+        }
+    }
+    step.clamp(1, GEO_STEP_MAX as i32) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_matches_redis() {
+        assert_eq!(encode_wgs84(13.361389, 38.115556), Some(3479099956230698));
+        assert_eq!(encode_wgs84(15.087269, 37.502669), Some(3479447370796909));
+        assert_eq!(encode_wgs84(0.0, 86.0), None);
+        let (longitude, latitude) = decode_score(3479099956230698.0);
+        assert_eq!(format!("{:.17}", longitude), "13.36138933897018433");
+        assert_eq!(format!("{:.17}", latitude), "38.11555639549629859");
+        assert_eq!(geohash_string(3479099956230698.0), "sqc8b49rny0");
+        assert_eq!(geohash_string(3479447370796909.0), "sqdtr74hyu0");
+    }
+
+    #[test]
+    fn test_distance_and_boxes() {
+        let (lon1, lat1) = decode_score(3479099956230698.0);
+        let (lon2, lat2) = decode_score(3479447370796909.0);
+        assert_eq!(
+            format!("{:.4}", distance(lon1, lat1, lon2, lat2)),
+            "166274.1516"
+        );
+
+        let search = Search {
+            longitude: 15.0,
+            latitude: 37.0,
+            shape: Shape::Radius(200_000.0),
+        };
+        let boxes = search.boxes();
+        let center = boxes[0].unwrap();
+        let (min, max) = center.score_range();
+        assert!(min < max);
+        assert_eq!(boxes.iter().flatten().count(), 4);
+        assert_eq!(center.moved(1, 0).moved(-1, 0), center);
+        assert_eq!(interleave(0xffff, 0), 0x55555555);
+        assert_eq!(
+            deinterleave(interleave(12345, 67890)),
+            (67890 << 32) | 12345
+        );
+    }
+}
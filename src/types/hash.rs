@@ -0,0 +1,310 @@
+use std::collections::{BTreeSet, HashMap};
+
+use bytes::Bytes;
+
+use super::dict::Dict;
+use super::encoding_limits;
+use super::listpack::ListPack;
+
+#[derive(Debug, Clone)]
+enum Entries {
+    /// Small hashes are kept as a listpack of fields each followed by its
+    /// value and searched linearly, which is both smaller and faster than a
+    /// hash table at this size.
+    ListPack(ListPack),
+    Table(Dict<Bytes, Bytes>),
+}
+
+/// The pairs of a listpack of fields and values.
+fn pairs(listpack: &ListPack) -> impl Iterator<Item = (&[u8], &[u8])> {
+    let mut entries = listpack.iter();
+    std::iter::from_fn(move || Some((entries.next()?, entries.next()?)))
+}
+
+/// Expiration deadlines (unix time in milliseconds) of the fields that have one,
+/// indexed both ways so that the next field to expire is found in O(log n).
+#[derive(Debug, Clone, Default)]
+struct FieldExpires {
+    by_field: HashMap<Bytes, i64>,
+    by_time: BTreeSet<(i64, Bytes)>,
+}
+
+/// A hash value, converting itself from the compact encoding to a real hash
+/// table once it outgrows the size thresholds. It never converts back.
+#[derive(Debug, Clone)]
+pub struct Hash {
+    entries: Entries,
+    /// Only allocated while at least one field has a TTL.
+    expires: Option<Box<FieldExpires>>,
+}
+
+impl Default for Hash {
+    fn default() -> Self {
+        Hash {
+            entries: Entries::ListPack(ListPack::new()),
+            expires: None,
+        }
+    }
+}
+
+impl Hash {
+    pub fn new() -> Self {
+        Hash::default()
+    }
+
+    pub fn len(&self) -> usize {
+        match &self.entries {
+            Entries::ListPack(listpack) => listpack.len() / 2,
+            Entries::Table(table) => table.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Name of the encoding in use, as reported by OBJECT ENCODING.
+    pub fn encoding(&self) -> &'static str {
+        match &self.entries {
+            Entries::ListPack(_) => "listpack",
+            Entries::Table(_) => "hashtable",
+        }
+    }
+
+    pub fn get(&self, field: &[u8]) -> Option<&[u8]> {
+        match &self.entries {
+            Entries::ListPack(listpack) => pairs(listpack)
+                .find(|(f, _)| *f == field)
+                .map(|(_, value)| value),
+            Entries::Table(table) => table.get(field).map(|value| value.as_ref()),
+        }
+    }
+
+    /// Index of `field` among the pairs of a listpack.
+    fn listpack_position(listpack: &ListPack, field: &[u8]) -> Option<usize> {
+        pairs(listpack).position(|(f, _)| f == field)
+    }
+
+    pub fn contains(&self, field: &[u8]) -> bool {
+        self.get(field).is_some()
+    }
+
+    /// Set `field` to `value`, returning true if the field is new. Overwriting a
+    /// field clears its TTL.
+    pub fn insert(&mut self, field: Bytes, value: Bytes) -> bool {
+        self.clear_field_expire_time(&field);
+        self.insert_keep_ttl(field, value)
+    }
+
+    /// Set `field` to `value` keeping any TTL it has, returning true if the field is new.
+    pub fn insert_keep_ttl(&mut self, field: Bytes, value: Bytes) -> bool {
+        if let Entries::ListPack(listpack) = &self.entries {
+            let limits = encoding_limits();
+            let too_long = field.len() > limits.hash_max_listpack_value
+                || value.len() > limits.hash_max_listpack_value;
+            let too_many = listpack.len() / 2 >= limits.hash_max_listpack_entries
+                && Hash::listpack_position(listpack, &field).is_none();
+            if too_long || too_many {
+                self.convert_to_table();
+            }
+        }
+        match &mut self.entries {
+            Entries::ListPack(listpack) => match Hash::listpack_position(listpack, &field) {
+                Some(position) => {
+                    listpack.replace(2 * position + 1, &value);
+                    false
+                }
+                None => {
+                    listpack.push_back(&field);
+                    listpack.push_back(&value);
+                    true
+                }
+            },
+            Entries::Table(table) => table.insert(field, value).is_none(),
+        }
+    }
+
+    pub fn remove(&mut self, field: &[u8]) -> Option<Bytes> {
+        self.clear_field_expire_time(field);
+        match &mut self.entries {
+            Entries::ListPack(listpack) => {
+                let position = Hash::listpack_position(listpack, field)?;
+                let value = listpack.remove(2 * position + 1);
+                listpack.remove(2 * position);
+                value
+            }
+            Entries::Table(table) => table.remove(field),
+        }
+    }
+
+    /// The unix time in milliseconds at which `field` expires, if it has a TTL.
+    pub fn field_expire_time(&self, field: &[u8]) -> Option<i64> {
+        self.expires
+            .as_ref()
+            .and_then(|expires| expires.by_field.get(field).copied())
+    }
+
+    /// Set the expiration time of an existing field.
+    pub fn set_field_expire_time(&mut self, field: &Bytes, when: i64) {
+        self.clear_field_expire_time(field);
+        let expires = self.expires.get_or_insert_with(Default::default);
+        expires.by_field.insert(field.clone(), when);
+        expires.by_time.insert((when, field.clone()));
+    }
+
+    /// Remove the TTL of `field`, returning false if it had none.
+    pub fn clear_field_expire_time(&mut self, field: &[u8]) -> bool {
+        let Some(expires) = self.expires.as_mut() else {
+            return false;
+        };
+        let Some((field, when)) = expires.by_field.remove_entry(field) else {
+            return false;
+        };
+        expires.by_time.remove(&(when, field));
+        if expires.by_field.is_empty() {
+            self.expires = None;
+        }
+        true
+    }
+
+    /// The earliest expiration time among the fields, if any field has a TTL.
+    pub fn next_field_expire_time(&self) -> Option<i64> {
+        self.expires
+            .as_ref()
+            .and_then(|expires| expires.by_time.first().map(|(when, _)| *when))
+    }
+
+    /// Delete every field whose expiration time is at or before `now`,
+    /// returning the number of fields deleted.
+    pub fn expire_fields(&mut self, now: i64) -> usize {
+        let mut expired = 0;
+        while let Some(when) = self.next_field_expire_time() {
+            if when > now {
+                break;
+            }
+            let expires = self.expires.as_mut().unwrap();
+            let (_, field) = expires.by_time.pop_first().unwrap();
+            expires.by_field.remove(&field);
+            if expires.by_field.is_empty() {
+                self.expires = None;
+            }
+            self.remove(&field);
+            expired += 1;
+        }
+        expired
+    }
+
+    fn convert_to_table(&mut self) {
+        if let Entries::ListPack(listpack) = &self.entries {
+            let table = pairs(listpack)
+                .map(|(field, value)| {
+                    (Bytes::copy_from_slice(field), Bytes::copy_from_slice(value))
+                })
+                .collect();
+            self.entries = Entries::Table(table);
+        }
+    }
+
+    /// Visit a batch of about `count` fields starting at `cursor`, returning the
+    /// cursor to continue from, 0 once done. The compact encoding is visited in
+    /// one go.
+    pub fn scan(&self, cursor: u64, count: usize, mut visit: impl FnMut(&[u8], &[u8])) -> u64 {
+        match &self.entries {
+            Entries::ListPack(listpack) => {
+                for (field, value) in pairs(listpack) {
+                    visit(field, value);
+                }
+                0
+            }
+            Entries::Table(table) => {
+                table.scan_batch(cursor, count, |field, value| visit(field, value))
+            }
+        }
+    }
+
+    pub fn iter(&self) -> Box<dyn Iterator<Item = (&[u8], &[u8])> + '_> {
+        match &self.entries {
+            Entries::ListPack(listpack) => Box::new(pairs(listpack)),
+            Entries::Table(table) => Box::new(
+                table
+                    .iter()
+                    .map(|(field, value)| (field.as_ref(), value.as_ref())),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::EncodingLimits;
+
+    #[test]
+    fn test_insert_get_remove() {
+        let mut hash = Hash::new();
+        assert!(hash.insert(Bytes::from("f"), Bytes::from("1")));
+        assert!(!hash.insert(Bytes::from("f"), Bytes::from("2")));
+        assert_eq!(hash.get(b"f"), Some(&b"2"[..]));
+        assert_eq!(hash.len(), 1);
+        assert_eq!(hash.remove(b"f"), Some(Bytes::from("2")));
+        assert!(hash.is_empty());
+    }
+
+    #[test]
+    fn test_converts_on_entry_count() {
+        let mut hash = Hash::new();
+        for i in 0..EncodingLimits::DEFAULT.hash_max_listpack_entries {
+            hash.insert(Bytes::from(i.to_string()), Bytes::from("v"));
+        }
+        assert_eq!(hash.encoding(), "listpack");
+        hash.insert(Bytes::from("one more"), Bytes::from("v"));
+        assert_eq!(hash.encoding(), "hashtable");
+        assert_eq!(
+            hash.len(),
+            EncodingLimits::DEFAULT.hash_max_listpack_entries + 1
+        );
+        assert_eq!(hash.get(b"7"), Some(&b"v"[..]));
+    }
+
+    #[test]
+    fn test_converts_on_value_length() {
+        let mut hash = Hash::new();
+        hash.insert(Bytes::from("f"), Bytes::from("v"));
+        hash.insert(
+            Bytes::from("big"),
+            Bytes::from(vec![
+                b'x';
+                EncodingLimits::DEFAULT.hash_max_listpack_value + 1
+            ]),
+        );
+        assert_eq!(hash.encoding(), "hashtable");
+        assert_eq!(hash.iter().count(), 2);
+    }
+
+    #[test]
+    fn test_field_expiration() {
+        let mut hash = Hash::new();
+        hash.insert(Bytes::from("a"), Bytes::from("1"));
+        hash.insert(Bytes::from("b"), Bytes::from("2"));
+        hash.insert(Bytes::from("c"), Bytes::from("3"));
+        hash.set_field_expire_time(&Bytes::from("a"), 200);
+        hash.set_field_expire_time(&Bytes::from("b"), 100);
+        hash.set_field_expire_time(&Bytes::from("b"), 300);
+        assert_eq!(hash.next_field_expire_time(), Some(200));
+        assert_eq!(hash.expire_fields(250), 1);
+        assert!(!hash.contains(b"a"));
+        assert_eq!(hash.field_expire_time(b"b"), Some(300));
+
+        hash.insert(Bytes::from("b"), Bytes::from("overwritten"));
+        assert_eq!(hash.field_expire_time(b"b"), None);
+        assert_eq!(hash.next_field_expire_time(), None);
+
+        hash.set_field_expire_time(&Bytes::from("c"), 400);
+        hash.insert_keep_ttl(Bytes::from("c"), Bytes::from("4"));
+        assert_eq!(hash.field_expire_time(b"c"), Some(400));
+        assert!(hash.clear_field_expire_time(b"c"));
+        assert!(!hash.clear_field_expire_time(b"c"));
+        assert_eq!(hash.expire_fields(i64::MAX), 0);
+        assert_eq!(hash.len(), 2);
+    }
+}
@@ -0,0 +1,667 @@
+//! HyperLogLog with the exact byte layout redis uses, so values can be moved
+//! between servers with GET and SET.
+//!
+//! A value starts with a 16 byte header: the "HYLL" magic, the encoding, three
+//! unused bytes and the cached cardinality as a little endian u64 whose most
+//! significant bit flags the cache as stale. The registers follow, either
+//! dense (16384 packed 6 bit registers) or sparse (run length opcodes):
+//!
+//! * ZERO `00xxxxxx`: 1 to 64 zero registers.
+//! * XZERO `01xxxxxx yyyyyyyy`: 1 to 16384 zero registers.
+//! * VAL `1vvvvvxx`: 1 to 4 registers set to a value of 1 to 32.
+
+/// Number of bits of the hash used to pick a register.
+const HLL_P: u32 = 14;
+/// Number of bits left to count the run of zeros in.
+const HLL_Q: usize = 64 - HLL_P as usize;
+pub const HLL_REGISTERS: usize = 1 << HLL_P;
+const HLL_P_MASK: u64 = HLL_REGISTERS as u64 - 1;
+const HLL_BITS: usize = 6;
+const HLL_REGISTER_MAX: u8 = (1 << HLL_BITS) - 1;
+const HLL_HDR_SIZE: usize = 16;
+pub const HLL_DENSE_SIZE: usize = HLL_HDR_SIZE + (HLL_REGISTERS * HLL_BITS).div_ceil(8);
+const HLL_ALPHA_INF: f64 = 0.721_347_520_444_481_7;
+const HLL_DENSE: u8 = 0;
+const HLL_SPARSE: u8 = 1;
+
+const HLL_SPARSE_VAL_MAX_VALUE: u8 = 32;
+const HLL_SPARSE_VAL_MAX_LEN: usize = 4;
+const HLL_SPARSE_ZERO_MAX_LEN: usize = 64;
+const HLL_SPARSE_XZERO_MAX_LEN: usize = 16384;
+/// A sparse value growing past this many bytes is converted to dense.
+pub const HLL_SPARSE_MAX_BYTES: usize = 3000;
+
+const MURMUR_SEED: u64 = 0xadc8_3b19;
+
+/// Raised when the registers of a value do not add up, which redis reports
+/// as a corrupted object.
+#[derive(Debug, PartialEq, Eq)]
+pub struct InvalidHll;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Dense,
+    Sparse,
+}
+
+/// One opcode of the sparse representation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Opcode {
+    Zero(usize),
+    XZero(usize),
+    Val(u8, usize),
+}
+
+impl Opcode {
+    fn read(bytes: &[u8], pos: usize) -> Opcode {
+        let byte = bytes[pos];
+        if byte & 0x80 != 0 {
+            Opcode::Val(((byte >> 2) & 0x1f) + 1, (byte & 0x03) as usize + 1)
+        } else if byte & 0x40 != 0 {
+            let low = bytes.get(pos + 1).copied().unwrap_or(0);
+            Opcode::XZero((((byte & 0x3f) as usize) << 8 | low as usize) + 1)
+        } else {
+            Opcode::Zero((byte & 0x3f) as usize + 1)
+        }
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Opcode::XZero(_) => 2,
+            Opcode::Zero(_) | Opcode::Val(..) => 1,
+        }
+    }
+
+    fn span(self) -> usize {
+        match self {
+            Opcode::Zero(len) | Opcode::XZero(len) | Opcode::Val(_, len) => len,
+        }
+    }
+
+    /// The byte of a single byte opcode.
+    fn byte(self) -> u8 {
+        match self {
+            Opcode::Zero(len) => (len - 1) as u8,
+            Opcode::Val(value, len) => ((value - 1) << 2 | (len - 1) as u8) | 0x80,
+            Opcode::XZero(_) => unreachable!("XZERO opcodes take two bytes"),
+        }
+    }
+
+    fn write(self, out: &mut Vec<u8>) {
+        match self {
+            Opcode::XZero(len) => {
+                let len = len - 1;
+                out.push((len >> 8) as u8 | 0x40);
+                out.push((len & 0xff) as u8);
+            }
+            Opcode::Zero(_) | Opcode::Val(..) => out.push(self.byte()),
+        }
+    }
+
+    /// The shortest zero run opcode for `len` registers.
+    fn zeros(len: usize) -> Opcode {
+        if len > HLL_SPARSE_ZERO_MAX_LEN {
+            Opcode::XZero(len)
+        } else {
+            Opcode::Zero(len)
+        }
+    }
+}
+
+/// MurmurHash2, 64 bit version by Austin Appleby, as redis hashes elements.
+fn murmurhash64a(key: &[u8], seed: u64) -> u64 {
+    const M: u64 = 0xc6a4_a793_5bd1_e995;
+    const R: u32 = 47;
+    let mut h = seed ^ (key.len() as u64).wrapping_mul(M);
+
+    let mut chunks = key.chunks_exact(8);
+    for chunk in &mut chunks {
+        let mut k = u64::from_le_bytes(chunk.try_into().unwrap());
+        k = k.wrapping_mul(M);
+        k ^= k >> R;
+        k = k.wrapping_mul(M);
+        h ^= k;
+        h = h.wrapping_mul(M);
+    }
+    let tail = chunks.remainder();
+    if !tail.is_empty() {
+        for (i, byte) in tail.iter().enumerate() {
+            h ^= (*byte as u64) << (8 * i);
+        }
+        h = h.wrapping_mul(M);
+    }
+
+    h ^= h >> R;
+    h = h.wrapping_mul(M);
+    h ^= h >> R;
+    h
+}
+
+/// The register an element maps to and the length of the run of zeros of its
+/// hash plus one, which is the value the register must at least hold.
+fn pattern_len(element: &[u8]) -> (usize, u8) {
+    let hash = murmurhash64a(element, MURMUR_SEED);
+    let index = (hash & HLL_P_MASK) as usize;
+    // Setting bit Q bounds the count to Q + 1.
+    let hash = (hash >> HLL_P) | (1 << HLL_Q);
+    (index, hash.trailing_zeros() as u8 + 1)
+}
+
+fn dense_get(registers: &[u8], index: usize) -> u8 {
+    let byte = index * HLL_BITS / 8;
+    let fb = (index * HLL_BITS) & 7;
+    let b0 = registers[byte] as u16;
+    let b1 = registers.get(byte + 1).copied().unwrap_or(0) as u16;
+    (((b0 >> fb) | (b1 << (8 - fb))) & HLL_REGISTER_MAX as u16) as u8
+}
+
+fn dense_set(registers: &mut [u8], index: usize, value: u8) {
+    let byte = index * HLL_BITS / 8;
+    let fb = (index * HLL_BITS) & 7;
+    let value = value as u16;
+    let max = HLL_REGISTER_MAX as u16;
+    registers[byte] &= !((max << fb) as u8);
+    registers[byte] |= (value << fb) as u8;
+    if let Some(next) = registers.get_mut(byte + 1) {
+        *next &= !((max >> (8 - fb)) as u8);
+        *next |= (value >> (8 - fb)) as u8;
+    }
+}
+
+/// Redis' sigma function of the improved cardinality estimator by Otmar Ertl.
+fn sigma(mut x: f64) -> f64 {
+    if x == 1.0 {
+        return f64::INFINITY;
+    }
+    let mut y = 1.0;
+    let mut z = x;
+    loop {
+        x *= x;
+        let previous = z;
+        z += x * y;
+        y += y;
+        if previous == z {
+            return z;
+        }
+    }
+}
+
+/// Redis' tau function of the improved cardinality estimator by Otmar Ertl.
+fn tau(mut x: f64) -> f64 {
+    if x == 0.0 || x == 1.0 {
+        return 0.0;
+    }
+    let mut y = 1.0;
+    let mut z = 1.0 - x;
+    loop {
+        x = x.sqrt();
+        let previous = z;
+        y *= 0.5;
+        z -= (1.0 - x).powi(2) * y;
+        if previous == z {
+            return z / 3.0;
+        }
+    }
+}
+
+/// Estimate the cardinality from a histogram of the register values.
+fn estimate(histogram: &[u32; HLL_Q + 2]) -> u64 {
+    let m = HLL_REGISTERS as f64;
+    let mut z = m * tau((m - histogram[HLL_Q + 1] as f64) / m);
+    for j in (1..=HLL_Q).rev() {
+        z += histogram[j] as f64;
+        z *= 0.5;
+    }
+    z += m * sigma(histogram[0] as f64 / m);
+    (HLL_ALPHA_INF * m * m / z).round() as u64
+}
+
+/// Registers as one byte each, the form used to merge several values.
+pub type RawRegisters = [u8; HLL_REGISTERS];
+
+/// A HyperLogLog stored in redis' string representation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HyperLogLog {
+    bytes: Vec<u8>,
+}
+
+impl Default for HyperLogLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HyperLogLog {
+    /// An empty, sparse HyperLogLog with a valid cached cardinality of 0.
+    pub fn new() -> Self {
+        let mut bytes = Vec::with_capacity(HLL_HDR_SIZE + 2);
+        bytes.extend_from_slice(b"HYLL");
+        bytes.push(HLL_SPARSE);
+        bytes.resize(HLL_HDR_SIZE, 0);
+        let mut remaining = HLL_REGISTERS;
+        while remaining > 0 {
+            let len = remaining.min(HLL_SPARSE_XZERO_MAX_LEN);
+            Opcode::XZero(len).write(&mut bytes);
+            remaining -= len;
+        }
+        HyperLogLog { bytes }
+    }
+
+    /// Wrap a string value, returning None if it does not have a valid header.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < HLL_HDR_SIZE || &bytes[..4] != b"HYLL" || bytes[4] > HLL_SPARSE {
+            return None;
+        }
+        if bytes[4] == HLL_DENSE && bytes.len() != HLL_DENSE_SIZE {
+            return None;
+        }
+        Some(HyperLogLog {
+            bytes: bytes.to_vec(),
+        })
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    pub fn encoding(&self) -> Encoding {
+        if self.bytes[4] == HLL_DENSE {
+            Encoding::Dense
+        } else {
+            Encoding::Sparse
+        }
+    }
+
+    /// The cached cardinality, unless it was invalidated by a change.
+    pub fn cached_cardinality(&self) -> Option<u64> {
+        let card = u64::from_le_bytes(self.bytes[8..16].try_into().unwrap());
+        (card & (1 << 63) == 0).then_some(card)
+    }
+
+    pub fn set_cached_cardinality(&mut self, cardinality: u64) {
+        self.bytes[8..16].copy_from_slice(&cardinality.to_le_bytes());
+    }
+
+    pub fn invalidate_cache(&mut self) {
+        self.bytes[15] |= 1 << 7;
+    }
+
+    fn registers(&self) -> &[u8] {
+        &self.bytes[HLL_HDR_SIZE..]
+    }
+
+    fn registers_mut(&mut self) -> &mut [u8] {
+        &mut self.bytes[HLL_HDR_SIZE..]
+    }
+
+    /// The sparse opcodes in order along with their byte offsets.
+    fn opcodes(&self) -> impl Iterator<Item = (usize, Opcode)> + '_ {
+        let registers = self.registers();
+        let mut pos = 0;
+        std::iter::from_fn(move || {
+            if pos >= registers.len() {
+                return None;
+            }
+            let opcode = Opcode::read(registers, pos);
+            let offset = pos;
+            pos += opcode.size();
+            Some((offset, opcode))
+        })
+    }
+
+    /// Add an element, returning whether a register changed.
+    pub fn add(&mut self, element: &[u8]) -> Result<bool, InvalidHll> {
+        let (index, count) = pattern_len(element);
+        self.set(index, count)
+    }
+
+    /// Raise register `index` to `count`, returning whether it changed.
+    pub fn set(&mut self, index: usize, count: u8) -> Result<bool, InvalidHll> {
+        match self.encoding() {
+            Encoding::Dense => Ok(self.dense_raise(index, count)),
+            Encoding::Sparse => self.sparse_set(index, count),
+        }
+    }
+
+    fn dense_raise(&mut self, index: usize, count: u8) -> bool {
+        let registers = self.registers_mut();
+        if dense_get(registers, index) < count {
+            dense_set(registers, index, count);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Update a sparse register in place, the way redis does so that the
+    /// resulting bytes are the same: split the opcode covering the register,
+    /// then merge the VAL opcodes around it that now hold the same value.
+    fn sparse_set(&mut self, index: usize, count: u8) -> Result<bool, InvalidHll> {
+        if count > HLL_SPARSE_VAL_MAX_VALUE {
+            return self.promote_and_set(index, count);
+        }
+
+        let mut first = 0;
+        let mut prev = None;
+        let mut found = None;
+        for (pos, opcode) in self.opcodes() {
+            if index < first + opcode.span() {
+                found = Some((pos, opcode));
+                break;
+            }
+            prev = Some(pos);
+            first += opcode.span();
+        }
+        let Some((pos, opcode)) = found else {
+            return Err(InvalidHll);
+        };
+
+        match opcode {
+            Opcode::Val(value, _) if value >= count => return Ok(false),
+            Opcode::Val(_, 1) | Opcode::Zero(1) => {
+                self.registers_mut()[pos] = Opcode::Val(count, 1).byte();
+            }
+            _ => {
+                let last = first + opcode.span() - 1;
+                let mut sequence = Vec::with_capacity(5);
+                match opcode {
+                    Opcode::Val(value, _) => {
+                        if index != first {
+                            Opcode::Val(value, index - first).write(&mut sequence);
+                        }
+                        Opcode::Val(count, 1).write(&mut sequence);
+                        if index != last {
+                            Opcode::Val(value, last - index).write(&mut sequence);
+                        }
+                    }
+                    Opcode::Zero(_) | Opcode::XZero(_) => {
+                        if index != first {
+                            Opcode::zeros(index - first).write(&mut sequence);
+                        }
+                        Opcode::Val(count, 1).write(&mut sequence);
+                        if index != last {
+                            Opcode::zeros(last - index).write(&mut sequence);
+                        }
+                    }
+                }
+                if sequence.len() > opcode.size()
+                    && self.bytes.len() + sequence.len() - opcode.size() > HLL_SPARSE_MAX_BYTES
+                {
+                    return self.promote_and_set(index, count);
+                }
+                let start = HLL_HDR_SIZE + pos;
+                self.bytes.splice(start..start + opcode.size(), sequence);
+            }
+        }
+
+        // Merge adjacent VAL opcodes with the same value, scanning up to five
+        // opcodes from the one before the update.
+        let mut pos = HLL_HDR_SIZE + prev.unwrap_or(0);
+        let mut scan = 5;
+        while pos < self.bytes.len() && scan > 0 {
+            scan -= 1;
+            let opcode = Opcode::read(&self.bytes, pos);
+            let Opcode::Val(value, len) = opcode else {
+                pos += opcode.size();
+                continue;
+            };
+            if let Some(Opcode::Val(next_value, next_len)) =
+                (pos + 1 < self.bytes.len()).then(|| Opcode::read(&self.bytes, pos + 1))
+            {
+                if value == next_value && len + next_len <= HLL_SPARSE_VAL_MAX_LEN {
+                    self.bytes.remove(pos);
+                    self.bytes[pos] = Opcode::Val(value, len + next_len).byte();
+                    continue;
+                }
+            }
+            pos += 1;
+        }
+        Ok(true)
+    }
+
+    fn promote_and_set(&mut self, index: usize, count: u8) -> Result<bool, InvalidHll> {
+        self.to_dense()?;
+        Ok(self.dense_raise(index, count))
+    }
+
+    /// Convert to the dense encoding, returning whether it was sparse.
+    pub fn to_dense(&mut self) -> Result<bool, InvalidHll> {
+        if self.encoding() == Encoding::Dense {
+            return Ok(false);
+        }
+        let mut dense = vec![0; HLL_DENSE_SIZE];
+        dense[..HLL_HDR_SIZE].copy_from_slice(&self.bytes[..HLL_HDR_SIZE]);
+        dense[4] = HLL_DENSE;
+        let mut index = 0;
+        for (_, opcode) in self.opcodes() {
+            if index + opcode.span() > HLL_REGISTERS {
+                return Err(InvalidHll);
+            }
+            if let Opcode::Val(value, len) = opcode {
+                for i in index..index + len {
+                    dense_set(&mut dense[HLL_HDR_SIZE..], i, value);
+                }
+            }
+            index += opcode.span();
+        }
+        if index != HLL_REGISTERS {
+            return Err(InvalidHll);
+        }
+        self.bytes = dense;
+        Ok(true)
+    }
+
+    /// Fold the registers into `max`, keeping the highest value of each.
+    pub fn merge_into(&self, max: &mut RawRegisters) -> Result<(), InvalidHll> {
+        match self.encoding() {
+            Encoding::Dense => {
+                for (i, register) in max.iter_mut().enumerate() {
+                    *register = (*register).max(dense_get(self.registers(), i));
+                }
+            }
+            Encoding::Sparse => {
+                let mut index = 0;
+                for (_, opcode) in self.opcodes() {
+                    if index + opcode.span() > HLL_REGISTERS {
+                        return Err(InvalidHll);
+                    }
+                    if let Opcode::Val(value, len) = opcode {
+                        for register in &mut max[index..index + len] {
+                            *register = (*register).max(value);
+                        }
+                    }
+                    index += opcode.span();
+                }
+                if index != HLL_REGISTERS {
+                    return Err(InvalidHll);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Estimate the cardinality, ignoring the cache.
+    pub fn count(&self) -> Result<u64, InvalidHll> {
+        let mut histogram = [0; HLL_Q + 2];
+        match self.encoding() {
+            Encoding::Dense => {
+                for i in 0..HLL_REGISTERS {
+                    histogram[dense_get(self.registers(), i) as usize] += 1;
+                }
+            }
+            Encoding::Sparse => {
+                let mut index = 0;
+                for (_, opcode) in self.opcodes() {
+                    let value = match opcode {
+                        Opcode::Val(value, _) => value as usize,
+                        Opcode::Zero(_) | Opcode::XZero(_) => 0,
+                    };
+                    histogram[value] += opcode.span() as u32;
+                    index += opcode.span();
+                }
+                if index != HLL_REGISTERS {
+                    return Err(InvalidHll);
+                }
+            }
+        }
+        Ok(estimate(&histogram))
+    }
+
+    /// Dense register values, converting to the dense encoding first.
+    pub fn dense_registers(&mut self) -> Result<Vec<u8>, InvalidHll> {
+        self.to_dense()?;
+        Ok((0..HLL_REGISTERS)
+            .map(|i| dense_get(self.registers(), i))
+            .collect())
+    }
+
+    /// Human readable sparse opcodes, as printed by PFDEBUG DECODE.
+    pub fn decode(&self) -> String {
+        self.opcodes()
+            .map(|(_, opcode)| match opcode {
+                Opcode::Zero(len) => format!("z:{}", len),
+                Opcode::XZero(len) => format!("Z:{}", len),
+                Opcode::Val(value, len) => format!("v:{},{}", value, len),
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// Estimate the cardinality of merged raw registers.
+pub fn count_raw(registers: &RawRegisters) -> u64 {
+    let mut histogram = [0; HLL_Q + 2];
+    for register in registers {
+        histogram[*register as usize] += 1;
+    }
+    estimate(&histogram)
+}
+
+/// Check the register encoding and the estimation error against a dense and a
+/// sparse HyperLogLog fed up to `max_elements` elements, as PFSELFTEST does.
+pub fn self_test(max_elements: u64) -> Result<(), String> {
+    let mut dense = HyperLogLog::new();
+    dense.to_dense().unwrap();
+
+    for _ in 0..1000 {
+        let values: Vec<u8> = (0..HLL_REGISTERS)
+            .map(|_| (crate::util::random_u64() as u8) & HLL_REGISTER_MAX)
+            .collect();
+        for (i, value) in values.iter().enumerate() {
+            dense_set(dense.registers_mut(), i, *value);
+        }
+        for (i, value) in values.iter().enumerate() {
+            let actual = dense_get(dense.registers(), i);
+            if actual != *value {
+                return Err(format!(
+                    "TESTFAILED Register {} should be {} but is {}",
+                    i, value, actual
+                ));
+            }
+        }
+    }
+
+    dense.registers_mut().fill(0);
+    let mut sparse = HyperLogLog::new();
+    let relative_error = 1.04 / (HLL_REGISTERS as f64).sqrt();
+    let mut checkpoint = 1;
+    let seed = crate::util::random_u64();
+    for j in 1..=max_elements {
+        let element = (j ^ seed).to_le_bytes();
+        dense.add(&element).unwrap();
+        sparse
+            .add(&element)
+            .map_err(|_| "TESTFAILED sparse add failed")?;
+        if j != checkpoint {
+            continue;
+        }
+        if (j as usize) < HLL_SPARSE_MAX_BYTES / 2 && sparse.encoding() != Encoding::Sparse {
+            return Err(String::from("TESTFAILED sparse encoding not used"));
+        }
+        let count = dense.count().unwrap();
+        if sparse.count() != Ok(count) {
+            return Err(String::from("TESTFAILED dense/sparse disagree"));
+        }
+        // Cardinality 10 gets a much higher error from time to time because
+        // of collisions, so only tolerate one off there.
+        let max_error = if j == 10 {
+            1
+        } else {
+            (relative_error * 6.0 * checkpoint as f64).ceil() as u64
+        };
+        let error = checkpoint.abs_diff(count);
+        if error > max_error {
+            return Err(format!(
+                "TESTFAILED Too big error. card:{} abserr:{}",
+                checkpoint, error
+            ));
+        }
+        checkpoint *= 10;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new_is_sparse_and_empty() {
+        let hll = HyperLogLog::new();
+        assert_eq!(hll.as_bytes().len(), 18);
+        assert_eq!(&hll.as_bytes()[16..], &[0x7f, 0xff]);
+        assert_eq!(hll.encoding(), Encoding::Sparse);
+        assert_eq!(hll.cached_cardinality(), Some(0));
+        assert_eq!(hll.count(), Ok(0));
+        assert_eq!(hll.decode(), "Z:16384");
+        assert!(HyperLogLog::from_bytes(b"HYLL").is_none());
+        assert!(HyperLogLog::from_bytes(hll.as_bytes()).is_some());
+    }
+
+    #[test]
+    fn test_sparse_set_splits_and_merges() {
+        let mut hll = HyperLogLog::new();
+        assert_eq!(hll.set(100, 3), Ok(true));
+        assert_eq!(hll.decode(), "Z:100 v:3,1 Z:16283");
+        assert_eq!(hll.set(100, 2), Ok(false));
+        assert_eq!(hll.set(101, 3), Ok(true));
+        assert_eq!(hll.decode(), "Z:100 v:3,2 Z:16282");
+        assert_eq!(hll.set(0, 1), Ok(true));
+        assert_eq!(hll.decode(), "v:1,1 Z:99 v:3,2 Z:16282");
+        assert_eq!(hll.set(5, 40), Ok(true));
+        assert_eq!(hll.encoding(), Encoding::Dense);
+        let registers = hll.dense_registers().unwrap();
+        assert_eq!(
+            (registers[0], registers[5], registers[100], registers[101]),
+            (1, 40, 3, 3)
+        );
+        assert_eq!(registers.iter().filter(|r| **r != 0).count(), 4);
+    }
+
+    #[test]
+    fn test_count_matches_across_encodings() {
+        let mut sparse = HyperLogLog::new();
+        for i in 0..1000 {
+            sparse.add(format!("element:{}", i).as_bytes()).unwrap();
+        }
+        let mut dense = sparse.clone();
+        assert_eq!(dense.to_dense(), Ok(true));
+        let count = sparse.count().unwrap();
+        assert_eq!(dense.count(), Ok(count));
+        assert!(count.abs_diff(1000) < 20, "count {}", count);
+
+        let mut max = [0; HLL_REGISTERS];
+        sparse.merge_into(&mut max).unwrap();
+        dense.merge_into(&mut max).unwrap();
+        assert_eq!(count_raw(&max), count);
+    }
+
+    #[test]
+    fn test_self_test() {
+        assert_eq!(self_test(100_000), Ok(()));
+    }
+}
@@ -0,0 +1,385 @@
+use std::fmt;
+
+use bytes::Bytes;
+
+/// A sequence of strings stored back to back in a single allocation, in the
+/// spirit of redis' listpack. Each entry is its length as a varint, its bytes,
+/// then the size of those two as a varint read from the right, so that the
+/// sequence can be walked in both directions. Saves the allocation and the
+/// header of every entry at the cost of linear time access, which is what
+/// small collections want.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct ListPack {
+    data: Vec<u8>,
+    len: usize,
+}
+
+/// Number of bytes of `value` as a varint.
+fn varint_size(mut value: usize) -> usize {
+    let mut size = 1;
+    while value >= 128 {
+        value >>= 7;
+        size += 1;
+    }
+    size
+}
+
+fn write_varint(buf: &mut Vec<u8>, mut value: usize) {
+    while value >= 128 {
+        buf.push((value as u8 & 127) | 128);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+/// Read the varint at `pos`, returning it along with its size.
+fn read_varint(data: &[u8], pos: usize) -> (usize, usize) {
+    let mut value = 0;
+    let mut size = 0;
+    loop {
+        let byte = data[pos + size];
+        value |= ((byte & 127) as usize) << (7 * size);
+        size += 1;
+        if byte & 128 == 0 {
+            return (value, size);
+        }
+    }
+}
+
+/// Write `value` so that it can be read from its last byte backwards.
+fn write_backlen(buf: &mut Vec<u8>, value: usize) {
+    let start = buf.len();
+    write_varint(buf, value);
+    buf[start..].reverse();
+}
+
+/// Read the backwards varint ending right before `end`, returning it along
+/// with its size.
+fn read_backlen(data: &[u8], end: usize) -> (usize, usize) {
+    let mut value = 0;
+    let mut size = 0;
+    loop {
+        let byte = data[end - 1 - size];
+        value |= ((byte & 127) as usize) << (7 * size);
+        size += 1;
+        if byte & 128 == 0 {
+            return (value, size);
+        }
+    }
+}
+
+fn encode_entry(value: &[u8]) -> Vec<u8> {
+    let head = varint_size(value.len()) + value.len();
+    let mut entry = Vec::with_capacity(head + varint_size(head));
+    write_varint(&mut entry, value.len());
+    entry.extend_from_slice(value);
+    write_backlen(&mut entry, head);
+    entry
+}
+
+impl ListPack {
+    pub fn new() -> Self {
+        ListPack::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Size of the encoded entries in bytes.
+    pub fn bytes(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Bytes an entry holding `value` takes.
+    pub fn entry_size(value: &[u8]) -> usize {
+        let head = varint_size(value.len()) + value.len();
+        head + varint_size(head)
+    }
+
+    /// The value of the entry at byte offset `pos`, along with the offset of
+    /// the next entry.
+    fn entry_at(&self, pos: usize) -> (&[u8], usize) {
+        let (len, size) = read_varint(&self.data, pos);
+        let start = pos + size;
+        let head = size + len;
+        (
+            &self.data[start..start + len],
+            pos + head + varint_size(head),
+        )
+    }
+
+    /// Byte offset of the entry at `index`, or of the end for `len`.
+    fn offset(&self, index: usize) -> usize {
+        if index > self.len / 2 {
+            let mut pos = self.data.len();
+            for _ in index..self.len {
+                let (head, size) = read_backlen(&self.data, pos);
+                pos -= head + size;
+            }
+            pos
+        } else {
+            let mut pos = 0;
+            for _ in 0..index {
+                pos = self.entry_at(pos).1;
+            }
+            pos
+        }
+    }
+
+    pub fn get(&self, index: usize) -> Option<&[u8]> {
+        if index >= self.len {
+            return None;
+        }
+        Some(self.entry_at(self.offset(index)).0)
+    }
+
+    pub fn first(&self) -> Option<&[u8]> {
+        self.iter().next()
+    }
+
+    pub fn last(&self) -> Option<&[u8]> {
+        self.iter().next_back()
+    }
+
+    /// Index of the first entry equal to `value`.
+    pub fn position(&self, value: &[u8]) -> Option<usize> {
+        self.iter().position(|entry| entry == value)
+    }
+
+    pub fn push_back(&mut self, value: &[u8]) {
+        self.data.extend_from_slice(&encode_entry(value));
+        self.len += 1;
+    }
+
+    pub fn push_front(&mut self, value: &[u8]) {
+        self.insert(0, value);
+    }
+
+    /// Insert `value` at `index`, shifting the following entries.
+    pub fn insert(&mut self, index: usize, value: &[u8]) {
+        assert!(index <= self.len, "index {} out of range", index);
+        let pos = self.offset(index);
+        self.data.splice(pos..pos, encode_entry(value));
+        self.len += 1;
+    }
+
+    /// Replace the value of the entry at `index`.
+    pub fn replace(&mut self, index: usize, value: &[u8]) {
+        assert!(index < self.len, "index {} out of range", index);
+        let pos = self.offset(index);
+        let end = self.entry_at(pos).1;
+        self.data.splice(pos..end, encode_entry(value));
+    }
+
+    /// Remove the entry at `index`, returning its value.
+    pub fn remove(&mut self, index: usize) -> Option<Bytes> {
+        if index >= self.len {
+            return None;
+        }
+        let pos = self.offset(index);
+        let (value, end) = self.entry_at(pos);
+        let value = Bytes::copy_from_slice(value);
+        self.data.drain(pos..end);
+        self.len -= 1;
+        Some(value)
+    }
+
+    /// Remove the `count` entries starting at `index`.
+    pub fn remove_range(&mut self, index: usize, count: usize) {
+        let count = count.min(self.len.saturating_sub(index));
+        if count == 0 {
+            return;
+        }
+        let start = self.offset(index);
+        let mut end = start;
+        for _ in 0..count {
+            end = self.entry_at(end).1;
+        }
+        self.data.drain(start..end);
+        self.len -= count;
+    }
+
+    pub fn pop_front(&mut self) -> Option<Bytes> {
+        self.remove(0)
+    }
+
+    pub fn pop_back(&mut self) -> Option<Bytes> {
+        self.remove(self.len.checked_sub(1)?)
+    }
+
+    /// Split off the entries from `index` on into a new listpack.
+    pub fn split_off(&mut self, index: usize) -> ListPack {
+        let index = index.min(self.len);
+        let pos = self.offset(index);
+        let tail = ListPack {
+            data: self.data.split_off(pos),
+            len: self.len - index,
+        };
+        self.len = index;
+        tail
+    }
+
+    /// Move the entries of `other` to the end of this listpack.
+    pub fn append(&mut self, other: &mut ListPack) {
+        self.data.append(&mut other.data);
+        self.len += other.len;
+        other.len = 0;
+    }
+
+    /// Keep only the entries for which `keep` returns true.
+    pub fn retain(&mut self, mut keep: impl FnMut(usize, &[u8]) -> bool) {
+        let mut data = Vec::with_capacity(self.data.len());
+        let mut len = 0;
+        let mut pos = 0;
+        for index in 0..self.len {
+            let (value, next) = self.entry_at(pos);
+            if keep(index, value) {
+                data.extend_from_slice(&self.data[pos..next]);
+                len += 1;
+            }
+            pos = next;
+        }
+        self.data = data;
+        self.len = len;
+    }
+
+    pub fn iter(&self) -> Iter<'_> {
+        Iter {
+            listpack: self,
+            front: 0,
+            back: self.data.len(),
+            remaining: self.len,
+        }
+    }
+
+    /// Iterate from the entry at `index` towards the tail.
+    pub fn iter_from(&self, index: usize) -> Iter<'_> {
+        let index = index.min(self.len);
+        Iter {
+            listpack: self,
+            front: self.offset(index),
+            back: self.data.len(),
+            remaining: self.len - index,
+        }
+    }
+}
+
+impl fmt::Debug for ListPack {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.iter().map(String::from_utf8_lossy))
+            .finish()
+    }
+}
+
+impl<'a> FromIterator<&'a [u8]> for ListPack {
+    fn from_iter<I: IntoIterator<Item = &'a [u8]>>(iter: I) -> Self {
+        let mut listpack = ListPack::new();
+        for value in iter {
+            listpack.push_back(value);
+        }
+        listpack
+    }
+}
+
+pub struct Iter<'a> {
+    listpack: &'a ListPack,
+    /// Byte offsets of the next entry from either end.
+    front: usize,
+    back: usize,
+    remaining: usize,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<&'a [u8]> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let (value, next) = self.listpack.entry_at(self.front);
+        self.front = next;
+        self.remaining -= 1;
+        Some(value)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a> DoubleEndedIterator for Iter<'a> {
+    fn next_back(&mut self) -> Option<&'a [u8]> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let (head, size) = read_backlen(&self.listpack.data, self.back);
+        self.back -= head + size;
+        self.remaining -= 1;
+        Some(self.listpack.entry_at(self.back).0)
+    }
+}
+
+impl ExactSizeIterator for Iter<'_> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn contents(listpack: &ListPack) -> Vec<Vec<u8>> {
+        listpack.iter().map(|value| value.to_vec()).collect()
+    }
+
+    #[test]
+    fn test_matches_vec_model() {
+        let mut listpack = ListPack::new();
+        let mut model: Vec<Vec<u8>> = Vec::new();
+        for i in 0..300usize {
+            // Lengths around the one and two byte varint boundaries.
+            let value = vec![b'a' + (i % 26) as u8; (i * 37) % 300];
+            let index = (i * 7) % (model.len() + 1);
+            listpack.insert(index, &value);
+            model.insert(index, value);
+        }
+        assert_eq!(contents(&listpack), model);
+        let reversed: Vec<Vec<u8>> = listpack.iter().rev().map(|v| v.to_vec()).collect();
+        assert_eq!(reversed, model.iter().rev().cloned().collect::<Vec<_>>());
+        for i in 0..100usize {
+            let index = (i * 13) % model.len();
+            assert_eq!(
+                listpack.remove(index).unwrap().to_vec(),
+                model.remove(index)
+            );
+            let index = (i * 5) % model.len();
+            listpack.replace(index, b"replaced");
+            model[index] = b"replaced".to_vec();
+        }
+        assert_eq!(contents(&listpack), model);
+        assert_eq!(listpack.len(), model.len());
+        assert_eq!(listpack.get(150), Some(&model[150][..]));
+        assert_eq!(listpack.get(model.len()), None);
+        let tail: Vec<&[u8]> = listpack.iter_from(198).collect();
+        assert_eq!(tail, vec![&model[198][..], &model[199][..]]);
+    }
+
+    #[test]
+    fn test_split_append_and_retain() {
+        let mut listpack: ListPack = ["a", "b", "c", "d"].iter().map(|v| v.as_bytes()).collect();
+        let mut tail = listpack.split_off(1);
+        assert_eq!(contents(&listpack), vec![b"a".to_vec()]);
+        assert_eq!(tail.len(), 3);
+        tail.retain(|index, value| index != 0 && value != b"d");
+        listpack.append(&mut tail);
+        assert_eq!(contents(&listpack), vec![b"a".to_vec(), b"c".to_vec()]);
+        assert!(tail.is_empty());
+        listpack.remove_range(0, 5);
+        assert!(listpack.is_empty());
+        assert_eq!(listpack.bytes(), 0);
+        assert_eq!(listpack.pop_back(), None);
+    }
+}
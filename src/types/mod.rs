@@ -0,0 +1,60 @@
+use std::sync::RwLock;
+
+pub mod dict;
+pub mod geohash;
+pub mod hash;
+pub mod hyperloglog;
+pub mod listpack;
+pub mod quicklist;
+pub mod rax;
+pub mod set;
+pub mod skiplist;
+pub mod stream;
+pub mod zset;
+
+/// Thresholds past which collections convert from their compact encoding to
+/// a full data structure, named after the redis options setting them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EncodingLimits {
+    pub hash_max_listpack_entries: usize,
+    pub hash_max_listpack_value: usize,
+    pub set_max_intset_entries: usize,
+    pub set_max_listpack_entries: usize,
+    pub set_max_listpack_value: usize,
+    pub zset_max_listpack_entries: usize,
+    pub zset_max_listpack_value: usize,
+    /// Entries per quicklist node when positive, or when negative a size
+    /// limit per node from -1 for 4kb to -5 for 64kb.
+    pub list_max_listpack_size: i64,
+}
+
+impl EncodingLimits {
+    pub const DEFAULT: EncodingLimits = EncodingLimits {
+        hash_max_listpack_entries: 128,
+        hash_max_listpack_value: 64,
+        set_max_intset_entries: 512,
+        set_max_listpack_entries: 128,
+        set_max_listpack_value: 64,
+        zset_max_listpack_entries: 128,
+        zset_max_listpack_value: 64,
+        list_max_listpack_size: -2,
+    };
+}
+
+impl Default for EncodingLimits {
+    fn default() -> Self {
+        EncodingLimits::DEFAULT
+    }
+}
+
+/// Process wide, like the redis options they come from, since values are
+/// created and converted far from any configuration.
+static ENCODING_LIMITS: RwLock<EncodingLimits> = RwLock::new(EncodingLimits::DEFAULT);
+
+pub fn encoding_limits() -> EncodingLimits {
+    *ENCODING_LIMITS.read().unwrap()
+}
+
+pub fn set_encoding_limits(limits: EncodingLimits) {
+    *ENCODING_LIMITS.write().unwrap() = limits;
+}
@@ -0,0 +1,435 @@
+use std::collections::VecDeque;
+
+use bytes::Bytes;
+
+use super::encoding_limits;
+use super::listpack::ListPack;
+
+/// Node size limits for list-max-listpack-size from -1 to -5.
+const NODE_SIZE_LIMITS: [usize; 5] = [4096, 8192, 16384, 32768, 65536];
+/// Largest node size when nodes are limited by their number of entries.
+const SIZE_SAFETY_LIMIT: usize = 8192;
+
+/// The most entries and bytes a node may hold, as configured.
+fn node_limits() -> (usize, usize) {
+    match encoding_limits().list_max_listpack_size {
+        size if size > 0 => (size as usize, SIZE_SAFETY_LIMIT),
+        size => (
+            usize::MAX,
+            NODE_SIZE_LIMITS[size.unsigned_abs().clamp(1, 5) as usize - 1],
+        ),
+    }
+}
+
+/// A chunk of contiguous list entries.
+#[derive(Debug, Clone, Default)]
+struct Node {
+    entries: ListPack,
+}
+
+impl Node {
+    fn with_entry(value: &[u8]) -> Self {
+        let mut entries = ListPack::new();
+        entries.push_back(value);
+        Node { entries }
+    }
+
+    /// An empty node accepts any value, so that oversized values get a node of their own.
+    fn can_fit(&self, value: &[u8]) -> bool {
+        let (max_entries, max_bytes) = node_limits();
+        self.entries.is_empty()
+            || (self.entries.len() < max_entries
+                && self.entries.bytes() + ListPack::entry_size(value) <= max_bytes)
+    }
+
+    fn can_merge(&self, other: &Node) -> bool {
+        let (max_entries, max_bytes) = node_limits();
+        self.entries.len() + other.entries.len() <= max_entries
+            && self.entries.bytes() + other.entries.bytes() <= max_bytes
+    }
+}
+
+/// A doubly ended list stored as a sequence of bounded nodes, in the spirit of
+/// redis' quicklist. Keeping entries chunked bounds the cost of inserts and
+/// removals in the middle of the list while keeping pushes and pops at either
+/// end cheap.
+#[derive(Debug, Clone, Default)]
+pub struct QuickList {
+    nodes: VecDeque<Node>,
+    len: usize,
+}
+
+impl QuickList {
+    pub fn new() -> Self {
+        QuickList::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Name of the encoding in use, as reported by OBJECT ENCODING: a list
+    /// that fits a single node is a plain listpack.
+    pub fn encoding(&self) -> &'static str {
+        if self.nodes.len() <= 1 {
+            "listpack"
+        } else {
+            "quicklist"
+        }
+    }
+
+    pub fn push_front(&mut self, value: Bytes) {
+        match self.nodes.front_mut() {
+            Some(node) if node.can_fit(&value) => node.entries.push_front(&value),
+            _ => self.nodes.push_front(Node::with_entry(&value)),
+        }
+        self.len += 1;
+    }
+
+    pub fn push_back(&mut self, value: Bytes) {
+        match self.nodes.back_mut() {
+            Some(node) if node.can_fit(&value) => node.entries.push_back(&value),
+            _ => self.nodes.push_back(Node::with_entry(&value)),
+        }
+        self.len += 1;
+    }
+
+    pub fn pop_front(&mut self) -> Option<Bytes> {
+        let node = self.nodes.front_mut()?;
+        let value = node.entries.pop_front()?;
+        if node.entries.is_empty() {
+            self.nodes.pop_front();
+        }
+        self.len -= 1;
+        Some(value)
+    }
+
+    pub fn pop_back(&mut self) -> Option<Bytes> {
+        let node = self.nodes.back_mut()?;
+        let value = node.entries.pop_back()?;
+        if node.entries.is_empty() {
+            self.nodes.pop_back();
+        }
+        self.len -= 1;
+        Some(value)
+    }
+
+    /// Find the node holding the entry at `index`, walking from whichever end is closer.
+    fn locate(&self, index: usize) -> Option<(usize, usize)> {
+        if index >= self.len {
+            return None;
+        }
+        if index < self.len / 2 {
+            let mut remaining = index;
+            for (i, node) in self.nodes.iter().enumerate() {
+                if remaining < node.entries.len() {
+                    return Some((i, remaining));
+                }
+                remaining -= node.entries.len();
+            }
+        } else {
+            let mut remaining = self.len - 1 - index;
+            for (i, node) in self.nodes.iter().enumerate().rev() {
+                if remaining < node.entries.len() {
+                    return Some((i, node.entries.len() - 1 - remaining));
+                }
+                remaining -= node.entries.len();
+            }
+        }
+        None
+    }
+
+    pub fn get(&self, index: usize) -> Option<&[u8]> {
+        let (node_index, offset) = self.locate(index)?;
+        self.nodes[node_index].entries.get(offset)
+    }
+
+    /// Replace the entry at `index`, returning false if the index is out of range.
+    pub fn set(&mut self, index: usize, value: Bytes) -> bool {
+        match self.locate(index) {
+            Some((node_index, offset)) => {
+                self.nodes[node_index].entries.replace(offset, &value);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Insert `value` so that it ends up at position `index`, splitting a full node if needed.
+    pub fn insert(&mut self, index: usize, value: Bytes) {
+        if index == 0 {
+            return self.push_front(value);
+        }
+        let Some((node_index, offset)) = self.locate(index) else {
+            return self.push_back(value);
+        };
+        if self.nodes[node_index].can_fit(&value) {
+            self.nodes[node_index].entries.insert(offset, &value);
+        } else if offset == 0 && self.nodes[node_index - 1].can_fit(&value) {
+            self.nodes[node_index - 1].entries.push_back(&value);
+        } else {
+            let node = &mut self.nodes[node_index];
+            let tail = Node {
+                entries: node.entries.split_off(offset),
+            };
+            let mut next_index = node_index + 1;
+            if node.can_fit(&value) {
+                node.entries.push_back(&value);
+            } else {
+                self.nodes.insert(next_index, Node::with_entry(&value));
+                next_index += 1;
+            }
+            self.nodes.insert(next_index, tail);
+        }
+        self.len += 1;
+    }
+
+    /// Remove and return the entry at `index`.
+    pub fn remove(&mut self, index: usize) -> Option<Bytes> {
+        let (node_index, offset) = self.locate(index)?;
+        let node = &mut self.nodes[node_index];
+        let value = node.entries.remove(offset)?;
+        self.len -= 1;
+        if node.entries.is_empty() {
+            self.nodes.remove(node_index);
+        } else {
+            self.merge_with_neighbours(node_index);
+        }
+        Some(value)
+    }
+
+    /// Merge the node at `node_index` into its neighbours when they fit in a single node.
+    fn merge_with_neighbours(&mut self, node_index: usize) {
+        let mut node_index = node_index;
+        if node_index > 0 && self.nodes[node_index - 1].can_merge(&self.nodes[node_index]) {
+            let mut node = self.nodes.remove(node_index).unwrap();
+            node_index -= 1;
+            self.nodes[node_index].entries.append(&mut node.entries);
+        }
+        if node_index + 1 < self.nodes.len()
+            && self.nodes[node_index].can_merge(&self.nodes[node_index + 1])
+        {
+            let mut next = self.nodes.remove(node_index + 1).unwrap();
+            self.nodes[node_index].entries.append(&mut next.entries);
+        }
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &[u8]> {
+        self.nodes.iter().flat_map(|node| node.entries.iter())
+    }
+
+    /// Iterate from the entry at `index` towards the tail, without walking the preceding nodes.
+    pub fn iter_from(&self, index: usize) -> impl Iterator<Item = &[u8]> {
+        let (node_index, offset) = self.locate(index).unwrap_or((self.nodes.len(), 0));
+        self.nodes
+            .range(node_index..)
+            .enumerate()
+            .flat_map(move |(i, node)| node.entries.iter_from(if i == 0 { offset } else { 0 }))
+    }
+
+    /// Keep only the entries in the inclusive range `start..=end`.
+    pub fn trim(&mut self, start: usize, end: usize) {
+        if start > end || start >= self.len {
+            self.clear();
+            return;
+        }
+        let end = end.min(self.len - 1);
+        let from_tail = self.len - 1 - end;
+        self.drop_front(start);
+        self.drop_back(from_tail);
+    }
+
+    fn drop_front(&mut self, mut count: usize) {
+        while count > 0 {
+            let Some(node) = self.nodes.front_mut() else {
+                break;
+            };
+            if node.entries.len() <= count {
+                count -= node.entries.len();
+                self.len -= node.entries.len();
+                self.nodes.pop_front();
+            } else {
+                node.entries.remove_range(0, count);
+                self.len -= count;
+                count = 0;
+            }
+        }
+    }
+
+    fn drop_back(&mut self, mut count: usize) {
+        while count > 0 {
+            let Some(node) = self.nodes.back_mut() else {
+                break;
+            };
+            if node.entries.len() <= count {
+                count -= node.entries.len();
+                self.len -= node.entries.len();
+                self.nodes.pop_back();
+            } else {
+                let keep = node.entries.len() - count;
+                node.entries.remove_range(keep, count);
+                self.len -= count;
+                count = 0;
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.nodes.clear();
+        self.len = 0;
+    }
+
+    /// Remove up to `limit` entries equal to `value` (all of them when `limit` is 0),
+    /// scanning from the tail when `from_tail` is set. Returns the number removed.
+    pub fn remove_matching(&mut self, value: &[u8], limit: usize, from_tail: bool) -> usize {
+        let mut removed = 0;
+        let node_count = self.nodes.len();
+        for i in 0..node_count {
+            if limit != 0 && removed == limit {
+                break;
+            }
+            let node_index = if from_tail { node_count - 1 - i } else { i };
+            let node = &mut self.nodes[node_index];
+            let mut matches: Vec<usize> = node
+                .entries
+                .iter()
+                .enumerate()
+                .filter(|(_, entry)| *entry == value)
+                .map(|(offset, _)| offset)
+                .collect();
+            if from_tail {
+                matches.reverse();
+            }
+            if limit != 0 {
+                matches.truncate(limit - removed);
+            }
+            matches.sort_unstable();
+            node.entries
+                .retain(|offset, _| matches.binary_search(&offset).is_err());
+            removed += matches.len();
+        }
+        self.nodes.retain(|node| !node.entries.is_empty());
+        self.len -= removed;
+        removed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn list_of(values: &[&str]) -> QuickList {
+        let mut list = QuickList::new();
+        for value in values {
+            list.push_back(Bytes::copy_from_slice(value.as_bytes()));
+        }
+        list
+    }
+
+    fn contents(list: &QuickList) -> Vec<String> {
+        list.iter()
+            .map(|v| String::from_utf8(v.to_vec()).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_push_and_pop() {
+        let mut list = QuickList::new();
+        list.push_back(Bytes::from("b"));
+        list.push_front(Bytes::from("a"));
+        list.push_back(Bytes::from("c"));
+        assert_eq!(list.len(), 3);
+        assert_eq!(list.pop_front(), Some(Bytes::from("a")));
+        assert_eq!(list.pop_back(), Some(Bytes::from("c")));
+        assert_eq!(list.pop_back(), Some(Bytes::from("b")));
+        assert_eq!(list.pop_back(), None);
+        assert!(list.is_empty());
+    }
+
+    #[test]
+    fn test_entries_are_chunked() {
+        let mut list = QuickList::new();
+        for i in 0..5000 {
+            list.push_back(Bytes::from(i.to_string()));
+        }
+        assert!(list.nodes.len() > 1);
+        assert_eq!(list.encoding(), "quicklist");
+        assert!(list
+            .nodes
+            .iter()
+            .all(|node| node.entries.bytes() <= node_limits().1));
+        assert_eq!(list.get(0), Some(&b"0"[..]));
+        assert_eq!(list.get(2500), Some(&b"2500"[..]));
+        assert_eq!(list.get(4999), Some(&b"4999"[..]));
+        assert_eq!(list.get(5000), None);
+    }
+
+    #[test]
+    fn test_insert_and_remove_match_vec_model() {
+        let mut list = QuickList::new();
+        let mut model: Vec<Bytes> = Vec::new();
+        for i in 0..600usize {
+            let value = Bytes::from(i.to_string());
+            let index = (i * 7) % (model.len() + 1);
+            list.insert(index, value.clone());
+            model.insert(index, value);
+        }
+        assert_eq!(
+            list.iter().map(Bytes::copy_from_slice).collect::<Vec<_>>(),
+            model
+        );
+        for i in 0..300usize {
+            let index = (i * 13) % model.len();
+            assert_eq!(list.remove(index), Some(model.remove(index)));
+        }
+        assert_eq!(
+            list.iter().map(Bytes::copy_from_slice).collect::<Vec<_>>(),
+            model
+        );
+        assert_eq!(list.len(), model.len());
+    }
+
+    #[test]
+    fn test_iter_from() {
+        let list = list_of(&["a", "b", "c", "d"]);
+        let tail: Vec<&[u8]> = list.iter_from(2).collect();
+        assert_eq!(tail, vec![&b"c"[..], &b"d"[..]]);
+        assert_eq!(list.iter_from(4).count(), 0);
+    }
+
+    #[test]
+    fn test_trim() {
+        let mut list = QuickList::new();
+        for i in 0..500 {
+            list.push_back(Bytes::from(i.to_string()));
+        }
+        list.trim(100, 199);
+        assert_eq!(list.len(), 100);
+        assert_eq!(list.get(0), Some(&b"100"[..]));
+        assert_eq!(list.get(99), Some(&b"199"[..]));
+        list.trim(5, 2);
+        assert!(list.is_empty());
+    }
+
+    #[test]
+    fn test_remove_matching() {
+        let mut list = list_of(&["a", "x", "b", "x", "c", "x"]);
+        assert_eq!(list.remove_matching(b"x", 2, true), 2);
+        assert_eq!(contents(&list), vec!["a", "x", "b", "c"]);
+        assert_eq!(list.remove_matching(b"x", 0, false), 1);
+        assert_eq!(contents(&list), vec!["a", "b", "c"]);
+        assert_eq!(list.len(), 3);
+    }
+
+    #[test]
+    fn test_set() {
+        let mut list = list_of(&["a", "b"]);
+        assert!(list.set(1, Bytes::from("z")));
+        assert!(!list.set(2, Bytes::from("z")));
+        assert_eq!(contents(&list), vec!["a", "z"]);
+    }
+}
@@ -0,0 +1,349 @@
+/// A node of the tree. Its key is the concatenation of the prefixes from the
+/// root down to it; children are ordered by the first byte of their prefix.
+#[derive(Debug, Clone)]
+struct Node<V> {
+    prefix: Vec<u8>,
+    value: Option<V>,
+    children: Vec<Node<V>>,
+}
+
+impl<V> Node<V> {
+    fn new(prefix: Vec<u8>, value: Option<V>) -> Self {
+        Node {
+            prefix,
+            value,
+            children: Vec::new(),
+        }
+    }
+
+    fn child_position(&self, byte: u8) -> Result<usize, usize> {
+        self.children
+            .binary_search_by_key(&byte, |child| child.prefix[0])
+    }
+
+    /// Collapse a valueless node with a single child into that child.
+    fn compress(&mut self) {
+        if self.value.is_none() && self.children.len() == 1 {
+            let child = self.children.pop().unwrap();
+            self.prefix.extend_from_slice(&child.prefix);
+            self.value = child.value;
+            self.children = child.children;
+        }
+    }
+
+    fn count(&self) -> usize {
+        1 + self.children.iter().map(Node::count).sum::<usize>()
+    }
+
+    fn first<'a>(&'a self, path: &mut Vec<u8>) -> Option<&'a V> {
+        path.extend_from_slice(&self.prefix);
+        match &self.value {
+            Some(value) => Some(value),
+            None => self.children.first()?.first(path),
+        }
+    }
+
+    fn last<'a>(&'a self, path: &mut Vec<u8>) -> Option<&'a V> {
+        path.extend_from_slice(&self.prefix);
+        match self.children.last() {
+            Some(child) => child.last(path),
+            None => self.value.as_ref(),
+        }
+    }
+
+    /// The smallest entry at or after `key` (strictly after unless `inclusive`),
+    /// where `key` is relative to the end of this node's prefix.
+    fn seek_forward<'a>(
+        &'a self,
+        key: &[u8],
+        inclusive: bool,
+        path: &mut Vec<u8>,
+    ) -> Option<&'a V> {
+        if key.is_empty() {
+            if inclusive && self.value.is_some() {
+                return self.value.as_ref();
+            }
+            return self.children.first().and_then(|child| child.first(path));
+        }
+        for child in &self.children {
+            let len = path.len();
+            let found = match child.prefix[0].cmp(&key[0]) {
+                std::cmp::Ordering::Less => None,
+                std::cmp::Ordering::Greater => child.first(path),
+                std::cmp::Ordering::Equal => {
+                    let common = common_prefix_len(&child.prefix, key);
+                    if common == child.prefix.len() {
+                        path.extend_from_slice(&child.prefix);
+                        child.seek_forward(&key[common..], inclusive, path)
+                    } else if common == key.len() || child.prefix[common] > key[common] {
+                        child.first(path)
+                    } else {
+                        None
+                    }
+                }
+            };
+            if found.is_some() {
+                return found;
+            }
+            path.truncate(len);
+        }
+        None
+    }
+
+    /// The largest entry at or before `key` (strictly before unless `inclusive`),
+    /// where `key` is relative to the end of this node's prefix.
+    fn seek_backward<'a>(
+        &'a self,
+        key: &[u8],
+        inclusive: bool,
+        path: &mut Vec<u8>,
+    ) -> Option<&'a V> {
+        if key.is_empty() {
+            return self.value.as_ref().filter(|_| inclusive);
+        }
+        for child in self.children.iter().rev() {
+            let len = path.len();
+            let found = match child.prefix[0].cmp(&key[0]) {
+                std::cmp::Ordering::Greater => None,
+                std::cmp::Ordering::Less => child.last(path),
+                std::cmp::Ordering::Equal => {
+                    let common = common_prefix_len(&child.prefix, key);
+                    if common == child.prefix.len() {
+                        path.extend_from_slice(&child.prefix);
+                        child.seek_backward(&key[common..], inclusive, path)
+                    } else if common < key.len() && child.prefix[common] < key[common] {
+                        child.last(path)
+                    } else {
+                        None
+                    }
+                }
+            };
+            if found.is_some() {
+                return found;
+            }
+            path.truncate(len);
+        }
+        self.value.as_ref()
+    }
+}
+
+fn common_prefix_len(a: &[u8], b: &[u8]) -> usize {
+    a.iter().zip(b).take_while(|(a, b)| a == b).count()
+}
+
+/// A compressed radix tree mapping byte strings to values, kept in byte order so
+/// that it can be seeked and walked in both directions like redis' rax.
+#[derive(Debug, Clone)]
+pub struct Rax<V> {
+    root: Node<V>,
+    len: usize,
+}
+
+impl<V> Default for Rax<V> {
+    fn default() -> Self {
+        Rax {
+            root: Node::new(Vec::new(), None),
+            len: 0,
+        }
+    }
+}
+
+impl<V> Rax<V> {
+    pub fn new() -> Self {
+        Rax::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Number of nodes in the tree, including the root.
+    pub fn node_count(&self) -> usize {
+        self.root.count()
+    }
+
+    pub fn insert(&mut self, key: &[u8], value: V) -> Option<V> {
+        let mut node = &mut self.root;
+        let mut key = key;
+        loop {
+            if key.is_empty() {
+                let old = node.value.replace(value);
+                if old.is_none() {
+                    self.len += 1;
+                }
+                return old;
+            }
+            let index = match node.child_position(key[0]) {
+                Ok(index) => index,
+                Err(index) => {
+                    node.children
+                        .insert(index, Node::new(key.to_vec(), Some(value)));
+                    self.len += 1;
+                    return None;
+                }
+            };
+            let child = &mut node.children[index];
+            let common = common_prefix_len(&child.prefix, key);
+            if common < child.prefix.len() {
+                let suffix = child.prefix.split_off(common);
+                let mut split = Node::new(suffix, child.value.take());
+                split.children = std::mem::take(&mut child.children);
+                child.children.push(split);
+            }
+            node = child;
+            key = &key[common..];
+        }
+    }
+
+    pub fn get(&self, key: &[u8]) -> Option<&V> {
+        let mut node = &self.root;
+        let mut key = key;
+        while !key.is_empty() {
+            let child = &node.children[node.child_position(key[0]).ok()?];
+            key = key.strip_prefix(child.prefix.as_slice())?;
+            node = child;
+        }
+        node.value.as_ref()
+    }
+
+    pub fn get_mut(&mut self, key: &[u8]) -> Option<&mut V> {
+        let mut node = &mut self.root;
+        let mut key = key;
+        while !key.is_empty() {
+            let index = node.child_position(key[0]).ok()?;
+            let child = &mut node.children[index];
+            key = key.strip_prefix(child.prefix.as_slice())?;
+            node = child;
+        }
+        node.value.as_mut()
+    }
+
+    pub fn remove(&mut self, key: &[u8]) -> Option<V> {
+        fn remove_from<V>(node: &mut Node<V>, key: &[u8]) -> Option<V> {
+            if key.is_empty() {
+                return node.value.take();
+            }
+            let index = node.child_position(key[0]).ok()?;
+            let child = &mut node.children[index];
+            let rest = key.strip_prefix(child.prefix.as_slice())?;
+            let removed = remove_from(child, rest)?;
+            if child.value.is_none() && child.children.is_empty() {
+                node.children.remove(index);
+            } else {
+                child.compress();
+            }
+            Some(removed)
+        }
+        let removed = remove_from(&mut self.root, key)?;
+        self.len -= 1;
+        Some(removed)
+    }
+
+    pub fn first(&self) -> Option<(Vec<u8>, &V)> {
+        let mut path = Vec::new();
+        let value = self.root.first(&mut path)?;
+        Some((path, value))
+    }
+
+    pub fn last(&self) -> Option<(Vec<u8>, &V)> {
+        let mut path = Vec::new();
+        let value = self.root.last(&mut path)?;
+        Some((path, value))
+    }
+
+    /// The first entry whose key is at or after `key`, or strictly after it
+    /// unless `inclusive`.
+    pub fn seek_forward(&self, key: &[u8], inclusive: bool) -> Option<(Vec<u8>, &V)> {
+        let mut path = Vec::new();
+        let value = self.root.seek_forward(key, inclusive, &mut path)?;
+        Some((path, value))
+    }
+
+    /// The last entry whose key is at or before `key`, or strictly before it
+    /// unless `inclusive`.
+    pub fn seek_backward(&self, key: &[u8], inclusive: bool) -> Option<(Vec<u8>, &V)> {
+        let mut path = Vec::new();
+        let value = self.root.seek_backward(key, inclusive, &mut path)?;
+        Some((path, value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys_forward(rax: &Rax<u32>) -> Vec<Vec<u8>> {
+        let mut keys = Vec::new();
+        let mut next = rax.first();
+        while let Some((key, _)) = next {
+            next = rax.seek_forward(&key, false);
+            keys.push(key);
+        }
+        keys
+    }
+
+    #[test]
+    fn test_insert_get_remove() {
+        let mut rax = Rax::new();
+        for (i, key) in [
+            "romane", "romanus", "romulus", "rubens", "ruber", "rom", "r",
+        ]
+        .iter()
+        .enumerate()
+        {
+            assert_eq!(rax.insert(key.as_bytes(), i as u32), None);
+        }
+        assert_eq!(rax.insert(b"rom", 10), Some(5));
+        assert_eq!(rax.len(), 7);
+        assert_eq!(rax.get(b"romulus"), Some(&2));
+        assert_eq!(rax.get(b"roma"), None);
+        assert_eq!(rax.get(b"rom"), Some(&10));
+
+        assert_eq!(rax.remove(b"rom"), Some(10));
+        assert_eq!(rax.remove(b"rom"), None);
+        assert_eq!(rax.remove(b"romanus"), Some(1));
+        assert_eq!(rax.get(b"romane"), Some(&0));
+        *rax.get_mut(b"ruber").unwrap() += 100;
+        assert_eq!(rax.get(b"ruber"), Some(&104));
+        assert_eq!(rax.len(), 5);
+        assert_eq!(
+            keys_forward(&rax),
+            ["r", "romane", "romulus", "rubens", "ruber"].map(|key| key.as_bytes().to_vec())
+        );
+    }
+
+    #[test]
+    fn test_seek() {
+        let mut rax = Rax::new();
+        for key in [10u64, 20, 30, 256, 1000] {
+            rax.insert(&key.to_be_bytes(), key as u32);
+        }
+        let forward = |key: u64, inclusive| {
+            rax.seek_forward(&key.to_be_bytes(), inclusive)
+                .map(|(_, value)| *value)
+        };
+        let backward = |key: u64, inclusive| {
+            rax.seek_backward(&key.to_be_bytes(), inclusive)
+                .map(|(_, value)| *value)
+        };
+        assert_eq!(forward(0, true), Some(10));
+        assert_eq!(forward(20, true), Some(20));
+        assert_eq!(forward(20, false), Some(30));
+        assert_eq!(forward(31, true), Some(256));
+        assert_eq!(forward(1000, false), None);
+        assert_eq!(backward(255, true), Some(30));
+        assert_eq!(backward(256, false), Some(30));
+        assert_eq!(backward(256, true), Some(256));
+        assert_eq!(backward(10, false), None);
+        assert_eq!(backward(u64::MAX, true), Some(1000));
+        assert_eq!(rax.first().map(|(_, value)| *value), Some(10));
+        assert_eq!(
+            rax.last().map(|(key, _)| key),
+            Some(1000u64.to_be_bytes().to_vec())
+        );
+    }
+}
@@ -0,0 +1,286 @@
+use bytes::Bytes;
+
+use super::dict::Dict;
+use super::encoding_limits;
+use super::listpack::ListPack;
+use crate::util::{canonical_int, random_distinct_indexes, random_index};
+
+#[derive(Debug, Clone)]
+enum Members {
+    /// Sets made only of integers are kept as a sorted array searched with a
+    /// binary search, which takes a fraction of the memory of a hash table.
+    IntSet(Vec<i64>),
+    /// Other small sets are kept as an unordered listpack searched linearly.
+    ListPack(ListPack),
+    Table(Dict<Bytes, ()>),
+}
+
+/// A set value, converting itself from the integer encoding to a listpack
+/// once it holds a non integer member, and to a real hash table once it
+/// outgrows the size thresholds. It never converts back.
+#[derive(Debug, Clone)]
+pub struct Set {
+    members: Members,
+}
+
+impl Default for Set {
+    fn default() -> Self {
+        Set {
+            members: Members::IntSet(Vec::new()),
+        }
+    }
+}
+
+impl Set {
+    pub fn new() -> Self {
+        Set::default()
+    }
+
+    pub fn len(&self) -> usize {
+        match &self.members {
+            Members::IntSet(ints) => ints.len(),
+            Members::ListPack(listpack) => listpack.len(),
+            Members::Table(table) => table.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Name of the encoding in use, as reported by OBJECT ENCODING.
+    pub fn encoding(&self) -> &'static str {
+        match &self.members {
+            Members::IntSet(_) => "intset",
+            Members::ListPack(_) => "listpack",
+            Members::Table(_) => "hashtable",
+        }
+    }
+
+    pub fn contains(&self, member: &[u8]) -> bool {
+        match &self.members {
+            Members::IntSet(ints) => {
+                canonical_int(member).is_some_and(|value| ints.binary_search(&value).is_ok())
+            }
+            Members::ListPack(listpack) => listpack.position(member).is_some(),
+            Members::Table(table) => table.contains_key(member),
+        }
+    }
+
+    /// Add `member`, returning true if it was not already in the set.
+    pub fn insert(&mut self, member: Bytes) -> bool {
+        let limits = encoding_limits();
+        let fits_listpack = |len: usize| {
+            len < limits.set_max_listpack_entries && member.len() <= limits.set_max_listpack_value
+        };
+        match &mut self.members {
+            Members::IntSet(ints) => {
+                if let Some(value) = canonical_int(&member) {
+                    let Err(position) = ints.binary_search(&value) else {
+                        return false;
+                    };
+                    if ints.len() < limits.set_max_intset_entries {
+                        ints.insert(position, value);
+                        return true;
+                    }
+                    self.convert_to_table();
+                } else if fits_listpack(ints.len()) {
+                    self.convert_to_listpack();
+                } else {
+                    self.convert_to_table();
+                }
+            }
+            Members::ListPack(listpack) => {
+                if listpack.position(&member).is_some() {
+                    return false;
+                }
+                if !fits_listpack(listpack.len()) {
+                    self.convert_to_table();
+                }
+            }
+            Members::Table(_) => {}
+        }
+        match &mut self.members {
+            Members::IntSet(_) => unreachable!(),
+            Members::ListPack(listpack) => {
+                listpack.push_back(&member);
+                true
+            }
+            Members::Table(table) => table.insert(member, ()).is_none(),
+        }
+    }
+
+    /// Remove `member`, returning true if it was in the set.
+    pub fn remove(&mut self, member: &[u8]) -> bool {
+        match &mut self.members {
+            Members::IntSet(ints) => {
+                match canonical_int(member).map(|value| ints.binary_search(&value)) {
+                    Some(Ok(position)) => {
+                        ints.remove(position);
+                        true
+                    }
+                    _ => false,
+                }
+            }
+            Members::ListPack(listpack) => match listpack.position(member) {
+                Some(position) => {
+                    listpack.remove(position);
+                    true
+                }
+                None => false,
+            },
+            Members::Table(table) => table.remove(member).is_some(),
+        }
+    }
+
+    fn convert_to_listpack(&mut self) {
+        if let Members::IntSet(ints) = &self.members {
+            let listpack = ints.iter().map(|value| value.to_string()).fold(
+                ListPack::new(),
+                |mut listpack, member| {
+                    listpack.push_back(member.as_bytes());
+                    listpack
+                },
+            );
+            self.members = Members::ListPack(listpack);
+        }
+    }
+
+    fn convert_to_table(&mut self) {
+        if !matches!(self.members, Members::Table(_)) {
+            let table = self.iter().map(|member| (member, ())).collect();
+            self.members = Members::Table(table);
+        }
+    }
+
+    /// Visit a batch of about `count` members starting at `cursor`, returning
+    /// the cursor to continue from, 0 once done. The compact encodings are
+    /// visited in one go.
+    pub fn scan(&self, cursor: u64, count: usize, mut visit: impl FnMut(Bytes)) -> u64 {
+        match &self.members {
+            Members::IntSet(ints) => {
+                for value in ints {
+                    visit(Bytes::from(value.to_string()));
+                }
+                0
+            }
+            Members::ListPack(listpack) => {
+                for member in listpack.iter() {
+                    visit(Bytes::copy_from_slice(member));
+                }
+                0
+            }
+            Members::Table(table) => table.scan_batch(cursor, count, |member, _| {
+                visit(member.clone());
+            }),
+        }
+    }
+
+    /// The members, in ascending order for the integer encoding.
+    pub fn iter(&self) -> Box<dyn Iterator<Item = Bytes> + '_> {
+        match &self.members {
+            Members::IntSet(ints) => {
+                Box::new(ints.iter().map(|value| Bytes::from(value.to_string())))
+            }
+            Members::ListPack(listpack) => Box::new(listpack.iter().map(Bytes::copy_from_slice)),
+            Members::Table(table) => Box::new(table.keys().cloned()),
+        }
+    }
+
+    /// A random member, or None if the set is empty.
+    pub fn random_member(&self) -> Option<Bytes> {
+        if self.is_empty() {
+            return None;
+        }
+        self.iter().nth(random_index(self.len()))
+    }
+
+    /// `count` distinct random members, or the whole set if it is smaller.
+    pub fn random_members(&self, count: usize) -> Vec<Bytes> {
+        let members: Vec<Bytes> = self.iter().collect();
+        random_distinct_indexes(members.len(), count)
+            .into_iter()
+            .map(|index| members[index].clone())
+            .collect()
+    }
+
+    /// Remove and return up to `count` distinct random members.
+    pub fn pop_random(&mut self, count: usize) -> Vec<Bytes> {
+        let popped = self.random_members(count);
+        for member in &popped {
+            self.remove(member);
+        }
+        popped
+    }
+}
+
+impl FromIterator<Bytes> for Set {
+    fn from_iter<I: IntoIterator<Item = Bytes>>(members: I) -> Self {
+        let mut set = Set::new();
+        for member in members {
+            set.insert(member);
+        }
+        set
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::EncodingLimits;
+
+    #[test]
+    fn test_intset_stays_sorted_and_canonical() {
+        let mut set = Set::new();
+        assert!(set.insert(Bytes::from("3")));
+        assert!(set.insert(Bytes::from("-1")));
+        assert!(!set.insert(Bytes::from("3")));
+        assert_eq!(set.encoding(), "intset");
+        assert!(set.contains(b"-1"));
+        assert!(!set.contains(b"03"));
+        assert_eq!(
+            set.iter().collect::<Vec<_>>(),
+            vec![Bytes::from("-1"), Bytes::from("3")]
+        );
+
+        assert!(set.insert(Bytes::from("03")));
+        assert_eq!(set.encoding(), "listpack");
+        assert!(set.contains(b"3") && set.contains(b"03"));
+        assert!(set.remove(b"-1"));
+        assert_eq!(set.len(), 2);
+    }
+
+    #[test]
+    fn test_converts_on_entry_count() {
+        let limits = EncodingLimits::DEFAULT;
+        let mut set: Set = (0..limits.set_max_intset_entries)
+            .map(|i| Bytes::from(i.to_string()))
+            .collect();
+        assert_eq!(set.encoding(), "intset");
+        set.insert(Bytes::from(limits.set_max_intset_entries.to_string()));
+        assert_eq!(set.encoding(), "hashtable");
+        assert_eq!(set.len(), limits.set_max_intset_entries + 1);
+
+        let mut set: Set = (0..limits.set_max_listpack_entries)
+            .map(|i| Bytes::from(format!("m{}", i)))
+            .collect();
+        assert_eq!(set.encoding(), "listpack");
+        assert!(!set.insert(Bytes::from("m0")));
+        assert!(set.remove(b"m0") && !set.contains(b"m0"));
+        set.insert(Bytes::from("m0"));
+        set.insert(Bytes::from("one more"));
+        assert_eq!(set.encoding(), "hashtable");
+        assert_eq!(set.len(), limits.set_max_listpack_entries + 1);
+    }
+
+    #[test]
+    fn test_pop_random() {
+        let mut set: Set = ["a", "b", "c"].into_iter().map(Bytes::from).collect();
+        let popped = set.pop_random(2);
+        assert_eq!(popped.len(), 2);
+        assert_eq!(set.len(), 1);
+        assert!(popped.iter().all(|member| !set.contains(member)));
+        assert_eq!(set.pop_random(5).len(), 1);
+        assert!(set.is_empty());
+    }
+}
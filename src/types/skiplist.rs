@@ -0,0 +1,353 @@
+use std::cmp::Ordering;
+
+use bytes::Bytes;
+
+use crate::util::random_u64;
+
+/// Index of the header node, which holds no element.
+const HEAD: usize = 0;
+/// Marks the absence of a node.
+const NIL: usize = usize::MAX;
+const MAX_LEVEL: usize = 32;
+/// Probability of a node being promoted to the next level.
+const LEVEL_PROBABILITY: f64 = 0.25;
+
+#[derive(Debug, Clone, Copy)]
+struct Level {
+    forward: usize,
+    /// Number of elements skipped by following `forward`, used to compute ranks.
+    span: usize,
+}
+
+#[derive(Debug, Clone)]
+struct Node {
+    member: Bytes,
+    score: f64,
+    backward: usize,
+    levels: Vec<Level>,
+}
+
+/// Order of elements in a sorted set: by score, then by member bytes.
+pub fn compare(a: (f64, &[u8]), b: (f64, &[u8])) -> Ordering {
+    a.0.partial_cmp(&b.0)
+        .unwrap_or(Ordering::Equal)
+        .then_with(|| a.1.cmp(b.1))
+}
+
+/// A skiplist of (score, member) elements with spans on every link, giving
+/// O(log n) insertion, removal, rank and by-rank lookups. Nodes live in an
+/// arena and link to each other by index.
+#[derive(Debug, Clone)]
+pub struct SkipList {
+    nodes: Vec<Node>,
+    free: Vec<usize>,
+    tail: usize,
+    level: usize,
+    len: usize,
+}
+
+impl Default for SkipList {
+    fn default() -> Self {
+        let head = Node {
+            member: Bytes::new(),
+            score: 0.0,
+            backward: NIL,
+            levels: vec![
+                Level {
+                    forward: NIL,
+                    span: 0
+                };
+                MAX_LEVEL
+            ],
+        };
+        SkipList {
+            nodes: vec![head],
+            free: Vec::new(),
+            tail: NIL,
+            level: 1,
+            len: 0,
+        }
+    }
+}
+
+fn random_level() -> usize {
+    let threshold = (LEVEL_PROBABILITY * u16::MAX as f64) as u64;
+    let mut level = 1;
+    while level < MAX_LEVEL && (random_u64() & 0xffff) < threshold {
+        level += 1;
+    }
+    level
+}
+
+impl SkipList {
+    pub fn new() -> Self {
+        SkipList::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn forward(&self, node: usize, level: usize) -> usize {
+        self.nodes[node].levels[level].forward
+    }
+
+    fn is_before(&self, node: usize, score: f64, member: &[u8]) -> bool {
+        let node = &self.nodes[node];
+        compare((node.score, &node.member), (score, member)) == Ordering::Less
+    }
+
+    /// For every level, the last node before (score, member) and its rank.
+    fn find_update(&self, score: f64, member: &[u8]) -> ([usize; MAX_LEVEL], [usize; MAX_LEVEL]) {
+        let mut update = [HEAD; MAX_LEVEL];
+        let mut rank = [0; MAX_LEVEL];
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            rank[i] = if i == self.level - 1 { 0 } else { rank[i + 1] };
+            loop {
+                let next = self.forward(x, i);
+                if next == NIL || !self.is_before(next, score, member) {
+                    break;
+                }
+                rank[i] += self.nodes[x].levels[i].span;
+                x = next;
+            }
+            update[i] = x;
+        }
+        (update, rank)
+    }
+
+    /// Insert an element. The caller makes sure the member is not already present.
+    pub fn insert(&mut self, member: Bytes, score: f64) {
+        let (mut update, mut rank) = self.find_update(score, &member);
+        let level = random_level();
+        if level > self.level {
+            for i in self.level..level {
+                rank[i] = 0;
+                update[i] = HEAD;
+                self.nodes[HEAD].levels[i].span = self.len;
+            }
+            self.level = level;
+        }
+
+        let node = Node {
+            member,
+            score,
+            backward: if update[0] == HEAD { NIL } else { update[0] },
+            levels: vec![
+                Level {
+                    forward: NIL,
+                    span: 0
+                };
+                level
+            ],
+        };
+        let new = match self.free.pop() {
+            Some(index) => {
+                self.nodes[index] = node;
+                index
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        };
+
+        for i in 0..level {
+            let previous = update[i];
+            let skipped = rank[0] - rank[i];
+            self.nodes[new].levels[i] = Level {
+                forward: self.forward(previous, i),
+                span: self.nodes[previous].levels[i].span - skipped,
+            };
+            self.nodes[previous].levels[i] = Level {
+                forward: new,
+                span: skipped + 1,
+            };
+        }
+        for (i, previous) in update.iter().enumerate().take(self.level).skip(level) {
+            self.nodes[*previous].levels[i].span += 1;
+        }
+
+        match self.forward(new, 0) {
+            NIL => self.tail = new,
+            next => self.nodes[next].backward = new,
+        }
+        self.len += 1;
+    }
+
+    /// Remove an element, returning false if it is not in the list.
+    pub fn remove(&mut self, member: &[u8], score: f64) -> bool {
+        let (update, _) = self.find_update(score, member);
+        let x = self.forward(update[0], 0);
+        if x == NIL || self.nodes[x].score != score || self.nodes[x].member != member {
+            return false;
+        }
+
+        for (i, previous) in update.iter().enumerate().take(self.level) {
+            if self.forward(*previous, i) == x {
+                self.nodes[*previous].levels[i] = Level {
+                    forward: self.forward(x, i),
+                    span: self.nodes[*previous].levels[i].span + self.nodes[x].levels[i].span - 1,
+                };
+            } else {
+                self.nodes[*previous].levels[i].span -= 1;
+            }
+        }
+        match self.forward(x, 0) {
+            NIL => self.tail = self.nodes[x].backward,
+            next => self.nodes[next].backward = self.nodes[x].backward,
+        }
+        while self.level > 1 && self.forward(HEAD, self.level - 1) == NIL {
+            self.level -= 1;
+        }
+
+        self.nodes[x].member = Bytes::new();
+        self.nodes[x].levels = Vec::new();
+        self.free.push(x);
+        self.len -= 1;
+        true
+    }
+
+    /// Number of leading elements satisfying `predicate`, which must hold for a
+    /// prefix of the list and not after it (e.g. "score is below some bound").
+    pub fn count_prefix(&self, predicate: impl Fn(f64, &[u8]) -> bool) -> usize {
+        let mut x = HEAD;
+        let mut count = 0;
+        for i in (0..self.level).rev() {
+            loop {
+                let next = self.forward(x, i);
+                if next == NIL || !predicate(self.nodes[next].score, &self.nodes[next].member) {
+                    break;
+                }
+                count += self.nodes[x].levels[i].span;
+                x = next;
+            }
+        }
+        count
+    }
+
+    /// The node at the 0 based `rank`, or NIL if out of range.
+    fn node_at(&self, rank: usize) -> usize {
+        let target = rank + 1;
+        let mut traversed = 0;
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            loop {
+                let next = self.forward(x, i);
+                if next == NIL || traversed + self.nodes[x].levels[i].span > target {
+                    break;
+                }
+                traversed += self.nodes[x].levels[i].span;
+                x = next;
+            }
+            if traversed == target {
+                return x;
+            }
+        }
+        NIL
+    }
+
+    /// The elements with a rank in `start..end`, walking backwards if `rev`.
+    pub fn range(&self, start: usize, end: usize, rev: bool) -> Iter<'_> {
+        let end = end.min(self.len);
+        let (node, remaining) = match (start < end, rev) {
+            (false, _) => (NIL, 0),
+            (true, false) => (self.node_at(start), end - start),
+            (true, true) if end == self.len => (self.tail, end - start),
+            (true, true) => (self.node_at(end - 1), end - start),
+        };
+        Iter {
+            list: self,
+            node,
+            remaining,
+            rev,
+        }
+    }
+}
+
+pub struct Iter<'a> {
+    list: &'a SkipList,
+    node: usize,
+    remaining: usize,
+    rev: bool,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = (&'a Bytes, f64);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 || self.node == NIL {
+            return None;
+        }
+        let node = &self.list.nodes[self.node];
+        self.node = if self.rev {
+            node.backward
+        } else {
+            node.levels[0].forward
+        };
+        self.remaining -= 1;
+        Some((&node.member, node.score))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn members(iter: Iter<'_>) -> Vec<String> {
+        iter.map(|(member, _)| String::from_utf8_lossy(member).into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn test_insert_orders_by_score_then_member() {
+        let mut list = SkipList::new();
+        list.insert(Bytes::from("c"), 2.0);
+        list.insert(Bytes::from("a"), 1.0);
+        list.insert(Bytes::from("b"), 2.0);
+        assert_eq!(list.len(), 3);
+        assert_eq!(members(list.range(0, 3, false)), ["a", "b", "c"]);
+        assert_eq!(members(list.range(0, 3, true)), ["c", "b", "a"]);
+        assert_eq!(members(list.range(1, 10, false)), ["b", "c"]);
+        assert_eq!(list.count_prefix(|score, _| score < 2.0), 1);
+    }
+
+    #[test]
+    fn test_ranks_survive_many_updates() {
+        let mut list = SkipList::new();
+        for i in 0..1000 {
+            list.insert(Bytes::from(format!("{:04}", i)), (i % 100) as f64);
+        }
+        for i in (0..1000).step_by(3) {
+            assert!(list.remove(format!("{:04}", i).as_bytes(), (i % 100) as f64));
+        }
+        assert!(!list.remove(b"0000", 0.0));
+        assert_eq!(list.len(), 666);
+
+        let all: Vec<(Bytes, f64)> = list
+            .range(0, list.len(), false)
+            .map(|(member, score)| (member.clone(), score))
+            .collect();
+        assert!(all.windows(2).all(|pair| compare(
+            (pair[0].1, &pair[0].0),
+            (pair[1].1, &pair[1].0)
+        )
+        .is_lt()));
+        for (rank, (member, score)) in all.iter().enumerate() {
+            assert_eq!(list.range(rank, rank + 1, false).next().unwrap().0, member);
+            assert_eq!(
+                list.count_prefix(|s, m| compare((s, m), (*score, member)).is_lt()),
+                rank
+            );
+        }
+        assert_eq!(
+            list.range(0, list.len(), true).next().unwrap().0,
+            &all.last().unwrap().0
+        );
+    }
+}
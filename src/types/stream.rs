@@ -0,0 +1,704 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use bytes::Bytes;
+
+use super::rax::Rax;
+
+/// Largest number of entries in a block.
+pub const STREAM_NODE_MAX_ENTRIES: usize = 100;
+/// Largest size in bytes of the fields and values of a block.
+const STREAM_NODE_MAX_BYTES: usize = 4096;
+
+/// A stream entry ID: a millisecond timestamp and a sequence number.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct StreamId {
+    pub ms: u64,
+    pub seq: u64,
+}
+
+impl StreamId {
+    pub const MIN: StreamId = StreamId { ms: 0, seq: 0 };
+    pub const MAX: StreamId = StreamId {
+        ms: u64::MAX,
+        seq: u64::MAX,
+    };
+
+    pub fn new(ms: u64, seq: u64) -> Self {
+        StreamId { ms, seq }
+    }
+
+    /// The ID right after this one, if any.
+    pub fn next(&self) -> Option<StreamId> {
+        match self.seq.checked_add(1) {
+            Some(seq) => Some(StreamId::new(self.ms, seq)),
+            None => Some(StreamId::new(self.ms.checked_add(1)?, 0)),
+        }
+    }
+
+    /// The ID right before this one, if any.
+    pub fn prev(&self) -> Option<StreamId> {
+        match self.seq.checked_sub(1) {
+            Some(seq) => Some(StreamId::new(self.ms, seq)),
+            None => Some(StreamId::new(self.ms.checked_sub(1)?, u64::MAX)),
+        }
+    }
+
+    /// Big endian encoding, which sorts like the IDs themselves.
+    pub fn to_be_bytes(&self) -> [u8; 16] {
+        let mut bytes = [0; 16];
+        bytes[..8].copy_from_slice(&self.ms.to_be_bytes());
+        bytes[8..].copy_from_slice(&self.seq.to_be_bytes());
+        bytes
+    }
+
+    pub fn from_be_bytes(bytes: &[u8]) -> Self {
+        StreamId::new(
+            u64::from_be_bytes(bytes[..8].try_into().unwrap()),
+            u64::from_be_bytes(bytes[8..16].try_into().unwrap()),
+        )
+    }
+}
+
+impl fmt::Display for StreamId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.ms, self.seq)
+    }
+}
+
+pub type Fields = Vec<(Bytes, Bytes)>;
+
+#[derive(Debug, Clone)]
+pub struct StreamEntry {
+    pub id: StreamId,
+    pub fields: Fields,
+}
+
+/// A run of consecutive entries, stored in the tree under the ID of its first
+/// entry, the way redis packs entries into listpacks.
+#[derive(Debug, Clone, Default)]
+struct Block {
+    entries: Vec<StreamEntry>,
+    bytes: usize,
+}
+
+fn fields_size(fields: &Fields) -> usize {
+    fields
+        .iter()
+        .map(|(field, value)| field.len() + value.len())
+        .sum()
+}
+
+/// An entry delivered to a consumer of a group and not acknowledged yet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingEntry {
+    pub consumer: Bytes,
+    /// Unix time in milliseconds of the last delivery.
+    pub delivery_time: i64,
+    pub delivery_count: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Consumer {
+    /// Unix time in milliseconds of the last interaction with the group.
+    pub seen_time: i64,
+    /// Unix time in milliseconds of the last successful read or claim, -1 if none.
+    pub active_time: i64,
+    /// IDs of the entries pending for this consumer.
+    pub pending: BTreeSet<StreamId>,
+}
+
+impl Consumer {
+    fn new(now_ms: i64) -> Self {
+        Consumer {
+            seen_time: now_ms,
+            active_time: -1,
+            pending: BTreeSet::new(),
+        }
+    }
+}
+
+/// A consumer group: the last entry delivered to it, and the entries
+/// delivered but not yet acknowledged, both per group and per consumer.
+#[derive(Debug, Clone, Default)]
+pub struct ConsumerGroup {
+    pub last_id: StreamId,
+    /// Logical number of entries read by the group, None when it is unknown.
+    pub entries_read: Option<u64>,
+    pub pending: BTreeMap<StreamId, PendingEntry>,
+    pub consumers: BTreeMap<Bytes, Consumer>,
+}
+
+impl ConsumerGroup {
+    pub fn new(last_id: StreamId, entries_read: Option<u64>) -> Self {
+        ConsumerGroup {
+            last_id,
+            entries_read,
+            ..ConsumerGroup::default()
+        }
+    }
+
+    /// The consumer called `name`, created if needed, marked as seen at `now_ms`.
+    pub fn consumer_mut(&mut self, name: &Bytes, now_ms: i64) -> &mut Consumer {
+        let consumer = self
+            .consumers
+            .entry(name.clone())
+            .or_insert_with(|| Consumer::new(now_ms));
+        consumer.seen_time = now_ms;
+        consumer
+    }
+
+    /// Create a consumer, returning false if it already exists.
+    pub fn create_consumer(&mut self, name: &Bytes, now_ms: i64) -> bool {
+        if self.consumers.contains_key(name) {
+            return false;
+        }
+        self.consumers.insert(name.clone(), Consumer::new(now_ms));
+        true
+    }
+
+    /// Delete a consumer along with its pending entries, returning how many
+    /// entries it had pending.
+    pub fn delete_consumer(&mut self, name: &[u8]) -> Option<usize> {
+        let consumer = self.consumers.remove(name)?;
+        for id in &consumer.pending {
+            self.pending.remove(id);
+        }
+        Some(consumer.pending.len())
+    }
+
+    /// Record `id` as delivered to `consumer`, taking it away from the
+    /// consumer it was pending for, if any.
+    pub fn assign(
+        &mut self,
+        id: StreamId,
+        consumer: &Bytes,
+        delivery_time: i64,
+        delivery_count: u64,
+    ) {
+        let entry = PendingEntry {
+            consumer: consumer.clone(),
+            delivery_time,
+            delivery_count,
+        };
+        if let Some(previous) = self.pending.insert(id, entry) {
+            if let Some(owner) = self.consumers.get_mut(&previous.consumer) {
+                owner.pending.remove(&id);
+            }
+        }
+        self.consumer_mut(consumer, delivery_time)
+            .pending
+            .insert(id);
+    }
+
+    /// Acknowledge `id`, returning false if it was not pending.
+    pub fn ack(&mut self, id: StreamId) -> bool {
+        let Some(entry) = self.pending.remove(&id) else {
+            return false;
+        };
+        if let Some(consumer) = self.consumers.get_mut(&entry.consumer) {
+            consumer.pending.remove(&id);
+        }
+        true
+    }
+}
+
+/// A stream value: an append only log of entries ordered by ID.
+#[derive(Debug, Clone, Default)]
+pub struct Stream {
+    blocks: Rax<Block>,
+    len: usize,
+    last_id: StreamId,
+    /// Largest ID ever deleted by XDEL.
+    max_deleted_id: StreamId,
+    /// Number of entries ever added, including deleted ones.
+    entries_added: u64,
+    groups: BTreeMap<Bytes, ConsumerGroup>,
+}
+
+impl Stream {
+    pub fn new() -> Self {
+        Stream::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn last_id(&self) -> StreamId {
+        self.last_id
+    }
+
+    pub fn max_deleted_id(&self) -> StreamId {
+        self.max_deleted_id
+    }
+
+    pub fn entries_added(&self) -> u64 {
+        self.entries_added
+    }
+
+    /// Number of blocks in the tree, as reported by XINFO STREAM.
+    pub fn radix_tree_keys(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn radix_tree_nodes(&self) -> usize {
+        self.blocks.node_count()
+    }
+
+    /// The ID an auto generated ID would get at unix time `now_ms`, or None if
+    /// the sequence number is exhausted.
+    pub fn next_id(&self, now_ms: u64) -> Option<StreamId> {
+        if now_ms > self.last_id.ms {
+            Some(StreamId::new(now_ms, 0))
+        } else {
+            self.last_id.next()
+        }
+    }
+
+    /// Append an entry. The caller makes sure `id` is greater than the last ID.
+    pub fn add(&mut self, id: StreamId, fields: Fields) {
+        let size = fields_size(&fields);
+        let entry = StreamEntry { id, fields };
+        let last = self.blocks.last().map(|(key, block)| {
+            let full = block.entries.len() >= STREAM_NODE_MAX_ENTRIES
+                || block.bytes + size > STREAM_NODE_MAX_BYTES;
+            (key, full)
+        });
+        match last {
+            Some((key, false)) => {
+                let block = self.blocks.get_mut(&key).unwrap();
+                block.entries.push(entry);
+                block.bytes += size;
+            }
+            _ => {
+                let block = Block {
+                    entries: vec![entry],
+                    bytes: size,
+                };
+                self.blocks.insert(&id.to_be_bytes(), block);
+            }
+        }
+        self.len += 1;
+        self.last_id = id;
+        self.entries_added += 1;
+    }
+
+    /// Restore the stream's metadata, e.g. when loading it from a dump.
+    pub fn set_metadata(
+        &mut self,
+        last_id: StreamId,
+        max_deleted_id: StreamId,
+        entries_added: u64,
+    ) {
+        self.last_id = last_id;
+        self.max_deleted_id = max_deleted_id;
+        self.entries_added = entries_added;
+    }
+
+    pub fn first_entry(&self) -> Option<&StreamEntry> {
+        self.blocks.first()?.1.entries.first()
+    }
+
+    pub fn last_entry(&self) -> Option<&StreamEntry> {
+        self.blocks.last()?.1.entries.last()
+    }
+
+    pub fn get(&self, id: StreamId) -> Option<&StreamEntry> {
+        let (_, block) = self.blocks.seek_backward(&id.to_be_bytes(), true)?;
+        let position = block
+            .entries
+            .binary_search_by_key(&id, |entry| entry.id)
+            .ok()?;
+        Some(&block.entries[position])
+    }
+
+    /// The entries with IDs in `start..=end`, in descending order if `rev`,
+    /// stopping after `count` entries.
+    pub fn range(
+        &self,
+        start: StreamId,
+        end: StreamId,
+        rev: bool,
+        count: usize,
+    ) -> Vec<&StreamEntry> {
+        let mut entries = Vec::new();
+        if start > end || count == 0 {
+            return entries;
+        }
+        if rev {
+            let mut block = self.blocks.seek_backward(&end.to_be_bytes(), true);
+            while let Some((key, current)) = block {
+                for entry in current.entries.iter().rev() {
+                    if entry.id > end {
+                        continue;
+                    }
+                    if entry.id < start {
+                        return entries;
+                    }
+                    entries.push(entry);
+                    if entries.len() == count {
+                        return entries;
+                    }
+                }
+                block = self.blocks.seek_backward(&key, false);
+            }
+        } else {
+            let mut block = self
+                .blocks
+                .seek_backward(&start.to_be_bytes(), true)
+                .or_else(|| self.blocks.first());
+            while let Some((key, current)) = block {
+                for entry in &current.entries {
+                    if entry.id < start {
+                        continue;
+                    }
+                    if entry.id > end {
+                        return entries;
+                    }
+                    entries.push(entry);
+                    if entries.len() == count {
+                        return entries;
+                    }
+                }
+                block = self.blocks.seek_forward(&key, false);
+            }
+        }
+        entries
+    }
+
+    /// Delete the entry with `id`, returning false if there is none.
+    pub fn delete(&mut self, id: StreamId) -> bool {
+        if !self.remove_entry(id) {
+            return false;
+        }
+        self.max_deleted_id = self.max_deleted_id.max(id);
+        true
+    }
+
+    fn remove_entry(&mut self, id: StreamId) -> bool {
+        let Some((key, _)) = self.blocks.seek_backward(&id.to_be_bytes(), true) else {
+            return false;
+        };
+        let block = self.blocks.get_mut(&key).unwrap();
+        let Ok(position) = block.entries.binary_search_by_key(&id, |entry| entry.id) else {
+            return false;
+        };
+        let entry = block.entries.remove(position);
+        block.bytes -= fields_size(&entry.fields);
+        if block.entries.is_empty() {
+            self.blocks.remove(&key);
+        }
+        self.len -= 1;
+        true
+    }
+
+    /// Trim the oldest entries while `should_remove` holds, given an entry and
+    /// the stream length once everything up to it is removed. When
+    /// `approximate`, only whole blocks are removed, and at most `limit` entries
+    /// unless it is zero. Returns the number of entries removed.
+    fn trim(
+        &mut self,
+        approximate: bool,
+        limit: usize,
+        should_remove: impl Fn(&StreamEntry, usize) -> bool,
+    ) -> usize {
+        let mut removed = 0;
+        while let Some((key, block)) = self.blocks.first() {
+            let block_len = block.entries.len();
+            let within_limit = limit == 0 || removed + block_len <= limit;
+            if within_limit && should_remove(block.entries.last().unwrap(), self.len - block_len) {
+                self.blocks.remove(&key);
+                self.len -= block_len;
+                removed += block_len;
+                continue;
+            }
+            if !approximate {
+                while let Some(entry) = self.first_entry() {
+                    if !should_remove(entry, self.len - 1) {
+                        break;
+                    }
+                    self.remove_entry(entry.id);
+                    removed += 1;
+                }
+            }
+            break;
+        }
+        removed
+    }
+
+    /// Whether `trim` with the same arguments would remove any entry.
+    fn trims_any(
+        &self,
+        approximate: bool,
+        limit: usize,
+        should_remove: impl Fn(&StreamEntry, usize) -> bool,
+    ) -> bool {
+        let Some((_, block)) = self.blocks.first() else {
+            return false;
+        };
+        let block_len = block.entries.len();
+        let within_limit = limit == 0 || block_len <= limit;
+        if within_limit && should_remove(block.entries.last().unwrap(), self.len - block_len) {
+            return true;
+        }
+        !approximate
+            && self
+                .first_entry()
+                .is_some_and(|entry| should_remove(entry, self.len - 1))
+    }
+
+    /// Whether entries at or after `start` may have been deleted by XDEL.
+    pub fn has_tombstones(&self, start: StreamId) -> bool {
+        self.len > 0
+            && self.max_deleted_id != StreamId::MIN
+            && start <= self.max_deleted_id
+            && self.max_deleted_id <= self.last_id
+    }
+
+    /// The logical position of `id` in the stream, counting every entry ever
+    /// added, or None when deletions make it impossible to tell.
+    pub fn estimate_entries_read(&self, id: StreamId) -> Option<u64> {
+        if self.entries_added == 0 {
+            return Some(0);
+        }
+        if (self.len == 0 && id <= self.last_id) || id == self.last_id {
+            return Some(self.entries_added);
+        }
+        if id > self.last_id {
+            return None;
+        }
+        let first_id = self.first_entry().map_or(StreamId::MIN, |entry| entry.id);
+        if self.max_deleted_id == StreamId::MIN || self.max_deleted_id < first_id {
+            let before_first = self.entries_added - self.len as u64;
+            if id < first_id {
+                return Some(before_first);
+            }
+            if id == first_id {
+                return Some(before_first + 1);
+            }
+        }
+        None
+    }
+
+    /// Number of entries the group has yet to read, None when unknown.
+    pub fn group_lag(&self, group: &ConsumerGroup) -> Option<u64> {
+        if self.entries_added == 0 {
+            return Some(0);
+        }
+        let entries_read = match group.entries_read {
+            Some(read) if !self.has_tombstones(group.last_id) => Some(read),
+            _ => self.estimate_entries_read(group.last_id),
+        };
+        entries_read.map(|read| self.entries_added.saturating_sub(read))
+    }
+
+    pub fn groups(&self) -> &BTreeMap<Bytes, ConsumerGroup> {
+        &self.groups
+    }
+
+    pub fn group(&self, name: &[u8]) -> Option<&ConsumerGroup> {
+        self.groups.get(name)
+    }
+
+    pub fn group_mut(&mut self, name: &[u8]) -> Option<&mut ConsumerGroup> {
+        self.groups.get_mut(name)
+    }
+
+    /// Create a group, returning false if one with this name already exists.
+    pub fn create_group(&mut self, name: Bytes, group: ConsumerGroup) -> bool {
+        if self.groups.contains_key(&name) {
+            return false;
+        }
+        self.groups.insert(name, group);
+        true
+    }
+
+    pub fn destroy_group(&mut self, name: &[u8]) -> bool {
+        self.groups.remove(name).is_some()
+    }
+
+    /// Run `f` on the group called `name` while keeping read access to the
+    /// entries, which the group needs to resolve its pending IDs.
+    pub fn with_group<T>(
+        &mut self,
+        name: &[u8],
+        f: impl FnOnce(&Stream, &mut ConsumerGroup) -> T,
+    ) -> Option<T> {
+        let (name, mut group) = self.groups.remove_entry(name)?;
+        let result = f(self, &mut group);
+        self.groups.insert(name, group);
+        Some(result)
+    }
+
+    /// Deliver to `consumer` up to `count` entries the group never received,
+    /// adding them to the pending entries unless `noack`. Returns None if
+    /// there is no such group.
+    pub fn read_group(
+        &mut self,
+        group: &[u8],
+        consumer: &Bytes,
+        count: usize,
+        noack: bool,
+        now_ms: i64,
+    ) -> Option<Vec<StreamEntry>> {
+        self.with_group(group, |stream, group| {
+            let entries: Vec<StreamEntry> = match group.last_id.next() {
+                Some(start) => stream
+                    .range(start, StreamId::MAX, false, count)
+                    .into_iter()
+                    .cloned()
+                    .collect(),
+                None => Vec::new(),
+            };
+            group.consumer_mut(consumer, now_ms);
+            for entry in &entries {
+                group.entries_read = match group.entries_read {
+                    Some(read) if !stream.has_tombstones(entry.id) => Some(read + 1),
+                    _ => stream.estimate_entries_read(entry.id),
+                };
+                group.last_id = entry.id;
+                if !noack {
+                    group.assign(entry.id, consumer, now_ms, 1);
+                }
+            }
+            if !entries.is_empty() {
+                group.consumer_mut(consumer, now_ms).active_time = now_ms;
+            }
+            entries
+        })
+    }
+
+    /// Trim the stream to about `maxlen` entries.
+    pub fn trim_to_len(&mut self, maxlen: usize, approximate: bool, limit: usize) -> usize {
+        self.trim(approximate, limit, |_, len_after| len_after >= maxlen)
+    }
+
+    /// Remove the entries with an ID lower than `minid`.
+    pub fn trim_to_min_id(&mut self, minid: StreamId, approximate: bool, limit: usize) -> usize {
+        self.trim(approximate, limit, |entry, _| entry.id < minid)
+    }
+
+    /// Whether `trim_to_len` would remove any entry.
+    pub fn trims_to_len(&self, maxlen: usize, approximate: bool, limit: usize) -> bool {
+        self.trims_any(approximate, limit, |_, len_after| len_after >= maxlen)
+    }
+
+    /// Whether `trim_to_min_id` would remove any entry.
+    pub fn trims_to_min_id(&self, minid: StreamId, approximate: bool, limit: usize) -> bool {
+        self.trims_any(approximate, limit, |entry, _| entry.id < minid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields(value: &str) -> Fields {
+        vec![(Bytes::from("f"), Bytes::copy_from_slice(value.as_bytes()))]
+    }
+
+    fn sample(len: u64) -> Stream {
+        let mut stream = Stream::new();
+        for i in 1..=len {
+            stream.add(StreamId::new(i, 0), fields(&i.to_string()));
+        }
+        stream
+    }
+
+    fn ids(entries: Vec<&StreamEntry>) -> Vec<u64> {
+        entries.iter().map(|entry| entry.id.ms).collect()
+    }
+
+    #[test]
+    fn test_ids() {
+        assert_eq!(StreamId::new(1, u64::MAX).next(), Some(StreamId::new(2, 0)));
+        assert_eq!(StreamId::MAX.next(), None);
+        assert_eq!(StreamId::new(2, 0).prev(), Some(StreamId::new(1, u64::MAX)));
+        assert_eq!(StreamId::new(5, 7).to_string(), "5-7");
+        let id = StreamId::new(123, 456);
+        assert_eq!(StreamId::from_be_bytes(&id.to_be_bytes()), id);
+        let mut stream = sample(0);
+        assert_eq!(stream.next_id(10), Some(StreamId::new(10, 0)));
+        stream.add(StreamId::new(10, 5), fields("x"));
+        assert_eq!(stream.next_id(3), Some(StreamId::new(10, 6)));
+    }
+
+    #[test]
+    fn test_range_spans_blocks() {
+        let stream = sample(350);
+        assert_eq!(stream.len(), 350);
+        assert_eq!(stream.radix_tree_keys(), 4);
+        assert_eq!(
+            ids(stream.range(StreamId::new(98, 0), StreamId::new(103, 0), false, 100)),
+            [98, 99, 100, 101, 102, 103]
+        );
+        assert_eq!(
+            ids(stream.range(StreamId::new(98, 0), StreamId::MAX, true, 3)),
+            [350, 349, 348]
+        );
+        assert_eq!(
+            ids(stream.range(StreamId::MIN, StreamId::new(201, 0), true, 2)),
+            [201, 200]
+        );
+        assert!(stream
+            .range(StreamId::new(400, 0), StreamId::MAX, false, 10)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_delete_and_trim() {
+        let mut stream = sample(250);
+        assert!(stream.delete(StreamId::new(5, 0)));
+        assert!(!stream.delete(StreamId::new(5, 0)));
+        assert_eq!(stream.max_deleted_id(), StreamId::new(5, 0));
+
+        assert_eq!(stream.trim_to_len(120, true, 0), 99);
+        assert_eq!(stream.max_deleted_id(), StreamId::new(5, 0));
+        assert_eq!(stream.len(), 150);
+        assert_eq!(stream.trim_to_len(120, false, 0), 30);
+        assert_eq!(stream.first_entry().unwrap().id, StreamId::new(131, 0));
+        assert_eq!(stream.trim_to_min_id(StreamId::new(200, 0), false, 0), 69);
+        assert_eq!(stream.first_entry().unwrap().id, StreamId::new(200, 0));
+        assert_eq!(stream.len(), 51);
+        assert_eq!(stream.entries_added(), 250);
+        assert_eq!(stream.last_entry().unwrap().id, StreamId::new(250, 0));
+    }
+
+    #[test]
+    fn test_group_delivery_and_lag() {
+        let mut stream = sample(5);
+        let alice = Bytes::from("alice");
+        let bob = Bytes::from("bob");
+        stream.create_group(Bytes::from("g"), ConsumerGroup::new(StreamId::MIN, None));
+        assert_eq!(stream.group_lag(stream.group(b"g").unwrap()), Some(5));
+
+        let read = stream.read_group(b"g", &alice, 2, false, 100).unwrap();
+        assert_eq!(read.len(), 2);
+        let group = stream.group(b"g").unwrap();
+        assert_eq!(group.last_id, StreamId::new(2, 0));
+        assert_eq!(group.entries_read, Some(2));
+        assert_eq!(stream.group_lag(group), Some(3));
+
+        let group = stream.group_mut(b"g").unwrap();
+        group.assign(StreamId::new(1, 0), &bob, 200, 2);
+        assert_eq!(group.consumers[&alice].pending.len(), 1);
+        assert_eq!(group.consumers[&bob].pending.len(), 1);
+        assert!(group.ack(StreamId::new(1, 0)));
+        assert!(!group.ack(StreamId::new(1, 0)));
+        assert_eq!(group.delete_consumer(b"alice"), Some(1));
+        assert!(group.pending.is_empty());
+
+        stream.delete(StreamId::new(4, 0));
+        assert_eq!(stream.group_lag(stream.group(b"g").unwrap()), None);
+        stream.read_group(b"g", &bob, 10, true, 300).unwrap();
+        let group = stream.group(b"g").unwrap();
+        assert_eq!(group.entries_read, Some(5));
+        assert_eq!(stream.group_lag(group), Some(0));
+        assert!(stream.read_group(b"missing", &bob, 10, true, 300).is_none());
+    }
+}
@@ -0,0 +1,369 @@
+use bytes::Bytes;
+
+use super::dict::Dict;
+use super::encoding_limits;
+use super::listpack::ListPack;
+use super::skiplist::{compare, SkipList};
+
+#[derive(Debug, Clone)]
+enum Elements {
+    /// Small sorted sets are kept as a listpack of members each followed by
+    /// its score, sorted by (score, member).
+    ListPack(ListPack),
+    /// Larger ones pair a skiplist, for ordered access, with a member to score
+    /// map, for O(1) score lookups.
+    SkipList {
+        scores: Dict<Bytes, f64>,
+        list: SkipList,
+    },
+}
+
+/// A sorted set value, converting itself from the compact encoding to a
+/// skiplist once it outgrows the size thresholds. It never converts back.
+#[derive(Debug, Clone)]
+pub struct ZSet {
+    elements: Elements,
+}
+
+impl Default for ZSet {
+    fn default() -> Self {
+        ZSet {
+            elements: Elements::ListPack(ListPack::new()),
+        }
+    }
+}
+
+/// A score interval, each end being inclusive unless marked exclusive.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScoreRange {
+    pub min: f64,
+    pub max: f64,
+    pub min_exclusive: bool,
+    pub max_exclusive: bool,
+}
+
+impl ScoreRange {
+    pub fn above_min(&self, score: f64) -> bool {
+        if self.min_exclusive {
+            score > self.min
+        } else {
+            score >= self.min
+        }
+    }
+
+    pub fn below_max(&self, score: f64) -> bool {
+        if self.max_exclusive {
+            score < self.max
+        } else {
+            score <= self.max
+        }
+    }
+}
+
+/// One end of a lexicographical interval.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LexBound {
+    /// `-`, before every member.
+    Min,
+    /// `+`, after every member.
+    Max,
+    Inclusive(Bytes),
+    Exclusive(Bytes),
+}
+
+/// A member interval, only meaningful when all members have the same score.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LexRange {
+    pub min: LexBound,
+    pub max: LexBound,
+}
+
+impl LexRange {
+    pub fn above_min(&self, member: &[u8]) -> bool {
+        match &self.min {
+            LexBound::Min => true,
+            LexBound::Max => false,
+            LexBound::Inclusive(min) => member >= min.as_ref(),
+            LexBound::Exclusive(min) => member > min.as_ref(),
+        }
+    }
+
+    pub fn below_max(&self, member: &[u8]) -> bool {
+        match &self.max {
+            LexBound::Min => false,
+            LexBound::Max => true,
+            LexBound::Inclusive(max) => member <= max.as_ref(),
+            LexBound::Exclusive(max) => member < max.as_ref(),
+        }
+    }
+}
+
+fn decode_score(entry: &[u8]) -> f64 {
+    f64::from_le_bytes(entry.try_into().expect("listpack scores are 8 bytes"))
+}
+
+/// The (member, score) pairs of a listpack in ascending order.
+fn pairs(listpack: &ListPack) -> impl Iterator<Item = (&[u8], f64)> {
+    let mut entries = listpack.iter();
+    std::iter::from_fn(move || Some((entries.next()?, decode_score(entries.next()?))))
+}
+
+/// The (member, score) pairs of a listpack in descending order.
+fn pairs_rev(listpack: &ListPack) -> impl Iterator<Item = (&[u8], f64)> {
+    let mut entries = listpack.iter();
+    std::iter::from_fn(move || {
+        let score = decode_score(entries.next_back()?);
+        Some((entries.next_back()?, score))
+    })
+}
+
+impl ZSet {
+    pub fn new() -> Self {
+        ZSet::default()
+    }
+
+    pub fn len(&self) -> usize {
+        match &self.elements {
+            Elements::ListPack(listpack) => listpack.len() / 2,
+            Elements::SkipList { scores, .. } => scores.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Name of the encoding in use, as reported by OBJECT ENCODING.
+    pub fn encoding(&self) -> &'static str {
+        match &self.elements {
+            Elements::ListPack(_) => "listpack",
+            Elements::SkipList { .. } => "skiplist",
+        }
+    }
+
+    pub fn score(&self, member: &[u8]) -> Option<f64> {
+        match &self.elements {
+            Elements::ListPack(listpack) => pairs(listpack)
+                .find(|(m, _)| *m == member)
+                .map(|(_, score)| score),
+            Elements::SkipList { scores, .. } => scores.get(member).copied(),
+        }
+    }
+
+    /// Set the score of `member`, returning true if the member is new.
+    pub fn insert(&mut self, member: Bytes, score: f64) -> bool {
+        if let Elements::ListPack(listpack) = &self.elements {
+            let limits = encoding_limits();
+            if member.len() > limits.zset_max_listpack_value
+                || (listpack.len() / 2 >= limits.zset_max_listpack_entries
+                    && self.score(&member).is_none())
+            {
+                self.convert_to_skiplist();
+            }
+        }
+        let existed = self.remove(&member);
+        let position = self.count_prefix(|s, m| compare((s, m), (score, &member)).is_lt());
+        match &mut self.elements {
+            Elements::ListPack(listpack) => {
+                listpack.insert(2 * position, &member);
+                listpack.insert(2 * position + 1, &score.to_le_bytes());
+            }
+            Elements::SkipList { scores, list } => {
+                scores.insert(member.clone(), score);
+                list.insert(member, score);
+            }
+        }
+        !existed
+    }
+
+    /// Remove `member`, returning true if it was in the set.
+    pub fn remove(&mut self, member: &[u8]) -> bool {
+        match &mut self.elements {
+            Elements::ListPack(listpack) => {
+                let Some(position) = pairs(listpack).position(|(m, _)| m == member) else {
+                    return false;
+                };
+                listpack.remove_range(2 * position, 2);
+                true
+            }
+            Elements::SkipList { scores, list } => match scores.remove(member) {
+                Some(score) => list.remove(member, score),
+                None => false,
+            },
+        }
+    }
+
+    fn convert_to_skiplist(&mut self) {
+        if let Elements::ListPack(listpack) = &self.elements {
+            let mut list = SkipList::new();
+            let mut scores = Dict::new();
+            for (member, score) in pairs(listpack) {
+                let member = Bytes::copy_from_slice(member);
+                scores.insert(member.clone(), score);
+                list.insert(member, score);
+            }
+            self.elements = Elements::SkipList { scores, list };
+        }
+    }
+
+    /// Number of leading elements satisfying `predicate`, which must hold for a
+    /// prefix of the set in (score, member) order and not after it.
+    pub fn count_prefix(&self, predicate: impl Fn(f64, &[u8]) -> bool) -> usize {
+        match &self.elements {
+            Elements::ListPack(listpack) => pairs(listpack)
+                .take_while(|(member, score)| predicate(*score, member))
+                .count(),
+            Elements::SkipList { list, .. } => list.count_prefix(predicate),
+        }
+    }
+
+    /// The 0 based rank of `member` in ascending order.
+    pub fn rank(&self, member: &[u8]) -> Option<usize> {
+        let score = self.score(member)?;
+        Some(self.count_prefix(|s, m| compare((s, m), (score, member)).is_lt()))
+    }
+
+    /// The ranks `start..end` of the elements within `range`.
+    pub fn score_range_ranks(&self, range: &ScoreRange) -> (usize, usize) {
+        let start = self.count_prefix(|score, _| !range.above_min(score));
+        let end = self.count_prefix(|score, _| range.below_max(score));
+        (start, end.max(start))
+    }
+
+    /// The ranks `start..end` of the elements within `range`.
+    pub fn lex_range_ranks(&self, range: &LexRange) -> (usize, usize) {
+        let start = self.count_prefix(|_, member| !range.above_min(member));
+        let end = self.count_prefix(|_, member| range.below_max(member));
+        (start, end.max(start))
+    }
+
+    /// The elements with a rank in `start..end`, in descending order if `rev`.
+    pub fn range(
+        &self,
+        start: usize,
+        end: usize,
+        rev: bool,
+    ) -> Box<dyn Iterator<Item = (&[u8], f64)> + '_> {
+        match &self.elements {
+            Elements::ListPack(listpack) => {
+                let end = end.min(listpack.len() / 2);
+                let count = end.saturating_sub(start);
+                if rev {
+                    Box::new(
+                        pairs_rev(listpack)
+                            .skip(listpack.len() / 2 - end)
+                            .take(count),
+                    )
+                } else {
+                    Box::new(pairs(listpack).skip(start).take(count))
+                }
+            }
+            Elements::SkipList { list, .. } => Box::new(
+                list.range(start, end, rev)
+                    .map(|(member, score)| (member.as_ref(), score)),
+            ),
+        }
+    }
+
+    /// All the elements in ascending order.
+    /// Visit a batch of about `count` elements starting at `cursor`, returning
+    /// the cursor to continue from, 0 once done. The compact encoding is
+    /// visited in one go.
+    pub fn scan(&self, cursor: u64, count: usize, mut visit: impl FnMut(&[u8], f64)) -> u64 {
+        match &self.elements {
+            Elements::ListPack(listpack) => {
+                for (member, score) in pairs(listpack) {
+                    visit(member, score);
+                }
+                0
+            }
+            Elements::SkipList { scores, .. } => {
+                scores.scan_batch(cursor, count, |member, score| visit(member, *score))
+            }
+        }
+    }
+
+    pub fn iter(&self) -> Box<dyn Iterator<Item = (&[u8], f64)> + '_> {
+        self.range(0, self.len(), false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::EncodingLimits;
+
+    fn members(zset: &ZSet, start: usize, end: usize, rev: bool) -> Vec<String> {
+        zset.range(start, end, rev)
+            .map(|(member, _)| String::from_utf8_lossy(member).into_owned())
+            .collect()
+    }
+
+    fn sample(extra: usize) -> ZSet {
+        let mut zset = ZSet::new();
+        zset.insert(Bytes::from("b"), 2.0);
+        zset.insert(Bytes::from("a"), 1.0);
+        zset.insert(Bytes::from("c"), 3.0);
+        for i in 0..extra {
+            zset.insert(Bytes::from(format!("z{:03}", i)), 100.0 + i as f64);
+        }
+        zset
+    }
+
+    #[test]
+    fn test_both_encodings_behave_the_same() {
+        for (extra, encoding) in [(0, "listpack"), (200, "skiplist")] {
+            let mut zset = sample(extra);
+            assert_eq!(zset.encoding(), encoding);
+            assert_eq!(members(&zset, 0, 3, false), ["a", "b", "c"]);
+            assert_eq!(zset.rank(b"c"), Some(2));
+            assert!(!zset.insert(Bytes::from("a"), 5.0));
+            assert_eq!(zset.score(b"a"), Some(5.0));
+            assert_eq!(members(&zset, 0, 3, false), ["b", "c", "a"]);
+            assert_eq!(members(&zset, 0, 2, true), ["c", "b"]);
+
+            let range = ScoreRange {
+                min: 2.0,
+                max: 5.0,
+                min_exclusive: true,
+                max_exclusive: false,
+            };
+            assert_eq!(zset.score_range_ranks(&range), (1, 3));
+            assert!(zset.remove(b"b"));
+            assert!(!zset.remove(b"b"));
+            assert_eq!(zset.len(), 2 + extra);
+        }
+    }
+
+    #[test]
+    fn test_lex_range_ranks() {
+        let mut zset = ZSet::new();
+        for member in ["a", "b", "c", "d"] {
+            zset.insert(Bytes::from(member), 0.0);
+        }
+        let range = LexRange {
+            min: LexBound::Exclusive(Bytes::from("a")),
+            max: LexBound::Inclusive(Bytes::from("c")),
+        };
+        assert_eq!(zset.lex_range_ranks(&range), (1, 3));
+        let everything = LexRange {
+            min: LexBound::Min,
+            max: LexBound::Max,
+        };
+        assert_eq!(zset.lex_range_ranks(&everything), (0, 4));
+    }
+
+    #[test]
+    fn test_converts_on_member_length() {
+        let mut zset = sample(0);
+        zset.insert(
+            Bytes::from(vec![
+                b'x';
+                EncodingLimits::DEFAULT.zset_max_listpack_value + 1
+            ]),
+            0.0,
+        );
+        assert_eq!(zset.encoding(), "skiplist");
+        assert_eq!(zset.rank(b"a"), Some(1));
+    }
+}
@@ -0,0 +1,231 @@
+use std::{
+    cell::Cell,
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+thread_local! {
+    static RANDOM_STATE: Cell<u64> = Cell::new(random_seed());
+}
+
+fn random_seed() -> u64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u128(
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or_default(),
+    );
+    hasher.finish() | 1
+}
+
+/// Current unix time in milliseconds.
+pub fn mstime() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+/// A fast, non cryptographic pseudo random number (xorshift64*).
+pub fn random_u64() -> u64 {
+    RANDOM_STATE.with(|state| {
+        let mut x = state.get();
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        state.set(x);
+        x.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    })
+}
+
+/// `bytes` as an integer, if they are the canonical representation of one (no
+/// sign for positives, no leading zeros), so that it converts back verbatim.
+pub fn canonical_int(bytes: &[u8]) -> Option<i64> {
+    let value: i64 = std::str::from_utf8(bytes).ok()?.parse().ok()?;
+    (value.to_string().as_bytes() == bytes).then_some(value)
+}
+
+/// A random index in `0..len`. `len` must not be zero.
+pub fn random_index(len: usize) -> usize {
+    (random_u64() % len as u64) as usize
+}
+
+/// Pick `count` distinct indexes in `0..len`, in random order.
+pub fn random_distinct_indexes(len: usize, count: usize) -> Vec<usize> {
+    let mut indexes: Vec<usize> = (0..len).collect();
+    let count = count.min(len);
+    for i in 0..count {
+        let j = i + random_index(len - i);
+        indexes.swap(i, j);
+    }
+    indexes.truncate(count);
+    indexes
+}
+
+/// Match `string` against a redis glob style `pattern`: `*` and `?` wildcards,
+/// `[...]` classes with ranges and `^` negation, and `\` escapes. A port of
+/// redis' `stringmatchlen`, including its guards against patterns that would
+/// otherwise take exponential time.
+pub fn string_match(pattern: &[u8], string: &[u8], nocase: bool) -> bool {
+    let mut skip_longer_matches = false;
+    string_match_impl(pattern, string, nocase, &mut skip_longer_matches, 0)
+}
+
+fn string_match_impl(
+    mut pattern: &[u8],
+    mut string: &[u8],
+    nocase: bool,
+    skip_longer_matches: &mut bool,
+    nesting: usize,
+) -> bool {
+    // Protect the stack from patterns with a huge number of stars.
+    if nesting > 1000 {
+        return false;
+    }
+    let same = |a: u8, b: u8| {
+        if nocase {
+            a.eq_ignore_ascii_case(&b)
+        } else {
+            a == b
+        }
+    };
+    while !pattern.is_empty() && !string.is_empty() {
+        match pattern[0] {
+            b'*' => {
+                while pattern.get(1) == Some(&b'*') {
+                    pattern = &pattern[1..];
+                }
+                if pattern.len() == 1 {
+                    return true;
+                }
+                while !string.is_empty() {
+                    if string_match_impl(
+                        &pattern[1..],
+                        string,
+                        nocase,
+                        skip_longer_matches,
+                        nesting + 1,
+                    ) {
+                        return true;
+                    }
+                    // The rest of the pattern failed against a suffix that is
+                    // too short already, so longer suffixes cannot do better.
+                    if *skip_longer_matches {
+                        return false;
+                    }
+                    string = &string[1..];
+                }
+                *skip_longer_matches = true;
+                return false;
+            }
+            b'?' => {
+                pattern = &pattern[1..];
+                string = &string[1..];
+            }
+            b'[' => {
+                pattern = &pattern[1..];
+                let negated = pattern.first() == Some(&b'^');
+                if negated {
+                    pattern = &pattern[1..];
+                }
+                let mut matched = false;
+                loop {
+                    match *pattern {
+                        [b'\\', escaped, ..] => {
+                            pattern = &pattern[1..];
+                            matched |= escaped == string[0];
+                        }
+                        [b']', ..] => break,
+                        // An unterminated class ends with the pattern.
+                        [] => break,
+                        [start, b'-', end, ..] => {
+                            let (mut start, mut end, mut c) = (start, end, string[0]);
+                            if start > end {
+                                std::mem::swap(&mut start, &mut end);
+                            }
+                            if nocase {
+                                start = start.to_ascii_lowercase();
+                                end = end.to_ascii_lowercase();
+                                c = c.to_ascii_lowercase();
+                            }
+                            pattern = &pattern[2..];
+                            matched |= (start..=end).contains(&c);
+                        }
+                        [c, ..] => matched |= same(c, string[0]),
+                    }
+                    pattern = &pattern[1..];
+                }
+                if matched == negated {
+                    return false;
+                }
+                pattern = pattern.get(1..).unwrap_or_default();
+                string = &string[1..];
+            }
+            _ => {
+                if pattern[0] == b'\\' && pattern.len() >= 2 {
+                    pattern = &pattern[1..];
+                }
+                if !same(pattern[0], string[0]) {
+                    return false;
+                }
+                pattern = &pattern[1..];
+                string = &string[1..];
+            }
+        }
+        if string.is_empty() {
+            while pattern.first() == Some(&b'*') {
+                pattern = &pattern[1..];
+            }
+            break;
+        }
+    }
+    pattern.is_empty() && string.is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_random_distinct_indexes() {
+        let mut indexes = random_distinct_indexes(10, 4);
+        assert_eq!(indexes.len(), 4);
+        indexes.sort_unstable();
+        indexes.dedup();
+        assert_eq!(indexes.len(), 4);
+        assert!(indexes.iter().all(|index| *index < 10));
+        assert_eq!(random_distinct_indexes(3, 10).len(), 3);
+    }
+
+    #[test]
+    fn test_string_match() {
+        assert!(string_match(b"*", b"anything", false));
+        assert!(string_match(b"h?llo", b"hello", false));
+        assert!(string_match(b"h*llo", b"heeeello", false));
+        assert!(string_match(b"h[ae]llo", b"hallo", false));
+        assert!(!string_match(b"h[ae]llo", b"hillo", false));
+        assert!(string_match(b"h[^e]llo", b"hallo", false));
+        assert!(!string_match(b"h[^e]llo", b"hello", false));
+        assert!(string_match(b"h[a-b]llo", b"hbllo", false));
+        assert!(string_match(b"h[b-a]llo", b"hbllo", false));
+        assert!(string_match(b"h\\*llo", b"h*llo", false));
+        assert!(!string_match(b"h\\*llo", b"hello", false));
+        assert!(string_match(b"[\\]]", b"]", false));
+        assert!(string_match(b"HELLO", b"hello", true));
+        assert!(!string_match(b"HELLO", b"hello", false));
+        assert!(string_match(b"a*", b"a", false));
+        assert!(!string_match(b"a?", b"a", false));
+        // An unterminated class matches up to the end of the pattern.
+        assert!(string_match(b"a[bc", b"ab", false));
+        assert!(!string_match(b"", b"a", false));
+        assert!(string_match(b"", b"", false));
+        assert!(string_match(&b"a*".repeat(50), &[b'a'; 60], false));
+        assert!(!string_match(
+            b"a*a*a*a*a*a*a*a*a*a*a*a*a*a*a*a*a*a*a*a*b",
+            &[b'a'; 1000],
+            false
+        ));
+    }
+}